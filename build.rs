@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const REPLACEMENT_TABLES: &str = "src/html5_parser/tokenizer/replacement_tables.rs";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", REPLACEMENT_TABLES);
+
+    let source = fs::read_to_string(REPLACEMENT_TABLES).expect("cannot read replacement tables");
+    let entities = named_entities(&source);
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(
+        Path::new(&out_dir).join("entity_trie.rs"),
+        generate_trie(&entities),
+    )
+    .expect("cannot write entity trie");
+}
+
+// Reads the entries of the TOKEN_NAMED_CHARS map, which are written as
+// `"name;" => "\u{XXXX}\u{XXXX}",`
+fn named_entities(source: &str) -> Vec<(String, String)> {
+    let start = source
+        .find("TOKEN_NAMED_CHARS")
+        .expect("TOKEN_NAMED_CHARS not found");
+
+    let mut entities = Vec::new();
+    for line in source[start..].lines().skip(1) {
+        let line = line.trim();
+        if line.starts_with("};") {
+            break;
+        }
+        let Some((name, value)) = line.split_once("=>") else {
+            continue;
+        };
+
+        let name = name.trim().trim_matches('"').to_string();
+        let value = value
+            .trim()
+            .trim_end_matches(',')
+            .trim_matches('"')
+            .split("\\u{")
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let hex = part.trim_end_matches('}');
+                char::from_u32(u32::from_str_radix(hex, 16).expect("invalid code point"))
+                    .expect("invalid code point")
+            })
+            .collect();
+        entities.push((name, value));
+    }
+
+    assert!(!entities.is_empty(), "no named entities found");
+    entities
+}
+
+#[derive(Default)]
+struct TrieNode {
+    children: BTreeMap<u8, TrieNode>,
+    value: Option<String>,
+}
+
+// Generates the NODES and EDGES tables of a trie over the entity names. The edges of a node are
+// stored consecutively and sorted by byte, so a lookup can binary search them.
+fn generate_trie(entities: &[(String, String)]) -> String {
+    let mut root = TrieNode::default();
+    for (name, value) in entities {
+        let mut node = &mut root;
+        for byte in name.bytes() {
+            node = node.children.entry(byte).or_default();
+        }
+        node.value = Some(value.clone());
+    }
+
+    // Number the nodes breadth first, so the children of every node get consecutive indices
+    let mut nodes = vec![&root];
+    let mut edges = Vec::new();
+    let mut first_edges = Vec::new();
+    let mut idx = 0;
+    while idx < nodes.len() {
+        let node = nodes[idx];
+        first_edges.push(edges.len());
+        for (&byte, child) in &node.children {
+            edges.push((byte, nodes.len()));
+            nodes.push(child);
+        }
+        idx += 1;
+    }
+    assert!(nodes.len() <= u16::MAX as usize, "too many trie nodes");
+
+    let mut out = String::new();
+    writeln!(out, "// Generated by build.rs from TOKEN_NAMED_CHARS").unwrap();
+    writeln!(out, "static NODES: [TrieNode; {}] = [", nodes.len()).unwrap();
+    for (node, first_edge) in nodes.iter().zip(first_edges) {
+        let value = match &node.value {
+            Some(value) => format!("Some({:?})", value),
+            None => "None".to_string(),
+        };
+        writeln!(
+            out,
+            "    TrieNode {{ first_edge: {}, edge_count: {}, value: {} }},",
+            first_edge,
+            node.children.len(),
+            value
+        )
+        .unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    writeln!(out, "static EDGES: [(u8, u16); {}] = [", edges.len()).unwrap();
+    for (byte, child) in edges {
+        writeln!(out, "    ({}, {}),", byte, child).unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    out
+}
@@ -1,8 +1,8 @@
 use std::process::exit;
 
+use gosub_engine::html5_parser::document_format::{parse_document, DocumentFormat};
 use gosub_engine::html5_parser::input_stream::Confidence;
 use gosub_engine::html5_parser::input_stream::{Encoding, InputStream};
-use gosub_engine::html5_parser::parser::Html5Parser;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let url = std::env::args()
@@ -19,6 +19,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("could not get url. Status code {}", response.status());
         exit(1);
     }
+    // Documents served with an XML MIME type are parsed as XML, everything else as HTML
+    let format = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(DocumentFormat::from_mime_type)
+        .unwrap_or(DocumentFormat::Html);
     let html = response.text()?;
 
     let mut stream = InputStream::new();
@@ -30,8 +37,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         stream.detect_encoding()
     }
 
-    let mut parser = Html5Parser::new(&mut stream);
-    let (document, parse_error) = parser.parse();
+    let (document, parse_error) = parse_document(&mut stream, format);
 
     println!("Generated tree: \n\n {}", document);
 
@@ -0,0 +1,42 @@
+use std::io::Read;
+use std::process::exit;
+use std::{fs, io};
+
+use gosub_engine::html5_parser::highlighter::{highlight, OutputFormat, STYLESHEET};
+
+fn main() -> io::Result<()> {
+    let mut format = OutputFormat::Ansi;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--html" => format = OutputFormat::Html,
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => {
+                println!("Usage: gosub-highlight [--html] [file]");
+                exit(1);
+            }
+        }
+    }
+
+    // Reads from stdin when no file is given
+    let bytes = match path {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut bytes = Vec::new();
+            io::stdin().read_to_end(&mut bytes)?;
+            bytes
+        }
+    };
+    let html = String::from_utf8_lossy(&bytes);
+
+    let highlighted = highlight(&html, format);
+    match format {
+        OutputFormat::Ansi => println!("{}", highlighted),
+        OutputFormat::Html => println!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<style>\n{}</style>\n</head>\n<body>\n{}\n</body>\n</html>",
+            STYLESHEET, highlighted
+        ),
+    }
+
+    Ok(())
+}
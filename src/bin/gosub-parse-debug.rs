@@ -0,0 +1,165 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Write};
+use std::process::exit;
+
+use gosub_engine::html5_parser::input_stream::InputStream;
+use gosub_engine::html5_parser::parser::document::Document;
+use gosub_engine::html5_parser::parser::trace::TraceStep;
+use gosub_engine::html5_parser::parser::Html5Parser;
+
+const USAGE: &str =
+    "Usage: gosub-parse-debug [--break-tag <name>]... [--break-error <code>]... <file>";
+
+const HELP: &str = "\
+Commands:
+  s, <enter>   step to the next token
+  c            continue to the next breakpoint
+  b <name>     break on start and end tags with this name
+  e <code>     break on errors that contain this code
+  l            list the breakpoints
+  d            delete all breakpoints
+  q            quit";
+
+#[derive(Debug)]
+enum Breakpoint {
+    Tag(String),
+    Error(String),
+}
+
+impl Breakpoint {
+    fn hit(&self, step: &TraceStep) -> bool {
+        match self {
+            Breakpoint::Tag(name) => step.tag_name.as_deref() == Some(name.as_str()),
+            Breakpoint::Error(code) => step.errors.iter().any(|error| error.contains(code)),
+        }
+    }
+}
+
+struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    stepping: bool,    // Stop at every step instead of only at breakpoints
+    interactive: bool, // False once the commands on stdin have run out
+    steps: usize,
+}
+
+impl Debugger {
+    fn on_step(&mut self, step: &TraceStep, document: &Document) {
+        self.steps += 1;
+
+        let hits: Vec<&Breakpoint> = self
+            .breakpoints
+            .iter()
+            .filter(|breakpoint| breakpoint.hit(step))
+            .collect();
+        if !self.interactive || (!self.stepping && hits.is_empty()) {
+            return;
+        }
+
+        print!("{}", step.to_report(self.steps));
+        println!("    tree:");
+        for line in document.to_html5lib_tree().lines() {
+            println!("    {}", line);
+        }
+        for breakpoint in hits {
+            println!("Breakpoint: {:?}", breakpoint);
+        }
+
+        self.prompt();
+    }
+
+    // Reads commands until one of them resumes parsing
+    fn prompt(&mut self) {
+        let stdin = io::stdin();
+        loop {
+            print!("> ");
+            let _ = io::stdout().flush();
+
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                // Without further commands the rest of the input is parsed without stopping
+                println!();
+                self.interactive = false;
+                return;
+            }
+
+            let line = line.trim();
+            let (command, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let arg = arg.trim();
+            match command {
+                "" | "s" => {
+                    self.stepping = true;
+                    return;
+                }
+                "c" => {
+                    self.stepping = false;
+                    return;
+                }
+                "b" if !arg.is_empty() => {
+                    self.breakpoints.push(Breakpoint::Tag(arg.to_lowercase()))
+                }
+                "e" if !arg.is_empty() => self.breakpoints.push(Breakpoint::Error(arg.to_string())),
+                "l" => {
+                    for breakpoint in &self.breakpoints {
+                        println!("{:?}", breakpoint);
+                    }
+                }
+                "d" => self.breakpoints.clear(),
+                "q" => exit(0),
+                _ => println!("{}", HELP),
+            }
+        }
+    }
+}
+
+fn usage() -> ! {
+    println!("{}", USAGE);
+    exit(1);
+}
+
+fn main() -> io::Result<()> {
+    let mut breakpoints = Vec::new();
+    let mut path = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--break-tag" | "--break-error" => {
+                let Some(value) = args.next() else { usage() };
+                breakpoints.push(match arg.as_str() {
+                    "--break-tag" => Breakpoint::Tag(value.to_lowercase()),
+                    _ => Breakpoint::Error(value),
+                });
+            }
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let Some(path) = path else { usage() };
+
+    let mut stream = InputStream::new();
+    stream.read_from_file(File::open(path)?, None)?;
+
+    // Without breakpoints the debugger stops at the first step
+    let mut debugger = Debugger {
+        stepping: breakpoints.is_empty(),
+        breakpoints,
+        interactive: true,
+        steps: 0,
+    };
+    println!("{}\n", HELP);
+
+    let mut parser = Html5Parser::new(&mut stream);
+    parser.on_trace_step(|step, document| debugger.on_step(step, document));
+    let (document, errors) = parser.parse();
+    let tree = document.to_html5lib_tree();
+    drop(parser);
+
+    println!(
+        "Parsed in {} steps with {} errors",
+        debugger.steps,
+        errors.len()
+    );
+    print!("{}", tree);
+
+    Ok(())
+}
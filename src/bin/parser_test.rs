@@ -1,12 +1,20 @@
+use gosub_engine::html5_parser::error_logger::{ParseError, ParserError};
 use gosub_engine::html5_parser::input_stream::InputStream;
-use gosub_engine::html5_parser::node::NodeData;
-use gosub_engine::html5_parser::parser::document::Document;
-use gosub_engine::html5_parser::parser::Html5Parser;
+use gosub_engine::html5_parser::node::{Node, HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE};
+use gosub_engine::html5_parser::parser::{Html5Parser, ParserOptions};
 use regex::Regex;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
-use std::{env, fs, io};
+use std::sync::{mpsc, Once};
+use std::time::Duration;
+use std::{env, fs, io, panic, thread};
+
+// Parsing runs on its own thread, so a parser that panics or does not terminate fails the test
+// instead of ending the run
+const PARSE_TIMEOUT: Duration = Duration::from_secs(10);
+const PARSE_THREAD_NAME: &str = "parser";
 
 pub struct TestResults {
     tests: usize,           // Number of tests (as defined in the suite)
@@ -14,15 +22,20 @@ pub struct TestResults {
     succeeded: usize,  // How many succeeded assertions
     failed: usize,     // How many failed assertions
     failed_position: usize, // How many failed assertions where position is not correct
+    missing_errors: usize, // How many expected errors were not found
+    extra_errors: usize, // How many errors were found that were not expected
+    not_compared: usize, // How many expected tree construction errors were not compared
 }
 
+#[derive(Clone)]
 struct Test {
-    file_path: String,              // Filename of the test
-    line: usize,                    // Line number of the test
-    data: String,                   // input stream
-    errors: Vec<Error>,             // errors
-    document: Vec<String>,          // document tree
-    document_fragment: Vec<String>, // fragment
+    file_path: String,                 // Filename of the test
+    line: usize,                       // Line number of the test
+    data: String,                      // input stream
+    errors: Vec<Error>,                // errors
+    document: Vec<String>,             // document tree
+    document_fragment: Option<String>, // context element when parsing a fragment
+    scripting: Option<bool>,           // scripting mode, or None for the default mode
 }
 
 fn main() -> io::Result<()> {
@@ -35,16 +48,15 @@ fn main() -> io::Result<()> {
         succeeded: 0,
         failed: 0,
         failed_position: 0,
+        missing_errors: 0,
+        extra_errors: 0,
+        not_compared: 0,
     };
 
     for entry in fs::read_dir(dir + "/tree-construction")? {
         let entry = entry?;
         let path = entry.path();
 
-        if !path.ends_with("tests1.dat") {
-            continue;
-        }
-
         if !path.is_file() || path.extension().unwrap() != "dat" {
             continue;
         }
@@ -52,17 +64,12 @@ fn main() -> io::Result<()> {
         let tests = read_tests(path.clone())?;
         println!("🏃‍♂️ Running {} tests from 🗄️ {:?}\n", tests.len(), path);
 
-        let mut test_idx = 1;
-        for test in tests {
-            // if test_idx == 3 {
-            run_tree_test(test_idx, &test, &mut results);
-            // }
-
-            test_idx += 1;
+        for (test_idx, test) in (1..).zip(tests.iter()) {
+            run_tree_test(test_idx, test, &mut results);
         }
     }
 
-    println!("🏁 Tests completed: Ran {} tests, {} assertions, {} succeeded, {} failed ({} position failures)", results.tests, results.assertions, results.succeeded, results.failed, results.failed_position);
+    println!("🏁 Tests completed: Ran {} tests, {} assertions, {} succeeded, {} failed ({} position failures, {} missing errors, {} extra errors), {} tree construction errors not compared", results.tests, results.assertions, results.succeeded, results.failed, results.failed_position, results.missing_errors, results.extra_errors, results.not_compared);
     Ok(())
 }
 
@@ -77,9 +84,12 @@ fn read_tests(file_path: PathBuf) -> io::Result<Vec<Test>> {
         data: "".to_string(),
         errors: vec![],
         document: vec![],
-        document_fragment: vec![],
+        document_fragment: None,
+        scripting: None,
     };
     let mut section: Option<&str> = None;
+    // Errors are written as "(line,col): code" or "(line:col) code"
+    let error_re = Regex::new(r"^\((?P<line>\d+)[,:](?P<col>\d+)\):? (?P<code>.+)$").unwrap();
 
     for (line_num, line) in reader.lines().enumerate() {
         if line.is_err() {
@@ -92,30 +102,42 @@ fn read_tests(file_path: PathBuf) -> io::Result<Vec<Test>> {
                 || !current_test.errors.is_empty()
                 || !current_test.document.is_empty()
             {
-                current_test.data = current_test.data.trim_end().to_string();
+                finish_test(&mut current_test);
                 tests.push(current_test);
                 current_test = Test {
                     file_path: file_path.to_str().unwrap().to_string(),
-                    line: line_num,
+                    line: line_num + 1,
                     data: "".to_string(),
                     errors: vec![],
                     document: vec![],
-                    document_fragment: vec![],
+                    document_fragment: None,
+                    scripting: None,
                 };
             }
             section = Some("data");
         } else if line.starts_with('#') {
             section = match line.as_str() {
-                "#errors" => Some("errors"),
+                "#errors" | "#new-errors" => Some("errors"),
                 "#document" => Some("document"),
+                "#document-fragment" => Some("document_fragment"),
+                "#script-on" => {
+                    current_test.scripting = Some(true);
+                    None
+                }
+                "#script-off" => {
+                    current_test.scripting = Some(false);
+                    None
+                }
                 _ => None,
             };
         } else if let Some(sec) = section {
             match sec {
-                "data" => current_test.data.push_str(&line),
+                "data" => {
+                    current_test.data.push_str(&line);
+                    current_test.data.push('\n');
+                }
                 "errors" => {
-                    let re = Regex::new(r"\((?P<line>\d+),(?P<col>\d+)\): (?P<code>.+)").unwrap();
-                    if let Some(caps) = re.captures(&line) {
+                    if let Some(caps) = error_re.captures(&line) {
                         let line = caps.name("line").unwrap().as_str().parse::<i64>().unwrap();
                         let col = caps.name("col").unwrap().as_str().parse::<i64>().unwrap();
                         let code = caps.name("code").unwrap().as_str().to_string();
@@ -124,7 +146,9 @@ fn read_tests(file_path: PathBuf) -> io::Result<Vec<Test>> {
                     }
                 }
                 "document" => current_test.document.push(line),
-                "document_fragment" => current_test.document_fragment.push(line),
+                "document_fragment" if !line.is_empty() => {
+                    current_test.document_fragment = Some(line)
+                }
                 _ => (),
             }
         }
@@ -135,13 +159,96 @@ fn read_tests(file_path: PathBuf) -> io::Result<Vec<Test>> {
         || !current_test.errors.is_empty()
         || !current_test.document.is_empty()
     {
-        current_test.data = current_test.data.trim_end().to_string();
+        finish_test(&mut current_test);
         tests.push(current_test);
     }
 
     Ok(tests)
 }
 
+// Removes what separates the test from the next one: the newline after the last line of data and
+// the empty line after the document
+fn finish_test(test: &mut Test) {
+    test.data.pop();
+    while test.document.last().is_some_and(|line| line.is_empty()) {
+        test.document.pop();
+    }
+}
+
+// Returns the context element of a fragment test, ie: "td" or "svg path"
+fn context_node(context: &str) -> Node {
+    let (namespace, name) = match context.split_once(' ') {
+        Some(("svg", name)) => (SVG_NAMESPACE, name),
+        Some(("math", name)) => (MATHML_NAMESPACE, name),
+        _ => (HTML_NAMESPACE, context),
+    };
+    Node::new_element(name, HashMap::new(), namespace)
+}
+
+// Parses the test data and returns the tree in the html5lib format together with the errors
+fn parse(test: &Test) -> (String, Vec<ParseError>) {
+    let mut is = InputStream::new();
+    is.read_from_str(test.data.as_str(), None);
+
+    let options = ParserOptions {
+        scripting_enabled: test.scripting.unwrap_or(true),
+        ..Default::default()
+    };
+
+    match &test.document_fragment {
+        Some(context) => {
+            let context = context_node(context);
+            let mut parser = Html5Parser::new_fragment(&mut is, &context, options);
+            let (document, _, parse_errors) = parser.parse_fragment();
+            // The parsed nodes are the children of the html root element
+            let root_id = document.get_root().children.first().copied().unwrap_or(0);
+            (document.to_html5lib_tree_from(root_id), parse_errors)
+        }
+        None => {
+            let mut parser = Html5Parser::new_with_options(&mut is, options);
+            let (document, parse_errors) = parser.parse();
+            (document.to_html5lib_tree(), parse_errors)
+        }
+    }
+}
+
+// Parses on a separate thread, turning panics and timeouts into an error
+fn run_parser(test: &Test) -> Result<(String, Vec<ParseError>), String> {
+    // Panics of the parser are reported as failed tests, so keep them out of the output
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if thread::current().name() != Some(PARSE_THREAD_NAME) {
+                default_hook(info);
+            }
+        }));
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let thread_test = test.clone();
+    thread::Builder::new()
+        .name(PARSE_THREAD_NAME.to_string())
+        .spawn(move || {
+            let result = panic::catch_unwind(|| parse(&thread_test));
+            let _ = sender.send(result);
+        })
+        .map_err(|err| format!("Could not start the parser: {}", err))?;
+
+    match receiver.recv_timeout(PARSE_TIMEOUT) {
+        Ok(Ok(result)) => Ok(result),
+        Ok(Err(payload)) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(format!("Parser panicked: {}", message))
+        }
+        Err(_) => Err("Parser did not finish".to_string()),
+    }
+}
+
 fn run_tree_test(test_idx: usize, test: &Test, results: &mut TestResults) {
     println!(
         "🧪 Running test #{}: {}::{}",
@@ -152,63 +259,25 @@ fn run_tree_test(test_idx: usize, test: &Test, results: &mut TestResults) {
 
     let old_failed = results.failed;
 
-    let mut is = InputStream::new();
-    is.read_from_str(test.data.as_str(), None);
+    let (tree, parse_errors) = match run_parser(test) {
+        Ok(result) => result,
+        Err(message) => {
+            println!("❌ {}", message);
+            results.assertions += 1;
+            results.failed += 1;
+            println!("----------------------------------------");
+            return;
+        }
+    };
+
+    results.assertions += 1;
+    if match_document_tree(&tree, &test.document) {
+        results.succeeded += 1;
+    } else {
+        results.failed += 1;
+    }
 
-    let mut parser = Html5Parser::new(&mut is);
-    let (document, _parse_errors) = parser.parse();
-
-    match_document_tree(document, &test.document);
-
-    // if parse_errors.len() != test.errors.len() {
-    //     println!("❌ Unexpected errors found (wanted {}, got {}): ", test.errors.len(), parse_errors.len());
-    //     for want_err in &test.errors {
-    //         println!("     * Want: '{}' at {}:{}", want_err.code, want_err.line, want_err.col);
-    //     }
-    //     for got_err in &parse_errors {
-    //         println!("     * Got: '{}' at {}:{}", got_err.message, got_err.line, got_err.col);
-    //     }
-    //     results.assertions += 1;
-    //     results.failed += 1;
-    // } else {
-    //     println!("✅ Found {} errors", parse_errors.len());
-    // }
-    //
-    // // Check each error messages
-    // let mut idx = 0;
-    // for error in &test.errors {
-    //     if parse_errors.get(idx).is_none() {
-    //         println!("❌ Expected error '{}' at {}:{}", error.code, error.line, error.col);
-    //         results.assertions += 1;
-    //         results.failed += 1;
-    //         continue;
-    //     }
-    //
-    //     let err = parse_errors.get(idx).unwrap();
-    //     let got_error = Error{
-    //         code: err.message.to_string(),
-    //         line: err.line as i64,
-    //         col: err.col as i64,
-    //     };
-    //
-    //     match match_error(&got_error, &error) {
-    //         ErrorResult::Failure => {
-    //             results.assertions += 1;
-    //             results.failed += 1;
-    //         },
-    //         ErrorResult::PositionFailure => {
-    //             results.assertions += 1;
-    //             results.failed += 1;
-    //             results.failed_position += 1;
-    //         },
-    //         ErrorResult::Success => {
-    //             results.assertions += 1;
-    //             results.succeeded += 1;
-    //         }
-    //     }
-    //
-    //     idx += 1;
-    // }
+    match_errors(&parse_errors, &test.errors, results);
 
     if old_failed != results.failed {
         println!("----------------------------------------");
@@ -216,7 +285,7 @@ fn run_tree_test(test_idx: usize, test: &Test, results: &mut TestResults) {
         println!("{}", test.data);
         println!("----------------------------------------");
         println!("🌳 Generated tree: ");
-        println!("{}", document);
+        print!("{}", tree);
         println!("----------------------------------------");
         println!("🌳 Expected tree: ");
         for line in &test.document {
@@ -227,7 +296,6 @@ fn run_tree_test(test_idx: usize, test: &Test, results: &mut TestResults) {
     println!("----------------------------------------");
 }
 
-#[allow(dead_code)]
 #[derive(PartialEq)]
 enum ErrorResult {
     Success,         // Found the correct error
@@ -235,7 +303,7 @@ enum ErrorResult {
     PositionFailure, // Found the error, but on an incorrect position
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct Error {
     pub code: String,
     pub line: i64,
@@ -264,67 +332,107 @@ pub struct Error {
 -   Template contents are represented by the string "content" with the
     children below it.
 **/
+fn match_document_tree(tree: &str, expected: &[String]) -> bool {
+    let found: Vec<&str> = tree.lines().collect();
+
+    for (idx, expected_line) in expected.iter().enumerate() {
+        match found.get(idx) {
+            Some(line) if line == expected_line => println!("✅ {}", expected_line),
+            Some(line) => {
+                println!("❌ {}, Found unexpected node: {}", expected_line, line);
+                return false;
+            }
+            None => {
+                println!("❌ {}, Node not found", expected_line);
+                return false;
+            }
+        }
+    }
+
+    if let Some(line) = found.get(expected.len()) {
+        println!("❌ Found unexpected node: {}", line);
+        return false;
+    }
 
-fn match_document_tree(document: &Document, expected: &Vec<String>) -> bool {
-    match_node(0, -1, -1, document, expected);
     true
 }
 
-fn match_node(
-    node_idx: usize,
-    expected_id: isize,
-    indent: isize,
-    document: &Document,
-    expected: &Vec<String>,
-) -> Option<usize> {
-    let node = document.get_node_by_id(node_idx).unwrap();
-
-    if node_idx > 0 {
-        match &node.data {
-            NodeData::Element { name, .. } => {
-                let value = format!("|{}<{}>", " ".repeat((indent as usize * 2) + 1), name);
-                if value != expected[expected_id as usize] {
-                    println!(
-                        "❌ {}, Found unexpected element node: {}",
-                        expected[expected_id as usize], name
-                    );
-                    return None;
-                } else {
-                    println!("✅ {}", expected[expected_id as usize]);
-                }
+// Compares the errors of the parser with the expected errors. Each expected error is matched
+// against an exact match, or else against an error with the same code on another position.
+// Remaining errors of the parser are reported as extra errors.
+//
+// Only tokenizer errors are compared, as these are the only errors with a code in the HTML
+// specification. The parser reports tree construction errors with a message of its own, which
+// cannot be matched against the codes of html5lib, so those are reported as not compared.
+fn match_errors(parse_errors: &[ParseError], expected: &[Error], results: &mut TestResults) {
+    let mut found: Vec<Option<Error>> = parse_errors
+        .iter()
+        .filter(|err| ParserError::from_spec_code(&err.message).is_some())
+        .map(|err| {
+            Some(Error {
+                code: err.message.clone(),
+                line: err.line as i64,
+                col: err.col as i64,
+            })
+        })
+        .collect();
+
+    for expected_err in expected {
+        if ParserError::from_spec_code(&expected_err.code).is_none() {
+            println!(
+                "➖ Not compared: tree construction error '{}' at {}:{}",
+                expected_err.code, expected_err.line, expected_err.col
+            );
+            results.not_compared += 1;
+            continue;
+        }
+
+        results.assertions += 1;
+
+        let idx = found
+            .iter()
+            .position(|err| err.as_ref() == Some(expected_err))
+            .or_else(|| {
+                found.iter().position(|err| {
+                    err.as_ref()
+                        .is_some_and(|err| err.code == expected_err.code)
+                })
+            });
+
+        let result = match idx.and_then(|idx| found[idx].take()) {
+            Some(got_err) => match_error(&got_err, expected_err),
+            None => {
+                println!(
+                    "❌ Expected error '{}' at {}:{}",
+                    expected_err.code, expected_err.line, expected_err.col
+                );
+                results.missing_errors += 1;
+                ErrorResult::Failure
             }
-            NodeData::Text { value } => {
-                let value = format!("|{}\"{}\"", " ".repeat(indent as usize * 2 + 1), value);
-                if value != expected[expected_id as usize] {
-                    println!(
-                        "❌ {}, Found unexpected text node: {}",
-                        expected[expected_id as usize], value
-                    );
-                    return None;
-                } else {
-                    println!("✅ {}", expected[expected_id as usize]);
-                }
+        };
+
+        match result {
+            ErrorResult::Success => results.succeeded += 1,
+            ErrorResult::Failure => results.failed += 1,
+            ErrorResult::PositionFailure => {
+                results.failed += 1;
+                results.failed_position += 1;
             }
-            _ => {}
         }
     }
 
-    let mut next_expected_idx = expected_id + 1;
-
-    for &child_idx in &node.children {
-        if let Some(new_idx) =
-            match_node(child_idx, next_expected_idx, indent + 1, document, expected)
-        {
-            next_expected_idx = new_idx as isize;
-        } else {
-            return None;
-        }
+    for got_err in found.iter().flatten() {
+        println!(
+            "❌ Unexpected error '{}' at {}:{}",
+            got_err.code, got_err.line, got_err.col
+        );
+        results.assertions += 1;
+        results.failed += 1;
+        results.extra_errors += 1;
     }
-
-    Some(next_expected_idx as usize)
 }
 
-#[allow(dead_code)]
+// Compares an error of the parser with an expected error with the same code
 fn match_error(got_err: &Error, expected_err: &Error) -> ErrorResult {
     if got_err == expected_err {
         // Found an exact match
@@ -336,14 +444,6 @@ fn match_error(got_err: &Error, expected_err: &Error) -> ErrorResult {
         return ErrorResult::Success;
     }
 
-    if got_err.code != expected_err.code {
-        println!(
-            "❌ Expected error '{}' at {}:{}",
-            expected_err.code, expected_err.line, expected_err.col
-        );
-        return ErrorResult::Failure;
-    }
-
     // Found an error with the same code, but different line/pos
     println!(
         "⚠️ Unexpected error position '{}' at {}:{} (got: {}:{})",
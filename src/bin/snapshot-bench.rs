@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::{Duration, Instant};
+use std::{fs, io, panic};
+
+use gosub_engine::html5_parser::input_stream::InputStream;
+use gosub_engine::html5_parser::parser::document::Document;
+use gosub_engine::html5_parser::parser::Html5Parser;
+use regex::Regex;
+
+// Number of times the corpus is parsed and loaded. The fastest run is reported.
+const RUNS: usize = 3;
+
+const RAW_TEXT_ELEMENTS: [&str; 9] = [
+    "script", "style", "title", "textarea", "xmp", "iframe", "noembed", "noframes", "noscript",
+];
+
+fn main() -> io::Result<()> {
+    let paths: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        println!("Usage: snapshot-bench <file or directory>...");
+        exit(1);
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        collect_html_files(path, &mut files)?;
+    }
+    files.sort();
+
+    // The parser cannot handle raw text elements like <script> yet, so these are removed from
+    // the input. Files it still fails on are left out of the corpus.
+    let raw_text_elements: Vec<Regex> = RAW_TEXT_ELEMENTS
+        .iter()
+        .map(|name| Regex::new(&format!(r"(?is)<{0}\b.*?</{0}\s*>", name)).unwrap())
+        .collect();
+
+    let mut corpus = Vec::new();
+    let mut snapshots = Vec::new();
+    let mut skipped = 0;
+    panic::set_hook(Box::new(|_| {}));
+    for file in &files {
+        let bytes = fs::read(file)?;
+        let mut html = String::from_utf8_lossy(&bytes).into_owned();
+        for element in &raw_text_elements {
+            html = element.replace_all(&html, "").into_owned();
+        }
+        match panic::catch_unwind(|| parse(&html)) {
+            Ok(document) => {
+                let mut snapshot = Vec::new();
+                document.write_snapshot(&mut snapshot)?;
+                snapshots.push(snapshot);
+                corpus.push(html);
+            }
+            Err(_) => skipped += 1,
+        }
+    }
+    let _ = panic::take_hook();
+    let total_bytes: usize = corpus.iter().map(String::len).sum();
+    let snapshot_bytes: usize = snapshots.iter().map(Vec::len).sum();
+
+    let mut parse_time = Duration::MAX;
+    let mut load_time = Duration::MAX;
+    let mut nodes = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        nodes = corpus.iter().map(|html| parse(html).node_count()).sum();
+        parse_time = parse_time.min(start.elapsed());
+
+        let start = Instant::now();
+        for snapshot in &snapshots {
+            let document = Document::read_snapshot(&mut snapshot.as_slice())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            assert!(document.node_count() > 0);
+        }
+        load_time = load_time.min(start.elapsed());
+    }
+
+    let megabytes = |bytes: usize| bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "{} files ({} skipped), {:.2} MiB html, {:.2} MiB snapshots, {} nodes",
+        corpus.len(),
+        skipped,
+        megabytes(total_bytes),
+        megabytes(snapshot_bytes),
+        nodes
+    );
+    println!(
+        "parse: {:.3}s, load: {:.3}s, load/parse: {:.3}",
+        parse_time.as_secs_f64(),
+        load_time.as_secs_f64(),
+        load_time.as_secs_f64() / parse_time.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+fn parse(html: &str) -> Document {
+    let mut stream = InputStream::new();
+    stream.read_from_str(html, None);
+    let mut parser = Html5Parser::new(&mut stream);
+    parser.parse();
+    parser.into_document()
+}
+
+fn collect_html_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_html_files(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "html" || ext == "htm")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
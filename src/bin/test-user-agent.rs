@@ -35,11 +35,11 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut parser = Html5Parser::new(&mut stream);
     let (document, parse_error) = parser.parse();
 
-    match get_node_by_path(document, vec!["html", "body"]) {
+    match document.query_selector(0, "html > body")? {
         None => {
             println!("[No Body Found]");
         }
-        Some(node) => display_node(document, node),
+        Some(node_id) => display_node(document, document.get_node_by_id(node_id).unwrap()),
     }
 
     for e in parse_error {
@@ -49,43 +49,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn get_node<'a>(document: &'a Document, parent: &'a Node, name: &'a str) -> Option<&'a Node> {
-    for id in &parent.children {
-        match document.get_node_by_id(*id) {
-            None => {}
-            Some(node) => {
-                if node.name.eq(name) {
-                    return Some(node);
-                }
-            }
-        }
-    }
-    None
-}
-
-fn get_node_by_path<'a>(document: &'a Document, path: Vec<&'a str>) -> Option<&'a Node> {
-    let mut node = document.get_root();
-    match document.get_node_by_id(node.children[0]) {
-        None => {
-            return None;
-        }
-        Some(child) => {
-            node = child;
-        }
-    }
-    for name in path {
-        match get_node(document, node, name) {
-            Some(new_node) => {
-                node = new_node;
-            }
-            None => {
-                return None;
-            }
-        }
-    }
-    Some(node)
-}
-
 fn display_node(document: &Document, node: &Node) {
     if let NodeData::Text { value } = &node.data {
         if !value.eq("\n") {
@@ -0,0 +1,92 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use std::{fs, io};
+
+use gosub_engine::html5_parser::error_logger::ErrorLogger;
+use gosub_engine::html5_parser::input_stream::InputStream;
+use gosub_engine::html5_parser::tokenizer::token::Token;
+use gosub_engine::html5_parser::tokenizer::Tokenizer;
+
+// Number of times the corpus is tokenized. The fastest run is reported.
+const RUNS: usize = 3;
+
+fn main() -> io::Result<()> {
+    let paths: Vec<PathBuf> = std::env::args().skip(1).map(PathBuf::from).collect();
+    if paths.is_empty() {
+        println!("Usage: tokenizer-bench <file or directory>...");
+        exit(1);
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        collect_html_files(path, &mut files)?;
+    }
+    files.sort();
+
+    let mut corpus = Vec::new();
+    for file in &files {
+        let bytes = fs::read(file)?;
+        corpus.push(String::from_utf8_lossy(&bytes).into_owned());
+    }
+    let total_bytes: usize = corpus.iter().map(String::len).sum();
+
+    let mut best = Duration::MAX;
+    let mut tokens = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        tokens = corpus.iter().map(|html| tokenize(html)).sum();
+        best = best.min(start.elapsed());
+    }
+
+    let megabytes = total_bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "{} files, {:.2} MiB, {} tokens in {:.3}s: {:.2} MiB/s",
+        corpus.len(),
+        megabytes,
+        tokens,
+        best.as_secs_f64(),
+        megabytes / best.as_secs_f64()
+    );
+
+    Ok(())
+}
+
+// Tokenizes the html and returns the number of tokens
+fn tokenize(html: &str) -> usize {
+    let mut stream = InputStream::new();
+    stream.read_from_str(html, None);
+
+    let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+    let mut tokenizer = Tokenizer::new(&mut stream, None, error_logger);
+
+    let mut tokens = 0;
+    loop {
+        tokens += 1;
+        if let Token::EofToken = tokenizer.next_token() {
+            return tokens;
+        }
+    }
+}
+
+fn collect_html_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_file() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_html_files(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "html" || ext == "htm")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use crate::html5_parser::error_logger::ParseError;
+use crate::html5_parser::input_stream::InputStream;
+use crate::html5_parser::parser::document::Document;
+use crate::html5_parser::parser::Html5Parser;
+use crate::html5_parser::xml_parser::XmlParser;
+
+/// The syntax a document is written in, which decides the parser that is used for it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentFormat {
+    Html,
+    Xml,
+}
+
+impl DocumentFormat {
+    /// Returns the format for a MIME type (like a Content-Type header value), or None when the
+    /// MIME type is not a HTML or XML type. Parameters like `charset` are ignored.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        let essence = mime_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let (kind, subtype) = essence.split_once('/')?;
+
+        match (kind, subtype) {
+            ("text", "html") => Some(DocumentFormat::Html),
+            ("text" | "application", "xml") => Some(DocumentFormat::Xml),
+            (_, subtype) if subtype.ends_with("+xml") && !kind.is_empty() => {
+                Some(DocumentFormat::Xml)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses the input stream with the parser for the given format
+pub fn parse_document(
+    stream: &mut InputStream,
+    format: DocumentFormat,
+) -> (Document, Vec<ParseError>) {
+    match format {
+        DocumentFormat::Html => {
+            let mut parser = Html5Parser::new(stream);
+            let (_, errors) = parser.parse();
+            (parser.into_document(), errors)
+        }
+        DocumentFormat::Xml => {
+            let mut parser = XmlParser::new(stream);
+            let (_, errors) = parser.parse();
+            (parser.into_document(), errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::parser::document::DocumentType;
+
+    #[test]
+    fn test_from_mime_type() {
+        assert_eq!(
+            DocumentFormat::from_mime_type("text/html; charset=utf-8"),
+            Some(DocumentFormat::Html)
+        );
+        assert_eq!(
+            DocumentFormat::from_mime_type("application/xhtml+xml"),
+            Some(DocumentFormat::Xml)
+        );
+        assert_eq!(
+            DocumentFormat::from_mime_type("Image/SVG+XML"),
+            Some(DocumentFormat::Xml)
+        );
+        assert_eq!(
+            DocumentFormat::from_mime_type("text/xml"),
+            Some(DocumentFormat::Xml)
+        );
+        assert_eq!(DocumentFormat::from_mime_type("text/plain"), None);
+        assert_eq!(DocumentFormat::from_mime_type("+xml"), None);
+    }
+
+    #[test]
+    fn test_parse_document() {
+        let mut stream = InputStream::new();
+        stream.read_from_str("<p>a<br>b", None);
+        let (document, errors) = parse_document(&mut stream, DocumentFormat::Xml);
+        assert_eq!(document.doctype, DocumentType::XML);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "unclosed-element");
+
+        let mut stream = InputStream::new();
+        stream.read_from_str("<p>a<br>b", None);
+        let (document, _) = parse_document(&mut stream, DocumentFormat::Html);
+        assert_eq!(document.doctype, DocumentType::HTML);
+        assert_eq!(document.get_elements_by_tag_name("br").length(&document), 1);
+    }
+}
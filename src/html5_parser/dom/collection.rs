@@ -0,0 +1,164 @@
+use crate::html5_parser::parser::document::Document;
+use std::cell::RefCell;
+
+// Describes which elements are part of a collection
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CollectionFilter {
+    TagName(String),         // Elements with this qualified name ("*" for all elements)
+    ClassNames(Vec<String>), // Elements that have all of these classes
+}
+
+/// A live collection of elements, as returned by getElementsByTagName() and
+/// getElementsByClassName(). The collection does not borrow the document, but is evaluated
+/// against it each time it is accessed, so it always reflects the current state of the
+/// document. Results are cached until the document is mutated or the collection is used with
+/// another document.
+#[derive(Debug)]
+pub struct HtmlCollection {
+    filter: CollectionFilter,
+    cache: RefCell<Option<(usize, Vec<usize>)>>, // Document version and the elements found
+}
+
+impl HtmlCollection {
+    pub(crate) fn new(filter: CollectionFilter) -> Self {
+        Self {
+            filter,
+            cache: RefCell::new(None),
+        }
+    }
+
+    /// Returns the number of elements in the collection
+    pub fn length(&self, document: &Document) -> usize {
+        self.with_elements(document, |elements| elements.len())
+    }
+
+    /// Returns the element at the given index in tree order
+    pub fn item(&self, document: &Document, index: usize) -> Option<usize> {
+        self.with_elements(document, |elements| elements.get(index).copied())
+    }
+
+    /// Returns all elements in the collection in tree order
+    pub fn to_vec(&self, document: &Document) -> Vec<usize> {
+        self.with_elements(document, |elements| elements.to_vec())
+    }
+
+    fn with_elements<R>(&self, document: &Document, f: impl FnOnce(&[usize]) -> R) -> R {
+        let mut cache = self.cache.borrow_mut();
+        match &*cache {
+            Some((version, _)) if *version == document.version() => {}
+            _ => *cache = Some((document.version(), document.collect_elements(&self.filter))),
+        }
+
+        match &*cache {
+            Some((_, elements)) => f(elements),
+            None => f(&[]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5_parser::node::{Node, HTML_NAMESPACE, SVG_NAMESPACE};
+    use crate::html5_parser::parser::document::Document;
+    use std::collections::HashMap;
+
+    fn element(name: &str, attrs: &[(&str, &str)], namespace: &str) -> Node {
+        let mut attributes = HashMap::new();
+        for (key, value) in attrs {
+            attributes.insert(key.to_string(), value.to_string());
+        }
+        Node::new_element(name, attributes, namespace)
+    }
+
+    #[test]
+    fn test_collection_is_live() {
+        let mut document = Document::new();
+        let html_id = document.add_node(element("html", &[], HTML_NAMESPACE), 0);
+        let body_id = document.add_node(element("body", &[], HTML_NAMESPACE), html_id);
+        let p1_id = document.add_node(element("p", &[("class", "a")], HTML_NAMESPACE), body_id);
+
+        let paragraphs = document.get_elements_by_tag_name("p");
+        let with_a = document.get_elements_by_class_name("a");
+        assert_eq!(paragraphs.length(&document), 1);
+        assert_eq!(with_a.to_vec(&document), vec![p1_id]);
+
+        let div_id = document.add_node(element("div", &[], HTML_NAMESPACE), body_id);
+        let p2_id = document.add_node(element("p", &[], HTML_NAMESPACE), div_id);
+        assert_eq!(paragraphs.to_vec(&document), vec![p1_id, p2_id]);
+        assert_eq!(paragraphs.item(&document, 1), Some(p2_id));
+        assert_eq!(paragraphs.item(&document, 2), None);
+
+        document.set_attribute(div_id, "class", "b a");
+        assert_eq!(with_a.to_vec(&document), vec![p1_id, div_id]);
+
+        document.remove_attribute(p1_id, "class");
+        assert_eq!(with_a.to_vec(&document), vec![div_id]);
+
+        document.remove_node(div_id);
+        assert_eq!(paragraphs.to_vec(&document), vec![p1_id]);
+        assert!(with_a.to_vec(&document).is_empty());
+    }
+
+    #[test]
+    fn test_large_collection() {
+        let mut document = Document::new();
+        let body_id = document.add_node(element("body", &[], HTML_NAMESPACE), 0);
+        let ids: Vec<usize> = (0..80_000)
+            .map(|_| document.add_node(element("p", &[("class", "a")], HTML_NAMESPACE), body_id))
+            .collect();
+
+        // Ordering 80k siblings must not scan the siblings once per element
+        assert_eq!(
+            document.get_elements_by_tag_name("p").to_vec(&document),
+            ids
+        );
+        assert_eq!(
+            document.get_elements_by_class_name("a").to_vec(&document),
+            ids
+        );
+    }
+
+    #[test]
+    fn test_collection_with_other_document() {
+        let mut first = Document::new();
+        let first_p_id = first.add_node(element("p", &[], HTML_NAMESPACE), 0);
+        let mut second = Document::new();
+        let div_id = second.add_node(element("div", &[], HTML_NAMESPACE), 0);
+        let second_p_id = second.add_node(element("p", &[], HTML_NAMESPACE), div_id);
+
+        // Both documents have been mutated the same number of times
+        let paragraphs = first.get_elements_by_tag_name("p");
+        assert_eq!(paragraphs.to_vec(&first), vec![first_p_id]);
+        assert_eq!(paragraphs.to_vec(&second), vec![second_p_id]);
+        assert_eq!(paragraphs.to_vec(&first), vec![first_p_id]);
+    }
+
+    #[test]
+    fn test_tag_name_matching() {
+        let mut document = Document::new();
+        let html_id = document.add_node(element("html", &[], HTML_NAMESPACE), 0);
+        let div_id = document.add_node(element("div", &[], HTML_NAMESPACE), html_id);
+        let svg_id = document.add_node(element("svg", &[], SVG_NAMESPACE), div_id);
+        let fo_id = document.add_node(element("foreignObject", &[], SVG_NAMESPACE), svg_id);
+        document.add_node(Node::new_text("text"), div_id);
+
+        assert_eq!(
+            document.get_elements_by_tag_name("DIV").to_vec(&document),
+            vec![div_id]
+        );
+        assert_eq!(
+            document
+                .get_elements_by_tag_name("foreignObject")
+                .to_vec(&document),
+            vec![fo_id]
+        );
+        assert!(document
+            .get_elements_by_tag_name("foreignobject")
+            .to_vec(&document)
+            .is_empty());
+        assert_eq!(
+            document.get_elements_by_tag_name("*").to_vec(&document),
+            vec![html_id, div_id, svg_id, fo_id]
+        );
+    }
+}
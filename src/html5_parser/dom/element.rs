@@ -0,0 +1,807 @@
+use crate::html5_parser::dom::shadow::{ShadowRootError, ShadowRootInit};
+use crate::html5_parser::dom::{Element, HtmlElement, ShadowRoot, ShadowRootMode};
+use crate::html5_parser::node::{Node, NodeData, HTML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+use std::collections::HashMap;
+
+/// Read-only view on an element node in a document. The DOM attributes are reflected from the
+/// content attributes of the element each time they are read.
+#[derive(Clone, Copy)]
+pub struct ElementRef<'a> {
+    document: &'a Document,
+    node_id: usize,
+}
+
+/// Mutable view on an element node in a document. All writes update the content attributes of
+/// the element, so they are seen by the element indexes and any other view on the element.
+pub struct ElementMut<'a> {
+    document: &'a mut Document,
+    node_id: usize,
+}
+
+impl Document {
+    /// Returns a view on the element with the given node id, or None when the node is not an
+    /// element.
+    pub fn element(&self, node_id: usize) -> Option<ElementRef<'_>> {
+        match self.get_node_by_id(node_id) {
+            Some(node) if node.is_element() => Some(ElementRef {
+                document: self,
+                node_id,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable view on the element with the given node id, or None when the node is
+    /// not an element.
+    pub fn element_mut(&mut self, node_id: usize) -> Option<ElementMut<'_>> {
+        match self.get_node_by_id(node_id) {
+            Some(node) if node.is_element() => Some(ElementMut {
+                document: self,
+                node_id,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl<'a> ElementRef<'a> {
+    pub fn node_id(&self) -> usize {
+        self.node_id
+    }
+
+    pub fn node(&self) -> &'a Node {
+        self.document
+            .get_node_by_id(self.node_id)
+            .expect("element view on a removed node")
+    }
+
+    pub fn attributes(&self) -> &'a HashMap<String, String> {
+        match &self.node().data {
+            NodeData::Element { attributes, .. } => attributes,
+            _ => unreachable!("element view on a non-element node"),
+        }
+    }
+
+    pub fn get_attribute(&self, name: &str) -> Option<&'a str> {
+        self.attributes().get(name).map(|value| value.as_str())
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.attributes().contains_key(name)
+    }
+
+    fn is_html(&self) -> bool {
+        self.node().namespace.as_deref() == Some(HTML_NAMESPACE)
+    }
+
+    // Reflects a string content attribute: the value when present, the empty string otherwise
+    fn reflect_string(&self, name: &str) -> String {
+        self.get_attribute(name).unwrap_or_default().to_string()
+    }
+
+    // Returns the first value of the attribute found on this element or its ancestors
+    fn inherited_attribute(&self, name: &str) -> Option<&'a str> {
+        let mut current = Some(self.node());
+        while let Some(node) = current {
+            if let Some(value) = node.get_attribute(name) {
+                return Some(value.as_str());
+            }
+            current = node.parent.and_then(|id| self.document.get_node_by_id(id));
+        }
+        None
+    }
+
+    pub fn namespace_uri(&self) -> Option<&'a str> {
+        self.node().namespace.as_deref()
+    }
+
+    /// The prefix the element was created with. Elements created by the HTML parser never have
+    /// one, even when their name contains a colon.
+    pub fn prefix(&self) -> Option<&'a str> {
+        self.node().prefix.as_deref()
+    }
+
+    pub fn local_name(&self) -> &'a str {
+        &self.node().name
+    }
+
+    /// The qualified name, uppercased for HTML elements
+    pub fn tag_name(&self) -> String {
+        let qualified_name = match self.prefix() {
+            Some(prefix) => format!("{}:{}", prefix, self.local_name()),
+            None => self.local_name().to_string(),
+        };
+        if self.is_html() {
+            qualified_name.to_ascii_uppercase()
+        } else {
+            qualified_name
+        }
+    }
+
+    pub fn id(&self) -> String {
+        self.reflect_string("id")
+    }
+
+    pub fn class_name(&self) -> String {
+        self.reflect_string("class")
+    }
+
+    /// The unique classes from the class attribute, in order
+    pub fn class_list(&self) -> Vec<String> {
+        let mut classes: Vec<String> = Vec::new();
+        for class in self
+            .get_attribute("class")
+            .unwrap_or_default()
+            .split_ascii_whitespace()
+        {
+            if !classes.iter().any(|c| c == class) {
+                classes.push(class.to_string());
+            }
+        }
+        classes
+    }
+
+    pub fn slot(&self) -> String {
+        self.reflect_string("slot")
+    }
+
+    pub fn title(&self) -> String {
+        self.reflect_string("title")
+    }
+
+    pub fn lang(&self) -> String {
+        self.reflect_string("lang")
+    }
+
+    /// Whether the element should be translated. Elements without (a valid) translate attribute
+    /// inherit this from their parent.
+    pub fn translate(&self) -> bool {
+        let mut current = Some(self.node());
+        while let Some(node) = current {
+            match node
+                .get_attribute("translate")
+                .map(|v| v.to_ascii_lowercase())
+            {
+                Some(v) if v.is_empty() || v == "yes" => return true,
+                Some(v) if v == "no" => return false,
+                _ => {}
+            }
+            current = node.parent.and_then(|id| self.document.get_node_by_id(id));
+        }
+        true
+    }
+
+    /// The dir attribute, limited to the known values ltr, rtl and auto
+    pub fn dir(&self) -> String {
+        match self.get_attribute("dir").map(|v| v.to_ascii_lowercase()) {
+            Some(v) if v == "ltr" || v == "rtl" || v == "auto" => v,
+            _ => String::new(),
+        }
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.has_attribute("hidden")
+    }
+
+    pub fn inert(&self) -> bool {
+        self.has_attribute("inert")
+    }
+
+    pub fn access_key(&self) -> String {
+        self.reflect_string("accesskey")
+    }
+
+    /// Elements are draggable when the draggable attribute says so. Without it, only images and
+    /// links are draggable.
+    pub fn draggable(&self) -> bool {
+        match self
+            .get_attribute("draggable")
+            .map(|v| v.to_ascii_lowercase())
+        {
+            Some(v) if v == "true" => true,
+            Some(v) if v == "false" => false,
+            _ => {
+                self.is_html()
+                    && (self.local_name() == "img"
+                        || (self.local_name() == "a" && self.has_attribute("href")))
+            }
+        }
+    }
+
+    /// Whether spelling is checked. Elements without (a valid) spellcheck attribute inherit this
+    /// from their parent, defaulting to true.
+    pub fn spellcheck(&self) -> bool {
+        let mut current = Some(self.node());
+        while let Some(node) = current {
+            match node
+                .get_attribute("spellcheck")
+                .map(|v| v.to_ascii_lowercase())
+            {
+                Some(v) if v.is_empty() || v == "true" => return true,
+                Some(v) if v == "false" => return false,
+                _ => {}
+            }
+            current = node.parent.and_then(|id| self.document.get_node_by_id(id));
+        }
+        true
+    }
+
+    /// The autocapitalization hint of the element, or the empty string when there is none
+    pub fn autocapitalize(&self) -> String {
+        let hint = match self
+            .get_attribute("autocapitalize")
+            .map(|v| v.to_ascii_lowercase())
+        {
+            Some(v) if v == "off" || v == "none" => "none",
+            Some(v) if v == "on" || v == "sentences" => "sentences",
+            Some(v) if v == "words" => "words",
+            Some(v) if v == "characters" => "characters",
+            // Any other value (including the empty string) means the default
+            Some(_) => "sentences",
+            None => "",
+        };
+        hint.to_string()
+    }
+
+    /// The popover state: "auto", "manual" or None when the element is not a popover
+    pub fn popover(&self) -> Option<String> {
+        let value = self.get_attribute("popover")?.to_ascii_lowercase();
+        let state = if value.is_empty() || value == "auto" {
+            "auto"
+        } else {
+            "manual"
+        };
+        Some(state.to_string())
+    }
+
+    /// The text of the element as rendered, which follows the flat tree: shadow trees and slotted
+    /// content are included, fallback content of slots with assigned nodes is not. Line breaks
+    /// are turned into newlines, and the contents of script, style and template elements are
+    /// left out. No CSS is applied, so elements hidden by styles are still included.
+    pub fn inner_text(&self) -> String {
+        let mut text = String::new();
+
+        let mut stack = self.document.flat_tree_children(self.node_id);
+        stack.reverse();
+        while let Some(id) = stack.pop() {
+            let node = match self.document.get_node_by_id(id) {
+                Some(node) => node,
+                None => continue,
+            };
+            let is_html = node.namespace.as_deref() == Some(HTML_NAMESPACE);
+            match &node.data {
+                NodeData::Text { value } => text.push_str(value),
+                NodeData::Element { name, .. } if is_html && name == "br" => text.push('\n'),
+                NodeData::Element { name, .. }
+                    if is_html && matches!(name.as_str(), "script" | "style" | "template") => {}
+                _ => stack.extend(self.document.flat_tree_children(id).into_iter().rev()),
+            }
+        }
+
+        text
+    }
+
+    pub fn text_content(&self) -> String {
+        self.document
+            .descendants(self.node_id)
+            .into_iter()
+            .filter_map(
+                |id| match self.document.get_node_by_id(id).map(|n| &n.data) {
+                    Some(NodeData::Text { value }) => Some(value.as_str()),
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
+    /// Returns the slot the element is assigned to, when it is in an open shadow tree
+    pub fn assigned_slot(&self) -> Option<usize> {
+        self.document.assigned_slot(self.node_id)
+    }
+
+    /// Returns the language of the element, as inherited from the closest lang attribute
+    pub fn language(&self) -> Option<&'a str> {
+        self.inherited_attribute("lang")
+    }
+
+    /// Returns the node id of the shadow root of the element, when it is an open shadow root
+    pub fn shadow_root(&self) -> Option<usize> {
+        let root_id = self.document.shadow_root(self.node_id)?;
+        match self.document.shadow_root_info(root_id) {
+            Some(shadow_root) if shadow_root.mode == ShadowRootMode::Open => Some(root_id),
+            _ => None,
+        }
+    }
+
+    fn shadow_root_info(&self) -> Option<&'a ShadowRoot> {
+        self.shadow_root()
+            .and_then(|root_id| self.document.shadow_root_info(root_id))
+    }
+
+    /// Creates a detached Element with the current state of this element
+    pub fn to_element(&self) -> Element {
+        Element {
+            namespace_uri: self.namespace_uri().map(String::from),
+            prefix: self.prefix().map(String::from),
+            local_name: self.local_name().to_string(),
+            tag_name: self.tag_name(),
+            id: self.id(),
+            class_name: self.class_name(),
+            class_list: self.class_list(),
+            slot: self.slot(),
+            attributes: self.attributes().clone(),
+            shadow_root: self.shadow_root_info().cloned().map(Box::new),
+        }
+    }
+
+    /// Creates a detached HtmlElement with the current state of this element, or None when this
+    /// is not an element in the HTML namespace
+    pub fn to_html_element(&self) -> Option<HtmlElement> {
+        if !self.is_html() {
+            return None;
+        }
+
+        Some(HtmlElement {
+            namespace_uri: self.namespace_uri().map(String::from),
+            prefix: self.prefix().map(String::from),
+            local_name: self.local_name().to_string(),
+            tag_name: self.tag_name(),
+            id: self.id(),
+            class_name: self.class_name(),
+            class_list: self.class_list(),
+            slot: self.slot(),
+            attributes: self.attributes().clone(),
+            shadow_root: self.shadow_root_info().cloned(),
+            title: self.title(),
+            lang: self.lang(),
+            translate: self.translate(),
+            dir: self.dir(),
+            hidden: self.hidden(),
+            inert: self.inert(),
+            access_key: self.access_key(),
+            access_key_label: String::new(),
+            draggable: self.draggable(),
+            spellcheck: self.spellcheck(),
+            autocapitalize: self.autocapitalize(),
+            inner_text: self.inner_text(),
+            outer_text: self.inner_text(),
+            popover: self.popover(),
+        })
+    }
+}
+
+impl<'a> ElementMut<'a> {
+    /// Returns a read-only view on the element
+    pub fn as_ref(&self) -> ElementRef<'_> {
+        ElementRef {
+            document: self.document,
+            node_id: self.node_id,
+        }
+    }
+
+    /// Attaches a shadow root to the element, see Document::attach_shadow()
+    pub fn attach_shadow(&mut self, init: ShadowRootInit) -> Result<usize, ShadowRootError> {
+        self.document.attach_shadow(self.node_id, init)
+    }
+
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.document.set_attribute(self.node_id, name, value);
+    }
+
+    pub fn remove_attribute(&mut self, name: &str) {
+        self.document.remove_attribute(self.node_id, name);
+    }
+
+    /// Adds the attribute when it's not present and removes it otherwise. Returns true when the
+    /// attribute is present afterwards.
+    pub fn toggle_attribute(&mut self, name: &str) -> bool {
+        if self.as_ref().has_attribute(name) {
+            self.remove_attribute(name);
+            false
+        } else {
+            self.set_attribute(name, "");
+            true
+        }
+    }
+
+    fn set_boolean_attribute(&mut self, name: &str, value: bool) {
+        if value {
+            self.set_attribute(name, "");
+        } else {
+            self.remove_attribute(name);
+        }
+    }
+
+    pub fn set_id(&mut self, id: &str) {
+        self.set_attribute("id", id);
+    }
+
+    pub fn set_class_name(&mut self, class_name: &str) {
+        self.set_attribute("class", class_name);
+    }
+
+    // Writes the class list back to the class attribute, as DOMTokenList does on changes
+    fn set_class_list(&mut self, classes: &[String]) {
+        self.set_attribute("class", &classes.join(" "));
+    }
+
+    /// Adds the class when it's not in the class list yet
+    pub fn add_class(&mut self, class: &str) {
+        let mut classes = self.as_ref().class_list();
+        if !classes.iter().any(|c| c == class) {
+            classes.push(class.to_string());
+        }
+        self.set_class_list(&classes);
+    }
+
+    /// Removes the class from the class list
+    pub fn remove_class(&mut self, class: &str) {
+        if !self.as_ref().has_attribute("class") {
+            return;
+        }
+        let mut classes = self.as_ref().class_list();
+        classes.retain(|c| c != class);
+        self.set_class_list(&classes);
+    }
+
+    /// Adds the class when it's not in the class list and removes it otherwise. Returns true
+    /// when the class is in the list afterwards.
+    pub fn toggle_class(&mut self, class: &str) -> bool {
+        if self.as_ref().class_list().iter().any(|c| c == class) {
+            self.remove_class(class);
+            false
+        } else {
+            self.add_class(class);
+            true
+        }
+    }
+
+    pub fn set_slot(&mut self, slot: &str) {
+        self.set_attribute("slot", slot);
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.set_attribute("title", title);
+    }
+
+    pub fn set_lang(&mut self, lang: &str) {
+        self.set_attribute("lang", lang);
+    }
+
+    pub fn set_translate(&mut self, translate: bool) {
+        self.set_attribute("translate", if translate { "yes" } else { "no" });
+    }
+
+    pub fn set_dir(&mut self, dir: &str) {
+        self.set_attribute("dir", dir);
+    }
+
+    pub fn set_hidden(&mut self, hidden: bool) {
+        self.set_boolean_attribute("hidden", hidden);
+    }
+
+    pub fn set_inert(&mut self, inert: bool) {
+        self.set_boolean_attribute("inert", inert);
+    }
+
+    pub fn set_access_key(&mut self, access_key: &str) {
+        self.set_attribute("accesskey", access_key);
+    }
+
+    pub fn set_draggable(&mut self, draggable: bool) {
+        self.set_attribute("draggable", if draggable { "true" } else { "false" });
+    }
+
+    pub fn set_spellcheck(&mut self, spellcheck: bool) {
+        self.set_attribute("spellcheck", if spellcheck { "true" } else { "false" });
+    }
+
+    pub fn set_autocapitalize(&mut self, autocapitalize: &str) {
+        self.set_attribute("autocapitalize", autocapitalize);
+    }
+
+    pub fn set_popover(&mut self, popover: Option<&str>) {
+        match popover {
+            Some(value) => self.set_attribute("popover", value),
+            None => self.remove_attribute("popover"),
+        }
+    }
+
+    /// Replaces all children of the element with the given text. Line breaks are turned into
+    /// br elements.
+    pub fn set_inner_text(&mut self, text: &str) {
+        let children = self.as_ref().node().children.clone();
+        for child_id in children {
+            self.document.remove_node(child_id);
+        }
+
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        for (idx, line) in text.split('\n').enumerate() {
+            if idx > 0 {
+                self.document.add_node(
+                    Node::new_element("br", HashMap::new(), HTML_NAMESPACE),
+                    self.node_id,
+                );
+            }
+            if !line.is_empty() {
+                self.document.add_node(Node::new_text(line), self.node_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::input_stream::InputStream;
+    use crate::html5_parser::node::SVG_NAMESPACE;
+    use crate::html5_parser::xml_parser::XmlParser;
+
+    fn element(name: &str, attrs: &[(&str, &str)], namespace: &str) -> Node {
+        let mut attributes = HashMap::new();
+        for (key, value) in attrs {
+            attributes.insert(key.to_string(), value.to_string());
+        }
+        Node::new_element(name, attributes, namespace)
+    }
+
+    #[test]
+    fn test_reflected_attributes() {
+        let mut document = Document::new();
+        let html_id = document.add_node(
+            element(
+                "html",
+                &[("lang", "nl"), ("translate", "no"), ("spellcheck", "false")],
+                HTML_NAMESPACE,
+            ),
+            0,
+        );
+        let div_id = document.add_node(
+            element(
+                "div",
+                &[
+                    ("id", "main"),
+                    ("class", " a b  a "),
+                    ("dir", "RTL"),
+                    ("hidden", ""),
+                    ("popover", "bogus"),
+                    ("autocapitalize", "on"),
+                ],
+                HTML_NAMESPACE,
+            ),
+            html_id,
+        );
+        document.add_node(Node::new_text("hello"), div_id);
+        let svg_id = document.add_node(element("svg", &[("dir", "up")], SVG_NAMESPACE), div_id);
+
+        let div = document.element(div_id).unwrap();
+        assert_eq!(div.tag_name(), "DIV");
+        assert_eq!(div.id(), "main");
+        assert_eq!(div.class_name(), " a b  a ");
+        assert_eq!(div.class_list(), vec!["a", "b"]);
+        assert_eq!(div.lang(), "");
+        assert_eq!(div.language(), Some("nl"));
+        assert!(!div.translate());
+        assert!(!div.spellcheck());
+        assert_eq!(div.dir(), "rtl");
+        assert!(div.hidden());
+        assert!(!div.draggable());
+        assert_eq!(div.popover(), Some("manual".to_string()));
+        assert_eq!(div.autocapitalize(), "sentences");
+        assert_eq!(div.inner_text(), "hello");
+
+        let svg = document.element(svg_id).unwrap();
+        assert_eq!(svg.tag_name(), "svg");
+        assert_eq!(svg.dir(), "");
+        assert!(svg.to_html_element().is_none());
+
+        assert!(document.element(0).is_none());
+    }
+
+    #[test]
+    fn test_writes_update_attributes() {
+        let mut document = Document::new();
+        let div_id = document.add_node(element("div", &[], HTML_NAMESPACE), 0);
+
+        let mut div = document.element_mut(div_id).unwrap();
+        div.set_id("foo");
+        div.add_class("a");
+        div.add_class("b");
+        div.add_class("a");
+        assert!(!div.toggle_class("a"));
+        div.set_hidden(true);
+        div.set_translate(false);
+        div.set_draggable(true);
+        assert!(div.toggle_attribute("inert"));
+        div.set_popover(Some("auto"));
+        div.set_popover(None);
+
+        let node = document.get_node_by_id(div_id).unwrap();
+        assert_eq!(node.get_attribute("id").unwrap(), "foo");
+        assert_eq!(node.get_attribute("class").unwrap(), "b");
+        assert_eq!(node.get_attribute("hidden").unwrap(), "");
+        assert_eq!(node.get_attribute("translate").unwrap(), "no");
+        assert_eq!(node.get_attribute("draggable").unwrap(), "true");
+        assert_eq!(node.get_attribute("inert").unwrap(), "");
+        assert!(node.get_attribute("popover").is_none());
+
+        // The indexes are kept up to date
+        assert_eq!(document.get_element_by_id("foo"), Some(div_id));
+        assert_eq!(
+            document.get_elements_by_class_name("b").to_vec(&document),
+            vec![div_id]
+        );
+
+        let mut div = document.element_mut(div_id).unwrap();
+        div.set_hidden(false);
+        div.set_inner_text("one\r\ntwo");
+        let html = div.as_ref().to_html_element().unwrap();
+        assert!(!html.hidden);
+        assert!(html.inert);
+        assert_eq!(html.inner_text, "one\ntwo");
+        assert_eq!(html.class_list, vec!["b"]);
+
+        let children = &document.get_node_by_id(div_id).unwrap().children;
+        assert_eq!(children.len(), 3);
+        assert_eq!(document.get_node_by_id(children[1]).unwrap().name, "br");
+    }
+
+    #[test]
+    fn test_inherited_attributes() {
+        let mut document = Document::new();
+        let html_id = document.add_node(
+            element(
+                "html",
+                &[("translate", "no"), ("spellcheck", "")],
+                HTML_NAMESPACE,
+            ),
+            0,
+        );
+        let body_id = document.add_node(
+            element(
+                "body",
+                &[("translate", "bogus"), ("spellcheck", "FALSE")],
+                HTML_NAMESPACE,
+            ),
+            html_id,
+        );
+        let div_id = document.add_node(
+            element(
+                "div",
+                &[("translate", "YES"), ("spellcheck", "maybe")],
+                HTML_NAMESPACE,
+            ),
+            body_id,
+        );
+        let p_id = document.add_node(element("p", &[], HTML_NAMESPACE), div_id);
+
+        let html = document.element(html_id).unwrap();
+        assert!(!html.translate());
+        assert!(html.spellcheck());
+
+        // Invalid values inherit from the parent
+        let body = document.element(body_id).unwrap();
+        assert!(!body.translate());
+        assert!(!body.spellcheck());
+
+        let div = document.element(div_id).unwrap();
+        assert!(div.translate());
+        assert!(!div.spellcheck());
+
+        let p = document.element(p_id).unwrap();
+        assert!(p.translate());
+        assert!(!p.spellcheck());
+
+        // Without any attribute in the ancestors, both default to true
+        let other_id = document.add_node(element("div", &[], HTML_NAMESPACE), 0);
+        let other = document.element(other_id).unwrap();
+        assert!(other.translate());
+        assert!(other.spellcheck());
+    }
+
+    #[test]
+    fn test_autocapitalize() {
+        let mut document = Document::new();
+        let cases = [
+            (None, ""),
+            (Some(""), "sentences"),
+            (Some("bogus"), "sentences"),
+            (Some("OFF"), "none"),
+            (Some("none"), "none"),
+            (Some("on"), "sentences"),
+            (Some("Words"), "words"),
+            (Some("characters"), "characters"),
+        ];
+        for (value, expected) in cases {
+            let attrs: Vec<(&str, &str)> =
+                value.map(|v| ("autocapitalize", v)).into_iter().collect();
+            let id = document.add_node(element("input", &attrs, HTML_NAMESPACE), 0);
+            assert_eq!(
+                document.element(id).unwrap().autocapitalize(),
+                expected,
+                "autocapitalize={:?}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn test_draggable_and_hidden() {
+        let mut document = Document::new();
+        let img_id = document.add_node(element("img", &[], HTML_NAMESPACE), 0);
+        let link_id = document.add_node(element("a", &[("href", "/")], HTML_NAMESPACE), 0);
+        let anchor_id = document.add_node(element("a", &[("hidden", "false")], HTML_NAMESPACE), 0);
+        let fixed_img_id =
+            document.add_node(element("img", &[("draggable", "false")], HTML_NAMESPACE), 0);
+        let div_id = document.add_node(element("div", &[("draggable", "TRUE")], HTML_NAMESPACE), 0);
+        let svg_link_id = document.add_node(element("a", &[("href", "/")], SVG_NAMESPACE), 0);
+
+        assert!(document.element(img_id).unwrap().draggable());
+        assert!(document.element(link_id).unwrap().draggable());
+        assert!(!document.element(anchor_id).unwrap().draggable());
+        assert!(!document.element(fixed_img_id).unwrap().draggable());
+        assert!(document.element(div_id).unwrap().draggable());
+        assert!(!document.element(svg_link_id).unwrap().draggable());
+
+        // hidden is a boolean attribute, so any value means hidden
+        assert!(!document.element(img_id).unwrap().hidden());
+        assert!(document.element(anchor_id).unwrap().hidden());
+        let mut anchor = document.element_mut(anchor_id).unwrap();
+        anchor.set_hidden(false);
+        assert!(!anchor.as_ref().hidden());
+        assert!(!anchor.as_ref().has_attribute("hidden"));
+        anchor.set_hidden(true);
+        assert!(anchor.as_ref().hidden());
+    }
+
+    #[test]
+    fn test_inner_text() {
+        let mut document = Document::new();
+        let div_id = document.add_node(element("div", &[], HTML_NAMESPACE), 0);
+        document.add_node(Node::new_text("one"), div_id);
+        document.add_node(element("br", &[], HTML_NAMESPACE), div_id);
+        for name in ["script", "style", "template"] {
+            let id = document.add_node(element(name, &[], HTML_NAMESPACE), div_id);
+            document.add_node(Node::new_text(name), id);
+        }
+        let span_id = document.add_node(element("span", &[], HTML_NAMESPACE), div_id);
+        document.add_node(Node::new_text("two"), span_id);
+        document.add_node(element("br", &[], HTML_NAMESPACE), span_id);
+        // Only HTML br elements are line breaks
+        let svg_id = document.add_node(element("br", &[], SVG_NAMESPACE), div_id);
+        document.add_node(Node::new_text("three"), svg_id);
+
+        let div = document.element(div_id).unwrap();
+        assert_eq!(div.inner_text(), "one\ntwo\nthree");
+        assert_eq!(div.text_content(), "onescriptstyletemplatetwothree");
+    }
+
+    #[test]
+    fn test_prefix() {
+        // The HTML parser keeps colons in the local name
+        let mut document = Document::new();
+        let id = document.add_node(element("foo:bar", &[], HTML_NAMESPACE), 0);
+        let foo = document.element(id).unwrap();
+        assert_eq!(foo.prefix(), None);
+        assert_eq!(foo.local_name(), "foo:bar");
+        assert_eq!(foo.tag_name(), "FOO:BAR");
+
+        let mut stream = InputStream::new();
+        stream.read_from_str(
+            "<html xmlns='http://www.w3.org/1999/xhtml'><svg:svg \
+             xmlns:svg='http://www.w3.org/2000/svg'/></html>",
+            None,
+        );
+        let mut parser = XmlParser::new(&mut stream);
+        parser.parse();
+        let document = parser.into_document();
+        let svg_id = document.get_root().children[0];
+        let svg_id = document.get_node_by_id(svg_id).unwrap().children[0];
+        let svg = document.element(svg_id).unwrap();
+        assert_eq!(svg.prefix(), Some("svg"));
+        assert_eq!(svg.local_name(), "svg");
+        assert_eq!(svg.tag_name(), "svg:svg");
+        assert_eq!(svg.to_element().prefix.as_deref(), Some("svg"));
+    }
+}
@@ -0,0 +1,289 @@
+use crate::html5_parser::input_stream::InputStream;
+use crate::html5_parser::node::{Node, NodeData, HTML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+use crate::html5_parser::parser::{Html5Parser, ParserOptions};
+use crate::html5_parser::serializer::{serialize_children, serialize_node, SerializerOptions};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Reasons why markup could not be set on a node
+#[derive(Debug, Clone, PartialEq)]
+pub enum MarkupError {
+    /// No node with the given id exists in the document
+    NodeNotFound(usize),
+    /// The node is not an element
+    NotAnElement(usize),
+    /// The node is a child of the document node, and cannot be replaced. Maps to a
+    /// NoModificationAllowedError in the DOM.
+    NoModificationAllowed,
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkupError::NodeNotFound(id) => write!(f, "node {} not found", id),
+            MarkupError::NotAnElement(id) => write!(f, "node {} is not an element", id),
+            MarkupError::NoModificationAllowed => {
+                write!(f, "children of the document cannot be replaced")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MarkupError {}
+
+impl Document {
+    /// Returns the serialized children of the node (innerHTML). The children of a template are
+    /// found in its template contents.
+    pub fn inner_html(&self, node_id: usize) -> String {
+        serialize_children(self, node_id, &SerializerOptions::default())
+    }
+
+    /// Returns the serialized node including its children (outerHTML)
+    pub fn outer_html(&self, node_id: usize) -> String {
+        serialize_node(self, node_id, &SerializerOptions::default())
+    }
+
+    /// Replaces the children of the element with the result of parsing the html as a fragment,
+    /// with the element as context. For a template element the template contents are replaced.
+    pub fn set_inner_html(&mut self, node_id: usize, html: &str) -> Result<(), MarkupError> {
+        let context = match self.get_node_by_id(node_id) {
+            Some(node) if node.is_element() => node.clone(),
+            Some(_) => return Err(MarkupError::NotAnElement(node_id)),
+            None => return Err(MarkupError::NodeNotFound(node_id)),
+        };
+
+        let parent_id = self.template_contents(node_id).unwrap_or(node_id);
+        let children = self.get_node_by_id(parent_id).map(|n| n.children.clone());
+        for child_id in children.unwrap_or_default() {
+            self.remove_node(child_id);
+        }
+
+        self.insert_fragment(&context, html, parent_id);
+        Ok(())
+    }
+
+    /// Replaces the element with the result of parsing the html as a fragment, with the parent
+    /// of the element as context. Does nothing when the element has no parent.
+    pub fn set_outer_html(&mut self, node_id: usize, html: &str) -> Result<(), MarkupError> {
+        let parent_id = match self.get_node_by_id(node_id) {
+            Some(node) if node.is_element() => match node.parent {
+                Some(parent_id) => parent_id,
+                None => return Ok(()),
+            },
+            Some(_) => return Err(MarkupError::NotAnElement(node_id)),
+            None => return Err(MarkupError::NodeNotFound(node_id)),
+        };
+
+        let parent = match self.get_node_by_id(parent_id) {
+            Some(parent) => parent,
+            None => return Err(MarkupError::NodeNotFound(parent_id)),
+        };
+        // Children of a document fragment (like a shadow root) are parsed in a body context
+        let context = match parent.data {
+            NodeData::Document => return Err(MarkupError::NoModificationAllowed),
+            NodeData::DocumentFragment => Node::new_element("body", HashMap::new(), HTML_NAMESPACE),
+            _ => parent.clone(),
+        };
+        let position = parent
+            .children
+            .iter()
+            .position(|&id| id == node_id)
+            .unwrap_or(parent.children.len());
+
+        let new_ids = self.insert_fragment(&context, html, parent_id);
+
+        // The new nodes are appended to the parent, move them in front of the replaced node
+        self.move_children(parent_id, &new_ids, position);
+        self.remove_node(node_id);
+
+        Ok(())
+    }
+
+    // Parses the html as a fragment with the given context element and appends the resulting
+    // nodes to the parent. Returns the ids of the appended nodes.
+    fn insert_fragment(&mut self, context: &Node, html: &str, parent_id: usize) -> Vec<usize> {
+        let mut stream = InputStream::new();
+        stream.read_from_str(html, None);
+
+        let mut parser = Html5Parser::new_fragment(&mut stream, context, ParserOptions::default());
+        let (fragment, node_ids, _) = parser.parse_fragment();
+
+        node_ids
+            .iter()
+            .map(|&id| self.import_node(fragment, id, parent_id))
+            .collect()
+    }
+
+    // Copies the node and its descendants (including template contents) from another document
+    // and appends the copy to the parent. Returns the id of the copy. Nodes still to copy are kept
+    // on a stack instead of recursing, so deeply nested fragments can't overflow the call stack.
+    fn import_node(&mut self, source: &Document, source_id: usize, parent_id: usize) -> usize {
+        let mut stack = Vec::new();
+        let copy_id = match self.copy_node(source, source_id, parent_id, &mut stack) {
+            Some(copy_id) => copy_id,
+            None => return parent_id,
+        };
+
+        while let Some((source_id, parent_id)) = stack.pop() {
+            self.copy_node(source, source_id, parent_id, &mut stack);
+        }
+
+        copy_id
+    }
+
+    // Copies a single node and appends it to the parent. Its children and template contents are
+    // pushed on the stack together with the id of the copy they are to be appended to.
+    fn copy_node(
+        &mut self,
+        source: &Document,
+        source_id: usize,
+        parent_id: usize,
+        stack: &mut Vec<(usize, usize)>,
+    ) -> Option<usize> {
+        let node = source.get_node_by_id(source_id)?;
+
+        let mut copy = node.clone();
+        copy.parent = None;
+        copy.children = Vec::new();
+        let node_id = self.add_node(copy, parent_id);
+
+        // Pushed in reverse: the children are copied before the template contents
+        if let Some(source_contents_id) = source.template_contents(source_id) {
+            let contents_id = self.create_template_contents(node_id);
+            if let Some(contents) = source.get_node_by_id(source_contents_id) {
+                stack.extend(contents.children.iter().rev().map(|&id| (id, contents_id)));
+            }
+        }
+        stack.extend(node.children.iter().rev().map(|&id| (id, node_id)));
+
+        Some(node_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document_with_body() -> (Document, usize) {
+        let mut document = Document::new();
+        let html_id =
+            document.add_node(Node::new_element("html", HashMap::new(), HTML_NAMESPACE), 0);
+        let body_id = document.add_node(
+            Node::new_element("body", HashMap::new(), HTML_NAMESPACE),
+            html_id,
+        );
+        (document, body_id)
+    }
+
+    #[test]
+    fn test_inner_html() {
+        let (mut document, body_id) = document_with_body();
+
+        document
+            .set_inner_html(body_id, "<p id=a>one<p>two &amp; <br>three")
+            .unwrap();
+        assert_eq!(
+            document.inner_html(body_id),
+            "<p id=\"a\">one</p><p>two &amp; <br>three</p>"
+        );
+        assert!(document.get_element_by_id("a").is_some());
+
+        document.set_inner_html(body_id, "text").unwrap();
+        assert_eq!(document.inner_html(body_id), "text");
+        assert_eq!(document.get_element_by_id("a"), None);
+        assert_eq!(document.outer_html(body_id), "<body>text</body>");
+    }
+
+    #[test]
+    fn test_inner_html_context() {
+        let (mut document, body_id) = document_with_body();
+        let table_id = document.add_node(
+            Node::new_element("table", HashMap::new(), HTML_NAMESPACE),
+            body_id,
+        );
+        document
+            .set_inner_html(table_id, "<tr><td>1</td></tr>")
+            .unwrap();
+        assert_eq!(
+            document.inner_html(table_id),
+            "<tbody><tr><td>1</td></tr></tbody>"
+        );
+
+        let textarea_id = document.add_node(
+            Node::new_element("textarea", HashMap::new(), HTML_NAMESPACE),
+            body_id,
+        );
+        document.set_inner_html(textarea_id, "<b>x</b>").unwrap();
+        assert_eq!(document.inner_html(textarea_id), "&lt;b&gt;x&lt;/b&gt;");
+
+        let template_id = document.add_node(
+            Node::new_element("template", HashMap::new(), HTML_NAMESPACE),
+            body_id,
+        );
+        document.create_template_contents(template_id);
+        document
+            .set_inner_html(template_id, "<td>cell</td><template><i></i></template>")
+            .unwrap();
+        assert!(document
+            .get_node_by_id(template_id)
+            .unwrap()
+            .children
+            .is_empty());
+        assert_eq!(
+            document.inner_html(template_id),
+            "<td>cell</td><template><i></i></template>"
+        );
+    }
+
+    #[test]
+    fn test_outer_html() {
+        let (mut document, body_id) = document_with_body();
+        document
+            .set_inner_html(body_id, "<p>a</p><div id=x>b</div><p>c</p>")
+            .unwrap();
+
+        let div_id = document.get_element_by_id("x").unwrap();
+        assert_eq!(document.outer_html(div_id), "<div id=\"x\">b</div>");
+
+        document
+            .set_outer_html(div_id, "<span>1</span>2<span>3</span>")
+            .unwrap();
+        assert_eq!(
+            document.inner_html(body_id),
+            "<p>a</p><span>1</span>2<span>3</span><p>c</p>"
+        );
+        assert!(document.get_node_by_id(div_id).is_none());
+
+        let html_id = document.get_root().children[0];
+        assert_eq!(
+            document.set_outer_html(html_id, "<html></html>"),
+            Err(MarkupError::NoModificationAllowed)
+        );
+
+        let text_id = document.get_node_by_id(body_id).unwrap().children[2];
+        assert_eq!(
+            document.set_inner_html(text_id, "x"),
+            Err(MarkupError::NotAnElement(text_id))
+        );
+    }
+
+    #[test]
+    fn test_import_deep_fragment() {
+        // Parsing a deeply nested fragment is slow in debug builds, so the parsed fragment is
+        // built directly
+        let mut fragment = Document::new();
+        let mut parent_id = 0;
+        for _ in 0..20_000 {
+            parent_id = fragment.add_node(
+                Node::new_element("div", HashMap::new(), HTML_NAMESPACE),
+                parent_id,
+            );
+        }
+
+        let (mut document, body_id) = document_with_body();
+        let first_id = fragment.get_node_by_id(0).unwrap().children[0];
+        let copy_id = document.import_node(&fragment, first_id, body_id);
+        assert_eq!(document.outer_html(copy_id), fragment.to_html());
+    }
+}
@@ -1,23 +1,47 @@
+pub mod collection;
+pub mod element;
+pub mod inner_html;
+pub mod shadow;
+pub mod slot;
+
 use std::collections::HashMap;
+use std::fmt;
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct ShadowRoot {
     pub mode: ShadowRootMode,
     pub delegates_focus: bool,
     pub slot_assignment: SlotAssignmentMode,
-    pub host: Box<Element>,
+    pub clonable: bool,
+    pub serializable: bool,
+    pub declarative: bool,
     // pub onslotchange: Option<EventHandler>,
+    pub host: usize, // Node id of the host element
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SlotAssignmentMode {
     Manual,
     Named,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ShadowRootMode {
     Open,
     Closed,
 }
 
+impl fmt::Display for ShadowRootMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShadowRootMode::Open => write!(f, "open"),
+            ShadowRootMode::Closed => write!(f, "closed"),
+        }
+    }
+}
+
+/// Snapshot of an element, see ElementRef::to_element()
+#[derive(Debug, Clone, PartialEq)]
 pub struct Element {
     pub namespace_uri: Option<String>,
     pub prefix: Option<String>,
@@ -31,6 +55,8 @@ pub struct Element {
     pub shadow_root: Option<Box<ShadowRoot>>,
 }
 
+/// Snapshot of an HTML element, see ElementRef::to_html_element()
+#[derive(Debug, Clone, PartialEq)]
 pub struct HtmlElement {
     // Element fields
     pub namespace_uri: Option<String>,
@@ -50,8 +76,8 @@ pub struct HtmlElement {
     pub translate: bool,
     pub dir: String,
 
-    pub hidden: Option<bool>,
-    pub insert: bool,
+    pub hidden: bool,
+    pub inert: bool,
     pub access_key: String,
     pub access_key_label: String,
     pub draggable: bool,
@@ -0,0 +1,357 @@
+use crate::html5_parser::dom::{ShadowRoot, ShadowRootMode, SlotAssignmentMode};
+use crate::html5_parser::node::{Node, HTML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+use std::fmt;
+
+/// Elements that can host a shadow root, next to autonomous custom elements
+const SHADOW_HOST_ELEMENTS: [&str; 18] = [
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "div",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "main",
+    "nav",
+    "p",
+    "section",
+    "span",
+];
+
+/// Names that match the custom element name production but are reserved by SVG and MathML
+const RESERVED_CUSTOM_ELEMENT_NAMES: [&str; 8] = [
+    "annotation-xml",
+    "color-profile",
+    "font-face",
+    "font-face-src",
+    "font-face-uri",
+    "font-face-format",
+    "font-face-name",
+    "missing-glyph",
+];
+
+/// Options for attaching a shadow root, see Document::attach_shadow()
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowRootInit {
+    pub mode: ShadowRootMode,
+    pub delegates_focus: bool,
+    pub slot_assignment: SlotAssignmentMode,
+    pub clonable: bool,
+    pub serializable: bool,
+}
+
+impl ShadowRootInit {
+    /// Returns the default options for the given mode
+    pub fn new(mode: ShadowRootMode) -> Self {
+        Self {
+            mode,
+            delegates_focus: false,
+            slot_assignment: SlotAssignmentMode::Named,
+            clonable: false,
+            serializable: false,
+        }
+    }
+}
+
+/// Reasons why a shadow root could not be attached. Both map to a NotSupportedError in the DOM.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShadowRootError {
+    /// The node is not an element that can host a shadow root
+    InvalidHost(String),
+    /// The element already hosts a shadow root
+    AlreadyAttached,
+}
+
+impl fmt::Display for ShadowRootError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShadowRootError::InvalidHost(name) => {
+                write!(f, "<{}> cannot host a shadow root", name)
+            }
+            ShadowRootError::AlreadyAttached => write!(f, "element already hosts a shadow root"),
+        }
+    }
+}
+
+impl std::error::Error for ShadowRootError {}
+
+/// Returns true when the name is a valid custom element name
+pub fn is_valid_custom_element_name(name: &str) -> bool {
+    if !name.starts_with(|c: char| c.is_ascii_lowercase()) || !name.contains('-') {
+        return false;
+    }
+    if RESERVED_CUSTOM_ELEMENT_NAMES.contains(&name) {
+        return false;
+    }
+
+    name.chars().all(|c| {
+        c.is_ascii_lowercase()
+            || c.is_ascii_digit()
+            || matches!(c, '-' | '.' | '_')
+            || !c.is_ascii()
+    })
+}
+
+impl Document {
+    /// Attaches a new shadow root to the given host element and returns the node id of the
+    /// shadow root. The shadow root is a document fragment node that is not a child of the host.
+    pub fn attach_shadow(
+        &mut self,
+        host_id: usize,
+        init: ShadowRootInit,
+    ) -> Result<usize, ShadowRootError> {
+        self.attach_shadow_root(host_id, init, false)
+    }
+
+    // Attaches a shadow root. A declarative shadow root can be replaced by a new shadow root
+    // with the same mode, in which case the existing root is emptied and returned.
+    pub(crate) fn attach_shadow_root(
+        &mut self,
+        host_id: usize,
+        init: ShadowRootInit,
+        declarative: bool,
+    ) -> Result<usize, ShadowRootError> {
+        let host = match self.get_node_by_id(host_id) {
+            Some(node) if node.is_element() => node,
+            Some(node) => return Err(ShadowRootError::InvalidHost(node.name.clone())),
+            None => return Err(ShadowRootError::InvalidHost(String::new())),
+        };
+        if host.namespace.as_deref() != Some(HTML_NAMESPACE)
+            || !(SHADOW_HOST_ELEMENTS.contains(&host.name.as_str())
+                || is_valid_custom_element_name(&host.name))
+        {
+            return Err(ShadowRootError::InvalidHost(host.name.clone()));
+        }
+
+        if let Some(&root_id) = self.shadow_hosts.get(&host_id) {
+            let shadow_root = &self.shadow_roots[&root_id];
+            if !shadow_root.declarative || shadow_root.mode != init.mode {
+                return Err(ShadowRootError::AlreadyAttached);
+            }
+
+            let children = self.get_node_by_id(root_id).map(|n| n.children.clone());
+            for child_id in children.unwrap_or_default() {
+                self.remove_node(child_id);
+            }
+            if let Some(shadow_root) = self.shadow_roots.get_mut(&root_id) {
+                shadow_root.declarative = false;
+            }
+            return Ok(root_id);
+        }
+
+        let root_id = self.add_detached_node(Node::new_document_fragment());
+        self.shadow_roots.insert(
+            root_id,
+            ShadowRoot {
+                mode: init.mode,
+                delegates_focus: init.delegates_focus,
+                slot_assignment: init.slot_assignment,
+                clonable: init.clonable,
+                serializable: init.serializable,
+                declarative,
+                host: host_id,
+            },
+        );
+        self.shadow_hosts.insert(host_id, root_id);
+
+        Ok(root_id)
+    }
+
+    /// Returns the node id of the shadow root attached to the given element, regardless of
+    /// its mode.
+    pub fn shadow_root(&self, host_id: usize) -> Option<usize> {
+        self.shadow_hosts.get(&host_id).copied()
+    }
+
+    /// Returns the shadow root information when the given node is a shadow root
+    pub fn shadow_root_info(&self, root_id: usize) -> Option<&ShadowRoot> {
+        self.shadow_roots.get(&root_id)
+    }
+
+    /// Returns the host element of the given shadow root
+    pub fn host(&self, root_id: usize) -> Option<usize> {
+        self.shadow_roots
+            .get(&root_id)
+            .map(|shadow_root| shadow_root.host)
+    }
+
+    /// Returns the node id of the document fragment holding the contents of the given template
+    /// element.
+    pub fn template_contents(&self, template_id: usize) -> Option<usize> {
+        self.template_contents.get(&template_id).copied()
+    }
+
+    // Creates the (empty) template contents fragment for the given template element
+    pub(crate) fn create_template_contents(&mut self, template_id: usize) -> usize {
+        let contents_id = self.add_detached_node(Node::new_document_fragment());
+        self.template_contents.insert(template_id, contents_id);
+        contents_id
+    }
+
+    // Uses an existing node (a shadow root) as the contents of the given template element
+    pub(crate) fn set_template_contents(&mut self, template_id: usize, contents_id: usize) {
+        self.template_contents.insert(template_id, contents_id);
+    }
+
+    /// Returns the root of the tree the node is in. This is the document, a shadow root, a
+    /// template contents fragment or the topmost node of a detached subtree.
+    pub fn root_node(&self, node_id: usize) -> usize {
+        let mut current = node_id;
+        while let Some(parent_id) = self.get_node_by_id(current).and_then(|node| node.parent) {
+            current = parent_id;
+        }
+        current
+    }
+
+    /// Returns the root of the node, where shadow roots are crossed to their hosts
+    pub fn shadow_including_root(&self, node_id: usize) -> usize {
+        let mut root_id = self.root_node(node_id);
+        while let Some(host_id) = self.host(root_id) {
+            root_id = self.root_node(host_id);
+        }
+        root_id
+    }
+
+    /// Returns true when the node is in the document, possibly inside one or more shadow trees
+    pub fn is_connected(&self, node_id: usize) -> bool {
+        self.get_node_by_id(node_id).is_some() && self.shadow_including_root(node_id) == 0
+    }
+
+    /// Returns the ids of all shadow-including descendants of the given node in shadow-including
+    /// tree order: the shadow root of a host comes right after the host, before its children.
+    /// The node itself is not included.
+    pub fn shadow_including_descendants(&self, node_id: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+
+        let mut stack = self.shadow_including_children(node_id);
+        stack.reverse();
+
+        while let Some(id) = stack.pop() {
+            result.push(id);
+            stack.extend(self.shadow_including_children(id).into_iter().rev());
+        }
+
+        result
+    }
+
+    // Returns the shadow root (if any) followed by the children of the node
+    fn shadow_including_children(&self, node_id: usize) -> Vec<usize> {
+        let node = match self.get_node_by_id(node_id) {
+            Some(node) => node,
+            None => return Vec::new(),
+        };
+
+        self.shadow_root(node_id)
+            .into_iter()
+            .chain(node.children.iter().copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn element(document: &mut Document, name: &str, parent_id: usize) -> usize {
+        document.add_node(
+            Node::new_element(name, HashMap::new(), HTML_NAMESPACE),
+            parent_id,
+        )
+    }
+
+    #[test]
+    fn test_valid_custom_element_names() {
+        assert!(is_valid_custom_element_name("my-element"));
+        assert!(is_valid_custom_element_name("x-ünïcode"));
+        assert!(!is_valid_custom_element_name("div"));
+        assert!(!is_valid_custom_element_name("My-element"));
+        assert!(!is_valid_custom_element_name("-element"));
+        assert!(!is_valid_custom_element_name("font-face"));
+    }
+
+    #[test]
+    fn test_attach_shadow() {
+        let mut document = Document::new();
+        let div_id = element(&mut document, "div", 0);
+        let input_id = element(&mut document, "input", 0);
+        let custom_id = element(&mut document, "my-widget", 0);
+
+        let root_id = document
+            .attach_shadow(div_id, ShadowRootInit::new(ShadowRootMode::Open))
+            .unwrap();
+        assert_eq!(document.shadow_root(div_id), Some(root_id));
+        assert_eq!(document.host(root_id), Some(div_id));
+        assert_eq!(
+            document.shadow_root_info(root_id).unwrap().mode,
+            ShadowRootMode::Open
+        );
+        assert!(document.get_node_by_id(div_id).unwrap().children.is_empty());
+
+        assert_eq!(
+            document.attach_shadow(div_id, ShadowRootInit::new(ShadowRootMode::Open)),
+            Err(ShadowRootError::AlreadyAttached)
+        );
+        assert_eq!(
+            document.attach_shadow(input_id, ShadowRootInit::new(ShadowRootMode::Open)),
+            Err(ShadowRootError::InvalidHost("input".to_string()))
+        );
+        assert!(document
+            .attach_shadow(custom_id, ShadowRootInit::new(ShadowRootMode::Closed))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_declarative_shadow_root_is_replaced() {
+        let mut document = Document::new();
+        let div_id = element(&mut document, "div", 0);
+        let init = ShadowRootInit::new(ShadowRootMode::Open);
+
+        let root_id = document.attach_shadow_root(div_id, init, true).unwrap();
+        let span_id = element(&mut document, "span", root_id);
+
+        assert_eq!(
+            document.attach_shadow(div_id, ShadowRootInit::new(ShadowRootMode::Closed)),
+            Err(ShadowRootError::AlreadyAttached)
+        );
+        assert_eq!(document.attach_shadow(div_id, init), Ok(root_id));
+        assert!(document.get_node_by_id(span_id).is_none());
+        assert!(!document.shadow_root_info(root_id).unwrap().declarative);
+        assert_eq!(
+            document.attach_shadow(div_id, init),
+            Err(ShadowRootError::AlreadyAttached)
+        );
+    }
+
+    #[test]
+    fn test_shadow_including_traversal() {
+        let mut document = Document::new();
+        let div_id = element(&mut document, "div", 0);
+        let p_id = element(&mut document, "p", div_id);
+        let root_id = document
+            .attach_shadow(div_id, ShadowRootInit::new(ShadowRootMode::Closed))
+            .unwrap();
+        let span_id = element(&mut document, "span", root_id);
+
+        assert_eq!(document.descendants(0), vec![div_id, p_id]);
+        assert_eq!(
+            document.shadow_including_descendants(0),
+            vec![div_id, root_id, span_id, p_id]
+        );
+        assert_eq!(document.root_node(span_id), root_id);
+        assert_eq!(document.shadow_including_root(span_id), 0);
+        assert!(document.is_connected(span_id));
+
+        document.remove_node(div_id);
+        assert!(document.get_node_by_id(span_id).is_none());
+        assert!(document.shadow_root_info(root_id).is_none());
+        assert!(!document.is_connected(span_id));
+    }
+}
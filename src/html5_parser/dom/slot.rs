@@ -0,0 +1,382 @@
+use crate::html5_parser::dom::{ShadowRootMode, SlotAssignmentMode};
+use crate::html5_parser::node::{NodeData, HTML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+
+// Slots and their assigned nodes are computed from the current state of the tree each time they
+// are requested, so there is no assignment state that must be kept in sync with mutations. Only
+// the manually assigned nodes of slots in shadow trees with manual slot assignment are stored.
+impl Document {
+    /// Returns true when the node is a slot element
+    pub fn is_slot(&self, node_id: usize) -> bool {
+        match self.get_node_by_id(node_id) {
+            Some(node) => {
+                node.is_element()
+                    && node.name == "slot"
+                    && node.namespace.as_deref() == Some(HTML_NAMESPACE)
+            }
+            None => false,
+        }
+    }
+
+    /// Returns true when the node can be assigned to a slot (elements and text nodes)
+    pub fn is_slottable(&self, node_id: usize) -> bool {
+        matches!(
+            self.get_node_by_id(node_id).map(|node| &node.data),
+            Some(NodeData::Element { .. } | NodeData::Text { .. })
+        )
+    }
+
+    // Returns the name of a slot, or the name of the slot a slottable wants to be assigned to
+    fn slot_name(&self, node_id: usize, attribute: &str) -> &str {
+        self.get_node_by_id(node_id)
+            .and_then(|node| node.get_attribute(attribute))
+            .map_or("", |name| name.as_str())
+    }
+
+    /// Finds the slot the slottable is assigned to. When open is set, slots in closed shadow
+    /// trees are not returned.
+    pub fn find_slot(&self, slottable_id: usize, open: bool) -> Option<usize> {
+        if !self.is_slottable(slottable_id) {
+            return None;
+        }
+
+        let parent_id = self.get_node_by_id(slottable_id)?.parent?;
+        let root_id = self.shadow_root(parent_id)?;
+        let shadow_root = self.shadow_root_info(root_id)?;
+        if open && shadow_root.mode != ShadowRootMode::Open {
+            return None;
+        }
+
+        if shadow_root.slot_assignment == SlotAssignmentMode::Manual {
+            return self
+                .manual_slot_assignments
+                .iter()
+                .filter(|(_, nodes)| nodes.contains(&slottable_id))
+                .map(|(&slot_id, _)| slot_id)
+                .find(|&slot_id| self.root_node(slot_id) == root_id);
+        }
+
+        let name = self.slot_name(slottable_id, "slot");
+        self.descendants(root_id)
+            .into_iter()
+            .find(|&id| self.is_slot(id) && self.slot_name(id, "name") == name)
+    }
+
+    /// Returns the slottables assigned to the slot, in tree order for named assignment and in
+    /// assignment order for manual assignment.
+    pub fn find_slottables(&self, slot_id: usize) -> Vec<usize> {
+        let root_id = self.root_node(slot_id);
+        let shadow_root = match self.shadow_root_info(root_id) {
+            Some(shadow_root) if self.is_slot(slot_id) => shadow_root,
+            _ => return Vec::new(),
+        };
+        let host_id = shadow_root.host;
+
+        if shadow_root.slot_assignment == SlotAssignmentMode::Manual {
+            return self
+                .manual_slot_assignments
+                .get(&slot_id)
+                .map(|nodes| {
+                    nodes
+                        .iter()
+                        .copied()
+                        .filter(|&id| {
+                            self.get_node_by_id(id).and_then(|node| node.parent) == Some(host_id)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+        }
+
+        match self.get_node_by_id(host_id) {
+            Some(host) => host
+                .children
+                .iter()
+                .copied()
+                .filter(|&id| self.find_slot(id, false) == Some(slot_id))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the flattened slottables of the slot: nested slots are replaced by their own
+    /// assigned nodes, and the children of a slot are used as fallback when nothing is assigned.
+    pub fn find_flattened_slottables(&self, slot_id: usize) -> Vec<usize> {
+        if self.shadow_root_info(self.root_node(slot_id)).is_none() {
+            return Vec::new();
+        }
+
+        let mut slottables = self.find_slottables(slot_id);
+        if slottables.is_empty() {
+            if let Some(slot) = self.get_node_by_id(slot_id) {
+                slottables = slot
+                    .children
+                    .iter()
+                    .copied()
+                    .filter(|&id| self.is_slottable(id))
+                    .collect();
+            }
+        }
+
+        let mut result = Vec::new();
+        for id in slottables {
+            if self.is_slot(id) && self.shadow_root_info(self.root_node(id)).is_some() {
+                result.extend(self.find_flattened_slottables(id));
+            } else {
+                result.push(id);
+            }
+        }
+        result
+    }
+
+    /// Returns the slot the node is assigned to, as exposed by Element.assignedSlot. Slots in
+    /// closed shadow trees are not returned.
+    pub fn assigned_slot(&self, slottable_id: usize) -> Option<usize> {
+        self.find_slot(slottable_id, true)
+    }
+
+    /// Returns the nodes assigned to the slot, optionally flattened, as exposed by
+    /// HTMLSlotElement.assignedNodes()
+    pub fn assigned_nodes(&self, slot_id: usize, flatten: bool) -> Vec<usize> {
+        if flatten {
+            self.find_flattened_slottables(slot_id)
+        } else {
+            self.find_slottables(slot_id)
+        }
+    }
+
+    /// Returns only the elements of assigned_nodes()
+    pub fn assigned_elements(&self, slot_id: usize, flatten: bool) -> Vec<usize> {
+        self.assigned_nodes(slot_id, flatten)
+            .into_iter()
+            .filter(|&id| {
+                self.get_node_by_id(id)
+                    .is_some_and(|node| node.is_element())
+            })
+            .collect()
+    }
+
+    /// Manually assigns the given slottables to the slot, as done by HTMLSlotElement.assign().
+    /// The nodes are removed from any slot they were manually assigned to before. Assignments
+    /// only take effect in shadow trees with manual slot assignment.
+    pub fn assign(&mut self, slot_id: usize, node_ids: &[usize]) {
+        if !self.is_slot(slot_id) {
+            return;
+        }
+
+        let mut nodes: Vec<usize> = Vec::new();
+        for &id in node_ids {
+            if self.is_slottable(id) && !nodes.contains(&id) {
+                nodes.push(id);
+            }
+        }
+
+        for assigned in self.manual_slot_assignments.values_mut() {
+            assigned.retain(|id| !nodes.contains(id));
+        }
+        self.manual_slot_assignments
+            .retain(|_, assigned| !assigned.is_empty());
+        if !nodes.is_empty() {
+            self.manual_slot_assignments.insert(slot_id, nodes);
+        } else {
+            self.manual_slot_assignments.remove(&slot_id);
+        }
+    }
+
+    /// Returns the children of the node in the flat tree. A shadow host has the children of its
+    /// shadow root, a slot has its assigned nodes (or its fallback content) and any
+    /// other node has its regular children.
+    pub fn flat_tree_children(&self, node_id: usize) -> Vec<usize> {
+        if let Some(root_id) = self.shadow_root(node_id) {
+            return self
+                .get_node_by_id(root_id)
+                .map(|root| root.children.clone())
+                .unwrap_or_default();
+        }
+
+        if self.is_slot(node_id) && self.shadow_root_info(self.root_node(node_id)).is_some() {
+            let assigned = self.find_slottables(node_id);
+            if !assigned.is_empty() {
+                return assigned;
+            }
+        }
+
+        self.get_node_by_id(node_id)
+            .map(|node| node.children.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the parent of the node in the flat tree, or None when the node has no parent or
+    /// is not rendered in the flat tree (a child of a shadow host that is not assigned to a slot)
+    pub fn flat_tree_parent(&self, node_id: usize) -> Option<usize> {
+        let parent_id = self.get_node_by_id(node_id)?.parent?;
+
+        if self.shadow_root(parent_id).is_some() {
+            return self.find_slot(node_id, false);
+        }
+        if let Some(host_id) = self.host(parent_id) {
+            return Some(host_id);
+        }
+        if self.is_slot(parent_id) && !self.find_slottables(parent_id).is_empty() {
+            // The fallback content of a slot with assigned nodes is not rendered
+            return None;
+        }
+
+        Some(parent_id)
+    }
+
+    /// Returns the descendants of the node in flat tree order. The node itself is not included.
+    pub fn flat_tree_descendants(&self, node_id: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+
+        let mut stack = self.flat_tree_children(node_id);
+        stack.reverse();
+
+        while let Some(id) = stack.pop() {
+            result.push(id);
+            stack.extend(self.flat_tree_children(id).into_iter().rev());
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5_parser::dom::shadow::ShadowRootInit;
+    use crate::html5_parser::dom::{ShadowRootMode, SlotAssignmentMode};
+    use crate::html5_parser::node::{Node, HTML_NAMESPACE};
+    use crate::html5_parser::parser::document::Document;
+    use std::collections::HashMap;
+
+    fn element(
+        document: &mut Document,
+        name: &str,
+        attrs: &[(&str, &str)],
+        parent: usize,
+    ) -> usize {
+        let attributes: HashMap<String, String> = attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        document.add_node(Node::new_element(name, attributes, HTML_NAMESPACE), parent)
+    }
+
+    #[test]
+    fn test_named_slot_assignment() {
+        let mut document = Document::new();
+        let host_id = element(&mut document, "div", &[], 0);
+        let title_id = element(&mut document, "h1", &[("slot", "title")], host_id);
+        let text_id = document.add_node(Node::new_text("body"), host_id);
+        let unknown_id = element(&mut document, "p", &[("slot", "unknown")], host_id);
+
+        let root_id = document
+            .attach_shadow(host_id, ShadowRootInit::new(ShadowRootMode::Closed))
+            .unwrap();
+        let title_slot_id = element(&mut document, "slot", &[("name", "title")], root_id);
+        let default_slot_id = element(&mut document, "slot", &[], root_id);
+        let second_default_id = element(&mut document, "slot", &[], root_id);
+
+        assert_eq!(document.find_slot(title_id, false), Some(title_slot_id));
+        assert_eq!(document.find_slot(text_id, false), Some(default_slot_id));
+        assert_eq!(document.find_slot(unknown_id, false), None);
+        assert_eq!(document.find_slot(title_id, true), None);
+        assert_eq!(document.assigned_slot(title_id), None);
+
+        assert_eq!(document.find_slottables(title_slot_id), vec![title_id]);
+        assert_eq!(document.find_slottables(default_slot_id), vec![text_id]);
+        assert!(document.find_slottables(second_default_id).is_empty());
+        assert!(document
+            .assigned_elements(default_slot_id, false)
+            .is_empty());
+
+        // Changing the slot attribute reassigns the element
+        document.set_attribute(unknown_id, "slot", "");
+        assert_eq!(
+            document.find_slottables(default_slot_id),
+            vec![text_id, unknown_id]
+        );
+    }
+
+    #[test]
+    fn test_manual_slot_assignment() {
+        let mut document = Document::new();
+        let host_id = element(&mut document, "div", &[], 0);
+        let a_id = element(&mut document, "span", &[("slot", "x")], host_id);
+        let b_id = element(&mut document, "span", &[], host_id);
+        let outside_id = element(&mut document, "span", &[], 0);
+
+        let mut init = ShadowRootInit::new(ShadowRootMode::Open);
+        init.slot_assignment = SlotAssignmentMode::Manual;
+        let root_id = document.attach_shadow(host_id, init).unwrap();
+        let slot1_id = element(&mut document, "slot", &[("name", "x")], root_id);
+        let slot2_id = element(&mut document, "slot", &[], root_id);
+
+        // Names are ignored with manual assignment
+        assert_eq!(document.find_slot(a_id, false), None);
+
+        document.assign(slot1_id, &[b_id, a_id, outside_id]);
+        assert_eq!(document.find_slottables(slot1_id), vec![b_id, a_id]);
+        assert_eq!(document.assigned_slot(a_id), Some(slot1_id));
+
+        document.assign(slot2_id, &[a_id]);
+        assert_eq!(document.find_slottables(slot1_id), vec![b_id]);
+        assert_eq!(document.find_slottables(slot2_id), vec![a_id]);
+
+        document.remove_node(slot2_id);
+        assert_eq!(document.find_slot(a_id, false), None);
+    }
+
+    #[test]
+    fn test_flat_tree() {
+        // <div id=outer>
+        //   #shadow-root: <p><inner-el><slot></slot></inner-el></p>
+        //   "light"
+        // <inner-el>
+        //   #shadow-root: <b><slot>fallback</slot></b>
+        let mut document = Document::new();
+        let outer_id = element(&mut document, "div", &[], 0);
+        let light_id = document.add_node(Node::new_text("light"), outer_id);
+
+        let outer_root_id = document
+            .attach_shadow(outer_id, ShadowRootInit::new(ShadowRootMode::Open))
+            .unwrap();
+        let p_id = element(&mut document, "p", &[], outer_root_id);
+        let inner_id = element(&mut document, "inner-el", &[], p_id);
+        let outer_slot_id = element(&mut document, "slot", &[], inner_id);
+
+        let inner_root_id = document
+            .attach_shadow(inner_id, ShadowRootInit::new(ShadowRootMode::Open))
+            .unwrap();
+        let b_id = element(&mut document, "b", &[], inner_root_id);
+        let inner_slot_id = element(&mut document, "slot", &[], b_id);
+        let fallback_id = document.add_node(Node::new_text("fallback"), inner_slot_id);
+
+        assert_eq!(
+            document.find_flattened_slottables(inner_slot_id),
+            vec![light_id]
+        );
+        assert_eq!(
+            document.flat_tree_descendants(outer_id),
+            vec![p_id, inner_id, b_id, inner_slot_id, outer_slot_id, light_id]
+        );
+        assert_eq!(document.flat_tree_parent(light_id), Some(outer_slot_id));
+        assert_eq!(
+            document.flat_tree_parent(outer_slot_id),
+            Some(inner_slot_id)
+        );
+        assert_eq!(document.flat_tree_parent(p_id), Some(outer_id));
+        assert_eq!(document.flat_tree_parent(fallback_id), None);
+
+        // Without assigned nodes the fallback content is rendered
+        document.remove_node(light_id);
+        assert_eq!(
+            document.flat_tree_descendants(inner_id),
+            vec![b_id, inner_slot_id, outer_slot_id]
+        );
+        assert_eq!(
+            document.find_flattened_slottables(inner_slot_id),
+            Vec::<usize>::new()
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use crate::html5_parser::input_stream::Position;
+use std::collections::HashSet;
 
 // Possible parser error enumerated
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ParserError {
     AbruptDoctypePublicIdentifier,
     AbruptDoctypeSystemIdentifier,
@@ -55,10 +57,82 @@ pub enum ParserError {
     ExpectedDocTypeButGotChars,
     ExpectedDocTypeButGotStartTag,
     ExpectedDocTypeButGotEndTag,
+
+    // Errors for exceeding the limits in ParserLimits
+    InputSizeLimitExceeded,
+    TreeDepthLimitExceeded,
+    NodeLimitExceeded,
+    AttributeLimitExceeded,
+    AttributeValueLengthLimitExceeded,
+    FormattingElementLimitExceeded,
+    ParseTimeLimitExceeded,
+
+    // Parsing was stopped through a CancellationToken
+    ParseCancelled,
 }
 
+// Errors of the tokenizer, which have a code in the parse errors section of the HTML
+// specification. Tree construction errors are not listed there.
+const SPEC_ERRORS: [ParserError; 49] = [
+    ParserError::AbruptDoctypePublicIdentifier,
+    ParserError::AbruptDoctypeSystemIdentifier,
+    ParserError::AbruptClosingOfEmptyComment,
+    ParserError::AbsenceOfDigitsInNumericCharacterReference,
+    ParserError::CdataInHtmlContent,
+    ParserError::CharacterReferenceOutsideUnicodeRange,
+    ParserError::ControlCharacterInInputStream,
+    ParserError::ControlCharacterReference,
+    ParserError::EndTagWithAttributes,
+    ParserError::DuplicateAttribute,
+    ParserError::EndTagWithTrailingSolidus,
+    ParserError::EofBeforeTagName,
+    ParserError::EofInCdata,
+    ParserError::EofInComment,
+    ParserError::EofInDoctype,
+    ParserError::EofInScriptHtmlCommentLikeText,
+    ParserError::EofInTag,
+    ParserError::IncorrectlyClosedComment,
+    ParserError::IncorrectlyOpenedComment,
+    ParserError::InvalidCharacterSequenceAfterDoctypeName,
+    ParserError::InvalidFirstCharacterOfTagName,
+    ParserError::MissingAttributeValue,
+    ParserError::MissingDoctypeName,
+    ParserError::MissingDoctypePublicIdentifier,
+    ParserError::MissingDoctypeSystemIdentifier,
+    ParserError::MissingEndTagName,
+    ParserError::MissingQuoteBeforeDoctypePublicIdentifier,
+    ParserError::MissingQuoteBeforeDoctypeSystemIdentifier,
+    ParserError::MissingSemicolonAfterCharacterReference,
+    ParserError::MissingWhitespaceAfterDoctypePublicKeyword,
+    ParserError::MissingWhitespaceAfterDoctypeSystemKeyword,
+    ParserError::MissingWhitespaceBeforeDoctypeName,
+    ParserError::MissingWhitespaceBetweenAttributes,
+    ParserError::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+    ParserError::NestedComment,
+    ParserError::NoncharacterCharacterReference,
+    ParserError::NoncharacterInInputStream,
+    ParserError::NonVoidHtmlElementStartTagWithTrailingSolidus,
+    ParserError::NullCharacterReference,
+    ParserError::SurrogateCharacterReference,
+    ParserError::SurrogateInInputStream,
+    ParserError::UnexpectedCharacterAfterDoctypeSystemIdentifier,
+    ParserError::UnexpectedCharacterInAttributeName,
+    ParserError::UnexpectedCharacterInUnquotedAttributeValue,
+    ParserError::UnexpectedEqualsSignBeforeAttributeName,
+    ParserError::UnexpectedNullCharacter,
+    ParserError::UnexpectedQuestionMarkInsteadOfTagName,
+    ParserError::UnexpectedSolidusInTag,
+    ParserError::UnknownNamedCharacterReference,
+];
+
 // Parser errors as string representation
 impl ParserError {
+    /// Returns the error with the given code from the HTML specification, or None when the code
+    /// is not defined by the specification
+    pub fn from_spec_code(code: &str) -> Option<ParserError> {
+        SPEC_ERRORS.into_iter().find(|error| error.as_str() == code)
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             ParserError::AbruptDoctypePublicIdentifier => "abrupt-doctype-public-identifier",
@@ -148,6 +222,16 @@ impl ParserError {
             ParserError::ExpectedDocTypeButGotChars => "expected-doctype-but-got-chars",
             ParserError::ExpectedDocTypeButGotStartTag => "expected-doctype-but-got-start-tag",
             ParserError::ExpectedDocTypeButGotEndTag => "expected-doctype-but-got-end-tag",
+            ParserError::InputSizeLimitExceeded => "input-size-limit-exceeded",
+            ParserError::TreeDepthLimitExceeded => "tree-depth-limit-exceeded",
+            ParserError::NodeLimitExceeded => "node-limit-exceeded",
+            ParserError::AttributeLimitExceeded => "attribute-limit-exceeded",
+            ParserError::AttributeValueLengthLimitExceeded => {
+                "attribute-value-length-limit-exceeded"
+            }
+            ParserError::FormattingElementLimitExceeded => "formatting-element-limit-exceeded",
+            ParserError::ParseTimeLimitExceeded => "parse-time-limit-exceeded",
+            ParserError::ParseCancelled => "parse-cancelled",
         }
     }
 }
@@ -164,12 +248,16 @@ pub struct ParseError {
 #[derive(Clone)]
 pub struct ErrorLogger {
     errors: Vec<ParseError>, // List of errors that occurred during parsing
+    logged: HashSet<(usize, usize, String)>, // Line, col and message of the logged errors
 }
 
 impl ErrorLogger {
     // Creates a new error logger
     pub fn new() -> Self {
-        ErrorLogger { errors: Vec::new() }
+        ErrorLogger {
+            errors: Vec::new(),
+            logged: HashSet::new(),
+        }
     }
 }
 
@@ -185,13 +273,21 @@ impl ErrorLogger {
         self.errors.clone()
     }
 
+    // Returns the number of errors
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+    }
+
+    // Returns the errors after the first count errors
+    pub fn errors_since(&self, count: usize) -> &[ParseError] {
+        &self.errors[count.min(self.errors.len())..]
+    }
+
     // Adds a new error to the error logger
     pub fn add_error(&mut self, pos: Position, message: &str) {
         // Check if the error already exists, if so, don't add it again
-        for err in &self.errors {
-            if err.line == pos.line && err.col == pos.col && err.message == *message {
-                return;
-            }
+        if !self.logged.insert((pos.line, pos.col, message.to_string())) {
+            return;
         }
 
         self.errors.push(ParseError {
@@ -206,7 +302,6 @@ impl ErrorLogger {
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
@@ -278,4 +373,17 @@ mod tests {
 
         assert_eq!(logger.get_errors().len(), 10);
     }
+
+    #[test]
+    fn test_from_spec_code() {
+        assert_eq!(
+            ParserError::from_spec_code("eof-in-tag"),
+            Some(ParserError::EofInTag)
+        );
+        assert_eq!(ParserError::from_spec_code("unexpected-end-tag"), None);
+        assert_eq!(
+            ParserError::from_spec_code("expected-doctype-but-got-chars"),
+            None
+        );
+    }
 }
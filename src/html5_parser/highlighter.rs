@@ -0,0 +1,417 @@
+use crate::html5_parser::error_logger::{ErrorLogger, ParseError};
+use crate::html5_parser::input_stream::InputStream;
+use crate::html5_parser::tokenizer::entity_trie::longest_match;
+use crate::html5_parser::tokenizer::raw_token::RawToken;
+use crate::html5_parser::tokenizer::state::State;
+use crate::html5_parser::tokenizer::token::Token;
+use crate::html5_parser::tokenizer::Tokenizer;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// How the highlighted source is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Text with ANSI escape codes for terminals
+    Ansi,
+    /// A `pre` element with a `span` for every styled part, see STYLESHEET for the classes
+    Html,
+}
+
+/// The kinds of source that get their own style
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Text,
+    /// The "<", "</", "=", ">" and "/>" of tags
+    Tag,
+    TagName,
+    AttributeName,
+    /// Attribute values, with their quotes
+    AttributeValue,
+    Comment,
+    Doctype,
+    CharacterReference,
+}
+
+impl Style {
+    /// Returns the CSS class of the style in HTML output
+    pub fn class(&self) -> &'static str {
+        match self {
+            Style::Text => "hl-text",
+            Style::Tag => "hl-tag",
+            Style::TagName => "hl-tag-name",
+            Style::AttributeName => "hl-attr-name",
+            Style::AttributeValue => "hl-attr-value",
+            Style::Comment => "hl-comment",
+            Style::Doctype => "hl-doctype",
+            Style::CharacterReference => "hl-char-ref",
+        }
+    }
+
+    fn ansi_code(&self) -> Option<&'static str> {
+        match self {
+            Style::Text => None,
+            Style::Tag => Some("36"),
+            Style::TagName => Some("1;34"),
+            Style::AttributeName => Some("33"),
+            Style::AttributeValue => Some("32"),
+            Style::Comment => Some("90"),
+            Style::Doctype => Some("35"),
+            Style::CharacterReference => Some("1;36"),
+        }
+    }
+}
+
+const ERROR_CLASS: &str = "hl-error";
+const ERROR_ANSI_CODE: &str = "97;41";
+
+/// Stylesheet for the classes of the HTML output
+pub const STYLESHEET: &str = "\
+.hl-tag { color: #0184bc; }
+.hl-tag-name { color: #4078f2; font-weight: bold; }
+.hl-attr-name { color: #c18401; }
+.hl-attr-value { color: #50a14f; }
+.hl-comment { color: #a0a1a7; font-style: italic; }
+.hl-doctype { color: #a626a4; }
+.hl-char-ref { color: #0184bc; font-weight: bold; }
+.hl-error { background: #e45649; color: #fff; }
+";
+
+/// Highlights the HTML source. Parse errors of the tokenizer are marked at their position; when
+/// that is the end of a line or of the input, a marked space is inserted there.
+pub fn highlight(html: &str, format: OutputFormat) -> String {
+    let mut stream = InputStream::new();
+    stream.read_from_str(html, None);
+    let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+    let mut tokenizer = Tokenizer::new(&mut stream, None, error_logger.clone());
+
+    let mut source = String::with_capacity(html.len());
+    let mut parts = Vec::new();
+    // The state text is read in, which is switched after start tags just like the parser does
+    let mut content_state = State::DataState;
+    loop {
+        let token = tokenizer.next_raw_token();
+        add_parts(&token, source.len(), content_state, &mut parts);
+        source.push_str(&token.raw);
+
+        match &token.token {
+            Token::StartTagToken { name, .. } => {
+                content_state = State::for_content_of(name);
+                tokenizer.state = content_state;
+            }
+            Token::EndTagToken { .. } => content_state = State::DataState,
+            Token::EofToken => break,
+            _ => {}
+        }
+    }
+
+    let errors = error_logger.borrow().get_errors();
+    write_highlighted(&source, &parts, &errors, format)
+}
+
+// Adds the styled parts of the token, which starts at the given offset in the source. The state
+// of text tokens tells if character references in them are decoded.
+fn add_parts(token: &RawToken, start: usize, state: State, parts: &mut Vec<(Range<usize>, Style)>) {
+    let raw = token.raw.as_str();
+    let mut add = |range: Range<usize>, style: Style| {
+        if !range.is_empty() {
+            parts.push((start + range.start..start + range.end, style));
+        }
+    };
+
+    match &token.token {
+        Token::StartTagToken { .. } | Token::EndTagToken { .. } => {
+            let prefix_len = if raw.starts_with("</") { 2 } else { 1 };
+            let name_end = raw[prefix_len..]
+                .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+                .map_or(raw.len(), |idx| prefix_len + idx);
+            add(0..prefix_len, Style::Tag);
+            add(prefix_len..name_end, Style::TagName);
+
+            let mut pos = name_end;
+            for attribute in &token.attributes {
+                add(pos..attribute.offset, Style::Text);
+                let name_end = attribute.offset + attribute.raw_name.len();
+                add(attribute.offset..name_end, Style::AttributeName);
+
+                let end = attribute.offset + attribute.raw.len();
+                if let Some(value) = &attribute.raw_value {
+                    // The "=" with the spaces around it comes before the value and its quote
+                    let quote_len = attribute.quote.map_or(0, char::len_utf8);
+                    let equals_len = raw[name_end..]
+                        .find(|c: char| !c.is_ascii_whitespace() && c != '=')
+                        .unwrap_or(raw.len() - name_end);
+                    let value_start = name_end + equals_len + quote_len;
+                    add(name_end..value_start - quote_len, Style::Tag);
+                    add(value_start - quote_len..value_start, Style::AttributeValue);
+                    for (range, style) in text_parts(value, true) {
+                        let style = match style {
+                            Style::Text => Style::AttributeValue,
+                            style => style,
+                        };
+                        add(value_start + range.start..value_start + range.end, style);
+                    }
+                    add(value_start + value.len()..end, Style::AttributeValue);
+                }
+                pos = end;
+            }
+            add(pos..raw.len(), Style::Tag);
+        }
+        Token::CommentToken { .. } => add(0..raw.len(), Style::Comment),
+        Token::DocTypeToken { .. } => add(0..raw.len(), Style::Doctype),
+        Token::TextToken { .. } if matches!(state, State::DataState | State::RcDataState) => {
+            for (range, style) in text_parts(raw, false) {
+                add(range, style);
+            }
+        }
+        Token::TextToken { .. } | Token::EofToken => add(0..raw.len(), Style::Text),
+    }
+}
+
+// Splits text into parts of plain text and character references
+fn text_parts(text: &str, in_attribute: bool) -> Vec<(Range<usize>, Style)> {
+    let mut parts = Vec::new();
+    let mut pos = 0;
+    for (amp, _) in text.match_indices('&') {
+        if amp < pos {
+            continue;
+        }
+        let len = character_reference_len(&text[amp + 1..], in_attribute);
+        if len == 0 {
+            continue;
+        }
+
+        if pos < amp {
+            parts.push((pos..amp, Style::Text));
+        }
+        parts.push((amp..amp + 1 + len, Style::CharacterReference));
+        pos = amp + 1 + len;
+    }
+    if pos < text.len() {
+        parts.push((pos..text.len(), Style::Text));
+    }
+    parts
+}
+
+// Returns the length of the character reference after a "&", or 0 when there is none. This
+// follows what the tokenizer decodes.
+fn character_reference_len(text: &str, in_attribute: bool) -> usize {
+    let bytes = text.as_bytes();
+    if bytes.first() == Some(&b'#') {
+        let is_hex = matches!(bytes.get(1), Some(b'x' | b'X'));
+        let prefix_len = if is_hex { 2 } else { 1 };
+        let digits = bytes[prefix_len.min(bytes.len())..]
+            .iter()
+            .take_while(|b| {
+                if is_hex {
+                    b.is_ascii_hexdigit()
+                } else {
+                    b.is_ascii_digit()
+                }
+            })
+            .count();
+        if digits == 0 {
+            return 0;
+        }
+        let len = prefix_len + digits;
+        return len + usize::from(bytes.get(len) == Some(&b';'));
+    }
+
+    match longest_match(text) {
+        // Legacy references without ";" are not decoded in attributes when an alphanumeric or
+        // "=" follows
+        Some((len, _))
+            if in_attribute
+                && !text[..len].ends_with(';')
+                && bytes
+                    .get(len)
+                    .is_some_and(|b| b.is_ascii_alphanumeric() || *b == b'=') =>
+        {
+            0
+        }
+        Some((len, _)) => len,
+        None => 0,
+    }
+}
+
+fn write_highlighted(
+    source: &str,
+    parts: &[(Range<usize>, Style)],
+    errors: &[ParseError],
+    format: OutputFormat,
+) -> String {
+    // Errors by the char offset in the input, which has normalized newlines
+    let mut error_messages: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    for error in errors {
+        error_messages
+            .entry(error.offset)
+            .or_default()
+            .push(error.message.as_str());
+    }
+
+    let mut out = String::with_capacity(source.len() * 2);
+    if format == OutputFormat::Html {
+        out.push_str("<pre class=\"highlight\">");
+    }
+
+    let mut offset = 0;
+    for (range, style) in parts {
+        start_style(&mut out, *style, format);
+        let text = &source[range.clone()];
+        for (idx, c) in text.char_indices() {
+            // A CRLF is a single newline in the input
+            let is_crlf = c == '\r' && source[range.start + idx + 1..].starts_with('\n');
+            if is_crlf {
+                out.push(c);
+                continue;
+            }
+
+            match error_messages.get(&offset) {
+                Some(messages) => {
+                    if c == '\n' || c == '\r' {
+                        write_error(&mut out, ' ', messages, *style, format);
+                        write_char(&mut out, c, format);
+                    } else {
+                        write_error(&mut out, c, messages, *style, format);
+                    }
+                }
+                None => write_char(&mut out, c, format),
+            }
+            offset += 1;
+        }
+        end_style(&mut out, *style, format);
+    }
+
+    if let Some(messages) = error_messages.get(&offset) {
+        write_error(&mut out, ' ', messages, Style::Text, format);
+    }
+
+    if format == OutputFormat::Html {
+        out.push_str("</pre>");
+    }
+    out
+}
+
+fn start_style(out: &mut String, style: Style, format: OutputFormat) {
+    match format {
+        OutputFormat::Ansi => {
+            if let Some(code) = style.ansi_code() {
+                out.push_str(&format!("\x1b[{}m", code));
+            }
+        }
+        OutputFormat::Html => {
+            if style != Style::Text {
+                out.push_str(&format!("<span class=\"{}\">", style.class()));
+            }
+        }
+    }
+}
+
+fn end_style(out: &mut String, style: Style, format: OutputFormat) {
+    if style == Style::Text {
+        return;
+    }
+    match format {
+        OutputFormat::Ansi => out.push_str("\x1b[0m"),
+        OutputFormat::Html => out.push_str("</span>"),
+    }
+}
+
+fn write_char(out: &mut String, c: char, format: OutputFormat) {
+    match (format, c) {
+        (OutputFormat::Html, '&') => out.push_str("&amp;"),
+        (OutputFormat::Html, '<') => out.push_str("&lt;"),
+        (OutputFormat::Html, '>') => out.push_str("&gt;"),
+        _ => out.push(c),
+    }
+}
+
+fn write_error(out: &mut String, c: char, messages: &[&str], style: Style, format: OutputFormat) {
+    match format {
+        OutputFormat::Ansi => {
+            out.push_str(&format!("\x1b[{}m", ERROR_ANSI_CODE));
+            out.push(c);
+            out.push_str("\x1b[0m");
+            start_style(out, style, format);
+        }
+        OutputFormat::Html => {
+            out.push_str(&format!(
+                "<span class=\"{}\" title=\"{}\">",
+                ERROR_CLASS,
+                messages.join(", ")
+            ));
+            write_char(out, c, format);
+            out.push_str("</span>");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_html() {
+        let html = "<!DOCTYPE html><p class=\"a&amp;b\" id=x>&lt;x&gt; &foo;<!-- c --></p>";
+        assert_eq!(
+            highlight(html, OutputFormat::Html),
+            "<pre class=\"highlight\">\
+            <span class=\"hl-doctype\">&lt;!DOCTYPE html&gt;</span>\
+            <span class=\"hl-tag\">&lt;</span><span class=\"hl-tag-name\">p</span> \
+            <span class=\"hl-attr-name\">class</span><span class=\"hl-tag\">=</span>\
+            <span class=\"hl-attr-value\">\"</span><span class=\"hl-attr-value\">a</span>\
+            <span class=\"hl-char-ref\">&amp;amp;</span><span class=\"hl-attr-value\">b</span>\
+            <span class=\"hl-attr-value\">\"</span> \
+            <span class=\"hl-attr-name\">id</span><span class=\"hl-tag\">=</span>\
+            <span class=\"hl-attr-value\">x</span><span class=\"hl-tag\">&gt;</span>\
+            <span class=\"hl-char-ref\">&amp;lt;</span>x<span class=\"hl-char-ref\">&amp;gt;</span> &amp;foo\
+            <span class=\"hl-error\" title=\"unknown-named-character-reference\">;</span>\
+            <span class=\"hl-comment\">&lt;!-- c --&gt;</span>\
+            <span class=\"hl-tag\">&lt;/</span><span class=\"hl-tag-name\">p</span><span class=\"hl-tag\">&gt;</span>\
+            </pre>"
+        );
+    }
+
+    #[test]
+    fn test_highlight_ansi() {
+        assert_eq!(
+            highlight("<b>&amp;</b>", OutputFormat::Ansi),
+            "\x1b[36m<\x1b[0m\x1b[1;34mb\x1b[0m\x1b[36m>\x1b[0m\x1b[1;36m&amp;\x1b[0m\
+            \x1b[36m</\x1b[0m\x1b[1;34mb\x1b[0m\x1b[36m>\x1b[0m"
+        );
+
+        // Character references are not decoded in scripts
+        assert_eq!(
+            highlight("<script>&amp;</script>", OutputFormat::Ansi)
+                .matches("1;36")
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_highlight_errors() {
+        // The error for the NUL is at the NUL, the one for the missing end of the tag at the
+        // end of the input
+        let output = highlight("a\r\n\0b<p", OutputFormat::Html);
+        assert_eq!(
+            output,
+            "<pre class=\"highlight\">a\r\n\
+            <span class=\"hl-error\" title=\"unexpected-null-character\">\0</span>b&lt;p\
+            <span class=\"hl-error\" title=\"eof-in-tag\"> </span></pre>"
+        );
+    }
+
+    #[test]
+    fn test_character_reference_len() {
+        assert_eq!(character_reference_len("amp;x", false), 4);
+        assert_eq!(character_reference_len("ampx", false), 3);
+        assert_eq!(character_reference_len("ampx", true), 0);
+        assert_eq!(character_reference_len("amp=", true), 0);
+        assert_eq!(character_reference_len("#x1F;", false), 5);
+        assert_eq!(character_reference_len("#123", false), 4);
+        assert_eq!(character_reference_len("#x", false), 0);
+        assert_eq!(character_reference_len("foo;", false), 0);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::html5_parser::tokenizer::{CHAR_CR, CHAR_LF};
+use std::borrow::Cow;
 use std::fs::File;
 use std::io::Read;
 use std::{fmt, io};
@@ -21,17 +22,40 @@ pub enum Confidence {
     Certain,
 }
 
+/// Decides what the column of a position counts
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub enum PositionMode {
+    /// Columns count chars (code points)
+    Chars,
+    /// Columns count UTF-16 code units, like browsers and the html5lib tests do. Chars outside
+    /// the BMP count as two.
+    Utf16,
+}
+
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub struct Position {
+    /// Offset in chars
     pub offset: usize,
     pub line: usize,
+    /// Column in chars or UTF-16 code units, depending on the position mode of the stream
     pub col: usize,
+    /// Offset in bytes of the UTF-8 input
+    pub byte_offset: usize,
+    /// Offset in UTF-16 code units
+    pub utf16_offset: usize,
 }
 
 impl Position {
-    /// Create a new position
+    /// Create a new position. The byte and UTF-16 offsets are set to the char offset, which is
+    /// right for ascii input.
     pub fn new(offset: usize, line: usize, col: usize) -> Self {
-        Position { offset, line, col }
+        Position {
+            offset,
+            line,
+            col,
+            byte_offset: offset,
+            utf16_offset: offset,
+        }
     }
 }
 
@@ -41,6 +65,13 @@ impl fmt::Display for Position {
     }
 }
 
+/// The part of the input stream a node or token was read from
+#[derive(PartialEq, Debug, Copy, Clone)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub enum Element {
     // Standard UTF character
@@ -101,13 +132,19 @@ pub struct InputStream {
     pub position: Position,
     /// Length (in chars) of the buffer
     pub length: usize,
-    /// Offsets of the given lines
-    line_offsets: Vec<usize>,
-    /// Reference to the actual buffer stream in characters
-    buffer: Vec<Element>,
+    /// What the columns of positions count
+    pub position_mode: PositionMode,
+    /// Positions of the starts of the lines that have been read so far
+    line_starts: Vec<Position>,
+    /// The decoded input with normalized newlines
+    buffer: String,
+    /// Byte offset in the buffer where the stream ends, see set_read_limit()
+    end: usize,
+    /// Byte offsets in the buffer of the newlines that were a CR or CRLF in the input, with
+    /// their original text
+    newline_sources: Vec<(usize, &'static str)>,
     /// Reference to the actual buffer stream in u8 bytes
     u8_buffer: Vec<u8>,
-    /// If all things are ok, both buffer and u8_buffer should refer to the same memory location (?)
     pub has_read_eof: bool, // True when we just read an EOF
 }
 
@@ -132,14 +169,13 @@ impl InputStream {
         InputStream {
             encoding: Encoding::UTF8,
             confidence: Confidence::Tentative,
-            position: Position {
-                offset: 0,
-                line: 1,
-                col: 1,
-            },
+            position: Position::new(0, 1, 1),
             length: 0,
-            line_offsets: vec![0], // first line always starts at 0
-            buffer: Vec::new(),
+            position_mode: PositionMode::Chars,
+            line_starts: vec![Position::new(0, 1, 1)], // first line always starts at 0
+            buffer: String::new(),
+            end: 0,
+            newline_sources: Vec::new(),
             u8_buffer: Vec::new(),
             has_read_eof: false,
         }
@@ -162,9 +198,12 @@ impl InputStream {
 
     /// Reset the stream reader back to the start
     pub fn reset(&mut self) {
-        self.position.offset = 0;
-        self.position.line = 1;
-        self.position.col = 1;
+        self.position = Position::new(0, 1, 1);
+    }
+
+    /// Sets what the columns of positions count. This must be set before reading.
+    pub fn set_position_mode(&mut self, mode: PositionMode) {
+        self.position_mode = mode;
     }
 
     /// Seek explicit offset in the stream (based on chars)
@@ -194,7 +233,20 @@ impl InputStream {
             }
         };
 
-        self.position = self.generate_position(abs_offset);
+        // Cap to length if we read past the end of the stream
+        let abs_offset = if abs_offset > self.length + 1 {
+            self.has_read_eof = true;
+            self.length
+        } else {
+            abs_offset.min(self.length)
+        };
+
+        while self.position.offset < abs_offset {
+            self.next_char();
+        }
+        while self.position.offset > abs_offset {
+            self.previous_char();
+        }
     }
 
     pub fn get_previous_position(&mut self) -> Position {
@@ -203,38 +255,72 @@ impl InputStream {
             return self.position;
         }
 
-        self.generate_position(self.position.offset - 1)
+        let c = self.buffer[..self.position.byte_offset].chars().next_back();
+        self.position_before(c.unwrap_or_default())
     }
 
-    /// Generate a new position structure for given offset
-    fn generate_position(&mut self, abs_offset: usize) -> Position {
-        let mut abs_offset = abs_offset;
-
-        // Cap to length if we read past the end of the stream
-        if abs_offset > self.length + 1 {
-            abs_offset = self.length;
-            self.has_read_eof = true;
+    // Returns the number of columns the char takes
+    fn columns(&self, c: char) -> usize {
+        match self.position_mode {
+            PositionMode::Chars => 1,
+            PositionMode::Utf16 => c.len_utf16(),
         }
+    }
 
-        // Detect lines (if needed)
-        self.read_line_endings_until(abs_offset);
+    // Returns the position before the given char, which is the char just before the current
+    // position
+    fn position_before(&self, c: char) -> Position {
+        let mut position = Position {
+            offset: self.position.offset - 1,
+            byte_offset: self.position.byte_offset - c.len_utf8(),
+            utf16_offset: self.position.utf16_offset - c.len_utf16(),
+            ..self.position
+        };
 
-        let mut last_line: usize = 0;
-        let mut last_offset = self.line_offsets[last_line];
-        for i in 0..self.line_offsets.len() {
-            if self.line_offsets[i] > abs_offset {
-                break;
-            }
+        if c == CHAR_LF {
+            position.line -= 1;
+            let line_start = self.line_starts[position.line - 1];
+            position.col = 1 + match self.position_mode {
+                PositionMode::Chars => position.offset - line_start.offset,
+                PositionMode::Utf16 => position.utf16_offset - line_start.utf16_offset,
+            };
+        } else {
+            position.col -= self.columns(c);
+        }
+        position
+    }
+
+    // Moves the position one char forward, keeping track of the line starts
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.buffer[self.position.byte_offset..self.end]
+            .chars()
+            .next()?;
+        self.position.offset += 1;
+        self.position.byte_offset += c.len_utf8();
+        self.position.utf16_offset += c.len_utf16();
+
+        if c == CHAR_LF {
+            self.start_line();
+        } else {
+            self.position.col += self.columns(c);
+        }
+        Some(c)
+    }
 
-            last_line = i;
-            last_offset = self.line_offsets[last_line];
+    // Moves the position to the start of the next line, after reading a line feed
+    fn start_line(&mut self) {
+        self.position.line += 1;
+        self.position.col = 1;
+        if self.line_starts.len() < self.position.line {
+            self.line_starts.push(self.position);
         }
+    }
 
-        // Set position values
-        Position {
-            offset: abs_offset,
-            line: last_line + 1,
-            col: abs_offset - last_offset + 1,
+    // Moves the position one char back
+    fn previous_char(&mut self) {
+        let c = self.buffer[..self.position.byte_offset].chars().next_back();
+        if let Some(c) = c {
+            self.position = self.position_before(c);
         }
     }
 
@@ -260,69 +346,26 @@ impl InputStream {
     /// Sets the encoding for this stream, and decodes the u8_buffer into the buffer with the
     /// correct encoding.
     pub fn force_set_encoding(&mut self, e: Encoding) {
-        match e {
-            Encoding::UTF8 => {
-                let str_buf;
-                unsafe {
-                    str_buf = std::str::from_utf8_unchecked(&self.u8_buffer)
-                        .replace("\u{000D}\u{000A}", "\u{000A}")
-                        .replace('\u{000D}', "\u{000A}");
-                }
-
-                // Convert the utf8 string into characters so we can use easy indexing
-                self.buffer = vec![];
-                for c in str_buf.chars() {
-                    // // Check if we have a non-bmp character. This means it's above 0x10000
-                    // let cp = c as u32;
-                    // if cp > 0x10000 && cp <= 0x10FFFF {
-                    //     let adjusted = cp - 0x10000;
-                    //     let lead = ((adjusted >> 10) & 0x3FF) as u16 + 0xD800;
-                    //     let trail = (adjusted & 0x3FF) as u16 + 0xDC00;
-                    //     self.buffer.push(Element::Surrogate(lead));
-                    //     self.buffer.push(Element::Surrogate(trail));
-                    //     continue;
-                    // }
-
-                    if (0xD800..=0xDFFF).contains(&(c as u32)) {
-                        self.buffer.push(Element::Surrogate(c as u16));
-                    } else {
-                        self.buffer.push(Element::Utf8(c));
-                    }
-                }
-                self.length = self.buffer.len();
-            }
-            Encoding::ASCII => {
-                // Convert the string into characters so we can use easy indexing. Any non-ascii chars (> 0x7F) are converted to '?'
-                self.buffer = self.normalize_newlines_and_ascii(&self.u8_buffer);
-                self.length = self.buffer.len();
-            }
-        }
+        (self.buffer, self.newline_sources) = match e {
+            // Invalid sequences are decoded as U+FFFD. Surrogates cannot occur in UTF-8.
+            Encoding::UTF8 => normalize_newlines(&String::from_utf8_lossy(&self.u8_buffer)),
+            // Any non-ascii chars (> 0x7F) are converted to '?'
+            Encoding::ASCII => normalize_newlines(
+                &self
+                    .u8_buffer
+                    .iter()
+                    .map(|&b| if b >= 0x80 { '?' } else { b as char })
+                    .collect::<String>(),
+            ),
+        };
+        self.end = self.buffer.len();
+        self.length = self.buffer.chars().count();
+        self.line_starts = vec![Position::new(0, 1, 1)];
+        self.reset();
 
         self.encoding = e;
     }
 
-    fn normalize_newlines_and_ascii(&self, buffer: &Vec<u8>) -> Vec<Element> {
-        let mut result = Vec::with_capacity(buffer.len());
-
-        for i in 0..buffer.len() {
-            if buffer[i] == CHAR_CR as u8 {
-                // convert CR to LF, or CRLF to LF
-                if i + 1 < buffer.len() && buffer[i + 1] == CHAR_LF as u8 {
-                    continue;
-                }
-                result.push(Element::Utf8(CHAR_LF));
-            } else if buffer[i] >= 0x80 {
-                // Convert high ascii to ?
-                result.push(Element::Utf8('?'));
-            } else {
-                // everything else is ok
-                result.push(Element::Utf8(buffer[i] as char))
-            }
-        }
-
-        result
-    }
-
     /// Populates the current buffer with the contents of given file f
     pub fn read_from_file(&mut self, mut f: File, e: Option<Encoding>) -> io::Result<()> {
         // First we read the u8 bytes into a buffer
@@ -339,6 +382,26 @@ impl InputStream {
         self.reset();
     }
 
+    /// Ends the stream after the given number of bytes of the (decoded) buffer, or at the char
+    /// boundary before it. The rest of the buffer is kept, and None reads up to the end of the
+    /// buffer again. Returns true when the buffer is longer than the limit.
+    pub(crate) fn set_read_limit(&mut self, max_len: Option<usize>) -> bool {
+        let mut end = max_len.map_or(self.buffer.len(), |max| max.min(self.buffer.len()));
+        while !self.buffer.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end != self.end {
+            self.end = end;
+            self.length = self.buffer[..end].chars().count();
+        }
+        end < self.buffer.len()
+    }
+
+    /// Returns the length in bytes of the (decoded) buffer, up to the read limit
+    pub(crate) fn byte_length(&self) -> usize {
+        self.end
+    }
+
     /// Returns the number of characters left in the buffer
     pub(crate) fn chars_left(&self) -> usize {
         self.length - self.position.offset
@@ -351,25 +414,14 @@ impl InputStream {
             return Element::Eof;
         }
 
-        // If we still can move forward in the stream, move forwards
-        if self.position.offset < self.length {
-            let c = self.buffer[self.position.offset];
-            self.seek(SeekMode::SeekCur, 1);
-            return c;
+        match self.next_char() {
+            Some(c) => Element::Utf8(c),
+            None => {
+                // otherwise, we have reached the end of the stream
+                self.has_read_eof = true;
+                Element::Eof
+            }
         }
-
-        // otherwise, we have reached the end of the stream
-        self.has_read_eof = true;
-
-        self.seek(SeekMode::SeekEnd, 0);
-
-        // // This is a kind of dummy position so the end of the files are read correctly.
-        // self.position = Position{
-        //     offset: self.position.offset,
-        //     line: self.position.line,
-        //     col: self.position.col,
-        // };
-        Element::Eof
     }
 
     pub(crate) fn unread(&mut self) {
@@ -380,49 +432,126 @@ impl InputStream {
         }
 
         // If we can track back from the offset, we can do so
-        if self.position.offset > 0 {
-            self.seek(SeekMode::SeekCur, -1);
+        self.previous_char();
+    }
+
+    /// Returns the part of the stream that has not been read yet
+    pub(crate) fn remaining(&self) -> &str {
+        if self.has_read_eof {
+            return "";
         }
+        &self.buffer[self.position.byte_offset..self.end]
+    }
+
+    /// Moves past the first len bytes of the remaining stream, which must end on a char
+    /// boundary. This is the fast path for reading runs of text.
+    pub(crate) fn skip_bytes(&mut self, len: usize) {
+        let start = self.position.byte_offset;
+        let end = start + len;
+
+        // Record the line starts within the run
+        let mut line_start = start;
+        while let Some(newline) = memchr::memchr(b'\n', &self.buffer.as_bytes()[line_start..end]) {
+            let next_line_start = line_start + newline + 1;
+            self.advance_over(line_start, next_line_start);
+            self.start_line();
+            line_start = next_line_start;
+        }
+
+        let col = self.position.col;
+        let (chars, utf16) = self.advance_over(line_start, end);
+        self.position.col = col
+            + match self.position_mode {
+                PositionMode::Chars => chars,
+                PositionMode::Utf16 => utf16,
+            };
+    }
+
+    // Advances the offsets over the given byte range of the buffer, and returns the number of
+    // chars and UTF-16 code units in it
+    fn advance_over(&mut self, start: usize, end: usize) -> (usize, usize) {
+        let bytes = &self.buffer.as_bytes()[start..end];
+        // Every char has one leading byte, and only chars of four bytes take two code units
+        let chars = bytes.iter().filter(|&&b| (b as i8) >= -0x40).count();
+        let utf16 = chars + bytes.iter().filter(|&&b| b >= 0xF0).count();
+
+        self.position.offset += chars;
+        self.position.byte_offset = end;
+        self.position.utf16_offset += utf16;
+        (chars, utf16)
+    }
+
+    /// Returns the input between the given byte offsets of the stream, with newlines as they were
+    /// before they were normalized
+    pub fn source(&self, start: usize, end: usize) -> Cow<'_, str> {
+        let text = &self.buffer[start..end];
+        let first = self
+            .newline_sources
+            .partition_point(|&(offset, _)| offset < start);
+        let last = self
+            .newline_sources
+            .partition_point(|&(offset, _)| offset < end);
+        if first == last {
+            return Cow::Borrowed(text);
+        }
+
+        let mut source = String::with_capacity(text.len() + last - first);
+        let mut copied = start;
+        for &(offset, newline) in &self.newline_sources[first..last] {
+            source.push_str(&self.buffer[copied..offset]);
+            source.push_str(newline);
+            copied = offset + 1;
+        }
+        source.push_str(&self.buffer[copied..end]);
+        Cow::Owned(source)
     }
 
     /// Looks ahead in the stream and returns len characters
     pub(crate) fn look_ahead_slice(&self, len: usize) -> String {
-        let end_pos = std::cmp::min(self.length, self.position.offset + len);
-
-        let slice = &self.buffer[self.position.offset..end_pos];
-        slice.iter().map(|e| e.to_string()).collect()
+        self.buffer[self.position.byte_offset..self.end]
+            .chars()
+            .take(len)
+            .collect()
     }
 
     /// Looks ahead in the stream, can use an optional index if we want to seek further
     /// (or back) in the stream.
     pub(crate) fn look_ahead(&self, offset: usize) -> Element {
-        // Trying to look after the stream
-        if self.position.offset + offset >= self.length {
-            return Element::Eof;
+        match self.buffer[self.position.byte_offset..self.end]
+            .chars()
+            .nth(offset)
+        {
+            Some(c) => Element::Utf8(c),
+            // Trying to look after the stream
+            None => Element::Eof,
         }
-
-        self.buffer[self.position.offset + offset]
     }
+}
 
-    /// Populates the line endings
-    fn read_line_endings_until(&mut self, abs_offset: usize) {
-        let mut last_offset = *self.line_offsets.last().unwrap();
-
-        while last_offset <= abs_offset {
-            if last_offset >= self.length {
-                self.line_offsets.push(last_offset + 1);
-                break;
-            }
-
-            // Check the next char to see if it's a '\n'
-            let c = self.buffer[last_offset];
-            if c == Element::Utf8('\n') {
-                self.line_offsets.push(last_offset + 1);
-            }
-
-            last_offset += 1;
-        }
+// Converts CR and CRLF to LF. Also returns the byte offsets of the converted newlines in the
+// result, with their original text.
+fn normalize_newlines(s: &str) -> (String, Vec<(usize, &'static str)>) {
+    if memchr::memchr(CHAR_CR as u8, s.as_bytes()).is_none() {
+        return (s.to_string(), Vec::new());
+    }
+
+    let mut normalized = String::with_capacity(s.len());
+    let mut newline_sources = Vec::new();
+    let mut rest = s;
+    while let Some(cr) = memchr::memchr(CHAR_CR as u8, rest.as_bytes()) {
+        normalized.push_str(&rest[..cr]);
+        let newline = if rest[cr + 1..].starts_with(CHAR_LF) {
+            "\r\n"
+        } else {
+            "\r"
+        };
+        newline_sources.push((normalized.len(), newline));
+        normalized.push(CHAR_LF);
+        rest = &rest[cr + newline.len()..];
     }
+    normalized.push_str(rest);
+
+    (normalized, newline_sources)
 }
 
 #[cfg(test)]
@@ -508,59 +637,17 @@ mod test {
     fn test_offsets() {
         let mut is = InputStream::new();
         is.read_from_str("abc", Some(Encoding::UTF8));
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 0,
-                line: 1,
-                col: 1
-            }
-        );
+        assert_eq!(is.position, Position::new(0, 1, 1));
         assert_eq!('a', is.read_char().utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 1,
-                line: 1,
-                col: 2
-            }
-        );
+        assert_eq!(is.position, Position::new(1, 1, 2));
         assert_eq!('b', is.read_char().utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 2,
-                line: 1,
-                col: 3
-            }
-        );
+        assert_eq!(is.position, Position::new(2, 1, 3));
         assert_eq!('c', is.read_char().utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 3,
-                line: 1,
-                col: 4
-            }
-        );
+        assert_eq!(is.position, Position::new(3, 1, 4));
         assert!(is.read_char().is_eof());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 3,
-                line: 1,
-                col: 4
-            }
-        );
+        assert_eq!(is.position, Position::new(3, 1, 4));
         assert!(is.read_char().is_eof());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 3,
-                line: 1,
-                col: 4
-            }
-        );
+        assert_eq!(is.position, Position::new(3, 1, 4));
 
         let mut is = InputStream::new();
         is.read_from_str(
@@ -570,101 +657,38 @@ mod test {
         assert_eq!(is.length, 40);
 
         is.seek(SeekMode::SeekSet, 0);
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 0,
-                line: 1,
-                col: 1
-            }
-        );
+        assert_eq!(is.position, Position::new(0, 1, 1));
         let c = is.read_char();
         assert_eq!('a', c.utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 1,
-                line: 1,
-                col: 2
-            }
-        );
+        assert_eq!(is.position, Position::new(1, 1, 2));
 
         is.seek(SeekMode::SeekSet, 7);
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 7,
-                line: 2,
-                col: 4
-            }
-        );
+        assert_eq!(is.position, Position::new(7, 2, 4));
         assert_eq!(is.chars_left(), 33);
 
         let c = is.read_char();
         assert_eq!('g', c.utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 8,
-                line: 2,
-                col: 5
-            }
-        );
+        assert_eq!(is.position, Position::new(8, 2, 5));
 
         let c = is.read_char();
         assert_eq!('\n', c.utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 9,
-                line: 3,
-                col: 1
-            }
-        );
+        assert_eq!(is.position, Position::new(9, 3, 1));
 
         let c = is.read_char();
         assert_eq!('\n', c.utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 10,
-                line: 4,
-                col: 1
-            }
-        );
+        assert_eq!(is.position, Position::new(10, 4, 1));
 
         let c = is.read_char();
         assert_eq!('h', c.utf8());
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 11,
-                line: 4,
-                col: 2
-            }
-        );
+        assert_eq!(is.position, Position::new(11, 4, 2));
         assert_eq!(is.chars_left(), 29);
 
         is.reset();
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 0,
-                line: 1,
-                col: 1
-            }
-        );
+        assert_eq!(is.position, Position::new(0, 1, 1));
         assert_eq!(is.chars_left(), 40);
 
         is.seek(SeekMode::SeekSet, 100);
-        assert_eq!(
-            is.position,
-            Position {
-                offset: 40,
-                line: 15,
-                col: 2
-            }
-        );
+        assert_eq!(is.position, Position::new(40, 15, 2));
         assert_eq!(is.chars_left(), 0);
     }
 
@@ -784,4 +808,109 @@ mod test {
         is.unread();
         assert!(is.read_char().is_eof());
     }
+
+    fn position(
+        offset: usize,
+        line: usize,
+        col: usize,
+        byte_offset: usize,
+        utf16_offset: usize,
+    ) -> Position {
+        Position {
+            offset,
+            line,
+            col,
+            byte_offset,
+            utf16_offset,
+        }
+    }
+
+    #[test]
+    fn test_skip_bytes() {
+        let mut is = InputStream::new();
+        is.read_from_str("ab\r\nc\u{e9}\rd<e", Some(Encoding::UTF8));
+        assert_eq!(is.remaining(), "ab\nc\u{e9}\nd<e");
+
+        is.skip_bytes(is.remaining().find('<').unwrap());
+        assert_eq!(is.position, position(7, 3, 2, 8, 7));
+        assert_eq!(is.remaining(), "<e");
+        assert_eq!(is.get_previous_position(), position(6, 3, 1, 7, 6));
+
+        // Unreading across the skipped line ends uses the line starts found while skipping
+        is.unread();
+        is.unread();
+        assert_eq!(is.position, position(5, 2, 3, 6, 5));
+        assert_eq!(is.read_char().utf8(), '\n');
+        assert_eq!(is.position, position(6, 3, 1, 7, 6));
+
+        is.seek(SeekMode::SeekEnd, 0);
+        assert!(is.read_char().is_eof());
+        assert_eq!(is.remaining(), "");
+    }
+
+    #[test]
+    fn test_source() {
+        let mut is = InputStream::new();
+        is.read_from_str("a\r\nb\rc\n\r\r\nd", Some(Encoding::UTF8));
+        assert_eq!(is.remaining(), "a\nb\nc\n\n\nd");
+
+        let len = is.remaining().len();
+        assert_eq!(is.source(0, len), "a\r\nb\rc\n\r\r\nd");
+        assert_eq!(is.source(0, 1), "a");
+        assert_eq!(is.source(1, 2), "\r\n");
+        assert_eq!(is.source(2, 5), "b\rc");
+        assert_eq!(is.source(5, 8), "\n\r\r\n");
+        assert!(matches!(is.source(5, 6), Cow::Borrowed("\n")));
+    }
+
+    #[test]
+    fn test_read_limit() {
+        let mut is = InputStream::new();
+        is.read_from_str("a\r\nb\u{1F47D}c", Some(Encoding::UTF8));
+        assert!(!is.set_read_limit(Some(100)));
+        assert!(is.set_read_limit(Some(5)));
+        assert_eq!(is.remaining(), "a\nb");
+        assert_eq!(is.length, 3);
+        assert_eq!(is.source(0, 3), "a\r\nb");
+        assert_eq!(is.look_ahead(3), Element::Eof);
+
+        is.seek(SeekMode::SeekEnd, 0);
+        assert!(is.eof());
+        assert_eq!(is.read_char(), Element::Eof);
+
+        // Lifting the limit continues the stream
+        assert!(!is.set_read_limit(None));
+        is.unread();
+        assert_eq!(is.remaining(), "\u{1F47D}c");
+        assert_eq!(is.length, 5);
+    }
+
+    #[test]
+    fn test_utf16_positions() {
+        let mut is = InputStream::new();
+        is.set_position_mode(PositionMode::Utf16);
+        is.read_from_str("a\u{1F47D}b\n\u{1F47D}\n", Some(Encoding::UTF8));
+
+        is.read_char();
+        is.read_char();
+        assert_eq!(is.position, position(2, 1, 4, 5, 3));
+        is.read_char();
+        is.read_char();
+        assert_eq!(is.position, position(4, 2, 1, 7, 5));
+        is.skip_bytes(5);
+        assert_eq!(is.position, position(6, 3, 1, 12, 8));
+
+        // The column at the end of a line counts the code units of the line
+        is.unread();
+        assert_eq!(is.position, position(5, 2, 3, 11, 7));
+        is.seek(SeekMode::SeekSet, 3);
+        assert_eq!(is.position, position(3, 1, 5, 6, 4));
+
+        // In char mode, the non-BMP char takes one column
+        is.set_position_mode(PositionMode::Chars);
+        is.reset();
+        is.read_char();
+        is.read_char();
+        assert_eq!(is.position, position(2, 1, 3, 5, 3));
+    }
 }
@@ -1,6 +1,14 @@
+pub mod highlighter;
 pub mod node;
 pub mod parser;
+pub mod rewriter;
+pub mod selector;
+pub mod serializer;
 pub mod tokenizer;
+pub mod xml_parser;
+pub mod xpath;
+
+pub mod document_format;
 
 pub mod dom;
 
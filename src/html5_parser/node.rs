@@ -11,6 +11,8 @@ pub const XMLNS_NAMESPACE: &str = "http://www.w3.org/2000/xmlns/";
 #[derive(Debug, PartialEq)]
 pub enum NodeType {
     Document,
+    DocumentFragment,
+    DocumentType,
     Text,
     Comment,
     Element,
@@ -20,6 +22,12 @@ pub enum NodeType {
 #[derive(Debug, PartialEq, Clone)]
 pub enum NodeData {
     Document,
+    DocumentFragment,
+    DocType {
+        name: String,
+        public_id: Option<String>,
+        system_id: Option<String>,
+    },
     Text {
         value: String,
     },
@@ -44,6 +52,8 @@ pub struct Node {
     pub name: String,
     /// namespace of the node
     pub namespace: Option<String>,
+    /// namespace prefix the element was created with, if any
+    pub prefix: Option<String>,
     /// actual data of the node
     pub data: NodeData,
 }
@@ -56,6 +66,7 @@ impl Clone for Node {
             children: self.children.clone(),
             name: self.name.clone(),
             namespace: self.namespace.clone(),
+            prefix: self.prefix.clone(),
             data: self.data.clone(),
         }
     }
@@ -71,6 +82,37 @@ impl Node {
             data: NodeData::Document {},
             name: "".to_string(),
             namespace: None,
+            prefix: None,
+        }
+    }
+
+    /// Create a new document fragment node, used for template contents and shadow roots
+    pub fn new_document_fragment() -> Self {
+        Node {
+            id: 0,
+            parent: None,
+            children: vec![],
+            data: NodeData::DocumentFragment,
+            name: "".to_string(),
+            namespace: None,
+            prefix: None,
+        }
+    }
+
+    /// Create a new doctype node
+    pub fn new_doctype(name: &str, public_id: Option<&str>, system_id: Option<&str>) -> Self {
+        Node {
+            id: 0,
+            parent: None,
+            children: vec![],
+            data: NodeData::DocType {
+                name: name.to_string(),
+                public_id: public_id.map(String::from),
+                system_id: system_id.map(String::from),
+            },
+            name: "".to_string(),
+            namespace: None,
+            prefix: None,
         }
     }
 
@@ -86,6 +128,7 @@ impl Node {
             },
             name: name.to_string(),
             namespace: Some(namespace.into()),
+            prefix: None,
         }
     }
 
@@ -100,6 +143,7 @@ impl Node {
             },
             name: "".to_string(),
             namespace: None,
+            prefix: None,
         }
     }
 
@@ -114,6 +158,21 @@ impl Node {
             },
             name: "".to_string(),
             namespace: None,
+            prefix: None,
+        }
+    }
+
+    /// Returns true when the node is an element node
+    pub fn is_element(&self) -> bool {
+        matches!(self.data, NodeData::Element { .. })
+    }
+
+    /// Returns the value of the given attribute, or None when the node is not an element or
+    /// does not have the attribute
+    pub fn get_attribute(&self, name: &str) -> Option<&String> {
+        match &self.data {
+            NodeData::Element { attributes, .. } => attributes.get(name),
+            _ => None,
         }
     }
 
@@ -148,7 +207,9 @@ pub trait NodeTrait {
 impl NodeTrait for Node {
     fn type_of(&self) -> NodeType {
         match self.data {
-            NodeData::Document { .. } => NodeType::Document,
+            NodeData::Document => NodeType::Document,
+            NodeData::DocumentFragment => NodeType::DocumentFragment,
+            NodeData::DocType { .. } => NodeType::DocumentType,
             NodeData::Text { .. } => NodeType::Text,
             NodeData::Comment { .. } => NodeType::Comment,
             NodeData::Element { .. } => NodeType::Element,
@@ -253,7 +314,7 @@ mod test {
         let node = Node::new_document();
         assert_eq!(node.id, 0);
         assert_eq!(node.parent, None);
-        assert_eq!(node.children, vec![]);
+        assert_eq!(node.children, Vec::<usize>::new());
         assert_eq!(node.name, "".to_string());
         assert_eq!(node.namespace, None);
         assert_eq!(node.data, NodeData::Document {});
@@ -266,7 +327,7 @@ mod test {
         let node = Node::new_element("div", attributes.clone(), HTML_NAMESPACE);
         assert_eq!(node.id, 0);
         assert_eq!(node.parent, None);
-        assert_eq!(node.children, vec![]);
+        assert_eq!(node.children, Vec::<usize>::new());
         assert_eq!(node.name, "div".to_string());
         assert_eq!(node.namespace, Some(HTML_NAMESPACE.into()));
         assert_eq!(
@@ -283,7 +344,7 @@ mod test {
         let node = Node::new_comment("test");
         assert_eq!(node.id, 0);
         assert_eq!(node.parent, None);
-        assert_eq!(node.children, vec![]);
+        assert_eq!(node.children, Vec::<usize>::new());
         assert_eq!(node.name, "".to_string());
         assert_eq!(node.namespace, None);
         assert_eq!(
@@ -299,7 +360,7 @@ mod test {
         let node = Node::new_text("test");
         assert_eq!(node.id, 0);
         assert_eq!(node.parent, None);
-        assert_eq!(node.children, vec![]);
+        assert_eq!(node.children, Vec::<usize>::new());
         assert_eq!(node.name, "".to_string());
         assert_eq!(node.namespace, None);
         assert_eq!(
@@ -310,6 +371,28 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_is_element() {
+        let node = Node::new_element("div", HashMap::new(), HTML_NAMESPACE);
+        assert!(node.is_element());
+        let node = Node::new_text("test");
+        assert!(!node.is_element());
+        let node = Node::new_document();
+        assert!(!node.is_element());
+    }
+
+    #[test]
+    fn test_get_attribute() {
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "test".to_string());
+        let node = Node::new_element("div", attributes, HTML_NAMESPACE);
+        assert_eq!(node.get_attribute("id"), Some(&"test".to_string()));
+        assert_eq!(node.get_attribute("class"), None);
+
+        let node = Node::new_comment("id");
+        assert_eq!(node.get_attribute("id"), None);
+    }
+
     #[test]
     fn test_is_special() {
         let mut attributes = HashMap::new();
@@ -322,6 +405,10 @@ mod test {
     fn test_type_of() {
         let node = Node::new_document();
         assert_eq!(node.type_of(), NodeType::Document);
+        let node = Node::new_document_fragment();
+        assert_eq!(node.type_of(), NodeType::DocumentFragment);
+        let node = Node::new_doctype("html", None, None);
+        assert_eq!(node.type_of(), NodeType::DocumentType);
         let node = Node::new_text("test");
         assert_eq!(node.type_of(), NodeType::Text);
         let node = Node::new_comment("test");
@@ -366,6 +453,8 @@ mod test {
     fn test_type_of_node() {
         let node = Node::new_document();
         assert_eq!(node.type_of(), NodeType::Document);
+        let node = Node::new_document_fragment();
+        assert_eq!(node.type_of(), NodeType::DocumentFragment);
         let node = Node::new_text("test");
         assert_eq!(node.type_of(), NodeType::Text);
         let node = Node::new_comment("test");
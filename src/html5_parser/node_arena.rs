@@ -35,6 +35,46 @@ impl NodeArena {
         id
     }
 
+    /// Create an arena with the given nodes, which keep their ids, parents and children. Used
+    /// when restoring an arena. The next id is raised above the ids of the nodes when needed.
+    pub fn from_nodes(nodes: Vec<Node>, next_id: usize) -> Self {
+        let next_id = nodes
+            .iter()
+            .map(|node| node.id + 1)
+            .fold(next_id, usize::max);
+        Self {
+            nodes: nodes.into_iter().map(|node| (node.id, node)).collect(),
+            next_id,
+        }
+    }
+
+    /// Returns the ids of all nodes in the arena, in ascending order
+    pub fn node_ids(&self) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.nodes.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Returns all nodes in the arena, in no particular order
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.values()
+    }
+
+    /// Returns the number of nodes in the arena
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the id the next added node will get
+    pub fn next_id(&self) -> usize {
+        self.next_id
+    }
+
+    /// Sets the id the next added node will get. Ids of existing nodes are never reused.
+    pub fn set_next_id(&mut self, next_id: usize) {
+        self.next_id = self.next_id.max(next_id);
+    }
+
     /// Add the node as a child the parent node
     pub fn attach_node(&mut self, parent_id: usize, node_id: usize) {
         //check if any children of node have parent as child
@@ -50,19 +90,23 @@ impl NodeArena {
     }
 
     /// Removes the node with the given id from the arena
-    fn remove_node(&mut self, node_id: usize) {
-        // Remove children
-        if let Some(node) = self.nodes.get_mut(&node_id) {
-            for child_id in node.children.clone() {
-                self.remove_node(child_id);
+    pub fn remove_node(&mut self, node_id: usize) {
+        let node = match self.nodes.remove(&node_id) {
+            Some(node) => node,
+            None => return,
+        };
+        if let Some(parent_id) = node.parent {
+            if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
+                parent_node.children.retain(|&id| id != node_id);
             }
         }
 
-        if let Some(node) = self.nodes.remove(&node_id) {
-            if let Some(parent_id) = node.parent {
-                if let Some(parent_node) = self.nodes.get_mut(&parent_id) {
-                    parent_node.children.retain(|&id| id != node_id);
-                }
+        // Remove descendants. Their parents are removed as well, so there is no need to detach
+        // them one by one.
+        let mut stack = node.children;
+        while let Some(child_id) = stack.pop() {
+            if let Some(child) = self.nodes.remove(&child_id) {
+                stack.extend(child.children);
             }
         }
     }
@@ -9,9 +9,10 @@ const ADOPTION_AGENCY_INNER_LOOP_DEPTH: usize = 3;
 impl<'a> Html5Parser<'a> {
     pub fn run_adoption_agency(&mut self, token: &Token) {
         // Step 1
+        // The nobr start tag runs the algorithm for the nobr element that is still open
         let subject = match token {
-            Token::EndTagToken { name, .. } => name,
-            _ => panic!("run adoption agency called with non end tag token"),
+            Token::StartTagToken { name, .. } | Token::EndTagToken { name, .. } => name,
+            _ => panic!("run adoption agency called with non tag token"),
         };
 
         // Step 2
@@ -1,24 +1,54 @@
-use crate::html5_parser::node::{Node, NodeData};
+use crate::html5_parser::dom::collection::{CollectionFilter, HtmlCollection};
+use crate::html5_parser::dom::ShadowRoot;
+use crate::html5_parser::input_stream::Span;
+use crate::html5_parser::node::{Node, NodeData, HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE};
 use crate::html5_parser::node_arena::NodeArena;
+use crate::html5_parser::parser::document_index::DocumentIndex;
 use crate::html5_parser::parser::quirks::QuirksMode;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Source of document versions, shared by all documents so that a version never belongs to more
+// than one document. A collection can then be used with any document without returning the
+// cached elements of another one.
+static NEXT_VERSION: AtomicUsize = AtomicUsize::new(0);
+
+fn next_version() -> usize {
+    NEXT_VERSION.fetch_add(1, Ordering::Relaxed)
+}
 
 #[derive(PartialEq, Debug, Copy, Clone)]
 pub enum DocumentType {
     HTML,
     IframeSrcDoc,
+    XML,
 }
 
 pub struct Document {
     arena: NodeArena,
-    pub doctype: DocumentType,   // Document type
-    pub quirks_mode: QuirksMode, // Quirks mode
+    index: DocumentIndex, // Id, tag and class indexes of all elements
+    version: usize,       // Changes on every mutation, used by live collections
+    pub(crate) template_contents: HashMap<usize, usize>, // Template element id -> contents fragment id
+    pub(crate) shadow_roots: HashMap<usize, ShadowRoot>, // Shadow root node id -> shadow root
+    pub(crate) shadow_hosts: HashMap<usize, usize>,      // Host element id -> shadow root node id
+    pub(crate) manual_slot_assignments: HashMap<usize, Vec<usize>>, // Slot id -> manually assigned nodes
+    pub(crate) node_spans: HashMap<usize, Span>, // Node id -> source span, when known
+    pub doctype: DocumentType,                   // Document type
+    pub quirks_mode: QuirksMode,                 // Quirks mode
 }
 
 impl Default for Document {
     fn default() -> Self {
         Self {
             arena: NodeArena::new(),
+            index: DocumentIndex::default(),
+            version: next_version(),
+            template_contents: HashMap::new(),
+            shadow_roots: HashMap::new(),
+            shadow_hosts: HashMap::new(),
+            manual_slot_assignments: HashMap::new(),
+            node_spans: HashMap::new(),
             doctype: DocumentType::HTML,
             quirks_mode: QuirksMode::NoQuirks,
         }
@@ -32,6 +62,13 @@ impl Document {
         arena.add_node(Node::new_document());
         Self {
             arena,
+            index: DocumentIndex::default(),
+            version: next_version(),
+            template_contents: HashMap::new(),
+            shadow_roots: HashMap::new(),
+            shadow_hosts: HashMap::new(),
+            manual_slot_assignments: HashMap::new(),
+            node_spans: HashMap::new(),
             doctype: DocumentType::HTML,
             quirks_mode: QuirksMode::NoQuirks,
         }
@@ -42,19 +79,187 @@ impl Document {
         self.arena.get_node(node_id)
     }
 
+    // Fetches a mutable node by id. Note that changes to the id or class attributes made through
+    // this node are not seen by the element indexes; use set_attribute() for those.
+    #[deprecated(
+        note = "attribute changes bypass the element indexes, use set_attribute() or get_mut_text_by_id()"
+    )]
     pub fn get_mut_node_by_id(&mut self, node_id: usize) -> Option<&mut Node> {
+        self.version = next_version();
         self.arena.get_mut_node(node_id)
     }
 
+    // Fetches the mutable value of a text or comment node. Unlike get_mut_node_by_id(), this
+    // cannot change attributes behind the indexes' back.
+    pub fn get_mut_text_by_id(&mut self, node_id: usize) -> Option<&mut String> {
+        match self.arena.get_mut_node(node_id).map(|node| &mut node.data) {
+            Some(NodeData::Text { value }) | Some(NodeData::Comment { value }) => {
+                self.version = next_version();
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
     // Add to the document
     pub fn add_node(&mut self, node: Node, parent_id: usize) -> usize {
         let node_id = self.arena.add_node(node);
         self.arena.attach_node(parent_id, node_id);
+        if let Some(node) = self.arena.get_node(node_id) {
+            self.index.add_element(node);
+        }
+        self.version = next_version();
+        node_id
+    }
+
+    // Adds a node to the document without attaching it to a parent. Used for the roots of
+    // template contents and shadow trees, and for nodes that are inserted later on.
+    pub(crate) fn add_detached_node(&mut self, node: Node) -> usize {
+        let node_id = self.arena.add_node(node);
+        if let Some(node) = self.arena.get_node(node_id) {
+            self.index.add_element(node);
+        }
+        self.version = next_version();
         node_id
     }
 
+    // Replaces all nodes of the document by the given nodes, which keep the ids, parents and
+    // children they already have. Used when restoring a document from a snapshot, so the nodes
+    // are added at once and the indexes are built in a single pass.
+    pub(crate) fn restore_nodes(&mut self, nodes: Vec<Node>, next_id: usize) {
+        self.arena = NodeArena::from_nodes(nodes, next_id);
+        self.index = DocumentIndex::default();
+        for node in self.arena.nodes() {
+            self.index.add_element(node);
+        }
+        self.version = next_version();
+    }
+
+    // Returns the ids of all nodes in the document, including detached nodes, in ascending order
+    pub(crate) fn node_ids(&self) -> Vec<usize> {
+        self.arena.node_ids()
+    }
+
+    // Returns the number of nodes in the document, including detached nodes
+    pub fn node_count(&self) -> usize {
+        self.arena.node_count()
+    }
+
+    // Returns the id the next added node will get
+    pub(crate) fn next_node_id(&self) -> usize {
+        self.arena.next_id()
+    }
+
+    // Sets the id the next added node will get, see NodeArena::set_next_id()
+    pub(crate) fn set_next_node_id(&mut self, next_id: usize) {
+        self.arena.set_next_id(next_id);
+    }
+
     pub fn append(&mut self, node_id: usize, parent_id: usize) {
         self.arena.attach_node(parent_id, node_id);
+        self.version = next_version();
+    }
+
+    // Moves the given children of the parent to the given position in its list of children.
+    // Ids that are not children of the parent are ignored.
+    pub(crate) fn move_children(&mut self, parent_id: usize, node_ids: &[usize], position: usize) {
+        if let Some(parent) = self.arena.get_mut_node(parent_id) {
+            let moved: Vec<usize> = node_ids
+                .iter()
+                .copied()
+                .filter(|id| parent.children.contains(id))
+                .collect();
+            parent.children.retain(|id| !moved.contains(id));
+            let position = position.min(parent.children.len());
+            parent.children.splice(position..position, moved);
+            self.version = next_version();
+        }
+    }
+
+    /// Removes the node and all its descendants from the document. Shadow trees and template
+    /// contents of the removed elements are removed as well. The root node cannot be removed.
+    pub fn remove_node(&mut self, node_id: usize) {
+        if node_id == 0 {
+            return;
+        }
+
+        let mut roots = vec![node_id];
+        while let Some(root_id) = roots.pop() {
+            let mut ids = vec![root_id];
+            ids.extend(self.descendants(root_id));
+            for id in ids {
+                if let Some(node) = self.arena.get_node(id) {
+                    self.index.remove_element(node);
+                }
+                if let Some(shadow_root_id) = self.shadow_hosts.remove(&id) {
+                    self.shadow_roots.remove(&shadow_root_id);
+                    // The declarative template that created the root no longer owns contents
+                    self.template_contents
+                        .retain(|_, &mut contents_id| contents_id != shadow_root_id);
+                    roots.push(shadow_root_id);
+                }
+                // A declarative shadow root is owned by its host, not by the template
+                if let Some(contents_id) = self.template_contents.remove(&id) {
+                    if !self.shadow_roots.contains_key(&contents_id) {
+                        roots.push(contents_id);
+                    }
+                }
+                self.manual_slot_assignments.remove(&id);
+                self.node_spans.remove(&id);
+                for assigned in self.manual_slot_assignments.values_mut() {
+                    assigned.retain(|&assigned_id| assigned_id != id);
+                }
+            }
+
+            self.arena.remove_node(root_id);
+        }
+
+        self.version = next_version();
+    }
+
+    /// Sets the attribute on the given element, keeping the element indexes up to date. Does
+    /// nothing when the node is not an element.
+    pub fn set_attribute(&mut self, node_id: usize, name: &str, value: &str) {
+        let old_value = match self.arena.get_mut_node(node_id).map(|node| &mut node.data) {
+            Some(NodeData::Element { attributes, .. }) => {
+                attributes.insert(name.to_string(), value.to_string())
+            }
+            _ => return,
+        };
+
+        self.index
+            .update_attribute(node_id, name, old_value.as_deref(), Some(value));
+        self.version = next_version();
+    }
+
+    /// Removes the attribute from the given element, keeping the element indexes up to date.
+    pub fn remove_attribute(&mut self, node_id: usize, name: &str) {
+        let old_value = match self.arena.get_mut_node(node_id).map(|node| &mut node.data) {
+            Some(NodeData::Element { attributes, .. }) => attributes.remove(name),
+            _ => return,
+        };
+
+        if old_value.is_some() {
+            self.index
+                .update_attribute(node_id, name, old_value.as_deref(), None);
+            self.version = next_version();
+        }
+    }
+
+    /// Returns the part of the source the node was read from, if known
+    pub fn node_span(&self, node_id: usize) -> Option<Span> {
+        self.node_spans.get(&node_id).copied()
+    }
+
+    /// Sets the part of the source the node was read from
+    pub fn set_node_span(&mut self, node_id: usize, span: Span) {
+        self.node_spans.insert(node_id, span);
+    }
+
+    // Returns the current version of the document. The version changes whenever the document
+    // is mutated, and is never the same for two documents.
+    pub(crate) fn version(&self) -> usize {
+        self.version
     }
 
     // // append a node to another parent
@@ -66,6 +271,120 @@ impl Document {
     pub fn get_root(&self) -> &Node {
         self.arena.get_node(0).expect("Root node not found !?")
     }
+
+    /// Returns the ids of all descendants of the given node in tree order. The node itself is
+    /// not included.
+    pub fn descendants(&self, node_id: usize) -> Vec<usize> {
+        let mut result = Vec::new();
+
+        let mut stack: Vec<usize> = match self.arena.get_node(node_id) {
+            Some(node) => node.children.iter().rev().copied().collect(),
+            None => return result,
+        };
+
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.arena.get_node(id) {
+                result.push(id);
+                stack.extend(node.children.iter().rev());
+            }
+        }
+
+        result
+    }
+
+    /// Returns the first element in tree order with the given id, or None when no such
+    /// element is connected to the document.
+    pub fn get_element_by_id(&self, element_id: &str) -> Option<usize> {
+        let candidates = self.index.elements_with_id(element_id);
+        self.sort_tree_order(candidates).first().copied()
+    }
+
+    /// Returns a live collection of all elements with the given qualified name. The name "*"
+    /// matches all elements.
+    pub fn get_elements_by_tag_name(&self, name: &str) -> HtmlCollection {
+        HtmlCollection::new(CollectionFilter::TagName(name.to_string()))
+    }
+
+    /// Returns a live collection of all elements that have all of the given (space separated)
+    /// class names.
+    pub fn get_elements_by_class_name(&self, names: &str) -> HtmlCollection {
+        HtmlCollection::new(CollectionFilter::ClassNames(
+            names.split_ascii_whitespace().map(String::from).collect(),
+        ))
+    }
+
+    // Returns all elements matching the collection filter in tree order
+    pub(crate) fn collect_elements(&self, filter: &CollectionFilter) -> Vec<usize> {
+        let candidates = match filter {
+            CollectionFilter::TagName(name) if name == "*" => self.index.all_elements(),
+            CollectionFilter::TagName(name) => {
+                let lower = name.to_ascii_lowercase();
+                let mut candidates = self.index.elements_with_tag(name);
+                if lower != *name {
+                    candidates.extend(self.index.elements_with_tag(&lower));
+                }
+                candidates.retain(|&id| match self.arena.get_node(id) {
+                    // HTML elements are matched against the lowercased name, others exactly
+                    Some(node) if node.namespace.as_deref() == Some(HTML_NAMESPACE) => {
+                        node.name == lower
+                    }
+                    Some(node) => node.name == *name,
+                    None => false,
+                });
+                candidates
+            }
+            CollectionFilter::ClassNames(names) => {
+                let quirks = self.quirks_mode == QuirksMode::Quirks;
+                let mut candidates = match names.first() {
+                    Some(first) if quirks => self.index.elements_with_class_ignore_case(first),
+                    Some(first) => self.index.elements_with_class(first),
+                    None => return Vec::new(),
+                };
+                candidates.retain(|&id| {
+                    let classes = match self
+                        .arena
+                        .get_node(id)
+                        .and_then(|n| n.get_attribute("class"))
+                    {
+                        Some(classes) => classes,
+                        None => return false,
+                    };
+                    names.iter().all(|name| {
+                        classes.split_ascii_whitespace().any(|class| {
+                            if quirks {
+                                class.eq_ignore_ascii_case(name)
+                            } else {
+                                class == name
+                            }
+                        })
+                    })
+                });
+                candidates
+            }
+        };
+
+        self.sort_tree_order(candidates)
+    }
+
+    // Sorts the given nodes in tree order and removes nodes that are not connected to the
+    // document root. Multiple nodes are ordered with a single walk over the tree, so building
+    // a collection stays linear in the size of the document.
+    fn sort_tree_order(&self, node_ids: Vec<usize>) -> Vec<usize> {
+        match node_ids.len() {
+            0 => Vec::new(),
+            1 if self.get_node_by_id(node_ids[0]).is_some() && self.root_node(node_ids[0]) == 0 => {
+                node_ids
+            }
+            1 => Vec::new(),
+            _ => {
+                let wanted: HashSet<usize> = node_ids.into_iter().collect();
+                self.descendants(0)
+                    .into_iter()
+                    .filter(|id| wanted.contains(id))
+                    .collect()
+            }
+        }
+    }
 }
 
 impl Document {
@@ -79,6 +398,13 @@ impl Document {
             NodeData::Document => {
                 writeln!(f, "{}Document", prefix)?;
             }
+            NodeData::DocumentFragment => match self.shadow_roots.get(&node.id) {
+                Some(shadow_root) => writeln!(f, "{}#shadow-root ({})", prefix, shadow_root.mode)?,
+                None => writeln!(f, "{}#document-fragment", prefix)?,
+            },
+            NodeData::DocType { name, .. } => {
+                writeln!(f, "{}<!DOCTYPE {}>", prefix, name)?;
+            }
             NodeData::Text { value } => {
                 writeln!(f, "{}{}", prefix, value)?;
             }
@@ -94,7 +420,13 @@ impl Document {
             }
         }
 
-        for child_id in &node.children {
+        // The shadow root is shown before the light tree children of its host, and template
+        // contents take the place of the (normally absent) template children.
+        let extra_root = self
+            .shadow_hosts
+            .get(&node.id)
+            .or_else(|| self.template_contents.get(&node.id));
+        for child_id in extra_root.iter().copied().chain(&node.children) {
             if let Some(child) = self.arena.get_node(*child_id) {
                 self.display_tree(child, indent + 2, f)?;
             }
@@ -104,6 +436,111 @@ impl Document {
     }
 }
 
+// Prefixes of attributes on foreign elements that are written as "prefix name" in the html5lib
+// tree format
+const FOREIGN_ATTRIBUTE_PREFIXES: [&str; 3] = ["xlink", "xml", "xmlns"];
+
+impl Document {
+    /// Dumps the document in the tree format of the html5lib tree construction tests (see
+    /// tests/data/html5lib-tests/tree-construction/README.md). Each line ends with a newline.
+    pub fn to_html5lib_tree(&self) -> String {
+        self.to_html5lib_tree_from(0)
+    }
+
+    /// Dumps the children of the given node in the html5lib tree format. This is used for
+    /// fragments, where the children of the root element are the parsed nodes.
+    pub fn to_html5lib_tree_from(&self, node_id: usize) -> String {
+        let mut out = String::new();
+
+        // Nodes still to dump, with their depth. An explicit stack instead of recursion keeps
+        // deeply nested documents from overflowing the call stack.
+        let mut stack: Vec<(usize, usize)> = match self.get_node_by_id(node_id) {
+            Some(node) => node.children.iter().rev().map(|&id| (id, 0)).collect(),
+            None => Vec::new(),
+        };
+        while let Some((id, depth)) = stack.pop() {
+            self.html5lib_tree(id, depth, &mut out, &mut stack);
+        }
+
+        out
+    }
+
+    // Dumps a single node and pushes its children (and template contents) on the stack
+    fn html5lib_tree(
+        &self,
+        node_id: usize,
+        depth: usize,
+        out: &mut String,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let node = match self.get_node_by_id(node_id) {
+            Some(node) => node,
+            None => return,
+        };
+        stack.extend(node.children.iter().rev().map(|&id| (id, depth + 1)));
+
+        let prefix = format!("| {}", "  ".repeat(depth));
+
+        match &node.data {
+            NodeData::Document | NodeData::DocumentFragment => {}
+            NodeData::DocType {
+                name,
+                public_id,
+                system_id,
+            } => {
+                let public_id = public_id.as_deref().unwrap_or_default();
+                let system_id = system_id.as_deref().unwrap_or_default();
+                if public_id.is_empty() && system_id.is_empty() {
+                    out.push_str(&format!("{}<!DOCTYPE {}>\n", prefix, name));
+                } else {
+                    out.push_str(&format!(
+                        "{}<!DOCTYPE {} \"{}\" \"{}\">\n",
+                        prefix, name, public_id, system_id
+                    ));
+                }
+            }
+            NodeData::Text { value } => out.push_str(&format!("{}\"{}\"\n", prefix, value)),
+            NodeData::Comment { value } => out.push_str(&format!("{}<!-- {} -->\n", prefix, value)),
+            NodeData::Element { name, attributes } => {
+                let is_html = node.namespace.as_deref() == Some(HTML_NAMESPACE);
+                let designator = match node.namespace.as_deref() {
+                    Some(SVG_NAMESPACE) => "svg ",
+                    Some(MATHML_NAMESPACE) => "math ",
+                    _ => "",
+                };
+                out.push_str(&format!("{}<{}{}>\n", prefix, designator, name));
+
+                let mut attributes: Vec<(String, &String)> = attributes
+                    .iter()
+                    .map(|(name, value)| match name.split_once(':') {
+                        Some((ns, local))
+                            if !is_html && FOREIGN_ATTRIBUTE_PREFIXES.contains(&ns) =>
+                        {
+                            (format!("{} {}", ns, local), value)
+                        }
+                        _ => (name.clone(), value),
+                    })
+                    .collect();
+                attributes.sort();
+                for (name, value) in attributes {
+                    out.push_str(&format!("{}  {}=\"{}\"\n", prefix, name, value));
+                }
+
+                if let Some(&contents_id) = self.template_contents.get(&node_id) {
+                    // A declarative shadow root is not part of the template
+                    if !self.shadow_roots.contains_key(&contents_id) {
+                        out.push_str(&format!("{}  content\n", prefix));
+                        // Dumped before the children of the template itself
+                        if let Some(contents) = self.get_node_by_id(contents_id) {
+                            stack.extend(contents.children.iter().rev().map(|&id| (id, depth + 2)));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl fmt::Display for Document {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.display_tree(self.get_root(), 0, f)
@@ -115,6 +552,123 @@ mod tests {
     use crate::html5_parser::node::HTML_NAMESPACE;
     use std::collections::HashMap;
 
+    #[test]
+    fn test_descendants() {
+        let mut document = super::Document::new();
+        let html_id = document.add_node(
+            super::Node::new_element("html", HashMap::new(), HTML_NAMESPACE),
+            0,
+        );
+        let head_id = document.add_node(
+            super::Node::new_element("head", HashMap::new(), HTML_NAMESPACE),
+            html_id,
+        );
+        let title_id = document.add_node(
+            super::Node::new_element("title", HashMap::new(), HTML_NAMESPACE),
+            head_id,
+        );
+        let body_id = document.add_node(
+            super::Node::new_element("body", HashMap::new(), HTML_NAMESPACE),
+            html_id,
+        );
+        let text_id = document.add_node(super::Node::new_text("hello"), body_id);
+
+        assert_eq!(
+            document.descendants(0),
+            vec![html_id, head_id, title_id, body_id, text_id]
+        );
+        assert_eq!(document.descendants(head_id), vec![title_id]);
+        assert!(document.descendants(text_id).is_empty());
+        assert!(document.descendants(1234).is_empty());
+    }
+
+    #[test]
+    fn test_get_element_by_id() {
+        let mut document = super::Document::new();
+        let html_id = document.add_node(
+            super::Node::new_element("html", HashMap::new(), HTML_NAMESPACE),
+            0,
+        );
+        let div_id = document.add_node(
+            super::Node::new_element("div", HashMap::new(), HTML_NAMESPACE),
+            html_id,
+        );
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "foo".to_string());
+        let p_id = document.add_node(
+            super::Node::new_element("p", attributes, HTML_NAMESPACE),
+            html_id,
+        );
+
+        assert_eq!(document.get_element_by_id("foo"), Some(p_id));
+        assert_eq!(document.get_element_by_id("bar"), None);
+
+        // The first element in tree order wins, not the first one added
+        document.set_attribute(div_id, "id", "foo");
+        assert_eq!(document.get_element_by_id("foo"), Some(div_id));
+
+        document.set_attribute(div_id, "id", "bar");
+        assert_eq!(document.get_element_by_id("foo"), Some(p_id));
+        assert_eq!(document.get_element_by_id("bar"), Some(div_id));
+
+        document.remove_attribute(p_id, "id");
+        assert_eq!(document.get_element_by_id("foo"), None);
+
+        document.remove_node(div_id);
+        assert_eq!(document.get_element_by_id("bar"), None);
+        assert!(document.get_node_by_id(div_id).is_none());
+
+        // Elements that are not connected to the document are not found
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "detached".to_string());
+        document.add_node(
+            super::Node::new_element("span", attributes, HTML_NAMESPACE),
+            1234,
+        );
+        assert_eq!(document.get_element_by_id("detached"), None);
+    }
+
+    #[test]
+    fn test_get_mut_text_by_id() {
+        let mut document = super::Document::new();
+        let div_id = document.add_node(
+            super::Node::new_element("div", HashMap::new(), HTML_NAMESPACE),
+            0,
+        );
+        let text_id = document.add_node(super::Node::new_text("foo"), div_id);
+
+        document
+            .get_mut_text_by_id(text_id)
+            .unwrap()
+            .push_str("bar");
+        assert_eq!(document.to_html5lib_tree(), "| <div>\n|   \"foobar\"\n");
+
+        // Elements have no text value
+        assert!(document.get_mut_text_by_id(div_id).is_none());
+    }
+
+    #[test]
+    fn test_get_elements_by_class_name_in_quirks_mode() {
+        let mut document = super::Document::new();
+        let mut attributes = HashMap::new();
+        attributes.insert("class".to_string(), "Foo bar".to_string());
+        let div_id = document.add_node(
+            super::Node::new_element("div", attributes, HTML_NAMESPACE),
+            0,
+        );
+
+        let collection = document.get_elements_by_class_name("foo BAR");
+        assert!(collection.to_vec(&document).is_empty());
+
+        document.quirks_mode = super::QuirksMode::Quirks;
+        let collection = document.get_elements_by_class_name("foo BAR");
+        assert_eq!(collection.to_vec(&document), vec![div_id]);
+        assert!(document
+            .get_elements_by_class_name("  ")
+            .to_vec(&document)
+            .is_empty());
+    }
+
     #[ignore]
     #[test]
     fn test_document() {
@@ -199,4 +753,87 @@ mod tests {
       "#
         );
     }
+
+    #[test]
+    fn test_to_html5lib_tree() {
+        use crate::html5_parser::node::SVG_NAMESPACE;
+
+        let mut document = super::Document::new();
+        document.add_node(
+            super::Node::new_doctype("html", Some("-//W3C//DTD HTML 4.01//EN"), None),
+            0,
+        );
+        document.add_node(super::Node::new_comment("hi"), 0);
+        let html_id = document.add_node(
+            super::Node::new_element("html", HashMap::new(), HTML_NAMESPACE),
+            0,
+        );
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "x".to_string());
+        attributes.insert("class".to_string(), "a b".to_string());
+        let body_id = document.add_node(
+            super::Node::new_element("body", attributes, HTML_NAMESPACE),
+            html_id,
+        );
+        document.add_node(super::Node::new_text("line 1\nline 2"), body_id);
+
+        let mut attributes = HashMap::new();
+        attributes.insert("xlink:href".to_string(), "#a".to_string());
+        attributes.insert("viewBox".to_string(), "0 0 1 1".to_string());
+        document.add_node(
+            super::Node::new_element("svg", attributes, SVG_NAMESPACE),
+            body_id,
+        );
+
+        let template_id = document.add_node(
+            super::Node::new_element("template", HashMap::new(), HTML_NAMESPACE),
+            body_id,
+        );
+        let contents_id = document.create_template_contents(template_id);
+        document.add_node(
+            super::Node::new_element("p", HashMap::new(), HTML_NAMESPACE),
+            contents_id,
+        );
+
+        assert_eq!(
+            document.to_html5lib_tree(),
+            r##"| <!DOCTYPE html "-//W3C//DTD HTML 4.01//EN" "">
+| <!-- hi -->
+| <html>
+|   <body>
+|     class="a b"
+|     id="x"
+|     "line 1
+line 2"
+|     <svg svg>
+|       viewBox="0 0 1 1"
+|       xlink href="#a"
+|     <template>
+|       content
+|         <p>
+"##
+        );
+        assert_eq!(document.to_html5lib_tree_from(contents_id), "| <p>\n");
+    }
+
+    #[test]
+    fn test_to_html5lib_tree_deep() {
+        let mut document = super::Document::new();
+        let mut parent_id = 0;
+        for _ in 0..5_000 {
+            parent_id = document.add_node(
+                super::Node::new_element("div", HashMap::new(), HTML_NAMESPACE),
+                parent_id,
+            );
+        }
+
+        // The indentation makes the dump quadratic in the depth, so this stays well below the
+        // depth of the serializer tests
+        let tree = document.to_html5lib_tree();
+        assert_eq!(tree.lines().count(), 5_000);
+        assert_eq!(
+            tree.lines().last().unwrap(),
+            format!("| {}<div>", "  ".repeat(4_999))
+        );
+    }
 }
@@ -0,0 +1,210 @@
+use crate::html5_parser::node::Node;
+use std::collections::{HashMap, HashSet};
+
+// Indexes over the element nodes in a document, so we don't need to scan the whole arena when
+// looking up elements by id, tag name or class name. Every key maps to a set of element ids, so
+// removing the elements of a large subtree stays linear. Lookups return the ids in node id order,
+// not in tree order.
+#[derive(Default)]
+pub(crate) struct DocumentIndex {
+    ids: HashMap<String, HashSet<usize>>, // id attribute value => element ids
+    tags: HashMap<String, HashSet<usize>>, // tag name => element ids
+    classes: HashMap<String, HashSet<usize>>, // class name => element ids
+}
+
+impl DocumentIndex {
+    // Adds the given element to all indexes
+    pub fn add_element(&mut self, node: &Node) {
+        if !node.is_element() {
+            return;
+        }
+
+        add_entry(&mut self.tags, &node.name, node.id);
+        if let Some(id) = node.get_attribute("id") {
+            self.update_attribute(node.id, "id", None, Some(id));
+        }
+        if let Some(class) = node.get_attribute("class") {
+            self.update_attribute(node.id, "class", None, Some(class));
+        }
+    }
+
+    // Removes the given element from all indexes
+    pub fn remove_element(&mut self, node: &Node) {
+        if !node.is_element() {
+            return;
+        }
+
+        remove_entry(&mut self.tags, &node.name, node.id);
+        if let Some(id) = node.get_attribute("id") {
+            self.update_attribute(node.id, "id", Some(id), None);
+        }
+        if let Some(class) = node.get_attribute("class") {
+            self.update_attribute(node.id, "class", Some(class), None);
+        }
+    }
+
+    // Updates the indexes when an attribute changes from old_value to new_value. Only the id and
+    // class attributes are indexed, all other attributes are ignored.
+    pub fn update_attribute(
+        &mut self,
+        node_id: usize,
+        name: &str,
+        old_value: Option<&str>,
+        new_value: Option<&str>,
+    ) {
+        match name {
+            "id" => {
+                if let Some(old) = old_value {
+                    remove_entry(&mut self.ids, old, node_id);
+                }
+                if let Some(new) = new_value {
+                    if !new.is_empty() {
+                        add_entry(&mut self.ids, new, node_id);
+                    }
+                }
+            }
+            "class" => {
+                if let Some(old) = old_value {
+                    for class in unique_classes(old) {
+                        remove_entry(&mut self.classes, class, node_id);
+                    }
+                }
+                if let Some(new) = new_value {
+                    for class in unique_classes(new) {
+                        add_entry(&mut self.classes, class, node_id);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Returns all elements with the given id attribute
+    pub fn elements_with_id(&self, id: &str) -> Vec<usize> {
+        sorted(self.ids.get(id))
+    }
+
+    // Returns all elements with the given tag name
+    pub fn elements_with_tag(&self, name: &str) -> Vec<usize> {
+        sorted(self.tags.get(name))
+    }
+
+    // Returns all elements that have the given class in their class attribute
+    pub fn elements_with_class(&self, class: &str) -> Vec<usize> {
+        sorted(self.classes.get(class))
+    }
+
+    // Returns all elements that have a class matching the given class ASCII case-insensitive
+    pub fn elements_with_class_ignore_case(&self, class: &str) -> Vec<usize> {
+        let mut ids: Vec<usize> = self
+            .classes
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case(class))
+            .flat_map(|(_, ids)| ids.iter().copied())
+            .collect();
+        // An element can have the class in several casings
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    // Returns all indexed elements
+    pub fn all_elements(&self) -> Vec<usize> {
+        self.tags.values().flatten().copied().collect()
+    }
+}
+
+// Returns the classes in the given class attribute value without duplicates
+pub(crate) fn unique_classes(value: &str) -> Vec<&str> {
+    let mut classes: Vec<&str> = Vec::new();
+    for class in value.split_ascii_whitespace() {
+        if !classes.contains(&class) {
+            classes.push(class);
+        }
+    }
+    classes
+}
+
+// Returns the ids of the set in node id order
+fn sorted(ids: Option<&HashSet<usize>>) -> Vec<usize> {
+    let mut ids: Vec<usize> = ids.map_or(Vec::new(), |ids| ids.iter().copied().collect());
+    ids.sort_unstable();
+    ids
+}
+
+fn add_entry(map: &mut HashMap<String, HashSet<usize>>, key: &str, node_id: usize) {
+    map.entry(key.to_string()).or_default().insert(node_id);
+}
+
+fn remove_entry(map: &mut HashMap<String, HashSet<usize>>, key: &str, node_id: usize) {
+    if let Some(ids) = map.get_mut(key) {
+        ids.remove(&node_id);
+        if ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::node::HTML_NAMESPACE;
+
+    fn element(id: usize, name: &str, attrs: &[(&str, &str)]) -> Node {
+        let mut attributes = HashMap::new();
+        for (key, value) in attrs {
+            attributes.insert(key.to_string(), value.to_string());
+        }
+        let mut node = Node::new_element(name, attributes, HTML_NAMESPACE);
+        node.id = id;
+        node
+    }
+
+    #[test]
+    fn test_add_and_remove() {
+        let mut index = DocumentIndex::default();
+        let div = element(1, "div", &[("id", "main"), ("class", "a b a")]);
+        let span = element(2, "span", &[("class", "b")]);
+
+        index.add_element(&div);
+        index.add_element(&span);
+        index.add_element(&Node::new_text("text"));
+
+        assert_eq!(index.elements_with_id("main"), vec![1]);
+        assert_eq!(index.elements_with_tag("div"), vec![1]);
+        assert_eq!(index.elements_with_class("a"), vec![1]);
+        assert_eq!(index.elements_with_class("b"), vec![1, 2]);
+        assert_eq!(index.elements_with_class_ignore_case("B"), vec![1, 2]);
+        assert_eq!(index.all_elements().len(), 2);
+
+        index.remove_element(&div);
+        assert!(index.elements_with_id("main").is_empty());
+        assert!(index.elements_with_tag("div").is_empty());
+        assert!(index.elements_with_class("a").is_empty());
+        assert_eq!(index.elements_with_class("b"), vec![2]);
+    }
+
+    #[test]
+    fn test_update_attribute() {
+        let mut index = DocumentIndex::default();
+
+        index.update_attribute(1, "id", None, Some("foo"));
+        assert_eq!(index.elements_with_id("foo"), vec![1]);
+        index.update_attribute(1, "id", Some("foo"), Some("bar"));
+        assert!(index.elements_with_id("foo").is_empty());
+        assert_eq!(index.elements_with_id("bar"), vec![1]);
+        index.update_attribute(1, "id", Some("bar"), Some(""));
+        assert!(index.elements_with_id("bar").is_empty());
+        assert!(index.elements_with_id("").is_empty());
+
+        index.update_attribute(1, "class", None, Some(" x  y "));
+        assert_eq!(index.elements_with_class("x"), vec![1]);
+        index.update_attribute(1, "class", Some(" x  y "), Some("y z"));
+        assert!(index.elements_with_class("x").is_empty());
+        assert_eq!(index.elements_with_class("y"), vec![1]);
+        assert_eq!(index.elements_with_class("z"), vec![1]);
+
+        index.update_attribute(1, "title", None, Some("ignored"));
+        assert!(index.elements_with_class("ignored").is_empty());
+    }
+}
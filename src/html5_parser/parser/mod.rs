@@ -1,9 +1,13 @@
 mod attr_replacements;
 pub mod document;
-mod quirks;
+mod document_index;
+pub mod quirks;
+pub mod trace;
 
 // ------------------------------------------------------------
 
+use crate::html5_parser::dom::shadow::ShadowRootInit;
+use crate::html5_parser::dom::{ShadowRootMode, SlotAssignmentMode};
 use crate::html5_parser::error_logger::{ErrorLogger, ParseError, ParserError};
 use crate::html5_parser::input_stream::InputStream;
 use crate::html5_parser::node::{Node, NodeData, HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE};
@@ -12,12 +16,23 @@ use crate::html5_parser::parser::attr_replacements::{
 };
 use crate::html5_parser::parser::document::{Document, DocumentType};
 use crate::html5_parser::parser::quirks::QuirksMode;
+use crate::html5_parser::parser::trace::{ParserTrace, TraceStep};
 use crate::html5_parser::tokenizer::state::State;
 use crate::html5_parser::tokenizer::token::Token;
-use crate::html5_parser::tokenizer::{Tokenizer, CHAR_NUL};
+use crate::html5_parser::tokenizer::{Options, Tokenizer, CHAR_NUL};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Elements that are not closed to stay within ParserLimits::max_tree_depth, because the insertion
+// modes expect them to be open until their own end tag
+const DEPTH_LIMIT_KEEPS_OPEN: [&str; 15] = [
+    "html", "head", "body", "frameset", "template", "select", "table", "caption", "colgroup",
+    "tbody", "thead", "tfoot", "tr", "td", "th",
+];
 
 // Insertion modes as defined in 13.2.4.1
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -92,13 +107,17 @@ macro_rules! acknowledge_closing_tag {
 // Pops the last element from the open elements until we reach $name
 macro_rules! pop_until {
     ($self:expr, $name:expr) => {
-        loop {
-            $self.open_elements.pop();
-            if current_node!($self).name != $name {
+        while let Some(node_id) = $self.open_elements.pop() {
+            if $self
+                .document
+                .get_node_by_id(node_id)
+                .expect("node not found")
+                .name
+                == $name
+            {
                 break;
             }
         }
-        // $self.open_elements.pop_until(|node_id| $self.document.get_node_by_id(*node_id).expect("node not found").name == $name);
     };
 }
 
@@ -185,17 +204,6 @@ macro_rules! current_node {
     }};
 }
 
-// Returns the current node as a mutable reference
-macro_rules! current_node_mut {
-    ($self:expr) => {{
-        let current_node_idx = $self.open_elements.last().unwrap_or(&0);
-        $self
-            .document
-            .get_mut_node_by_id(*current_node_idx)
-            .expect("Current node not found")
-    }};
-}
-
 #[macro_use]
 mod adoption_agency;
 
@@ -206,6 +214,12 @@ enum ActiveElement {
     Marker,
 }
 
+// Callback for the parse progress, see Html5Parser::on_progress()
+type ProgressFn<'a> = Box<dyn FnMut(Progress) + 'a>;
+
+// Callback for completed trace steps, see Html5Parser::on_trace_step()
+type TraceCallback<'a> = Box<dyn FnMut(&TraceStep, &Document) + 'a>;
+
 // The main parser object
 pub struct Html5Parser<'a> {
     tokenizer: Tokenizer<'a>,                       // tokenizer object
@@ -226,12 +240,119 @@ pub struct Html5Parser<'a> {
     ack_self_closing: bool, // Acknowledge self closing tags
     active_formatting_elements: Vec<ActiveElement>, // List of active formatting elements or markers
     is_fragment_case: bool, // Is the current parsing a fragment case
+    context_node: Option<Node>, // Context element when parsing a fragment
     document: Document,    // A reference to the document we are parsing
     error_logger: Rc<RefCell<ErrorLogger>>, // Error logger
+    limits: ParserLimits,  // Limits against hostile input
+    reported_limits: Vec<ParserError>, // Limits that have been reported as exceeded
+    input_truncated: bool, // True when the input was cut off at the maximum input size
+    cancellation_token: Option<CancellationToken>, // Stops parsing when cancelled
+    progress_callback: Option<ProgressFn<'a>>, // Called after every token
+    trace: Option<ParserTrace>, // Trace of the tree construction, if enabled
+    trace_error_count: usize, // Number of errors before the current trace step
+    trace_callback: Option<TraceCallback<'a>>, // Called after every trace step
 }
 
 impl<'a> Html5Parser<'a> {}
 
+/// Token to cancel a running parse from another thread. Clones share the same state, so a clone
+/// can be handed to the parser while the original is kept to cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the parse(s) that use this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress of a parse, as reported to the callback set with Html5Parser::on_progress()
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Number of bytes of the (decoded, UTF-8) input that have been consumed
+    pub consumed: usize,
+    /// Length of the (decoded, UTF-8) input in bytes
+    pub length: usize,
+}
+
+impl Progress {
+    /// Returns the consumed part of the input, from 0.0 to 1.0
+    pub fn fraction(&self) -> f64 {
+        match self.length {
+            0 => 1.0,
+            length => self.consumed as f64 / length as f64,
+        }
+    }
+}
+
+/// Options for the parser
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParserOptions {
+    /// Parse as if scripting is enabled. This changes how noscript elements are parsed.
+    pub scripting_enabled: bool,
+    /// Limits against hostile input
+    pub limits: ParserLimits,
+    /// Record a trace of the tree construction, see Html5Parser::trace()
+    pub trace: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            scripting_enabled: true,
+            limits: ParserLimits::default(),
+            trace: false,
+        }
+    }
+}
+
+/// Limits that protect the parser against hostile input, where None means no limit. Nothing is
+/// limited by default. Every limit that is exceeded is reported once as a parse error.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParserLimits {
+    /// Maximum size of the (decoded) input in bytes. Only the start of longer input is parsed,
+    /// the input stream itself keeps all of it.
+    pub max_input_size: Option<usize>,
+    /// Maximum number of elements a node can be nested in. Nodes that would be nested deeper are
+    /// inserted into the element at this depth instead, and an element that is opened there
+    /// closes the previous one. End tags of those closed elements close an outer element instead.
+    /// Elements the insertion modes depend on (html, head, body, table parts, template and
+    /// select) are never closed this way, so the stack of open elements can grow a little
+    /// beyond this depth.
+    pub max_tree_depth: Option<usize>,
+    /// Maximum number of nodes in the document. Parsing stops after the token that exceeds it.
+    pub max_nodes: Option<usize>,
+    /// Maximum number of attributes of an element. Further attributes are dropped.
+    pub max_attributes: Option<usize>,
+    /// Maximum length of an attribute value in bytes. Longer values are truncated.
+    pub max_attribute_value_length: Option<usize>,
+    /// Maximum number of elements in the list of active formatting elements after the last
+    /// marker. The oldest element is dropped from the list, so it is not reopened by later
+    /// blocks. Without a limit, misnested formatting elements with different attributes (like
+    /// `<b id=1><p><b id=2><p>...`) make the tree grow quadratically with the input.
+    pub max_formatting_elements: Option<usize>,
+    /// Maximum time spent in parse(). Parsing stops after the token that exceeds it.
+    pub max_parse_time: Option<Duration>,
+}
+
+impl Drop for Html5Parser<'_> {
+    // The input is only cut off for the parser, the stream of the caller keeps all of it
+    fn drop(&mut self) {
+        self.tokenizer.stream.set_read_limit(None);
+    }
+}
+
 // Defines the scopes for in_scope()
 enum Scope {
     Regular,
@@ -244,10 +365,75 @@ enum Scope {
 impl<'a> Html5Parser<'a> {
     // Creates a new parser object with the given input stream
     pub fn new(stream: &'a mut InputStream) -> Self {
+        Self::new_with_options(stream, ParserOptions::default())
+    }
+
+    // Creates a new parser object with the given input stream and options
+    pub fn new_with_options(stream: &'a mut InputStream, options: ParserOptions) -> Self {
+        Self::with_tokenizer_options(stream, options, None)
+    }
+
+    // Creates a parser for the HTML fragment parsing algorithm, where the input is parsed as the
+    // children of the given context element (as done by innerHTML). Use parse_fragment() to
+    // parse the input.
+    pub fn new_fragment(
+        stream: &'a mut InputStream,
+        context: &Node,
+        options: ParserOptions,
+    ) -> Self {
+        let is_html = context.namespace.as_deref() == Some(HTML_NAMESPACE);
+        let initial_state = match context.name.as_str() {
+            "title" | "textarea" if is_html => State::RcDataState,
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" if is_html => State::RawTextState,
+            "noscript" if is_html && options.scripting_enabled => State::RawTextState,
+            "script" if is_html => State::ScriptDataState,
+            "plaintext" if is_html => State::PlaintextState,
+            _ => State::DataState,
+        };
+
+        let mut parser = Self::with_tokenizer_options(
+            stream,
+            options,
+            Some(Options {
+                initial_state,
+                last_start_tag: context.name.clone(),
+            }),
+        );
+        parser.is_fragment_case = true;
+        parser.context_node = Some(context.clone());
+
+        // The fragment is parsed into a html root element, which takes the place of the context
+        // element on the stack of open elements
+        let root = Node::new_element("html", HashMap::new(), HTML_NAMESPACE);
+        let root_id = parser.document.add_node(root, 0);
+        parser.open_elements.push(root_id);
+
+        if is_html && context.name == "template" {
+            parser
+                .template_insertion_mode
+                .push(InsertionMode::InTemplate);
+        }
+        parser.reset_insertion_mode();
+
+        parser
+    }
+
+    fn with_tokenizer_options(
+        stream: &'a mut InputStream,
+        options: ParserOptions,
+        tokenizer_options: Option<Options>,
+    ) -> Self {
         // Create a new error logger that will be used in both the tokenizer and the parser
         let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
 
-        let tokenizer = Tokenizer::new(stream, None, error_logger.clone());
+        let limits = options.limits;
+        let trace = options.trace.then(ParserTrace::default);
+        // The stream ends at the limit while it is parsed, see the Drop implementation
+        let input_truncated = stream.set_read_limit(limits.max_input_size);
+
+        let mut tokenizer = Tokenizer::new(stream, tokenizer_options, error_logger.clone());
+        tokenizer.max_attributes = limits.max_attributes;
+        tokenizer.max_attribute_value_length = limits.max_attribute_value_length;
 
         Html5Parser {
             tokenizer,
@@ -260,7 +446,7 @@ impl<'a> Html5Parser<'a> {
             open_elements: Vec::new(),
             head_element: None,
             form_element: None,
-            scripting_enabled: true,
+            scripting_enabled: options.scripting_enabled,
             frameset_ok: true,
             foster_parenting: false,
             script_already_started: false,
@@ -268,26 +454,87 @@ impl<'a> Html5Parser<'a> {
             ack_self_closing: false,
             active_formatting_elements: vec![],
             is_fragment_case: false,
+            context_node: None,
             error_logger,
             document: Document::new(),
+            limits,
+            reported_limits: vec![],
+            input_truncated,
+            cancellation_token: None,
+            progress_callback: None,
+            trace,
+            trace_error_count: 0,
+            trace_callback: None,
         }
     }
 
+    // Sets the token that cancels parsing. The token is checked between tokens, so the document
+    // is left as it was after the last processed token. A cancelled parse reports a
+    // parse-cancelled error.
+    pub fn set_cancellation_token(&mut self, token: CancellationToken) {
+        self.cancellation_token = Some(token);
+    }
+
+    // Sets the callback that reports how much of the input stream has been consumed. It is
+    // called after every token that is read.
+    pub fn on_progress(&mut self, callback: impl FnMut(Progress) + 'a) {
+        self.progress_callback = Some(Box::new(callback));
+    }
+
     // Parses the input stream into a Node tree
     pub fn parse(&mut self) -> (&Document, Vec<ParseError>) {
+        let deadline = self.limits.max_parse_time.map(|time| Instant::now() + time);
+
         loop {
+            self.finish_trace_step();
+
+            // Stop parsing when the document has grown too large or parsing takes too long
+            if self
+                .limits
+                .max_nodes
+                .is_some_and(|max| self.document.node_count() > max)
+            {
+                self.limit_error(ParserError::NodeLimitExceeded);
+                break;
+            }
+            if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                self.limit_error(ParserError::ParseTimeLimitExceeded);
+                break;
+            }
+            if self
+                .cancellation_token
+                .as_ref()
+                .is_some_and(|token| token.is_cancelled())
+            {
+                self.limit_error(ParserError::ParseCancelled);
+                break;
+            }
+
             // If reprocess_token is true, we should process the same token again
+            let reprocessed = self.reprocess_token;
+            let error_count = self.error_logger.borrow().error_count();
             if !self.reprocess_token {
                 self.current_token = self.tokenizer.next_token();
+
+                if let Some(callback) = self.progress_callback.as_mut() {
+                    let stream = &self.tokenizer.stream;
+                    callback(Progress {
+                        consumed: stream.position.byte_offset.min(stream.byte_length()),
+                        length: stream.byte_length(),
+                    });
+                }
             }
             self.reprocess_token = false;
 
             // Break when we reach the end of the token stream
             if self.current_token.is_eof() {
+                if self.input_truncated {
+                    self.limit_error(ParserError::InputSizeLimitExceeded);
+                }
                 break;
             }
 
-            // println!("Token: {}", self.current_token);
+            self.start_trace_step(reprocessed, error_count);
 
             match self.insertion_mode {
                 // Checked: 1
@@ -318,7 +565,8 @@ impl<'a> Html5Parser<'a> {
                                 self.parse_error("doctype not allowed in initial insertion mode");
                             }
 
-                            self.insert_html_element(&self.current_token.clone());
+                            let node = self.create_node(&self.current_token, HTML_NAMESPACE);
+                            self.document.add_node(node, 0);
 
                             if self.document.doctype != DocumentType::IframeSrcDoc
                                 && self.parser_cannot_change_mode
@@ -423,7 +671,8 @@ impl<'a> Html5Parser<'a> {
                         }
                         Token::CommentToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::DocTypeToken { .. } => {
                             self.parse_error("doctype not allowed in before head insertion mode");
@@ -541,11 +790,13 @@ impl<'a> Html5Parser<'a> {
                     match &self.current_token {
                         Token::TextToken { .. } if self.current_token.is_empty_or_white() => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::CommentToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::DocTypeToken { .. } => {
                             self.parse_error("doctype not allowed in after head insertion mode");
@@ -637,7 +888,8 @@ impl<'a> Html5Parser<'a> {
                     match &self.current_token {
                         Token::TextToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::EofToken => {
                             self.parse_error("eof not allowed in text insertion mode");
@@ -681,7 +933,6 @@ impl<'a> Html5Parser<'a> {
                             }
                         }
                         _ => {
-                            // @TODO: this needs to check if there are any non-whitespaces, if so then reprocess using anything_else in "in_table"
                             self.flush_pending_table_character_tokens();
 
                             self.insertion_mode = self.original_insertion_mode;
@@ -745,7 +996,6 @@ impl<'a> Html5Parser<'a> {
 
                         if current_node!(self).name != "caption" {
                             self.parse_error("caption end tag not at top of stack");
-                            continue;
                         }
 
                         pop_until!(self, "caption");
@@ -759,11 +1009,13 @@ impl<'a> Html5Parser<'a> {
                     match &self.current_token {
                         Token::TextToken { .. } if self.current_token.is_empty_or_white() => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::CommentToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::DocTypeToken { .. } => {
                             self.parse_error("doctype not allowed in column group insertion mode");
@@ -862,7 +1114,7 @@ impl<'a> Html5Parser<'a> {
                 InsertionMode::InTableBody => {
                     match &self.current_token {
                         Token::StartTagToken { name, .. } if name == "tr" => {
-                            self.clear_stack_back_to_table_context();
+                            self.clear_stack_back_to_table_body_context();
 
                             self.insert_html_element(&self.current_token.clone());
 
@@ -873,7 +1125,7 @@ impl<'a> Html5Parser<'a> {
                                 "th or td tag not allowed in in table body insertion mode",
                             );
 
-                            self.clear_stack_back_to_table_context();
+                            self.clear_stack_back_to_table_body_context();
 
                             let token = Token::StartTagToken {
                                 name: "tr".to_string(),
@@ -894,7 +1146,7 @@ impl<'a> Html5Parser<'a> {
                                 continue;
                             }
 
-                            self.clear_stack_back_to_table_context();
+                            self.clear_stack_back_to_table_body_context();
                             self.open_elements.pop();
 
                             self.insertion_mode = InsertionMode::InTable;
@@ -912,7 +1164,7 @@ impl<'a> Html5Parser<'a> {
                                 continue;
                             }
 
-                            self.clear_stack_back_to_table_context();
+                            self.clear_stack_back_to_table_body_context();
                             self.open_elements.pop();
 
                             self.insertion_mode = InsertionMode::InTable;
@@ -927,7 +1179,7 @@ impl<'a> Html5Parser<'a> {
                                 continue;
                             }
 
-                            self.clear_stack_back_to_table_context();
+                            self.clear_stack_back_to_table_body_context();
                             self.open_elements.pop();
 
                             self.insertion_mode = InsertionMode::InTable;
@@ -1040,7 +1292,7 @@ impl<'a> Html5Parser<'a> {
                 // Checked: 1
                 InsertionMode::InCell => {
                     match &self.current_token {
-                        Token::StartTagToken { name, .. } if name == "th" || name == "td" => {
+                        Token::EndTagToken { name, .. } if name == "th" || name == "td" => {
                             let token_name = name.clone();
 
                             if !self.in_scope(name.as_str(), Scope::Table) {
@@ -1120,11 +1372,13 @@ impl<'a> Html5Parser<'a> {
                         }
                         Token::TextToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::CommentToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::DocTypeToken { .. } => {
                             self.parse_error("doctype not allowed in in select insertion mode");
@@ -1300,98 +1554,7 @@ impl<'a> Html5Parser<'a> {
                     }
                 }
                 // Checked: 1
-                InsertionMode::InTemplate => {
-                    match &self.current_token {
-                        Token::TextToken { .. } => {
-                            self.handle_in_body();
-                        }
-                        Token::CommentToken { .. } => {
-                            self.handle_in_body();
-                        }
-                        Token::DocTypeToken { .. } => {
-                            self.handle_in_body();
-                        }
-                        Token::StartTagToken { name, .. }
-                            if name == "base"
-                                || name == "basefont"
-                                || name == "bgsound"
-                                || name == "link"
-                                || name == "meta"
-                                || name == "noframes"
-                                || name == "script"
-                                || name == "style"
-                                || name == "template"
-                                || name == "title" =>
-                        {
-                            self.handle_in_head();
-                        }
-                        Token::EndTagToken { name, .. } if name == "template" => {
-                            self.handle_in_head();
-                        }
-                        Token::StartTagToken { name, .. }
-                            if name == "caption"
-                                || name == "colgroup"
-                                || name == "tbody"
-                                || name == "tfoot"
-                                || name == "thead" =>
-                        {
-                            self.template_insertion_mode.pop();
-                            self.template_insertion_mode.push(InsertionMode::InTable);
-
-                            self.insertion_mode = InsertionMode::InTable;
-                            self.reprocess_token = true;
-                        }
-                        Token::StartTagToken { name, .. } if name == "col" => {
-                            self.template_insertion_mode.pop();
-                            self.template_insertion_mode
-                                .push(InsertionMode::InColumnGroup);
-
-                            self.insertion_mode = InsertionMode::InColumnGroup;
-                            self.reprocess_token = true;
-                        }
-                        Token::StartTagToken { name, .. } if name == "tr" => {
-                            self.template_insertion_mode.pop();
-                            self.template_insertion_mode
-                                .push(InsertionMode::InTableBody);
-
-                            self.insertion_mode = InsertionMode::InTableBody;
-                            self.reprocess_token = true;
-                        }
-                        Token::StartTagToken { name, .. } if name == "td" || name == "th" => {
-                            self.template_insertion_mode.pop();
-                            self.template_insertion_mode.push(InsertionMode::InRow);
-
-                            self.insertion_mode = InsertionMode::InRow;
-                            self.reprocess_token = true;
-                        }
-                        Token::StartTagToken { .. } => {
-                            self.template_insertion_mode.pop();
-                            self.template_insertion_mode.push(InsertionMode::InBody);
-
-                            self.insertion_mode = InsertionMode::InBody;
-                            self.reprocess_token = true;
-                        }
-                        Token::EndTagToken { .. } => {
-                            self.parse_error("end tag not allowed in in template insertion mode");
-                            // ignore token
-                            continue;
-                        }
-                        Token::EofToken => {
-                            if !open_elements_has!(self, "template") {
-                                self.stop_parsing();
-                                continue;
-                            }
-
-                            self.parse_error("eof not allowed in in template insertion mode");
-
-                            pop_until!(self, "template");
-                            self.clear_active_formatting_elements_until_marker();
-                            self.template_insertion_mode.pop();
-                            self.reset_insertion_mode();
-                            self.reprocess_token = true;
-                        }
-                    }
-                }
+                InsertionMode::InTemplate => self.handle_in_template(),
                 // Checked: 1
                 InsertionMode::AfterBody => {
                     match &self.current_token {
@@ -1432,11 +1595,13 @@ impl<'a> Html5Parser<'a> {
                     match &self.current_token {
                         Token::TextToken { .. } if self.current_token.is_empty_or_white() => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::CommentToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::DocTypeToken { .. } => {
                             self.parse_error("doctype not allowed in frameset insertion mode");
@@ -1496,11 +1661,13 @@ impl<'a> Html5Parser<'a> {
                     match &self.current_token {
                         Token::TextToken { .. } if self.current_token.is_empty_or_white() => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::CommentToken { .. } => {
                             let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                            self.document.add_node(node, current_node!(self).id);
+                            self.document
+                                .add_node(node, self.adjusted_insert_location(None));
                         }
                         Token::DocTypeToken { .. } => {
                             self.parse_error("doctype not allowed in frameset insertion mode");
@@ -1585,12 +1752,40 @@ impl<'a> Html5Parser<'a> {
             }
         }
 
+        self.finish_trace_step();
+
         (
             &self.document,
             self.error_logger.borrow().get_errors().clone(),
         )
     }
 
+    // Parses the input stream as a fragment. Returns the ids of the parsed top level nodes, which
+    // are the children of the root element of the document.
+    pub fn parse_fragment(&mut self) -> (&Document, Vec<usize>, Vec<ParseError>) {
+        self.parse();
+
+        let children = match self.open_elements.first() {
+            Some(&root_id) if self.is_fragment_case => self
+                .document
+                .get_node_by_id(root_id)
+                .map(|root| root.children.clone())
+                .unwrap_or_default(),
+            _ => self.document.get_root().children.clone(),
+        };
+
+        (
+            &self.document,
+            children,
+            self.error_logger.borrow().get_errors().clone(),
+        )
+    }
+
+    // Returns the parsed document
+    pub fn into_document(mut self) -> Document {
+        std::mem::take(&mut self.document)
+    }
+
     // Retrieve a list of all errors generated by the parser/tokenizer
     pub fn get_parse_errors(&self) -> Vec<ParseError> {
         self.error_logger.borrow().get_errors().clone()
@@ -1603,26 +1798,113 @@ impl<'a> Html5Parser<'a> {
             .add_error(self.tokenizer.get_position(), message);
     }
 
+    // Returns the trace of the tree construction when ParserOptions.trace is set
+    pub fn trace(&self) -> Option<&ParserTrace> {
+        self.trace.as_ref()
+    }
+
+    // Sets a callback that is called with every completed trace step and the document as it is
+    // after the step. Parsing waits for the callback, so it can be used to step through the parse.
+    // This enables the trace when ParserOptions.trace is not set.
+    pub fn on_trace_step(&mut self, callback: impl FnMut(&TraceStep, &Document) + 'a) {
+        self.trace.get_or_insert_with(ParserTrace::default);
+        self.trace_callback = Some(Box::new(callback));
+    }
+
+    // Adds a trace step for processing the current token, which is completed by
+    // finish_trace_step() once the token has been processed. The error count is the number of
+    // errors before the token was read.
+    fn start_trace_step(&mut self, reprocessed: bool, error_count: usize) {
+        if let Some(trace) = self.trace.as_mut() {
+            self.trace_error_count = error_count;
+            trace.steps.push(TraceStep {
+                token: self.current_token.to_string(),
+                tag_name: match &self.current_token {
+                    Token::StartTagToken { name, .. } | Token::EndTagToken { name, .. } => {
+                        Some(name.clone())
+                    }
+                    _ => None,
+                },
+                reprocessed,
+                mode_before: format!("{:?}", self.insertion_mode),
+                mode_after: String::new(),
+                open_elements: vec![],
+                active_formatting_elements: vec![],
+                errors: vec![],
+            });
+        }
+    }
+
+    // Completes the last trace step with the state after its token, if it is not completed yet
+    fn finish_trace_step(&mut self) {
+        match self.trace.as_ref().and_then(|trace| trace.steps.last()) {
+            Some(step) if step.mode_after.is_empty() => {}
+            _ => return,
+        }
+
+        let describe = |node_id: usize| {
+            let node = self
+                .document
+                .get_node_by_id(node_id)
+                .expect("node not found");
+            match node.namespace.as_deref() {
+                Some(SVG_NAMESPACE) => format!("svg {}", node.name),
+                Some(MATHML_NAMESPACE) => format!("math {}", node.name),
+                _ => node.name.clone(),
+            }
+        };
+        let open_elements = self.open_elements.iter().map(|id| describe(*id)).collect();
+        let active_formatting_elements = self
+            .active_formatting_elements
+            .iter()
+            .map(|elem| match elem {
+                ActiveElement::Node(node_id) => describe(*node_id),
+                ActiveElement::Marker => "marker".to_string(),
+            })
+            .collect();
+
+        let errors = self
+            .error_logger
+            .borrow()
+            .errors_since(self.trace_error_count)
+            .iter()
+            .map(|error| error.message.clone())
+            .collect();
+
+        let mode_after = format!("{:?}", self.insertion_mode);
+        if let Some(step) = self.trace.as_mut().and_then(|trace| trace.steps.last_mut()) {
+            step.mode_after = mode_after;
+            step.open_elements = open_elements;
+            step.active_formatting_elements = active_formatting_elements;
+            step.errors = errors;
+
+            if let Some(callback) = self.trace_callback.as_mut() {
+                callback(step, &self.document);
+            }
+        }
+    }
+
+    // Sends a parse error for an exceeded limit, unless the limit has been reported already
+    fn limit_error(&mut self, error: ParserError) {
+        if !self.reported_limits.contains(&error) {
+            self.reported_limits.push(error);
+            self.parse_error(error.as_str());
+        }
+    }
+
     // Create a new node that is not connected or attached to the document arena
     fn create_node(&self, token: &Token, namespace: &str) -> Node {
-        let val: String;
         match token {
             Token::DocTypeToken {
                 name,
                 pub_identifier,
                 sys_identifier,
-                force_quirks,
-            } => {
-                val = format!(
-                    "doctype[{} {} {} {}]",
-                    name.as_deref().unwrap_or(""),
-                    pub_identifier.as_deref().unwrap_or(""),
-                    sys_identifier.as_deref().unwrap_or(""),
-                    force_quirks
-                );
-
-                return Node::new_element(val.as_str(), HashMap::new(), namespace);
-            }
+                ..
+            } => Node::new_doctype(
+                name.as_deref().unwrap_or(""),
+                pub_identifier.as_deref(),
+                sys_identifier.as_deref(),
+            ),
             Token::StartTagToken {
                 name, attributes, ..
             } => Node::new_element(name, attributes.clone(), namespace),
@@ -1635,8 +1917,28 @@ impl<'a> Html5Parser<'a> {
         }
     }
 
-    fn flush_pending_table_character_tokens(&self) {
-        todo!()
+    // Inserts the text collected in the in table text insertion mode. Text with anything else
+    // than whitespace is foster parented, like the "anything else" entry of the in table mode.
+    fn flush_pending_table_character_tokens(&mut self) {
+        let value: String = self.pending_table_character_tokens.drain(..).collect();
+        if value.is_empty() {
+            return;
+        }
+        let token = Token::TextToken { value };
+
+        if token.is_empty_or_white() {
+            let node = self.create_node(&token, HTML_NAMESPACE);
+            self.document
+                .add_node(node, self.adjusted_insert_location(None));
+            return;
+        }
+
+        self.parse_error("non-whitespace text not allowed in in table text insertion mode");
+        let current_token = std::mem::replace(&mut self.current_token, token);
+        self.foster_parenting = true;
+        self.handle_in_body();
+        self.foster_parenting = false;
+        self.current_token = current_token;
     }
 
     // Clear the active formatting stack until we reach the first marker
@@ -1691,13 +1993,12 @@ impl<'a> Html5Parser<'a> {
         let mut idx = self.open_elements.len() - 1;
 
         loop {
-            let node = open_elements_get!(self, idx);
+            let mut node = open_elements_get!(self, idx).clone();
             if idx == 0 {
                 last = true;
-                // @TODO:
-                // if fragment_case {
-                //   node = context element !???
-                // }
+                if let Some(context) = &self.context_node {
+                    node = context.clone();
+                }
             }
 
             if node.name == "select" {
@@ -1790,6 +2091,16 @@ impl<'a> Html5Parser<'a> {
 
     // Pop all elements back to a table context
     fn clear_stack_back_to_table_context(&mut self) {
+        while !self.open_elements.is_empty() {
+            if ["table", "template", "html"].contains(&current_node!(self).name.as_str()) {
+                return;
+            }
+            self.open_elements.pop();
+        }
+    }
+
+    // Pop all elements back to a table body context
+    fn clear_stack_back_to_table_body_context(&mut self) {
         while !self.open_elements.is_empty() {
             if ["tbody", "tfoot", "thead", "template", "html"]
                 .contains(&current_node!(self).name.as_str())
@@ -1875,7 +2186,6 @@ impl<'a> Html5Parser<'a> {
         let tag = current_node!(self).name.clone();
         if tag != "td" && tag != "th" {
             self.parse_error("current node should be td or th");
-            return;
         }
 
         pop_until_any!(self, ["td", "th"]);
@@ -1896,19 +2206,22 @@ impl<'a> Html5Parser<'a> {
                 self.reconstruct_formatting();
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::TextToken { .. } => {
                 self.reconstruct_formatting();
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
 
                 self.frameset_ok = false;
             }
             Token::CommentToken { .. } => {
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::DocTypeToken { .. } => {
                 self.parse_error("doctype not allowed in in body insertion mode");
@@ -1929,18 +2242,14 @@ impl<'a> Html5Parser<'a> {
                     return;
                 }
 
-                // Add attributes to html element
-                if let NodeData::Element {
-                    attributes: node_attributes,
-                    ..
-                } = &mut current_node_mut!(self).data
-                {
-                    for (key, value) in attributes {
-                        if !node_attributes.contains_key(key) {
-                            node_attributes.insert(key.clone(), value.clone());
-                        }
+                // Add attributes to html element. This goes through the document so the
+                // element indexes stay up to date.
+                let node_id = current_node!(self).id;
+                for (key, value) in attributes {
+                    if current_node!(self).get_attribute(key).is_none() {
+                        self.document.set_attribute(node_id, key, value);
                     }
-                };
+                }
             }
             Token::StartTagToken { name, .. }
                 if name == "base"
@@ -2256,8 +2565,7 @@ impl<'a> Html5Parser<'a> {
                 self.reconstruct_formatting();
 
                 let node_id = self.insert_html_element(&self.current_token.clone());
-                self.active_formatting_elements
-                    .push(ActiveElement::Node(node_id));
+                self.push_active_formatting_element(node_id);
             }
             Token::StartTagToken { name, .. }
                 if name == "b"
@@ -2276,8 +2584,7 @@ impl<'a> Html5Parser<'a> {
                 self.reconstruct_formatting();
 
                 let node_id = self.insert_html_element(&self.current_token.clone());
-                self.active_formatting_elements
-                    .push(ActiveElement::Node(node_id));
+                self.push_active_formatting_element(node_id);
             }
             Token::StartTagToken { name, .. } if name == "nobr" => {
                 self.reconstruct_formatting();
@@ -2289,8 +2596,7 @@ impl<'a> Html5Parser<'a> {
                 }
 
                 let node_id = self.insert_html_element(&self.current_token.clone());
-                self.active_formatting_elements
-                    .push(ActiveElement::Node(node_id));
+                self.push_active_formatting_element(node_id);
             }
             Token::EndTagToken { name, .. }
                 if name == "a"
@@ -2365,7 +2671,8 @@ impl<'a> Html5Parser<'a> {
                 }
 
                 let node = self.create_node(&br, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
 
                 self.open_elements.pop();
                 acknowledge_closing_tag!(self, *is_self_closing);
@@ -2398,7 +2705,8 @@ impl<'a> Html5Parser<'a> {
                 self.reconstruct_formatting();
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
                 self.open_elements.pop();
 
                 acknowledge_closing_tag!(self, *is_self_closing);
@@ -2415,7 +2723,8 @@ impl<'a> Html5Parser<'a> {
                 ..
             } if name == "param" || name == "source" || name == "track" => {
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
                 self.open_elements.pop();
 
                 acknowledge_closing_tag!(self, *is_self_closing);
@@ -2430,7 +2739,8 @@ impl<'a> Html5Parser<'a> {
                 }
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
                 self.open_elements.pop();
 
                 acknowledge_closing_tag!(self, *is_self_closing);
@@ -2451,7 +2761,8 @@ impl<'a> Html5Parser<'a> {
             }
             Token::StartTagToken { name, .. } if name == "textarea" => {
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
                 self.open_elements.pop();
 
                 // @TODO: if next token == LF, ignore and move on to the next one
@@ -2484,7 +2795,8 @@ impl<'a> Html5Parser<'a> {
                 self.reconstruct_formatting();
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
                 self.open_elements.pop();
 
                 self.frameset_ok = false;
@@ -2508,7 +2820,8 @@ impl<'a> Html5Parser<'a> {
                 self.reconstruct_formatting();
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::StartTagToken { name, .. } if name == "rb" || name == "rtc" => {
                 if self.in_scope("ruby", Scope::Regular) {
@@ -2520,7 +2833,8 @@ impl<'a> Html5Parser<'a> {
                 }
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::StartTagToken { name, .. } if name == "rp" || name == "rt" => {
                 if self.in_scope("ruby", Scope::Regular) {
@@ -2532,7 +2846,8 @@ impl<'a> Html5Parser<'a> {
                 }
 
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::StartTagToken {
                 name,
@@ -2602,7 +2917,33 @@ impl<'a> Html5Parser<'a> {
         }
 
         if any_other_end_tag {
-            // @TODO: do stuff
+            self.handle_any_other_end_tag_in_body();
+        }
+    }
+
+    // Closes the nearest open element with the name of the end tag, unless a special element
+    // comes first, in which case the token is ignored
+    fn handle_any_other_end_tag_in_body(&mut self) {
+        let subject = match &self.current_token {
+            Token::EndTagToken { name, .. } => name.clone(),
+            _ => return,
+        };
+
+        for idx in (0..self.open_elements.len()).rev() {
+            let node = open_elements_get!(self, idx);
+            if node.name == subject && node.namespace.as_deref() == Some(HTML_NAMESPACE) {
+                self.generate_all_implied_end_tags(Some(&subject), false);
+                if current_node!(self).name != subject {
+                    self.parse_error("end tag not at top of stack");
+                }
+                self.open_elements.truncate(idx);
+                return;
+            }
+            if node.is_special() {
+                self.parse_error("end tag closes a special element");
+                // ignore token
+                return;
+            }
         }
     }
 
@@ -2612,11 +2953,13 @@ impl<'a> Html5Parser<'a> {
         match &self.current_token {
             Token::TextToken { .. } if self.current_token.is_empty_or_white() => {
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::CommentToken { .. } => {
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::DocTypeToken { .. } => {
                 self.parse_error("doctype not allowed in before head insertion mode");
@@ -2669,7 +3012,7 @@ impl<'a> Html5Parser<'a> {
                 anything_else = true;
             }
             Token::StartTagToken { name, .. } if name == "template" => {
-                self.insert_html_element(&self.current_token.clone());
+                self.insert_template_element(&self.current_token.clone());
                 self.add_marker();
                 self.frameset_ok = false;
                 self.insertion_mode = InsertionMode::InTemplate;
@@ -2716,7 +3059,95 @@ impl<'a> Html5Parser<'a> {
     }
 
     fn handle_in_template(&mut self) {
-        todo!()
+        match &self.current_token {
+            Token::TextToken { .. } => {
+                self.handle_in_body();
+            }
+            Token::CommentToken { .. } => {
+                self.handle_in_body();
+            }
+            Token::DocTypeToken { .. } => {
+                self.handle_in_body();
+            }
+            Token::StartTagToken { name, .. }
+                if name == "base"
+                    || name == "basefont"
+                    || name == "bgsound"
+                    || name == "link"
+                    || name == "meta"
+                    || name == "noframes"
+                    || name == "script"
+                    || name == "style"
+                    || name == "template"
+                    || name == "title" =>
+            {
+                self.handle_in_head();
+            }
+            Token::EndTagToken { name, .. } if name == "template" => {
+                self.handle_in_head();
+            }
+            Token::StartTagToken { name, .. }
+                if name == "caption"
+                    || name == "colgroup"
+                    || name == "tbody"
+                    || name == "tfoot"
+                    || name == "thead" =>
+            {
+                self.template_insertion_mode.pop();
+                self.template_insertion_mode.push(InsertionMode::InTable);
+
+                self.insertion_mode = InsertionMode::InTable;
+                self.reprocess_token = true;
+            }
+            Token::StartTagToken { name, .. } if name == "col" => {
+                self.template_insertion_mode.pop();
+                self.template_insertion_mode
+                    .push(InsertionMode::InColumnGroup);
+
+                self.insertion_mode = InsertionMode::InColumnGroup;
+                self.reprocess_token = true;
+            }
+            Token::StartTagToken { name, .. } if name == "tr" => {
+                self.template_insertion_mode.pop();
+                self.template_insertion_mode
+                    .push(InsertionMode::InTableBody);
+
+                self.insertion_mode = InsertionMode::InTableBody;
+                self.reprocess_token = true;
+            }
+            Token::StartTagToken { name, .. } if name == "td" || name == "th" => {
+                self.template_insertion_mode.pop();
+                self.template_insertion_mode.push(InsertionMode::InRow);
+
+                self.insertion_mode = InsertionMode::InRow;
+                self.reprocess_token = true;
+            }
+            Token::StartTagToken { .. } => {
+                self.template_insertion_mode.pop();
+                self.template_insertion_mode.push(InsertionMode::InBody);
+
+                self.insertion_mode = InsertionMode::InBody;
+                self.reprocess_token = true;
+            }
+            Token::EndTagToken { .. } => {
+                self.parse_error("end tag not allowed in in template insertion mode");
+                // ignore token
+            }
+            Token::EofToken => {
+                if !open_elements_has!(self, "template") {
+                    self.stop_parsing();
+                    return;
+                }
+
+                self.parse_error("eof not allowed in in template insertion mode");
+
+                pop_until!(self, "template");
+                self.clear_active_formatting_elements_until_marker();
+                self.template_insertion_mode.pop();
+                self.reset_insertion_mode();
+                self.reprocess_token = true;
+            }
+        }
     }
 
     fn handle_in_table(&mut self) {
@@ -2735,7 +3166,8 @@ impl<'a> Html5Parser<'a> {
             }
             Token::CommentToken { .. } => {
                 let node = self.create_node(&self.current_token, HTML_NAMESPACE);
-                self.document.add_node(node, current_node!(self).id);
+                self.document
+                    .add_node(node, self.adjusted_insert_location(None));
             }
             Token::DocTypeToken { .. } => {
                 self.parse_error("doctype not allowed in in table insertion mode");
@@ -2885,58 +3317,80 @@ impl<'a> Html5Parser<'a> {
     }
 
     fn reconstruct_formatting(&mut self) {
-        // 1.
-        if self.active_formatting_elements.is_empty() {
-            return;
+        // 1. / 2. Nothing to do when the last entry is a marker or an open element
+        match self.active_formatting_elements.last() {
+            None | Some(ActiveElement::Marker) => return,
+            Some(ActiveElement::Node(node_id)) if self.open_elements.contains(node_id) => return,
+            Some(ActiveElement::Node(_)) => {}
         }
 
-        // 3.
-        let entry = self.active_formatting_elements.last().unwrap();
-
-        // 2.
-        match entry {
-            ActiveElement::Marker => return,
-            ActiveElement::Node(node_id) => {
-                let node = self.document.get_node_by_id(*node_id).unwrap();
-                if open_elements_has!(self, node.name) {
-                    return;
-                }
+        // 3. - 6. rewind to the entry after the last marker or open element
+        let mut idx = self.active_formatting_elements.len() - 1;
+        while idx > 0 {
+            match self.active_formatting_elements[idx - 1] {
+                ActiveElement::Marker => break,
+                ActiveElement::Node(node_id) if self.open_elements.contains(&node_id) => break,
+                ActiveElement::Node(_) => idx -= 1,
             }
         }
 
-        // 4. rewind:
-        let mut idx;
-        loop {
-            idx = self.active_formatting_elements.len() - 1;
-            if idx == 0 {
-                // create element
-            }
-
-            idx -= 1;
+        // 7. - 10. advance and create a new element for each entry
+        for idx in idx..self.active_formatting_elements.len() {
+            let node_id = match self.active_formatting_elements[idx] {
+                ActiveElement::Node(node_id) => node_id,
+                ActiveElement::Marker => continue,
+            };
+            let node = self.document.get_node_by_id(node_id).unwrap();
+            let attributes = match &node.data {
+                NodeData::Element { attributes, .. } => attributes.clone(),
+                _ => HashMap::new(),
+            };
+            let token = Token::StartTagToken {
+                name: node.name.clone(),
+                is_self_closing: false,
+                attributes,
+            };
 
-            match entry {
-                ActiveElement::Marker => break,
-                ActiveElement::Node(node_id) => {
-                    let node = self.document.get_node_by_id(*node_id).unwrap();
-                    if open_elements_has!(self, node.name) {
-                        break;
-                    }
-                }
-            }
+            let new_node_id = self.insert_html_element(&token);
+            self.active_formatting_elements[idx] = ActiveElement::Node(new_node_id);
         }
+    }
 
-        // 7. advance
-        loop {
-            idx += 1;
-
-            // 8/9. Create
-            // replace shzzls
+    // Pushes the element onto the list of active formatting elements. Of the identical elements
+    // after the last marker, only the last three are kept (the Noah's Ark clause).
+    fn push_active_formatting_element(&mut self, node_id: usize) {
+        let first_idx = self
+            .active_formatting_elements
+            .iter()
+            .rposition(|elem| elem == &ActiveElement::Marker)
+            .map_or(0, |idx| idx + 1);
+
+        let node = self.document.get_node_by_id(node_id).unwrap();
+        let identical: Vec<usize> = (first_idx..self.active_formatting_elements.len())
+            .filter(|&idx| match self.active_formatting_elements[idx] {
+                ActiveElement::Node(id) => self.document.get_node_by_id(id).is_some_and(|elem| {
+                    elem.name == node.name
+                        && elem.namespace == node.namespace
+                        && elem.data == node.data
+                }),
+                ActiveElement::Marker => false,
+            })
+            .collect();
+        if identical.len() >= 3 {
+            self.active_formatting_elements.remove(identical[0]);
+        }
 
-            // 10. If entry for new element is not last entry, return to advance
-            if idx == self.active_formatting_elements.len() {
-                break;
-            }
+        if self
+            .limits
+            .max_formatting_elements
+            .is_some_and(|max| self.active_formatting_elements.len() - first_idx >= max)
+        {
+            self.limit_error(ParserError::FormattingElementLimitExceeded);
+            self.active_formatting_elements.remove(first_idx);
         }
+
+        self.active_formatting_elements
+            .push(ActiveElement::Node(node_id));
     }
 
     fn stop_parsing(&self) {
@@ -2975,8 +3429,7 @@ impl<'a> Html5Parser<'a> {
         if let Token::StartTagToken { attributes, .. } = token {
             let mut new_attributes = HashMap::new();
             for (name, value) in attributes.iter() {
-                if MATHML_ADJUSTMENTS.contains_key(name) {
-                    let new_name = SVG_ADJUSTMENTS.get(name).unwrap();
+                if let Some(new_name) = MATHML_ADJUSTMENTS.get(name) {
                     new_attributes.insert(new_name.to_string(), value.clone());
                 } else {
                     new_attributes.insert(name.clone(), value.clone());
@@ -2990,9 +3443,13 @@ impl<'a> Html5Parser<'a> {
         if let Token::StartTagToken { attributes, .. } = token {
             let mut new_attributes = HashMap::new();
             for (name, value) in attributes.iter() {
-                if XML_ADJUSTMENTS.contains_key(name) {
-                    let new_name = SVG_ADJUSTMENTS.get(name).unwrap();
-                    new_attributes.insert(new_name.to_string(), value.clone());
+                // Namespaced attributes are stored by their qualified name (ie: "xlink:href")
+                if let Some((prefix, local_name, _)) = XML_ADJUSTMENTS.get(name) {
+                    let qualified_name = match *local_name {
+                        "" => prefix.to_string(),
+                        _ => format!("{}:{}", prefix, local_name),
+                    };
+                    new_attributes.insert(qualified_name, value.clone());
                 } else {
                     new_attributes.insert(name.clone(), value.clone());
                 }
@@ -3017,11 +3474,35 @@ impl<'a> Html5Parser<'a> {
         //      push new element queue onto relevant agent custom element reactions stack (???)
 
         //   insert element into adjusted_insert_location
+        let is_template = is_html_template(&node);
         let node_id = self.document.add_node(node, adjusted_insert_location);
+        if is_template {
+            self.document.create_template_contents(node_id);
+        }
 
         //     if parser not created as part of html fragment parsing algorithm
         //       pop the top element queue from the relevant agent custom element reactions stack (???)
 
+        // Beyond the maximum depth the element takes the place of the current node, which is
+        // closed. This keeps the stack of open elements (and the scope checks that walk it) small.
+        // Elements that the insertion modes rely on being open are never closed this way.
+        if self
+            .limits
+            .max_tree_depth
+            .is_some_and(|max| self.open_elements.len() > max)
+        {
+            self.limit_error(ParserError::TreeDepthLimitExceeded);
+            let current = current_node!(self);
+            let closable = current.namespace.as_deref() != Some(HTML_NAMESPACE)
+                || !DEPTH_LIMIT_KEEPS_OPEN.contains(&current.name.as_str());
+            if closable {
+                if let Some(closed_id) = self.open_elements.pop() {
+                    self.active_formatting_elements
+                        .retain(|elem| elem != &ActiveElement::Node(closed_id));
+                }
+            }
+        }
+
         // push element onto the stack of open elements so that is the new current node
         self.open_elements.push(node_id);
 
@@ -3029,6 +3510,61 @@ impl<'a> Html5Parser<'a> {
         node_id
     }
 
+    // Inserts a template element. When the template has a valid shadowrootmode attribute, a
+    // declarative shadow root is attached to the current node instead, and the template (which
+    // is not inserted into the tree) gets the shadow root as its contents.
+    fn insert_template_element(&mut self, token: &Token) {
+        let attributes = match token {
+            Token::StartTagToken { attributes, .. } => attributes,
+            _ => return,
+        };
+
+        let mode = match attributes
+            .get("shadowrootmode")
+            .map(|v| v.to_ascii_lowercase())
+        {
+            Some(mode) if mode == "open" => ShadowRootMode::Open,
+            Some(mode) if mode == "closed" => ShadowRootMode::Closed,
+            _ => {
+                self.insert_html_element(token);
+                return;
+            }
+        };
+
+        // Declarative shadow roots are not attached when parsing fragments
+        let host_id = current_node!(self).id;
+        if self.is_fragment_case
+            || self.open_elements.first() == Some(&host_id)
+            || self.document.shadow_root(host_id).is_some()
+        {
+            self.insert_html_element(token);
+            return;
+        }
+
+        let init = ShadowRootInit {
+            mode,
+            delegates_focus: attributes.contains_key("shadowrootdelegatesfocus"),
+            slot_assignment: SlotAssignmentMode::Named,
+            clonable: attributes.contains_key("shadowrootclonable"),
+            serializable: attributes.contains_key("shadowrootserializable"),
+        };
+        match self.document.attach_shadow_root(host_id, init, true) {
+            Ok(root_id) => {
+                let node = self.create_node(token, HTML_NAMESPACE);
+                let template_id = self.document.add_detached_node(node);
+                self.document.set_template_contents(template_id, root_id);
+                self.open_elements.push(template_id);
+            }
+            Err(err) => {
+                self.parse_error(&format!(
+                    "could not attach declarative shadow root: {}",
+                    err
+                ));
+                self.insert_html_element(token);
+            }
+        }
+    }
+
     fn parse_raw_data(&mut self) {
         todo!()
     }
@@ -3038,9 +3574,13 @@ impl<'a> Html5Parser<'a> {
     }
 
     fn adjusted_insert_location(&self, override_node: Option<&Node>) -> usize {
-        let target = match override_node {
-            Some(node) => node,
-            None => current_node!(self),
+        let target = match (override_node, self.limits.max_tree_depth) {
+            (Some(node), _) => node,
+            // Nodes that would be nested too deep go into the open element at the maximum depth
+            (None, Some(max)) if self.open_elements.len() > max => {
+                open_elements_get!(self, max.max(1) - 1)
+            }
+            (None, _) => current_node!(self),
         };
 
         let mut adjusted_insertion_location = target.id;
@@ -3071,11 +3611,414 @@ impl<'a> Html5Parser<'a> {
             adjusted_insertion_location = target.id
         }
 
-        if target.name == "template" {
-            // @todo!()
-            // be the content
+        // Nodes inserted into a template end up in its template contents instead
+        if target.name == "template" && target.namespace.as_deref() == Some(HTML_NAMESPACE) {
+            if let Some(contents_id) = self.document.template_contents(target.id) {
+                adjusted_insertion_location = contents_id;
+            }
         }
 
         adjusted_insertion_location
     }
 }
+
+// Returns true when the node is a template element in the HTML namespace
+fn is_html_template(node: &Node) -> bool {
+    node.is_element()
+        && node.name == "template"
+        && node.namespace.as_deref() == Some(HTML_NAMESPACE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_contents() {
+        let mut stream = InputStream::new();
+        stream.read_from_str("<div><template><p>a</p></template></div>", None);
+        let mut parser = Html5Parser::new(&mut stream);
+        let (document, _) = parser.parse();
+
+        let template = document.get_elements_by_tag_name("template");
+        let template_id = template.item(document, 0).unwrap();
+        assert!(document
+            .get_node_by_id(template_id)
+            .unwrap()
+            .children
+            .is_empty());
+
+        let contents_id = document.template_contents(template_id).unwrap();
+        let contents = document.get_node_by_id(contents_id).unwrap();
+        assert_eq!(contents.children.len(), 1);
+        assert_eq!(
+            document.get_node_by_id(contents.children[0]).unwrap().name,
+            "p"
+        );
+
+        // Template contents are not part of the document tree
+        assert_eq!(document.get_elements_by_tag_name("p").length(document), 0);
+    }
+
+    #[test]
+    fn test_declarative_shadow_root() {
+        let mut stream = InputStream::new();
+        stream.read_from_str(
+            "<div id=host><template shadowrootmode=closed shadowrootdelegatesfocus><span>a</span></template>b</div>\
+             <div id=other><template shadowrootmode=bogus></template></div>",
+            None,
+        );
+        let mut parser = Html5Parser::new(&mut stream);
+        let (document, _) = parser.parse();
+
+        let host_id = document.get_element_by_id("host").unwrap();
+        let root_id = document.shadow_root(host_id).unwrap();
+        let shadow_root = document.shadow_root_info(root_id).unwrap();
+        assert_eq!(shadow_root.mode, ShadowRootMode::Closed);
+        assert!(shadow_root.delegates_focus);
+        assert!(shadow_root.declarative);
+
+        // The template itself is not inserted, only its contents end up in the shadow root
+        let children = &document.get_node_by_id(host_id).unwrap().children;
+        assert_eq!(children.len(), 1);
+        let root = document.get_node_by_id(root_id).unwrap();
+        assert_eq!(
+            document.get_node_by_id(root.children[0]).unwrap().name,
+            "span"
+        );
+
+        // Closed shadow roots are not exposed on the element
+        assert_eq!(document.element(host_id).unwrap().shadow_root(), None);
+
+        // An invalid mode results in a regular template
+        let other_id = document.get_element_by_id("other").unwrap();
+        assert_eq!(document.shadow_root(other_id), None);
+        assert_eq!(
+            document
+                .get_elements_by_tag_name("template")
+                .length(document),
+            1
+        );
+    }
+
+    fn parse_with_limits(html: &str, limits: ParserLimits) -> (Document, Vec<String>) {
+        let mut stream = InputStream::new();
+        stream.read_from_str(html, None);
+        let options = ParserOptions {
+            limits,
+            ..Default::default()
+        };
+        let mut parser = Html5Parser::new_with_options(&mut stream, options);
+        let errors = parser
+            .parse()
+            .1
+            .into_iter()
+            .map(|error| error.message)
+            .collect();
+        (parser.into_document(), errors)
+    }
+
+    fn count_errors(errors: &[String], error: ParserError) -> usize {
+        errors.iter().filter(|e| *e == error.as_str()).count()
+    }
+
+    #[test]
+    fn test_tree_depth_limit() {
+        let limits = ParserLimits {
+            max_tree_depth: Some(4),
+            ..Default::default()
+        };
+        let html = "<div><div><div><p>a</p></div></div></div>";
+        let (document, errors) = parse_with_limits(html, limits);
+
+        // The p and its text would be nested in five elements, so they end up in the second div
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <html>\n|   <head>\n|   <body>\n|     <div>\n|       <div>\n\
+             |         <div>\n|         <p>\n|         \"a\"\n"
+        );
+        assert_eq!(
+            count_errors(&errors, ParserError::TreeDepthLimitExceeded),
+            1
+        );
+
+        // The html, body and table elements stay open, even beyond the limit
+        let limits = ParserLimits {
+            max_tree_depth: Some(1),
+            ..Default::default()
+        };
+        let (document, errors) = parse_with_limits("<p><hr></p>", limits);
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <html>\n|   <head>\n|   <body>\n|   <p>\n|   <hr>\n|   <p>\n"
+        );
+        assert_eq!(
+            count_errors(&errors, ParserError::TreeDepthLimitExceeded),
+            1
+        );
+
+        let limits = ParserLimits {
+            max_tree_depth: Some(4),
+            ..Default::default()
+        };
+        let html = "<!DOCTYPE html><body><table><tbody><tr><td><svg><g>foo</g><g>bar</g></svg>\
+                    </td></tr></tbody></table>";
+        let (document, _) = parse_with_limits(html, limits);
+        assert_eq!(document.get_elements_by_tag_name("g").length(&document), 2);
+    }
+
+    #[test]
+    fn test_node_and_time_limits() {
+        let html = "<p>a".repeat(100);
+        let limits = ParserLimits {
+            max_nodes: Some(20),
+            ..Default::default()
+        };
+        let (document, errors) = parse_with_limits(&html, limits);
+        assert!(document.node_count() > 20 && document.node_count() < 25);
+        assert_eq!(count_errors(&errors, ParserError::NodeLimitExceeded), 1);
+
+        let limits = ParserLimits {
+            max_parse_time: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let (document, errors) = parse_with_limits(&html, limits);
+        assert_eq!(document.node_count(), 1);
+        assert_eq!(
+            count_errors(&errors, ParserError::ParseTimeLimitExceeded),
+            1
+        );
+    }
+
+    #[test]
+    fn test_input_and_attribute_limits() {
+        let limits = ParserLimits {
+            max_input_size: Some(20),
+            max_attributes: Some(2),
+            max_attribute_value_length: Some(3),
+            ..Default::default()
+        };
+        let (document, errors) = parse_with_limits("<p a=1 b=12345 c>abcdefgh", limits);
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <html>\n|   <head>\n|   <body>\n|     <p>\n\
+             |       a=\"1\"\n|       b=\"123\"\n|       \"abc\"\n"
+        );
+        assert_eq!(
+            count_errors(&errors, ParserError::InputSizeLimitExceeded),
+            1
+        );
+        assert_eq!(
+            count_errors(&errors, ParserError::AttributeLimitExceeded),
+            1
+        );
+        assert_eq!(
+            count_errors(&errors, ParserError::AttributeValueLengthLimitExceeded),
+            1
+        );
+
+        // The input is only cut off for the parser
+        let mut stream = InputStream::new();
+        stream.read_from_str("<p>abcdefgh", None);
+        let options = ParserOptions {
+            limits: ParserLimits {
+                max_input_size: Some(5),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut parser = Html5Parser::new_with_options(&mut stream, options);
+        assert_eq!(
+            parser
+                .parse()
+                .0
+                .to_html5lib_tree()
+                .matches("\"ab\"")
+                .count(),
+            1
+        );
+        drop(parser);
+        assert_eq!(stream.length, 11);
+    }
+
+    #[test]
+    fn test_formatting_element_limit() {
+        // Every p reopens all b elements, as they have different attributes
+        let html: String = (0..200).map(|i| format!("<b id={}><p>x", i)).collect();
+        let (document, errors) = parse_with_limits(&html, ParserLimits::default());
+        assert!(document.node_count() > 20_000);
+        assert_eq!(
+            count_errors(&errors, ParserError::FormattingElementLimitExceeded),
+            0
+        );
+
+        let limits = ParserLimits {
+            max_formatting_elements: Some(10),
+            ..Default::default()
+        };
+        let (document, errors) = parse_with_limits(&html, limits);
+        assert!(document.node_count() < 200 * 13);
+        assert_eq!(
+            count_errors(&errors, ParserError::FormattingElementLimitExceeded),
+            1
+        );
+
+        // Identical elements are reopened three times at most, and nobr runs the adoption agency
+        let html = "<a href=x><p>".repeat(200) + &"<nobr><nobr>x".repeat(200);
+        let (document, _) = parse_with_limits(&html, ParserLimits::default());
+        assert!(document.node_count() < 200 * 10);
+    }
+
+    #[test]
+    fn test_progress() {
+        let html = "<p>a\u{e9}\u{20ac}</p>".repeat(10);
+        let mut stream = InputStream::new();
+        stream.read_from_str(&html, None);
+        let progress = RefCell::new(Vec::new());
+        let mut parser = Html5Parser::new(&mut stream);
+        parser.on_progress(|p| progress.borrow_mut().push(p));
+        parser.parse();
+        drop(parser);
+
+        let progress = progress.into_inner();
+        assert!(progress.windows(2).all(|p| p[0].consumed <= p[1].consumed));
+        // Progress is counted in bytes, not chars
+        assert!(progress.iter().all(|p| p.length == html.len()));
+        assert_eq!(progress.last().unwrap().consumed, html.len());
+        assert_eq!(progress.last().unwrap().fraction(), 1.0);
+    }
+
+    #[test]
+    fn test_cancellation() {
+        let html = "<div><p>a</p><table><tr><td>b</td></tr></table></div>".repeat(20);
+        let mut stream = InputStream::new();
+        stream.read_from_str(&html, None);
+        let token = CancellationToken::new();
+        let mut parser = Html5Parser::new(&mut stream);
+        parser.set_cancellation_token(token.clone());
+        let cancel = token.clone();
+        parser.on_progress(move |p| {
+            if p.fraction() >= 0.5 {
+                cancel.cancel();
+            }
+        });
+        let (_, errors) = parser.parse();
+        assert!(errors.iter().any(|e| e.message == "parse-cancelled"));
+        let document = parser.into_document();
+        assert!(token.is_cancelled());
+
+        // The document has the first half of the divs, and all nodes are linked both ways
+        let divs = document.get_elements_by_tag_name("div").length(&document);
+        assert!(divs > 5 && divs < 15);
+        for id in document.descendants(0) {
+            for child_id in &document.get_node_by_id(id).unwrap().children {
+                let child = document.get_node_by_id(*child_id).unwrap();
+                assert_eq!(child.parent, Some(id));
+            }
+        }
+
+        // A parser with a cancelled token does not parse anything
+        let mut stream = InputStream::new();
+        stream.read_from_str(&html, None);
+        let mut parser = Html5Parser::new(&mut stream);
+        parser.set_cancellation_token(token);
+        let (document, errors) = parser.parse();
+        assert_eq!(document.node_count(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "parse-cancelled");
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut stream = InputStream::new();
+        stream.read_from_str("<b><p>x</b><table><caption>y<svg>", None);
+        let options = ParserOptions {
+            trace: true,
+            ..Default::default()
+        };
+        let mut parser = Html5Parser::new_with_options(&mut stream, options);
+        parser.parse();
+        let trace = parser.trace().unwrap();
+
+        // The b start tag is reprocessed until the parser is in body
+        assert_eq!(trace.steps.len(), 13);
+        let modes: Vec<&str> = trace.steps[..6]
+            .iter()
+            .map(|step| step.mode_before.as_str())
+            .collect();
+        assert_eq!(
+            modes,
+            [
+                "Initial",
+                "BeforeHtml",
+                "BeforeHead",
+                "InHead",
+                "AfterHead",
+                "InBody"
+            ]
+        );
+        assert!(!trace.steps[0].reprocessed && trace.steps[5].reprocessed);
+        assert_eq!(trace.steps[5].open_elements, ["html", "body", "b"]);
+
+        let caption = &trace.steps[10];
+        assert_eq!(caption.token, "<caption>");
+        assert_eq!(caption.mode_after, "InCaption");
+        assert_eq!(caption.active_formatting_elements, ["b", "marker"]);
+        assert_eq!(
+            trace.steps[12].open_elements.last().map(String::as_str),
+            Some("svg svg")
+        );
+
+        assert!(trace.to_report().contains(
+            "#11 <caption>\n    mode:        InTable -> InCaption\n    \
+             open:        html > body > b > table > caption\n    formatting:  b, marker\n"
+        ));
+        assert!(trace.to_report().starts_with(
+            "#1 <b>\n    mode:        Initial -> BeforeHtml\n    open:        (none)\n"
+        ));
+        assert_eq!(ParserTrace::from_json(&trace.to_json()).unwrap(), *trace);
+
+        assert_eq!(trace.steps[0].tag_name.as_deref(), Some("b"));
+        assert_eq!(trace.steps[7].tag_name, None);
+        assert_eq!(
+            trace.steps[0].errors,
+            [ParserError::ExpectedDocTypeButGotStartTag.as_str()]
+        );
+        assert!(trace.steps[1].errors.is_empty());
+    }
+
+    #[test]
+    fn test_trace_callback() {
+        let mut stream = InputStream::new();
+        stream.read_from_str("<!DOCTYPE html><p>a<p>b", None);
+        let steps = RefCell::new(Vec::new());
+        let mut parser = Html5Parser::new(&mut stream);
+        parser.on_trace_step(|step, document| {
+            steps
+                .borrow_mut()
+                .push((step.token.clone(), document.node_count()));
+        });
+        parser.parse();
+        drop(parser);
+
+        // The partial document grows with every step
+        let steps = steps.into_inner();
+        let tokens: Vec<&str> = steps.iter().map(|(token, _)| token.as_str()).collect();
+        assert_eq!(
+            tokens,
+            [
+                "<!DOCTYPE html />",
+                "<p>",
+                "<p>",
+                "<p>",
+                "<p>",
+                "<p>",
+                "a",
+                "<p>",
+                "b"
+            ]
+        );
+        assert!(steps.windows(2).all(|s| s[0].1 <= s[1].1));
+        assert_eq!(steps.last().unwrap().1, 9);
+    }
+}
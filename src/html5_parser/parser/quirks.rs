@@ -1,6 +1,6 @@
 use crate::html5_parser::parser::Html5Parser;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Copy, Clone)]
 pub enum QuirksMode {
     Quirks,
     LimitedQuirks,
@@ -0,0 +1,96 @@
+use serde_derive::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Trace of the tree construction, recorded when ParserOptions.trace is set. Every step is a
+/// token that is processed in an insertion mode, so a reprocessed token has a step for every
+/// time it is processed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParserTrace {
+    pub steps: Vec<TraceStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceStep {
+    /// The token as displayed by Token
+    pub token: String,
+    /// Name of the token when it is a start or end tag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_name: Option<String>,
+    /// True when the token is reprocessed, after an earlier step asked for it
+    pub reprocessed: bool,
+    /// Insertion mode the token is processed in
+    pub mode_before: String,
+    /// Insertion mode after the token is processed
+    pub mode_after: String,
+    /// The stack of open elements after the token, from the html element to the current node.
+    /// Foreign elements are prefixed with their namespace, as in "svg circle".
+    pub open_elements: Vec<String>,
+    /// The list of active formatting elements after the token, with markers as "marker"
+    pub active_formatting_elements: Vec<String>,
+    /// Parse errors logged while reading and processing the token. Errors of the tokenizer belong
+    /// to the first step of a reprocessed token.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl ParserTrace {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("trace is valid json")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Renders the trace as a report with a numbered block for every step
+    pub fn to_report(&self) -> String {
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(idx, step)| step.to_report(idx + 1))
+            .collect()
+    }
+}
+
+impl TraceStep {
+    /// Renders the step as a block of the report, with the given step number
+    pub fn to_report(&self, number: usize) -> String {
+        let mut report = String::new();
+        let _ = write!(report, "#{} {}", number, self.token.escape_debug());
+        if self.reprocessed {
+            report.push_str(" (reprocessed)");
+        }
+        report.push('\n');
+
+        if self.mode_before == self.mode_after {
+            let _ = writeln!(report, "    mode:        {}", self.mode_before);
+        } else {
+            let _ = writeln!(
+                report,
+                "    mode:        {} -> {}",
+                self.mode_before, self.mode_after
+            );
+        }
+        let _ = writeln!(
+            report,
+            "    open:        {}",
+            list(&self.open_elements, " > ")
+        );
+        let _ = writeln!(
+            report,
+            "    formatting:  {}",
+            list(&self.active_formatting_elements, ", ")
+        );
+        if !self.errors.is_empty() {
+            let _ = writeln!(report, "    errors:      {}", self.errors.join(", "));
+        }
+        report
+    }
+}
+
+fn list(items: &[String], separator: &str) -> String {
+    if items.is_empty() {
+        return "(none)".to_string();
+    }
+    items.join(separator)
+}
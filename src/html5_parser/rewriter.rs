@@ -0,0 +1,1205 @@
+use crate::html5_parser::error_logger::ErrorLogger;
+use crate::html5_parser::input_stream::InputStream;
+use crate::html5_parser::node::SPECIAL_HTML_ELEMENTS;
+use crate::html5_parser::selector::{
+    CompoundSelector, SelectorError, SelectorList, SimpleSelector,
+};
+use crate::html5_parser::serializer::walker::VOID_ELEMENTS;
+use crate::html5_parser::tokenizer::raw_token::RawToken;
+use crate::html5_parser::tokenizer::state::State;
+use crate::html5_parser::tokenizer::token::Token;
+use crate::html5_parser::tokenizer::{Options, Tokenizer};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// Minimum number of bytes that are buffered before they are tokenized
+const MIN_RUN_LEN: usize = 64 * 1024;
+
+// Start tags that close an open p element (in button scope)
+const CLOSES_P: [&str; 40] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "center",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "listing",
+    "main",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "plaintext",
+    "pre",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+const HEADINGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+// HTML elements that end the search for an open element in scope
+const SCOPE_BOUNDARIES: [&str; 9] = [
+    "applet", "caption", "html", "table", "td", "th", "marquee", "object", "template",
+];
+
+// Foreign elements that end the search for an open element in scope. Their content is HTML.
+const FOREIGN_SCOPE_BOUNDARIES: [&str; 9] = [
+    "mi",
+    "mo",
+    "mn",
+    "ms",
+    "mtext",
+    "annotation-xml",
+    "foreignobject",
+    "desc",
+    "title",
+];
+
+// Start tags that end foreign content
+const FOREIGN_BREAKOUT: [&str; 44] = [
+    "b",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "center",
+    "code",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "embed",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "hr",
+    "i",
+    "img",
+    "li",
+    "listing",
+    "menu",
+    "meta",
+    "nobr",
+    "ol",
+    "p",
+    "pre",
+    "ruby",
+    "s",
+    "small",
+    "span",
+    "strong",
+    "strike",
+    "sub",
+    "sup",
+    "table",
+    "tt",
+    "u",
+    "ul",
+    "var",
+];
+
+/// How inserted content is written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentType {
+    /// The content is HTML and is written as is
+    Html,
+    /// The content is text, and "&", "<" and ">" are escaped
+    Text,
+}
+
+type ElementHandler<'h> = Box<dyn FnMut(&mut Element) + 'h>;
+
+/// A streaming HTML rewriter. The input is tokenized in chunks and written to the output as it
+/// comes in, with changes made by the handlers of the elements that match their selectors. No
+/// document is built. Text and comments are written as they come in, so only an unfinished tag
+/// at the end of the input is buffered.
+///
+/// Elements are matched on their start tag, so only selectors without combinators and
+/// pseudo-classes are supported (like `a[href]`, `img.logo` or `#main`). Open elements are
+/// tracked with a simplified version of the tree construction rules, so elements that are closed
+/// implicitly (like a `p` followed by a `div`, or an `li` followed by the next `li`) end where the
+/// parser would end them. Only elements that are still open are kept.
+pub struct HtmlRewriter<'h, W: Write> {
+    output: W,
+    handlers: Vec<(SelectorList, ElementHandler<'h>)>,
+    undecoded: Vec<u8>, // Bytes of an incomplete UTF-8 sequence at the end of the input
+    pending: String,    // Input that has not been written to the output yet
+    run_len: usize,     // Length of pending from which it is tokenized
+    state: State,       // Tokenizer state at the start of pending
+    last_start_tag: String, // Last start tag before pending
+    open_elements: Vec<OpenElement>, // Elements of which the end tag is not found yet
+    open_names: HashMap<String, usize>, // Number of open elements per name
+    removed_depth: usize, // Number of open elements that are removed with their content
+}
+
+// An element that is waiting for its end tag
+struct OpenElement {
+    name: String,
+    foreign: bool, // Element in svg or math content
+    matched: Option<MatchedElement>,
+}
+
+// Content that a handler added to the end of, or after, an open element
+struct MatchedElement {
+    append: String,
+    after: String,
+    removed: bool,
+}
+
+impl OpenElement {
+    // Returns true when the search for an element in scope stops at this element
+    fn is_scope_boundary(&self, extra_boundaries: &[&str]) -> bool {
+        let name = self.name.as_str();
+        if self.foreign {
+            FOREIGN_SCOPE_BOUNDARIES.contains(&name)
+        } else {
+            SCOPE_BOUNDARIES.contains(&name) || extra_boundaries.contains(&name)
+        }
+    }
+
+    fn is_special(&self) -> bool {
+        !self.foreign
+            && (SPECIAL_HTML_ELEMENTS.contains(&self.name.as_str()) || self.name == "address")
+    }
+}
+
+impl<'h, W: Write> HtmlRewriter<'h, W> {
+    /// Creates a rewriter that writes to the given output
+    pub fn new(output: W) -> Self {
+        HtmlRewriter {
+            output,
+            handlers: Vec::new(),
+            undecoded: Vec::new(),
+            pending: String::new(),
+            run_len: MIN_RUN_LEN,
+            state: State::DataState,
+            last_start_tag: String::new(),
+            open_elements: Vec::new(),
+            open_names: HashMap::new(),
+            removed_depth: 0,
+        }
+    }
+
+    /// Registers a handler that is called for every element that matches the selectors. When
+    /// more handlers match an element, they are called in the order they were registered.
+    pub fn on<F>(&mut self, selectors: &str, handler: F) -> Result<(), SelectorError>
+    where
+        F: FnMut(&mut Element) + 'h,
+    {
+        let list = SelectorList::parse(selectors)?;
+        for selector in &list.selectors {
+            let unsupported = selector.compounds.len() > 1
+                || selector.compounds[0]
+                    .simple_selectors
+                    .iter()
+                    .any(|simple| matches!(simple, SimpleSelector::PseudoClass(_)));
+            if unsupported {
+                return Err(SelectorError {
+                    message: "only selectors without combinators and pseudo-classes are supported"
+                        .into(),
+                    position: 0,
+                });
+            }
+        }
+
+        self.handlers.push((list, Box::new(handler)));
+        Ok(())
+    }
+
+    /// Rewrites the next chunk of the input. Chunks can end anywhere, even within a char.
+    pub fn write_chunk(&mut self, chunk: &[u8]) -> io::Result<()> {
+        self.decode(chunk);
+        if self.pending.len() >= self.run_len {
+            self.run(false)?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites the rest of the input and returns the output
+    pub fn end(mut self) -> io::Result<W> {
+        if !self.undecoded.is_empty() {
+            self.undecoded.clear();
+            self.pending.push(char::REPLACEMENT_CHARACTER);
+        }
+        self.run(true)?;
+
+        // Elements that were never closed get their content at the end
+        while !self.open_elements.is_empty() {
+            self.pop_element(None)?;
+        }
+
+        self.output.flush()?;
+        Ok(self.output)
+    }
+
+    // Adds the chunk to the pending input. Invalid UTF-8 is replaced by U+FFFD, and an incomplete
+    // sequence at the end is kept until the next chunk.
+    fn decode(&mut self, chunk: &[u8]) {
+        self.undecoded.extend_from_slice(chunk);
+
+        let mut start = 0;
+        loop {
+            match std::str::from_utf8(&self.undecoded[start..]) {
+                Ok(valid) => {
+                    self.pending.push_str(valid);
+                    start = self.undecoded.len();
+                    break;
+                }
+                Err(err) => {
+                    let valid_len = err.valid_up_to();
+                    let valid = &self.undecoded[start..start + valid_len];
+                    // Safe to unwrap, as from_utf8 checked this part
+                    self.pending.push_str(std::str::from_utf8(valid).unwrap());
+                    start += valid_len;
+
+                    match err.error_len() {
+                        Some(len) => {
+                            self.pending.push(char::REPLACEMENT_CHARACTER);
+                            start += len;
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        self.undecoded.drain(..start);
+    }
+
+    // Tokenizes the pending input and writes the tokens that are complete. Unless this is the
+    // last run, a token that ends at the end of the pending input might continue in the next
+    // chunk, so it is kept for the next run.
+    fn run(&mut self, is_last: bool) -> io::Result<()> {
+        let mut stream = InputStream::new();
+        stream.read_from_str(&self.pending, None);
+        let options = Options {
+            initial_state: self.state,
+            last_start_tag: self.last_start_tag.clone(),
+        };
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let mut tokenizer = Tokenizer::new(&mut stream, Some(options), error_logger);
+
+        let mut done = 0;
+        loop {
+            let token = tokenizer.next_raw_token();
+            let is_eof = token.token.is_eof();
+            if !is_last && (is_eof || done + token.raw.len() >= self.pending.len()) {
+                // Text and comments can be written up to the point from which they are
+                // tokenized again, so long tokens are not buffered
+                if let Some((len, state)) = self.split_point(&token) {
+                    if self.removed_depth == 0 {
+                        self.output.write_all(&token.raw.as_bytes()[..len])?;
+                    }
+                    done += len;
+                    self.state = state;
+                }
+                break;
+            }
+
+            done += token.raw.len();
+            let is_start_tag = matches!(token.token, Token::StartTagToken { .. });
+            self.rewrite_token(token)?;
+            // The content of elements like script and title is tokenized differently. In the
+            // parser, the tree builder switches the tokenizer state for them.
+            if is_start_tag {
+                tokenizer.state = self.state;
+            }
+
+            if is_eof {
+                break;
+            }
+        }
+
+        self.pending.drain(..done);
+        // Tokens that are longer than a chunk are tokenized again for every chunk, so the run
+        // length grows with them to keep the total work linear
+        self.run_len = MIN_RUN_LEN.max(self.pending.len() * 2);
+        Ok(())
+    }
+
+    // Returns the length of the start of an unfinished text or comment token that can be written
+    // already, and the state in which the rest of the token is tokenized. Text is split before
+    // the last "<", which might start a tag. In script data, an escaped section ("<!--" without
+    // "-->") has its own state, so the text is split before it.
+    fn split_point(&self, token: &RawToken) -> Option<(usize, State)> {
+        let raw = token.raw.as_str();
+        let len = match (&token.token, self.state) {
+            (Token::TextToken { .. }, State::PlaintextState) => raw.len(),
+            (Token::TextToken { .. }, State::ScriptDataState) => {
+                let mut end = 0; // End of the last escaped section
+                loop {
+                    let Some(start) = raw[end..].find("<!--").map(|idx| end + idx) else {
+                        break raw[end..].rfind('<').map_or(raw.len(), |idx| end + idx);
+                    };
+                    // "<!-->" opens and closes a section
+                    match raw[start + 2..].find("-->") {
+                        Some(idx) => end = start + 2 + idx + 3,
+                        None => break start,
+                    }
+                }
+            }
+            // A CDATA section in foreign content ends at "]]>" only
+            (Token::TextToken { .. }, _) => raw
+                .find("<![CDATA[")
+                .or(raw.rfind('<'))
+                .unwrap_or(raw.len()),
+            // A comment that ends with ">" might be complete, and is written as a whole
+            (Token::CommentToken { .. }, _) if raw.ends_with('>') => return None,
+            // A dash, "<" or "!" at the end might be the start of the end of the comment. The
+            // rest of a split comment is tokenized in the comment state.
+            (Token::CommentToken { .. }, State::CommentState) => {
+                raw.trim_end_matches(['-', '<', '!']).len()
+            }
+            (Token::CommentToken { .. }, _) if raw.starts_with("<!--") => {
+                let len = raw.trim_end_matches(['-', '<', '!']).len();
+                return (len > 4).then_some((len, State::CommentState));
+            }
+            (Token::CommentToken { .. }, State::BogusCommentState) => raw.len(),
+            (Token::CommentToken { .. }, _) if raw.starts_with("<?") => {
+                return Some((raw.len(), State::BogusCommentState));
+            }
+            _ => return None,
+        };
+
+        (len > 0).then_some((len, self.state))
+    }
+
+    fn rewrite_token(&mut self, token: RawToken) -> io::Result<()> {
+        match &token.token {
+            Token::StartTagToken {
+                name,
+                is_self_closing,
+                attributes,
+            } => {
+                self.state = State::for_content_of(name);
+                self.last_start_tag.clone_from(name);
+
+                // Void elements have no end tag, and neither have self-closing foreign elements
+                let is_foreign = self.in_foreign_content() || name == "svg" || name == "math";
+                let has_end_tag =
+                    !(VOID_ELEMENTS.contains(&name.as_str()) || (*is_self_closing && is_foreign));
+
+                self.close_implied_by_start_tag(name)?;
+
+                if self.removed_depth > 0 {
+                    if has_end_tag {
+                        self.push_element(name, None);
+                    }
+                    return Ok(());
+                }
+
+                let mut element: Option<Element> = None;
+                for (selectors, handler) in self.handlers.iter_mut() {
+                    if selectors
+                        .selectors
+                        .iter()
+                        .any(|selector| compound_matches(&selector.compounds[0], name, attributes))
+                    {
+                        let element = element.get_or_insert_with(|| {
+                            Element::new(&token, name, attributes, has_end_tag)
+                        });
+                        handler(element);
+                    }
+                }
+
+                let Some(element) = element else {
+                    if has_end_tag {
+                        self.push_element(name, None);
+                    }
+                    return self.output.write_all(token.raw.as_bytes());
+                };
+                self.write_element(element, name, has_end_tag)
+            }
+            Token::EndTagToken { name, .. } => {
+                self.state = State::DataState;
+
+                match self.closed_by_end_tag(name) {
+                    Some(idx) => self.pop_elements_through(idx, Some(&token.raw)),
+                    None if self.removed_depth > 0 => Ok(()),
+                    None => self.output.write_all(token.raw.as_bytes()),
+                }
+            }
+            Token::CommentToken { .. } => {
+                // A comment that was split over runs ends in the data state
+                self.state = State::DataState;
+                if self.removed_depth > 0 {
+                    return Ok(());
+                }
+                self.output.write_all(token.raw.as_bytes())
+            }
+            _ if self.removed_depth > 0 => Ok(()),
+            _ => self.output.write_all(token.raw.as_bytes()),
+        }
+    }
+
+    // Returns true when the current node is a foreign element that does not have HTML content
+    fn in_foreign_content(&self) -> bool {
+        self.open_elements
+            .last()
+            .is_some_and(|element| element.foreign && !element.is_scope_boundary(&[]))
+    }
+
+    // Closes the open elements that the start tag closes in the parser: a p before a block, an li
+    // before the next li, a table cell before the next cell and so on.
+    fn close_implied_by_start_tag(&mut self, name: &str) -> io::Result<()> {
+        if self.in_foreign_content() {
+            if !FOREIGN_BREAKOUT.contains(&name) {
+                return Ok(());
+            }
+            while self.in_foreign_content() {
+                self.pop_element(None)?;
+            }
+        }
+
+        // An li closes the previous li, unless there is a special element (like a nested list)
+        // in between. The same goes for dd and dt.
+        let list_items: &[&str] = match name {
+            "li" => &["li"],
+            "dd" | "dt" => &["dd", "dt"],
+            _ => &[],
+        };
+        if !list_items.is_empty() {
+            for idx in (0..self.open_elements.len()).rev() {
+                let element = &self.open_elements[idx];
+                if !element.foreign && list_items.contains(&element.name.as_str()) {
+                    self.pop_elements_through(idx, None)?;
+                    break;
+                }
+                if element.is_special() && !["address", "div", "p"].contains(&element.name.as_str())
+                {
+                    break;
+                }
+            }
+        }
+
+        if CLOSES_P.contains(&name) {
+            if let Some(idx) = self.in_scope(&["p"], &["button"]) {
+                self.pop_elements_through(idx, None)?;
+            }
+        }
+
+        let closed = match name {
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.current_node_in(&HEADINGS),
+            "option" => self.current_node_in(&["option"]),
+            "optgroup" => self
+                .current_node_in(&["option"])
+                .or_else(|| self.current_node_in(&["optgroup"])),
+            "a" => self.in_scope(&["a"], &[]),
+            "button" => self.in_scope(&["button"], &[]),
+            "td" | "th" => self.in_table_scope(&["td", "th"]),
+            "tr" => self.in_table_scope(&["tr"]),
+            "tbody" | "thead" | "tfoot" => self.in_table_scope(&["tbody", "thead", "tfoot"]),
+            "body" => self.current_node_in(&["head"]),
+            _ => None,
+        };
+        if let Some(idx) = closed {
+            self.pop_elements_through(idx, None)?;
+        }
+        // An optgroup closes both an option and the optgroup it is in
+        if name == "optgroup" {
+            if let Some(idx) = self.current_node_in(&["optgroup"]) {
+                self.pop_elements_through(idx, None)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the index of the open element that the end tag closes, or None when the parser
+    // ignores the end tag
+    fn closed_by_end_tag(&self, name: &str) -> Option<usize> {
+        // In foreign content, the end tag closes the closest foreign element with its name
+        for (idx, element) in self.open_elements.iter().enumerate().rev() {
+            if !element.foreign {
+                break;
+            }
+            if element.name.eq_ignore_ascii_case(name) {
+                return Some(idx);
+            }
+        }
+
+        match name {
+            // The body and html elements stay open until the end of the input
+            "body" | "html" | "br" => None,
+            "p" => self.in_scope(&["p"], &["button"]),
+            "li" => self.in_scope(&["li"], &["ol", "ul"]),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => self.in_scope(&HEADINGS, &[]),
+            "td" | "th" | "tr" | "tbody" | "thead" | "tfoot" | "caption" | "table" => {
+                self.in_table_scope(&[name])
+            }
+            _ if SPECIAL_HTML_ELEMENTS.contains(&name) || name == "address" => {
+                self.in_scope(&[name], &[])
+            }
+            // Other elements are closed when no special element is in between
+            _ => {
+                if !self.open_names.contains_key(name) {
+                    return None;
+                }
+                for (idx, element) in self.open_elements.iter().enumerate().rev() {
+                    if !element.foreign && element.name == name {
+                        return Some(idx);
+                    }
+                    if element.is_special() {
+                        return None;
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    // Returns the index of the closest open HTML element with one of the names, unless a scope
+    // boundary (or one of the extra boundaries) comes first
+    fn in_scope(&self, names: &[&str], extra_boundaries: &[&str]) -> Option<usize> {
+        if !names.iter().any(|name| self.open_names.contains_key(*name)) {
+            return None;
+        }
+
+        for (idx, element) in self.open_elements.iter().enumerate().rev() {
+            if !element.foreign && names.contains(&element.name.as_str()) {
+                return Some(idx);
+            }
+            if element.is_scope_boundary(extra_boundaries) {
+                return None;
+            }
+        }
+        None
+    }
+
+    // Same as in_scope, where only html, table and template are boundaries
+    fn in_table_scope(&self, names: &[&str]) -> Option<usize> {
+        if !names.iter().any(|name| self.open_names.contains_key(*name)) {
+            return None;
+        }
+
+        for (idx, element) in self.open_elements.iter().enumerate().rev() {
+            if !element.foreign && names.contains(&element.name.as_str()) {
+                return Some(idx);
+            }
+            if !element.foreign && ["html", "table", "template"].contains(&element.name.as_str()) {
+                return None;
+            }
+        }
+        None
+    }
+
+    // Returns the index of the current node when it is an HTML element with one of the names
+    fn current_node_in(&self, names: &[&str]) -> Option<usize> {
+        let idx = self.open_elements.len().checked_sub(1)?;
+        let element = &self.open_elements[idx];
+        (!element.foreign && names.contains(&element.name.as_str())).then_some(idx)
+    }
+
+    fn push_element(&mut self, name: &str, matched: Option<MatchedElement>) {
+        let foreign = self.in_foreign_content() || name == "svg" || name == "math";
+        if matched.as_ref().is_some_and(|matched| matched.removed) {
+            self.removed_depth += 1;
+        }
+        *self.open_names.entry(name.to_string()).or_default() += 1;
+        self.open_elements.push(OpenElement {
+            name: name.to_string(),
+            foreign,
+            matched,
+        });
+    }
+
+    // Closes the open elements from the current node up to and including the one at the index.
+    // The end tag, when there is one, belongs to that element.
+    fn pop_elements_through(&mut self, idx: usize, end_tag: Option<&str>) -> io::Result<()> {
+        while self.open_elements.len() > idx + 1 {
+            self.pop_element(None)?;
+        }
+        self.pop_element(end_tag)
+    }
+
+    // Closes the current node, and writes the content that was added to it and its end tag
+    fn pop_element(&mut self, end_tag: Option<&str>) -> io::Result<()> {
+        let Some(element) = self.open_elements.pop() else {
+            return Ok(());
+        };
+        if let Some(count) = self.open_names.get_mut(&element.name) {
+            *count -= 1;
+            if *count == 0 {
+                self.open_names.remove(&element.name);
+            }
+        }
+
+        let Some(matched) = element.matched else {
+            if let (Some(end_tag), 0) = (end_tag, self.removed_depth) {
+                self.output.write_all(end_tag.as_bytes())?;
+            }
+            return Ok(());
+        };
+
+        if matched.removed {
+            self.removed_depth -= 1;
+        }
+        if self.removed_depth > 0 {
+            return Ok(());
+        }
+        if !matched.removed {
+            self.output.write_all(matched.append.as_bytes())?;
+            if let Some(end_tag) = end_tag {
+                self.output.write_all(end_tag.as_bytes())?;
+            }
+        }
+        self.output.write_all(matched.after.as_bytes())
+    }
+
+    // Writes a matched element that went through its handlers
+    fn write_element(&mut self, element: Element, name: &str, has_end_tag: bool) -> io::Result<()> {
+        let mut out = element.before.clone();
+        match &element.replacement {
+            Some(replacement) => out.push_str(replacement),
+            None => {
+                element.write_start_tag(&mut out);
+                if has_end_tag {
+                    out.push_str(&element.prepend);
+                }
+            }
+        }
+
+        if has_end_tag {
+            let removed = element.replacement.is_some();
+            self.push_element(
+                name,
+                Some(MatchedElement {
+                    append: element.append,
+                    after: element.after,
+                    removed,
+                }),
+            );
+        } else {
+            out.push_str(&element.after);
+        }
+
+        self.output.write_all(out.as_bytes())
+    }
+}
+
+impl<'h, W: Write> Write for HtmlRewriter<'h, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_chunk(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+// Returns true when the start tag matches the compound selector, which has no pseudo-classes
+fn compound_matches(
+    compound: &CompoundSelector,
+    name: &str,
+    attributes: &HashMap<String, String>,
+) -> bool {
+    if let Some(type_selector) = &compound.type_selector {
+        if !name.eq_ignore_ascii_case(type_selector) {
+            return false;
+        }
+    }
+
+    compound.simple_selectors.iter().all(|simple| match simple {
+        SimpleSelector::Id(id) => attributes.get("id") == Some(id),
+        SimpleSelector::Class(class) => attributes
+            .get("class")
+            .is_some_and(|value| value.split_ascii_whitespace().any(|c| c == class)),
+        SimpleSelector::Attribute(selector) => attributes
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&selector.name))
+            .is_some_and(|(_, value)| selector.matches_value(value)),
+        SimpleSelector::PseudoClass(_) => false,
+    })
+}
+
+// An attribute of a matched element
+struct ElementAttribute {
+    name: String,
+    value: String,
+    raw: Option<String>, // Source of the attribute, or None when it is new or has a new value
+    raw_name_len: usize, // Length of the name in the source
+    gap: String,         // Whitespace (and solidus) before the attribute in the source
+}
+
+/// A start tag that matched the selectors of a handler. The handler can change its attributes,
+/// and insert content around it or replace it.
+pub struct Element {
+    name: String,
+    raw: String,
+    name_end: usize,     // End of the tag name in the raw tag
+    suffix_start: usize, // End of the last attribute in the raw tag
+    attributes: Vec<ElementAttribute>,
+    attributes_changed: bool,
+    can_have_content: bool,
+    before: String,
+    after: String,
+    prepend: String,
+    append: String,
+    replacement: Option<String>,
+}
+
+impl Element {
+    fn new(
+        token: &RawToken,
+        name: &str,
+        values: &HashMap<String, String>,
+        can_have_content: bool,
+    ) -> Self {
+        let raw = token.raw.clone();
+        let name_end = raw[1..]
+            .find(|c: char| c.is_ascii_whitespace() || c == '/' || c == '>')
+            .map_or(raw.len(), |idx| idx + 1);
+
+        let mut attributes: Vec<ElementAttribute> = Vec::new();
+        let mut end = name_end;
+        for attribute in &token.attributes {
+            // Only the first of duplicate attributes is in the token
+            let is_duplicate = attributes.iter().any(|a| a.name == attribute.name);
+            let value = match values.get(&attribute.name) {
+                Some(value) if !is_duplicate => value.clone(),
+                _ => attribute.raw_value.clone().unwrap_or_default(),
+            };
+
+            attributes.push(ElementAttribute {
+                name: attribute.name.clone(),
+                value,
+                raw: Some(attribute.raw.clone()),
+                raw_name_len: attribute.raw_name.len(),
+                gap: raw[end..attribute.offset].to_string(),
+            });
+            end = attribute.offset + attribute.raw.len();
+        }
+
+        Element {
+            name: name.to_string(),
+            raw,
+            name_end,
+            suffix_start: end,
+            attributes,
+            attributes_changed: false,
+            can_have_content,
+            before: String::new(),
+            after: String::new(),
+            prepend: String::new(),
+            append: String::new(),
+            replacement: None,
+        }
+    }
+
+    /// Returns the (lowercase) tag name
+    pub fn tag_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the value of the attribute, with character references decoded
+    pub fn get_attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.name.eq_ignore_ascii_case(name))
+            .map(|attribute| attribute.value.as_str())
+    }
+
+    pub fn has_attribute(&self, name: &str) -> bool {
+        self.get_attribute(name).is_some()
+    }
+
+    /// Returns the names and values of the attributes, in source order
+    pub fn attributes(&self) -> Vec<(&str, &str)> {
+        self.attributes
+            .iter()
+            .map(|attribute| (attribute.name.as_str(), attribute.value.as_str()))
+            .collect()
+    }
+
+    /// Sets the value of the attribute, or adds it when it does not exist
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        self.attributes_changed = true;
+
+        match self
+            .attributes
+            .iter_mut()
+            .find(|attribute| attribute.name.eq_ignore_ascii_case(name))
+        {
+            Some(attribute) => {
+                attribute.value = value.to_string();
+                attribute.raw = None;
+            }
+            None => self.attributes.push(ElementAttribute {
+                name: name.to_string(),
+                value: value.to_string(),
+                raw: None,
+                raw_name_len: name.len(),
+                gap: " ".to_string(),
+            }),
+        }
+    }
+
+    /// Removes the attribute (and any duplicates of it)
+    pub fn remove_attribute(&mut self, name: &str) {
+        let len = self.attributes.len();
+        self.attributes
+            .retain(|attribute| !attribute.name.eq_ignore_ascii_case(name));
+        self.attributes_changed |= self.attributes.len() != len;
+    }
+
+    /// Renames the attribute. Its value is kept as written.
+    pub fn rename_attribute(&mut self, name: &str, new_name: &str) {
+        let Some(attribute) = self
+            .attributes
+            .iter_mut()
+            .find(|attribute| attribute.name.eq_ignore_ascii_case(name))
+        else {
+            return;
+        };
+
+        if let Some(raw) = &attribute.raw {
+            attribute.raw = Some(format!("{}{}", new_name, &raw[attribute.raw_name_len..]));
+        }
+        attribute.name = new_name.to_string();
+        attribute.raw_name_len = new_name.len();
+        self.attributes_changed = true;
+    }
+
+    /// Inserts content before the element. Content of later calls is inserted after that of
+    /// earlier calls.
+    pub fn before(&mut self, content: &str, content_type: ContentType) {
+        self.before.push_str(&encode(content, content_type));
+    }
+
+    /// Inserts content after the element. Content of later calls is inserted before that of
+    /// earlier calls, so right after the element.
+    pub fn after(&mut self, content: &str, content_type: ContentType) {
+        self.after.insert_str(0, &encode(content, content_type));
+    }
+
+    /// Inserts content at the start of the element. Content of later calls is inserted before
+    /// that of earlier calls. Void elements cannot have content, so nothing is inserted.
+    pub fn prepend(&mut self, content: &str, content_type: ContentType) {
+        if self.can_have_content {
+            self.prepend.insert_str(0, &encode(content, content_type));
+        }
+    }
+
+    /// Inserts content at the end of the element. Content of later calls is inserted after that
+    /// of earlier calls. Void elements cannot have content, so nothing is inserted.
+    pub fn append(&mut self, content: &str, content_type: ContentType) {
+        if self.can_have_content {
+            self.append.push_str(&encode(content, content_type));
+        }
+    }
+
+    /// Replaces the element and its content with the given content
+    pub fn replace(&mut self, content: &str, content_type: ContentType) {
+        self.replacement = Some(encode(content, content_type));
+    }
+
+    /// Removes the element and its content
+    pub fn remove(&mut self) {
+        self.replacement = Some(String::new());
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.replacement.is_some()
+    }
+
+    // Writes the start tag. Attributes that did not change are written as they were.
+    fn write_start_tag(&self, out: &mut String) {
+        if !self.attributes_changed {
+            out.push_str(&self.raw);
+            return;
+        }
+
+        out.push_str(&self.raw[..self.name_end]);
+        for attribute in &self.attributes {
+            out.push_str(&attribute.gap);
+            match &attribute.raw {
+                Some(raw) => out.push_str(raw),
+                None => {
+                    out.push_str(&attribute.name);
+                    out.push_str("=\"");
+                    for c in attribute.value.chars() {
+                        match c {
+                            '&' => out.push_str("&amp;"),
+                            '"' => out.push_str("&quot;"),
+                            _ => out.push(c),
+                        }
+                    }
+                    out.push('"');
+                }
+            }
+        }
+        out.push_str(&self.raw[self.suffix_start..]);
+    }
+}
+
+fn encode(content: &str, content_type: ContentType) -> String {
+    match content_type {
+        ContentType::Html => content.to_string(),
+        ContentType::Text => {
+            let mut escaped = String::with_capacity(content.len());
+            for c in content.chars() {
+                match c {
+                    '&' => escaped.push_str("&amp;"),
+                    '<' => escaped.push_str("&lt;"),
+                    '>' => escaped.push_str("&gt;"),
+                    _ => escaped.push(c),
+                }
+            }
+            escaped
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite<'h>(html: &str, setup: impl FnOnce(&mut HtmlRewriter<'h, Vec<u8>>)) -> String {
+        let mut rewriter = HtmlRewriter::new(Vec::new());
+        setup(&mut rewriter);
+        rewriter.write_chunk(html.as_bytes()).unwrap();
+        String::from_utf8(rewriter.end().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_unchanged() {
+        let html = "<!DOCTYPE html>\r\n<P Class = 'x'>a &amp b<br/><!-- c --></p><a href";
+        assert_eq!(
+            rewrite(html, |r| r.on("a, p", |_| {}).unwrap()),
+            html.to_string()
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let html = "<a HREF='/x?a=1&amp;b=2' id=y title=\"t\">x</a><a>y</a>";
+        let output = rewrite(html, |r| {
+            r.on("a[href]", |el| {
+                let href = el.get_attribute("href").unwrap().to_string();
+                assert_eq!(href, "/x?a=1&b=2");
+                el.set_attribute("href", &format!("https://example.com{}", href));
+                el.remove_attribute("id");
+                el.rename_attribute("title", "data-title");
+                el.set_attribute("rel", "\"me\"");
+            })
+            .unwrap();
+        });
+        assert_eq!(
+            output,
+            "<a href=\"https://example.com/x?a=1&amp;b=2\" data-title=\"t\" rel=\"&quot;me&quot;\">x</a><a>y</a>"
+        );
+    }
+
+    #[test]
+    fn test_insert_content() {
+        let html = "<div class=x><div>a</div></div><img class=x>";
+        let output = rewrite(html, |r| {
+            r.on(".x", |el| {
+                el.before("<b>", ContentType::Html);
+                el.before("1", ContentType::Text);
+                el.prepend("2", ContentType::Text);
+                el.prepend("3", ContentType::Text);
+                el.append("4", ContentType::Text);
+                el.append("<5>", ContentType::Text);
+                el.after("6", ContentType::Text);
+                el.after("7", ContentType::Text);
+            })
+            .unwrap();
+        });
+        assert_eq!(
+            output,
+            "<b>1<div class=x>32<div>a</div>4&lt;5&gt;</div>76<b>1<img class=x>76"
+        );
+    }
+
+    #[test]
+    fn test_replace_and_remove() {
+        let html = "a<script src=x>if (a < b) {}</script>b<span><span>c</span>d</span>e<br>";
+        let output = rewrite(html, |r| {
+            r.on("script[src]", |el| {
+                el.replace("<!-- removed -->", ContentType::Html)
+            })
+            .unwrap();
+            r.on("span", |el| {
+                el.remove();
+                el.after("!", ContentType::Text);
+            })
+            .unwrap();
+            r.on("br", |el| el.remove()).unwrap();
+        });
+        assert_eq!(output, "a<!-- removed -->b!e");
+    }
+
+    #[test]
+    fn test_implicitly_closed_elements() {
+        let html = "<p class=ad>sponsored<div>real content</div><p>more</p>";
+        let output = rewrite(html, |r| r.on("p.ad", |el| el.remove()).unwrap());
+        assert_eq!(output, "<div>real content</div><p>more</p>");
+
+        let html = "<ul><li>a<li>b<ul><li>c</ul>d</ul><div><p>e</div>f\
+                    <table><tr><td>1<td>2<tr><td>3</table><dl><dt>g<dd>h</dl>";
+        let output = rewrite(html, |r| {
+            r.on("li, p, td, tr, dt, dd", |el| {
+                el.append("!", ContentType::Text)
+            })
+            .unwrap()
+        });
+        assert_eq!(
+            output,
+            "<ul><li>a!<li>b<ul><li>c!</ul>d!</ul><div><p>e!</div>f\
+             <table><tr><td>1!<td>2!!<tr><td>3!!</table><dl><dt>g!<dd>h!</dl>"
+        );
+
+        // Foreign content ends at an HTML element like p, which closes the svg
+        let html = "<span class=x><svg><path/><p>a</p></span>";
+        let output = rewrite(html, |r| {
+            r.on("svg, .x", |el| el.append("!", ContentType::Text))
+                .unwrap()
+        });
+        assert_eq!(output, "<span class=x><svg><path/>!<p>a</p>!</span>");
+    }
+
+    #[test]
+    fn test_large_input() {
+        let mut rewriter = HtmlRewriter::new(Vec::new());
+        rewriter
+            .on("li", |el| el.append("!", ContentType::Text))
+            .unwrap();
+        let chunk = "<li><a href=x>y</a>".repeat(1000);
+        for _ in 0..100 {
+            rewriter.write_chunk(chunk.as_bytes()).unwrap();
+            // Implicitly closed elements are not kept
+            assert!(rewriter.open_elements.len() <= 2);
+        }
+        let output = String::from_utf8(rewriter.end().unwrap()).unwrap();
+        assert_eq!(output, "<li><a href=x>y</a>!".repeat(100_000));
+    }
+
+    #[test]
+    fn test_raw_text_elements() {
+        // Tags in scripts and titles are text, so they are not matched
+        let html = "<title><a href=x></title><script>'<a href=y>'</script><a href=z>";
+        let mut hrefs = Vec::new();
+        let output = rewrite(html, |r| {
+            r.on("a", |el| {
+                hrefs.push(el.get_attribute("href").unwrap().to_string())
+            })
+            .unwrap();
+        });
+        assert_eq!(output, html);
+        assert_eq!(hrefs, ["z"]);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let html = "<p>caf\u{e9} <a href=\"/x\">x</a> &amp; <!-- c --></p>\r\n".repeat(100);
+        let setup = |r: &mut HtmlRewriter<Vec<u8>>| {
+            r.on("a", |el| el.set_attribute("href", "/y")).unwrap();
+            r.on("p", |el| el.append("!", ContentType::Text)).unwrap();
+        };
+        let expected = rewrite(&html, setup);
+        assert_eq!(
+            expected,
+            "<p>caf\u{e9} <a href=\"/y\">x</a> &amp; <!-- c -->!</p>\r\n".repeat(100)
+        );
+
+        // Write the input a byte at a time, with runs of every token
+        let mut rewriter = HtmlRewriter::new(Vec::new());
+        setup(&mut rewriter);
+        rewriter.run_len = 1;
+        for byte in html.as_bytes() {
+            rewriter.write_chunk(&[*byte]).unwrap();
+            rewriter.run_len = 1;
+        }
+        assert_eq!(
+            String::from_utf8(rewriter.end().unwrap()).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_long_tokens() {
+        let page = |n: usize| {
+            [
+                "<p>",
+                &"text ".repeat(n * 10),
+                "</p><script>",
+                &"if (a < b) {} <!-- x --> <!--<script>--> ".repeat(n),
+                "</script><svg><![CDATA[a<b]]></svg><?pi",
+                &"?".repeat(n * 10),
+                "><!--",
+                &"- ->".repeat(n * 10),
+                "--><a href=y></a><title>a<b</title><plaintext><a href=x>",
+            ]
+            .concat()
+        };
+        let rewrite_chunks = |html: &str, chunk_len: usize, run_len: usize| {
+            let mut hrefs = Vec::new();
+            let mut rewriter = HtmlRewriter::new(Vec::new());
+            rewriter
+                .on("a", |el| {
+                    hrefs.push(el.get_attribute("href").unwrap().to_string())
+                })
+                .unwrap();
+            for chunk in html.as_bytes().chunks(chunk_len) {
+                rewriter.run_len = run_len;
+                rewriter.write_chunk(chunk).unwrap();
+                // Only the end of the text and comments is kept for the next run
+                assert!(rewriter.pending.len() < MIN_RUN_LEN + chunk_len);
+            }
+            let output = String::from_utf8(rewriter.end().unwrap()).unwrap();
+            (output, hrefs)
+        };
+
+        let html = page(10_000);
+        assert_eq!(
+            rewrite_chunks(&html, 1000, MIN_RUN_LEN),
+            (html, vec!["y".to_string()])
+        );
+
+        // Split every token at every position
+        let html = page(3);
+        assert_eq!(rewrite_chunks(&html, 1, 1), (html, vec!["y".to_string()]));
+    }
+
+    #[test]
+    fn test_unsupported_selectors() {
+        let mut rewriter = HtmlRewriter::new(Vec::new());
+        assert!(rewriter.on("div > a", |_| {}).is_err());
+        assert!(rewriter.on("a:first-child", |_| {}).is_err());
+        assert!(rewriter.on("a[", |_| {}).is_err());
+        assert!(rewriter.on("A[HREF^=http i], #x, .y", |_| {}).is_ok());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::html5_parser::node::{Node, NodeData, HTML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+use crate::html5_parser::selector::{
+    AttributeOperator, AttributeSelector, Combinator, ComplexSelector, CompoundSelector, Nth,
+    PseudoClass, SelectorList, SimpleSelector,
+};
+
+// Everything that is needed to match a selector against a node in a document
+pub(crate) struct MatchContext<'a> {
+    pub document: &'a Document,
+    pub scope: Option<usize>, // Node that is matched by :scope (root element when None)
+}
+
+impl<'a> MatchContext<'a> {
+    pub fn matches_list(&self, list: &SelectorList, node_id: usize) -> bool {
+        list.selectors
+            .iter()
+            .any(|selector| self.matches_complex(selector, selector.compounds.len() - 1, node_id))
+    }
+
+    // Matches the complex selector from right to left, starting with the compound at idx
+    fn matches_complex(&self, selector: &ComplexSelector, idx: usize, node_id: usize) -> bool {
+        if !self.matches_compound(&selector.compounds[idx], node_id) {
+            return false;
+        }
+
+        if idx == 0 {
+            return true;
+        }
+
+        match selector.combinators[idx - 1] {
+            Combinator::Child => match self.parent_element(node_id) {
+                Some(parent_id) => self.matches_complex(selector, idx - 1, parent_id),
+                None => false,
+            },
+            Combinator::Descendant => {
+                let mut current = self.parent_element(node_id);
+                while let Some(ancestor_id) = current {
+                    if self.matches_complex(selector, idx - 1, ancestor_id) {
+                        return true;
+                    }
+                    current = self.parent_element(ancestor_id);
+                }
+                false
+            }
+            Combinator::NextSibling => {
+                let siblings = self.element_siblings(node_id);
+                match siblings.iter().position(|&id| id == node_id) {
+                    Some(pos) if pos > 0 => {
+                        self.matches_complex(selector, idx - 1, siblings[pos - 1])
+                    }
+                    _ => false,
+                }
+            }
+            Combinator::SubsequentSibling => {
+                let siblings = self.element_siblings(node_id);
+                siblings
+                    .iter()
+                    .take_while(|&&id| id != node_id)
+                    .any(|&id| self.matches_complex(selector, idx - 1, id))
+            }
+        }
+    }
+
+    fn matches_compound(&self, compound: &CompoundSelector, node_id: usize) -> bool {
+        let node = match self.document.get_node_by_id(node_id) {
+            Some(node) if node.is_element() => node,
+            _ => return false,
+        };
+
+        if let Some(type_selector) = &compound.type_selector {
+            if !names_match(node, &node.name, type_selector) {
+                return false;
+            }
+        }
+
+        compound
+            .simple_selectors
+            .iter()
+            .all(|simple| self.matches_simple(simple, node))
+    }
+
+    fn matches_simple(&self, simple: &SimpleSelector, node: &Node) -> bool {
+        match simple {
+            SimpleSelector::Id(id) => node.get_attribute("id") == Some(id),
+            SimpleSelector::Class(class) => node
+                .get_attribute("class")
+                .is_some_and(|value| value.split_ascii_whitespace().any(|c| c == class)),
+            SimpleSelector::Attribute(selector) => matches_attribute(selector, node),
+            SimpleSelector::PseudoClass(pseudo) => self.matches_pseudo_class(pseudo, node),
+        }
+    }
+
+    fn matches_pseudo_class(&self, pseudo: &PseudoClass, node: &Node) -> bool {
+        match pseudo {
+            PseudoClass::Not(list) => !self.matches_list(list, node.id),
+            PseudoClass::Is(list) | PseudoClass::Where(list) => self.matches_list(list, node.id),
+            PseudoClass::NthChild(nth, of) => {
+                self.matches_nth(nth, node, false, |id| self.matches_of(of, id))
+            }
+            PseudoClass::NthLastChild(nth, of) => {
+                self.matches_nth(nth, node, true, |id| self.matches_of(of, id))
+            }
+            PseudoClass::NthOfType(nth) => {
+                self.matches_nth(nth, node, false, |id| self.same_type(node, id))
+            }
+            PseudoClass::NthLastOfType(nth) => {
+                self.matches_nth(nth, node, true, |id| self.same_type(node, id))
+            }
+            PseudoClass::FirstChild => self.matches_nth(&FIRST, node, false, |_| true),
+            PseudoClass::LastChild => self.matches_nth(&FIRST, node, true, |_| true),
+            PseudoClass::OnlyChild => {
+                self.matches_nth(&FIRST, node, false, |_| true)
+                    && self.matches_nth(&FIRST, node, true, |_| true)
+            }
+            PseudoClass::FirstOfType => {
+                self.matches_nth(&FIRST, node, false, |id| self.same_type(node, id))
+            }
+            PseudoClass::LastOfType => {
+                self.matches_nth(&FIRST, node, true, |id| self.same_type(node, id))
+            }
+            PseudoClass::OnlyOfType => {
+                self.matches_nth(&FIRST, node, false, |id| self.same_type(node, id))
+                    && self.matches_nth(&FIRST, node, true, |id| self.same_type(node, id))
+            }
+            PseudoClass::Empty => node.children.iter().all(|&child_id| {
+                match self.document.get_node_by_id(child_id).map(|n| &n.data) {
+                    Some(NodeData::Element { .. }) => false,
+                    Some(NodeData::Text { value }) => value.is_empty(),
+                    _ => true,
+                }
+            }),
+            PseudoClass::Root => self.is_root(node),
+            PseudoClass::Scope => match self.scope {
+                Some(scope_id) if scope_id != 0 => node.id == scope_id,
+                _ => self.is_root(node),
+            },
+        }
+    }
+
+    // Finds the (1-based) position of the node between its element siblings that pass the
+    // filter, counted from the start or the end, and checks it against nth.
+    fn matches_nth<F>(&self, nth: &Nth, node: &Node, from_end: bool, filter: F) -> bool
+    where
+        F: Fn(usize) -> bool,
+    {
+        if !filter(node.id) {
+            return false;
+        }
+
+        let mut siblings = self.element_siblings(node.id);
+        if from_end {
+            siblings.reverse();
+        }
+
+        let mut index = 0;
+        for id in siblings {
+            if filter(id) {
+                index += 1;
+            }
+            if id == node.id {
+                return nth.matches(index);
+            }
+        }
+
+        false
+    }
+
+    fn matches_of(&self, of: &Option<SelectorList>, node_id: usize) -> bool {
+        match of {
+            Some(list) => self.matches_list(list, node_id),
+            None => true,
+        }
+    }
+
+    fn same_type(&self, node: &Node, other_id: usize) -> bool {
+        match self.document.get_node_by_id(other_id) {
+            Some(other) => other.name == node.name && other.namespace == node.namespace,
+            None => false,
+        }
+    }
+
+    fn is_root(&self, node: &Node) -> bool {
+        match node.parent.and_then(|id| self.document.get_node_by_id(id)) {
+            Some(parent) => parent.data == NodeData::Document,
+            None => false,
+        }
+    }
+
+    // Returns the parent of the node when that parent is an element
+    fn parent_element(&self, node_id: usize) -> Option<usize> {
+        let parent_id = self.document.get_node_by_id(node_id)?.parent?;
+        match self.document.get_node_by_id(parent_id) {
+            Some(parent) if parent.is_element() => Some(parent_id),
+            _ => None,
+        }
+    }
+
+    // Returns all element children of the node's parent (including the node itself)
+    fn element_siblings(&self, node_id: usize) -> Vec<usize> {
+        let parent = self
+            .document
+            .get_node_by_id(node_id)
+            .and_then(|node| node.parent)
+            .and_then(|id| self.document.get_node_by_id(id));
+
+        match parent {
+            Some(parent) => parent
+                .children
+                .iter()
+                .copied()
+                .filter(|&id| {
+                    self.document
+                        .get_node_by_id(id)
+                        .is_some_and(|n| n.is_element())
+                })
+                .collect(),
+            None => vec![node_id],
+        }
+    }
+}
+
+const FIRST: Nth = Nth { a: 0, b: 1 };
+
+// Element and attribute names are ASCII case-insensitive for elements in the HTML namespace
+fn names_match(node: &Node, name: &str, selector_name: &str) -> bool {
+    if node.namespace.as_deref() == Some(HTML_NAMESPACE) {
+        name.eq_ignore_ascii_case(selector_name)
+    } else {
+        name == selector_name
+    }
+}
+
+pub(crate) fn matches_attribute(selector: &AttributeSelector, node: &Node) -> bool {
+    let attributes = match &node.data {
+        NodeData::Element { attributes, .. } => attributes,
+        _ => return false,
+    };
+
+    let value = attributes
+        .iter()
+        .find(|(name, _)| names_match(node, name, &selector.name))
+        .map(|(_, value)| value);
+
+    match value {
+        Some(value) => matches_attribute_value(selector, value),
+        None => false,
+    }
+}
+
+// Matches the value of an attribute against the operator and value of the selector
+pub(crate) fn matches_attribute_value(selector: &AttributeSelector, value: &str) -> bool {
+    let (operator, expected) = match &selector.operator {
+        Some((operator, expected)) => (operator, expected),
+        None => return true,
+    };
+
+    let (value, expected) = if selector.case_insensitive {
+        (value.to_ascii_lowercase(), expected.to_ascii_lowercase())
+    } else {
+        (value.to_string(), expected.clone())
+    };
+
+    match operator {
+        AttributeOperator::Equals => value == expected,
+        AttributeOperator::Includes => {
+            !expected.is_empty() && value.split_ascii_whitespace().any(|v| v == expected)
+        }
+        AttributeOperator::DashMatch => {
+            value == expected || value.starts_with(&format!("{}-", expected))
+        }
+        AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(&expected),
+        AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+        AttributeOperator::Substring => !expected.is_empty() && value.contains(&expected),
+    }
+}
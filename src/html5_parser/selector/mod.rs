@@ -0,0 +1,436 @@
+mod matcher;
+mod parser;
+
+use crate::html5_parser::parser::document::Document;
+use std::fmt;
+
+/// A list of complex selectors separated by commas (ie: `div > p, span.foo`). A node matches the
+/// list when it matches any of the selectors in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectorList {
+    pub selectors: Vec<ComplexSelector>,
+}
+
+/// A sequence of compound selectors joined by combinators. The combinator at index `i` sits
+/// between the compounds at index `i` and `i + 1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComplexSelector {
+    pub compounds: Vec<CompoundSelector>,
+    pub combinators: Vec<Combinator>,
+}
+
+/// Combinators that can be found between two compound selectors
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Combinator {
+    /// `a b`
+    Descendant,
+    /// `a > b`
+    Child,
+    /// `a + b`
+    NextSibling,
+    /// `a ~ b`
+    SubsequentSibling,
+}
+
+/// A compound selector: an optional type selector followed by any number of simple selectors
+/// that must all match the same element (ie: `div#main.content[lang]:first-child`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundSelector {
+    /// Tag name to match, or None for the universal selector (or when no type is given)
+    pub type_selector: Option<String>,
+    pub simple_selectors: Vec<SimpleSelector>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimpleSelector {
+    Id(String),
+    Class(String),
+    Attribute(AttributeSelector),
+    PseudoClass(PseudoClass),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeSelector {
+    pub name: String,
+    /// Operator and value to match against. None when we only check for existence (`[href]`)
+    pub operator: Option<(AttributeOperator, String)>,
+    /// True when the `i` flag is given, so the value is matched ASCII case-insensitive
+    pub case_insensitive: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AttributeOperator {
+    /// `[attr=value]`
+    Equals,
+    /// `[attr~=value]`
+    Includes,
+    /// `[attr|=value]`
+    DashMatch,
+    /// `[attr^=value]`
+    Prefix,
+    /// `[attr$=value]`
+    Suffix,
+    /// `[attr*=value]`
+    Substring,
+}
+
+/// The `an+b` notation used by the `:nth-*` pseudo-classes
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Nth {
+    pub a: i32,
+    pub b: i32,
+}
+
+impl Nth {
+    /// Returns true when the (1-based) index matches an+b for some n >= 0
+    pub fn matches(&self, index: i32) -> bool {
+        if self.a == 0 {
+            return index == self.b;
+        }
+
+        // Widened so that index - b can't overflow for large (negative) b
+        let diff = index as i64 - self.b as i64;
+        let a = self.a as i64;
+        diff % a == 0 && diff / a >= 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PseudoClass {
+    Not(SelectorList),
+    Is(SelectorList),
+    Where(SelectorList),
+    NthChild(Nth, Option<SelectorList>),
+    NthLastChild(Nth, Option<SelectorList>),
+    NthOfType(Nth),
+    NthLastOfType(Nth),
+    FirstChild,
+    LastChild,
+    OnlyChild,
+    FirstOfType,
+    LastOfType,
+    OnlyOfType,
+    Empty,
+    Root,
+    Scope,
+}
+
+/// Error returned when a selector string cannot be parsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectorError {
+    /// Human readable description of the error
+    pub message: String,
+    /// Character offset in the selector string where the error was found
+    pub position: usize,
+}
+
+impl fmt::Display for SelectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for SelectorError {}
+
+impl AttributeSelector {
+    /// Returns true when the given attribute value matches the operator and value of the selector
+    pub fn matches_value(&self, value: &str) -> bool {
+        matcher::matches_attribute_value(self, value)
+    }
+}
+
+impl SelectorList {
+    /// Parses the given selector string into a selector list
+    pub fn parse(selector: &str) -> Result<SelectorList, SelectorError> {
+        parser::SelectorParser::new(selector).parse()
+    }
+
+    /// Returns true when the given node matches any of the selectors in this list
+    pub fn matches(&self, document: &Document, node_id: usize) -> bool {
+        self.matches_with_scope(document, node_id, None)
+    }
+
+    /// Returns true when the given node matches, where `:scope` refers to the scope node
+    pub fn matches_with_scope(
+        &self,
+        document: &Document,
+        node_id: usize,
+        scope: Option<usize>,
+    ) -> bool {
+        let ctx = matcher::MatchContext { document, scope };
+        ctx.matches_list(self, node_id)
+    }
+}
+
+impl Document {
+    /// Returns the first descendant element of node_id (in tree order) that matches the given
+    /// selectors, or None when nothing matches.
+    pub fn query_selector(
+        &self,
+        node_id: usize,
+        selectors: &str,
+    ) -> Result<Option<usize>, SelectorError> {
+        let list = SelectorList::parse(selectors)?;
+
+        Ok(self
+            .descendants(node_id)
+            .into_iter()
+            .find(|&id| list.matches_with_scope(self, id, Some(node_id))))
+    }
+
+    /// Returns all descendant elements of node_id (in tree order) that match the given selectors
+    pub fn query_selector_all(
+        &self,
+        node_id: usize,
+        selectors: &str,
+    ) -> Result<Vec<usize>, SelectorError> {
+        let list = SelectorList::parse(selectors)?;
+
+        Ok(self
+            .descendants(node_id)
+            .into_iter()
+            .filter(|&id| list.matches_with_scope(self, id, Some(node_id)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::node::{Node, HTML_NAMESPACE};
+    use std::collections::HashMap;
+
+    fn element(name: &str, attrs: &[(&str, &str)]) -> Node {
+        let mut attributes = HashMap::new();
+        for (key, value) in attrs {
+            attributes.insert(key.to_string(), value.to_string());
+        }
+        Node::new_element(name, attributes, HTML_NAMESPACE)
+    }
+
+    // <html>
+    //   <body>
+    //     <div id="main" class="content wide">
+    //       <p class="first">one</p>
+    //       <p lang="en-US">two</p>
+    //       <span>three</span>
+    //       <p>four</p>
+    //     </div>
+    //     <ul><li>a</li><li>b</li><li>c</li><li>d</li></ul>
+    //     <a href="https://example.com/page.html">link</a>
+    //     <em></em>
+    //   </body>
+    // </html>
+    fn create_document() -> Document {
+        let mut doc = Document::new();
+        let html = doc.add_node(element("html", &[]), 0);
+        let body = doc.add_node(element("body", &[]), html);
+
+        let div = doc.add_node(
+            element("div", &[("id", "main"), ("class", "content wide")]),
+            body,
+        );
+        let p1 = doc.add_node(element("p", &[("class", "first")]), div);
+        doc.add_node(Node::new_text("one"), p1);
+        let p2 = doc.add_node(element("p", &[("lang", "en-US")]), div);
+        doc.add_node(Node::new_text("two"), p2);
+        let span = doc.add_node(element("span", &[]), div);
+        doc.add_node(Node::new_text("three"), span);
+        let p3 = doc.add_node(element("p", &[]), div);
+        doc.add_node(Node::new_text("four"), p3);
+
+        let ul = doc.add_node(element("ul", &[]), body);
+        for text in ["a", "b", "c", "d"] {
+            let li = doc.add_node(element("li", &[]), ul);
+            doc.add_node(Node::new_text(text), li);
+        }
+
+        let a = doc.add_node(
+            element("a", &[("href", "https://example.com/page.html")]),
+            body,
+        );
+        doc.add_node(Node::new_text("link"), a);
+        doc.add_node(element("em", &[]), body);
+
+        doc
+    }
+
+    fn names(doc: &Document, ids: Vec<usize>) -> Vec<String> {
+        ids.iter()
+            .map(|id| {
+                let node = doc.get_node_by_id(*id).unwrap();
+                match node.get_attribute("id") {
+                    Some(id) => format!("{}#{}", node.name, id),
+                    None => node.name.clone(),
+                }
+            })
+            .collect()
+    }
+
+    fn select(doc: &Document, selector: &str) -> Vec<String> {
+        names(doc, doc.query_selector_all(0, selector).unwrap())
+    }
+
+    fn count(doc: &Document, selector: &str) -> usize {
+        doc.query_selector_all(0, selector).unwrap().len()
+    }
+
+    #[test]
+    fn test_type_id_class() {
+        let doc = create_document();
+        assert_eq!(count(&doc, "p"), 3);
+        assert_eq!(count(&doc, "P"), 3);
+        assert_eq!(count(&doc, "*"), 14);
+        assert_eq!(select(&doc, "#main"), vec!["div#main"]);
+        assert_eq!(select(&doc, "div.content.wide"), vec!["div#main"]);
+        assert_eq!(count(&doc, ".content.narrow"), 0);
+        assert_eq!(count(&doc, "p.first"), 1);
+    }
+
+    #[test]
+    fn test_attributes() {
+        let doc = create_document();
+        assert_eq!(count(&doc, "[href]"), 1);
+        assert_eq!(count(&doc, "[class=first]"), 1);
+        assert_eq!(count(&doc, "[class~=wide]"), 1);
+        assert_eq!(count(&doc, "[class~=wid]"), 0);
+        assert_eq!(count(&doc, "[lang|=en]"), 1);
+        assert_eq!(count(&doc, "[lang|=e]"), 0);
+        assert_eq!(count(&doc, "a[href^='https://']"), 1);
+        assert_eq!(count(&doc, "a[href$=\".html\"]"), 1);
+        assert_eq!(count(&doc, "a[href*=example]"), 1);
+        assert_eq!(count(&doc, "[lang=en-us]"), 0);
+        assert_eq!(count(&doc, "[lang=en-us i]"), 1);
+        assert_eq!(count(&doc, "[ LANG = 'en-US' ]"), 1);
+    }
+
+    #[test]
+    fn test_combinators() {
+        let doc = create_document();
+        assert_eq!(count(&doc, "body p"), 3);
+        assert_eq!(count(&doc, "body > p"), 0);
+        assert_eq!(count(&doc, "div > p"), 3);
+        assert_eq!(select(&doc, "p + span"), vec!["span"]);
+        assert_eq!(count(&doc, "p ~ p"), 2);
+        assert_eq!(count(&doc, "span ~ p"), 1);
+        assert_eq!(count(&doc, "html body div>p.first"), 1);
+        assert_eq!(count(&doc, "div ~ ul li"), 4);
+        assert_eq!(count(&doc, "p, li"), 7);
+    }
+
+    #[test]
+    fn test_logical_pseudo_classes() {
+        let doc = create_document();
+        assert_eq!(count(&doc, "p:not(.first)"), 2);
+        assert_eq!(count(&doc, "div > :not(p)"), 1);
+        assert_eq!(count(&doc, ":is(span, em)"), 2);
+        assert_eq!(count(&doc, ":where(ul, div) > :is(p, li)"), 7);
+        assert_eq!(count(&doc, "p:not(.first, [lang])"), 1);
+    }
+
+    #[test]
+    fn test_structural_pseudo_classes() {
+        let doc = create_document();
+        assert_eq!(count(&doc, "li:first-child"), 1);
+        assert_eq!(count(&doc, "li:last-child"), 1);
+        assert_eq!(count(&doc, "li:nth-child(2n)"), 2);
+        assert_eq!(count(&doc, "li:nth-child(odd)"), 2);
+        assert_eq!(count(&doc, "li:nth-child(-n + 2)"), 2);
+        assert_eq!(count(&doc, "li:nth-last-child(1)"), 1);
+        assert_eq!(count(&doc, "div > :nth-child(3)"), 1);
+        assert_eq!(count(&doc, "div > :nth-child(2 of p)"), 1);
+        assert_eq!(count(&doc, "p:nth-of-type(3)"), 1);
+        assert_eq!(count(&doc, "p:nth-last-of-type(3)"), 1);
+        assert_eq!(count(&doc, "p:first-of-type"), 1);
+        assert_eq!(count(&doc, "p:last-of-type"), 1);
+        assert_eq!(count(&doc, "span:only-of-type"), 1);
+        assert_eq!(count(&doc, "div:only-child"), 0);
+        assert_eq!(count(&doc, "body:only-child"), 1);
+        assert_eq!(select(&doc, ":empty"), vec!["em"]);
+        assert_eq!(select(&doc, ":root"), vec!["html"]);
+    }
+
+    #[test]
+    fn test_scoped_queries() {
+        let doc = create_document();
+        let div = doc.query_selector(0, "#main").unwrap().unwrap();
+
+        assert_eq!(doc.query_selector_all(div, "p").unwrap().len(), 3);
+        assert_eq!(doc.query_selector_all(div, "li").unwrap().len(), 0);
+        // Combinators may look outside the scope, but results are always descendants
+        assert_eq!(doc.query_selector_all(div, "body p").unwrap().len(), 3);
+        assert_eq!(
+            doc.query_selector_all(div, ":scope > span").unwrap().len(),
+            1
+        );
+        assert_eq!(doc.query_selector_all(div, "div").unwrap().len(), 0);
+
+        let first = doc.query_selector(div, "p").unwrap().unwrap();
+        assert_eq!(
+            doc.get_node_by_id(first).unwrap().get_attribute("class"),
+            Some(&"first".to_string())
+        );
+        assert_eq!(doc.query_selector(div, "table").unwrap(), None);
+    }
+
+    #[test]
+    fn test_nth() {
+        assert!(Nth { a: 2, b: 1 }.matches(1));
+        assert!(!Nth { a: 2, b: 1 }.matches(2));
+        assert!(Nth { a: 2, b: 1 }.matches(3));
+        assert!(Nth { a: 0, b: 3 }.matches(3));
+        assert!(!Nth { a: 0, b: 3 }.matches(6));
+        assert!(Nth { a: -1, b: 3 }.matches(1));
+        assert!(Nth { a: -1, b: 3 }.matches(3));
+        assert!(!Nth { a: -1, b: 3 }.matches(4));
+        assert!(!Nth { a: 3, b: 5 }.matches(2));
+        assert!(Nth { a: 1, b: -i32::MAX }.matches(1));
+        assert!(!Nth {
+            a: -1,
+            b: -i32::MAX
+        }
+        .matches(i32::MAX));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let doc = create_document();
+
+        let err = doc.query_selector(0, "").unwrap_err();
+        assert_eq!(err.to_string(), "expected selector at position 0");
+
+        let err = doc.query_selector(0, "div >").unwrap_err();
+        assert_eq!(err.to_string(), "expected selector after '>' at position 5");
+
+        let err = doc
+            .query_selector_all(0, "p:nth-child(n-99999999999)")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "number out of range in ':nth-child()' at position 14"
+        );
+
+        let err = doc.query_selector(0, "p:hover").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unknown pseudo-class ':hover' at position 1"
+        );
+
+        let err = doc.query_selector(0, "[href='foo]").unwrap_err();
+        assert_eq!(err.to_string(), "unterminated string at position 6");
+
+        let err = doc.query_selector(0, "p:nth-child(foo)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid an+b expression in ':nth-child()' at position 12"
+        );
+
+        let err = doc.query_selector(0, "div,").unwrap_err();
+        assert_eq!(err.to_string(), "expected selector at position 4");
+
+        assert!(doc.query_selector(0, "p::before").is_err());
+        assert!(doc.query_selector(0, "p)").is_err());
+        assert!(doc.query_selector(0, ":not(p").is_err());
+        assert!(doc.query_selector(0, "#").is_err());
+    }
+}
@@ -0,0 +1,628 @@
+use crate::html5_parser::selector::{
+    AttributeOperator, AttributeSelector, Combinator, ComplexSelector, CompoundSelector, Nth,
+    PseudoClass, SelectorError, SelectorList, SimpleSelector,
+};
+
+// Recursive descent parser for (a subset of) the selectors level 4 grammar
+pub(crate) struct SelectorParser {
+    chars: Vec<char>, // Selector string as characters
+    pos: usize,       // Current position in chars
+}
+
+impl SelectorParser {
+    pub fn new(selector: &str) -> Self {
+        SelectorParser {
+            chars: selector.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    // Parses the whole input as a selector list
+    pub fn parse(&mut self) -> Result<SelectorList, SelectorError> {
+        let list = self.parse_selector_list()?;
+
+        if let Some(c) = self.peek() {
+            return Err(self.error(format!("unexpected character '{}'", c)));
+        }
+
+        Ok(list)
+    }
+
+    fn error(&self, message: String) -> SelectorError {
+        self.error_at(message, self.pos)
+    }
+
+    fn error_at(&self, message: String, position: usize) -> SelectorError {
+        SelectorError { message, position }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    // Skips whitespace and returns true when anything was skipped
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while matches!(
+            self.peek(),
+            Some(' ') | Some('\t') | Some('\n') | Some('\r') | Some('\x0C')
+        ) {
+            self.pos += 1;
+        }
+        self.pos != start
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), SelectorError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{}' but found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}' but found end of input", expected))),
+        }
+    }
+
+    // Parses a comma separated list of complex selectors. Stops at the end of the input or at an
+    // unmatched ')', which is left for the caller to handle.
+    fn parse_selector_list(&mut self) -> Result<SelectorList, SelectorError> {
+        let mut selectors = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            selectors.push(self.parse_complex_selector()?);
+            self.skip_whitespace();
+
+            if self.peek() == Some(',') {
+                self.pos += 1;
+                continue;
+            }
+
+            break;
+        }
+
+        Ok(SelectorList { selectors })
+    }
+
+    fn parse_complex_selector(&mut self) -> Result<ComplexSelector, SelectorError> {
+        let mut compounds = Vec::new();
+        let mut combinators = Vec::new();
+
+        match self.parse_compound_selector()? {
+            Some(compound) => compounds.push(compound),
+            None => return Err(self.error("expected selector".into())),
+        }
+
+        loop {
+            let had_whitespace = self.skip_whitespace();
+
+            let combinator = match self.peek() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::NextSibling,
+                Some('~') => Combinator::SubsequentSibling,
+                Some(',') | Some(')') | None => break,
+                Some(_) if had_whitespace => Combinator::Descendant,
+                Some(c) => return Err(self.error(format!("unexpected character '{}'", c))),
+            };
+
+            if combinator != Combinator::Descendant {
+                let c = self.peek().unwrap_or_default();
+                self.pos += 1;
+                self.skip_whitespace();
+
+                match self.parse_compound_selector()? {
+                    Some(compound) => compounds.push(compound),
+                    None => return Err(self.error(format!("expected selector after '{}'", c))),
+                }
+            } else {
+                match self.parse_compound_selector()? {
+                    Some(compound) => compounds.push(compound),
+                    None => {
+                        let c = self.peek().unwrap_or_default();
+                        return Err(self.error(format!("unexpected character '{}'", c)));
+                    }
+                }
+            }
+
+            combinators.push(combinator);
+        }
+
+        Ok(ComplexSelector {
+            compounds,
+            combinators,
+        })
+    }
+
+    // Parses a compound selector, or returns None when there is no compound selector at the
+    // current position.
+    fn parse_compound_selector(&mut self) -> Result<Option<CompoundSelector>, SelectorError> {
+        let start = self.pos;
+
+        let type_selector = if self.peek() == Some('*') {
+            self.pos += 1;
+            None
+        } else if self.is_ident_start() {
+            Some(self.parse_ident()?)
+        } else {
+            None
+        };
+
+        let mut simple_selectors = Vec::new();
+        loop {
+            match self.peek() {
+                Some('#') => {
+                    self.pos += 1;
+                    if !self.is_ident_start() && !self.is_name_char() {
+                        return Err(self.error("expected identifier after '#'".into()));
+                    }
+                    simple_selectors.push(SimpleSelector::Id(self.parse_name()?));
+                }
+                Some('.') => {
+                    self.pos += 1;
+                    if !self.is_ident_start() {
+                        return Err(self.error("expected identifier after '.'".into()));
+                    }
+                    simple_selectors.push(SimpleSelector::Class(self.parse_ident()?));
+                }
+                Some('[') => {
+                    simple_selectors.push(SimpleSelector::Attribute(self.parse_attribute()?));
+                }
+                Some(':') => {
+                    simple_selectors.push(SimpleSelector::PseudoClass(self.parse_pseudo_class()?));
+                }
+                _ => break,
+            }
+        }
+
+        if self.pos == start {
+            return Ok(None);
+        }
+
+        Ok(Some(CompoundSelector {
+            type_selector,
+            simple_selectors,
+        }))
+    }
+
+    fn parse_attribute(&mut self) -> Result<AttributeSelector, SelectorError> {
+        self.expect('[')?;
+        self.skip_whitespace();
+
+        if !self.is_ident_start() {
+            return Err(self.error("expected attribute name".into()));
+        }
+        let name = self.parse_ident()?;
+        self.skip_whitespace();
+
+        let operator = match (self.peek(), self.peek_at(1)) {
+            (Some(']'), _) => {
+                self.pos += 1;
+                return Ok(AttributeSelector {
+                    name,
+                    operator: None,
+                    case_insensitive: false,
+                });
+            }
+            (Some('='), _) => AttributeOperator::Equals,
+            (Some('~'), Some('=')) => AttributeOperator::Includes,
+            (Some('|'), Some('=')) => AttributeOperator::DashMatch,
+            (Some('^'), Some('=')) => AttributeOperator::Prefix,
+            (Some('$'), Some('=')) => AttributeOperator::Suffix,
+            (Some('*'), Some('=')) => AttributeOperator::Substring,
+            _ => return Err(self.error("expected attribute operator or ']'".into())),
+        };
+        self.pos += if operator == AttributeOperator::Equals {
+            1
+        } else {
+            2
+        };
+        self.skip_whitespace();
+
+        let value = match self.peek() {
+            Some('"') | Some('\'') => self.parse_string()?,
+            _ if self.is_ident_start() => self.parse_ident()?,
+            _ => return Err(self.error("expected attribute value".into())),
+        };
+        self.skip_whitespace();
+
+        let mut case_insensitive = false;
+        match self.peek() {
+            Some('i') | Some('I') => {
+                case_insensitive = true;
+                self.pos += 1;
+            }
+            Some('s') | Some('S') => {
+                self.pos += 1;
+            }
+            _ => {}
+        }
+        self.skip_whitespace();
+        self.expect(']')?;
+
+        Ok(AttributeSelector {
+            name,
+            operator: Some((operator, value)),
+            case_insensitive,
+        })
+    }
+
+    fn parse_pseudo_class(&mut self) -> Result<PseudoClass, SelectorError> {
+        let start = self.pos;
+        self.expect(':')?;
+
+        if self.peek() == Some(':') {
+            return Err(self.error_at("pseudo-elements are not supported".into(), start));
+        }
+        if !self.is_ident_start() {
+            return Err(self.error("expected pseudo-class name".into()));
+        }
+        let name = self.parse_ident()?.to_ascii_lowercase();
+
+        if self.peek() != Some('(') {
+            return match name.as_str() {
+                "first-child" => Ok(PseudoClass::FirstChild),
+                "last-child" => Ok(PseudoClass::LastChild),
+                "only-child" => Ok(PseudoClass::OnlyChild),
+                "first-of-type" => Ok(PseudoClass::FirstOfType),
+                "last-of-type" => Ok(PseudoClass::LastOfType),
+                "only-of-type" => Ok(PseudoClass::OnlyOfType),
+                "empty" => Ok(PseudoClass::Empty),
+                "root" => Ok(PseudoClass::Root),
+                "scope" => Ok(PseudoClass::Scope),
+                _ => Err(self.error_at(format!("unknown pseudo-class ':{}'", name), start)),
+            };
+        }
+
+        self.pos += 1;
+        self.skip_whitespace();
+
+        let pseudo = match name.as_str() {
+            "not" => PseudoClass::Not(self.parse_selector_list()?),
+            "is" | "matches" => PseudoClass::Is(self.parse_selector_list()?),
+            "where" => PseudoClass::Where(self.parse_selector_list()?),
+            "nth-child" => {
+                let nth = self.parse_nth(&name)?;
+                PseudoClass::NthChild(nth, self.parse_nth_of()?)
+            }
+            "nth-last-child" => {
+                let nth = self.parse_nth(&name)?;
+                PseudoClass::NthLastChild(nth, self.parse_nth_of()?)
+            }
+            "nth-of-type" => PseudoClass::NthOfType(self.parse_nth(&name)?),
+            "nth-last-of-type" => PseudoClass::NthLastOfType(self.parse_nth(&name)?),
+            _ => return Err(self.error_at(format!("unknown pseudo-class ':{}()'", name), start)),
+        };
+
+        self.skip_whitespace();
+        self.expect(')')?;
+
+        Ok(pseudo)
+    }
+
+    // Parses the optional "of S" part of :nth-child() and :nth-last-child()
+    fn parse_nth_of(&mut self) -> Result<Option<SelectorList>, SelectorError> {
+        self.skip_whitespace();
+
+        let is_of = matches!(self.peek(), Some('o') | Some('O'))
+            && matches!(self.peek_at(1), Some('f') | Some('F'))
+            && matches!(
+                self.peek_at(2),
+                Some(' ') | Some('\t') | Some('\n') | Some('\r') | Some('\x0C')
+            );
+        if !is_of {
+            return Ok(None);
+        }
+
+        self.pos += 2;
+        Ok(Some(self.parse_selector_list()?))
+    }
+
+    // Parses the an+b microsyntax
+    fn parse_nth(&mut self, name: &str) -> Result<Nth, SelectorError> {
+        let start = self.pos;
+        let invalid = |parser: &Self| {
+            parser.error_at(format!("invalid an+b expression in ':{}()'", name), start)
+        };
+
+        if self.is_ident_start() {
+            let save = self.pos;
+            let ident = self.parse_ident()?.to_ascii_lowercase();
+            match ident.as_str() {
+                "odd" => return Ok(Nth { a: 2, b: 1 }),
+                "even" => return Ok(Nth { a: 2, b: 0 }),
+                _ => self.pos = save,
+            }
+        }
+
+        let mut sign = 1;
+        match self.peek() {
+            Some('+') => self.pos += 1,
+            Some('-') => {
+                sign = -1;
+                self.pos += 1;
+            }
+            _ => {}
+        }
+
+        let digits = self.parse_digits(name)?;
+
+        if matches!(self.peek(), Some('n') | Some('N')) {
+            self.pos += 1;
+            let a = sign * digits.unwrap_or(1);
+
+            self.skip_whitespace();
+            let b_sign = match self.peek() {
+                Some('+') => 1,
+                Some('-') => -1,
+                _ => return Ok(Nth { a, b: 0 }),
+            };
+            self.pos += 1;
+            self.skip_whitespace();
+
+            return match self.parse_digits(name)? {
+                Some(b) => Ok(Nth { a, b: b_sign * b }),
+                None => Err(invalid(self)),
+            };
+        }
+
+        match digits {
+            Some(b) => Ok(Nth { a: 0, b: sign * b }),
+            None => Err(invalid(self)),
+        }
+    }
+
+    fn parse_digits(&mut self, name: &str) -> Result<Option<i32>, SelectorError> {
+        let start = self.pos;
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Ok(None);
+        }
+
+        let s: String = self.chars[start..self.pos].iter().collect();
+        match s.parse::<i32>() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(self.error_at(format!("number out of range in ':{}()'", name), start)),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, SelectorError> {
+        let start = self.pos;
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+
+        let mut result = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => {
+                    return Err(self.error_at("unterminated string".into(), start));
+                }
+                Some(c) if c == quote => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        // Escaped newline is a line continuation
+                        Some('\n') => self.pos += 1,
+                        None => {}
+                        Some(_) => result.push(self.parse_escape()),
+                    }
+                }
+                Some(c) => {
+                    result.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn is_name_start_char(c: char) -> bool {
+        c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+    }
+
+    fn is_name_char(&self) -> bool {
+        match self.peek() {
+            Some(c) => Self::is_name_start_char(c) || c.is_ascii_digit() || c == '-',
+            None => false,
+        }
+    }
+
+    // Returns true when the current position starts an identifier
+    fn is_ident_start(&self) -> bool {
+        let starts_name = |c: Option<char>, next: Option<char>| match c {
+            Some('\\') => next.is_some() && next != Some('\n'),
+            Some(c) => Self::is_name_start_char(c),
+            None => false,
+        };
+
+        match self.peek() {
+            Some('-') => {
+                self.peek_at(1) == Some('-') || starts_name(self.peek_at(1), self.peek_at(2))
+            }
+            c => starts_name(c, self.peek_at(1)),
+        }
+    }
+
+    fn parse_ident(&mut self) -> Result<String, SelectorError> {
+        if !self.is_ident_start() {
+            return Err(self.error("expected identifier".into()));
+        }
+        self.parse_name()
+    }
+
+    // Parses a sequence of name characters (and escapes)
+    fn parse_name(&mut self) -> Result<String, SelectorError> {
+        let mut result = String::new();
+        loop {
+            match self.peek() {
+                Some('\\') if self.peek_at(1).is_some() && self.peek_at(1) != Some('\n') => {
+                    self.pos += 1;
+                    result.push(self.parse_escape());
+                }
+                Some(c) if self.is_name_char() => {
+                    result.push(c);
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+
+        if result.is_empty() {
+            return Err(self.error("expected identifier".into()));
+        }
+
+        Ok(result)
+    }
+
+    // Parses an escape sequence. The backslash has already been consumed.
+    fn parse_escape(&mut self) -> char {
+        let start = self.pos;
+        while self.pos - start < 6 && matches!(self.peek(), Some(c) if c.is_ascii_hexdigit()) {
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            let c = self.peek().unwrap_or('\u{FFFD}');
+            self.pos += 1;
+            return c;
+        }
+
+        let hex: String = self.chars[start..self.pos].iter().collect();
+        // A single whitespace after a hex escape belongs to the escape
+        if matches!(self.peek(), Some(' ') | Some('\t') | Some('\n')) {
+            self.pos += 1;
+        }
+
+        match u32::from_str_radix(&hex, 16) {
+            Ok(0) | Err(_) => '\u{FFFD}',
+            Ok(cp) => char::from_u32(cp).unwrap_or('\u{FFFD}'),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(selector: &str) -> SelectorList {
+        SelectorParser::new(selector).parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_compound() {
+        let list = parse("div#main.foo[href]");
+        assert_eq!(list.selectors.len(), 1);
+
+        let compound = &list.selectors[0].compounds[0];
+        assert_eq!(compound.type_selector, Some("div".into()));
+        assert_eq!(
+            compound.simple_selectors,
+            vec![
+                SimpleSelector::Id("main".into()),
+                SimpleSelector::Class("foo".into()),
+                SimpleSelector::Attribute(AttributeSelector {
+                    name: "href".into(),
+                    operator: None,
+                    case_insensitive: false,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_combinators() {
+        let list = parse("a b>c + d~e");
+        let complex = &list.selectors[0];
+        assert_eq!(complex.compounds.len(), 5);
+        assert_eq!(
+            complex.combinators,
+            vec![
+                Combinator::Descendant,
+                Combinator::Child,
+                Combinator::NextSibling,
+                Combinator::SubsequentSibling,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_nth() {
+        let cases = [
+            ("odd", 2, 1),
+            ("EVEN", 2, 0),
+            ("3", 0, 3),
+            ("+3", 0, 3),
+            ("-3", 0, -3),
+            ("n", 1, 0),
+            ("-n+3", -1, 3),
+            ("2n + 1", 2, 1),
+            ("3n- 2", 3, -2),
+            ("-2n", -2, 0),
+        ];
+
+        for (input, a, b) in cases {
+            let list = parse(&format!(":nth-of-type({})", input));
+            assert_eq!(
+                list.selectors[0].compounds[0].simple_selectors[0],
+                SimpleSelector::PseudoClass(PseudoClass::NthOfType(Nth { a, b })),
+                "parsing {}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_escapes() {
+        let list = parse(r"#foo\:bar .\31 23 [data-x='a\'b']");
+        let compounds = &list.selectors[0].compounds;
+        assert_eq!(
+            compounds[0].simple_selectors[0],
+            SimpleSelector::Id("foo:bar".into())
+        );
+        assert_eq!(
+            compounds[1].simple_selectors[0],
+            SimpleSelector::Class("123".into())
+        );
+        assert_eq!(
+            compounds[2].simple_selectors[0],
+            SimpleSelector::Attribute(AttributeSelector {
+                name: "data-x".into(),
+                operator: Some((AttributeOperator::Equals, "a'b".into())),
+                case_insensitive: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = |s: &str| SelectorParser::new(s).parse().unwrap_err().to_string();
+
+        assert_eq!(err("div >> p"), "expected selector after '>' at position 5");
+        assert_eq!(
+            err("a[href"),
+            "expected attribute operator or ']' at position 6"
+        );
+        assert_eq!(err("a[href=]"), "expected attribute value at position 7");
+        assert_eq!(
+            err("a::after"),
+            "pseudo-elements are not supported at position 1"
+        );
+        assert_eq!(err(".1foo"), "expected identifier after '.' at position 1");
+        assert_eq!(
+            err(":foo(a)"),
+            "unknown pseudo-class ':foo()' at position 0"
+        );
+        assert_eq!(
+            err(":not(a"),
+            "expected ')' but found end of input at position 6"
+        );
+        assert_eq!(err("a!"), "unexpected character '!' at position 1");
+    }
+}
@@ -0,0 +1,386 @@
+use crate::html5_parser::serializer::walker::is_space_character;
+use crate::html5_parser::serializer::TreeEvent;
+
+// Elements in which whitespace is kept as-is by strip_whitespace()
+const SPACE_PRESERVE_ELEMENTS: [&str; 9] = [
+    "pre", "textarea", "style", "script", "xmp", "iframe", "noembed", "noframes", "noscript",
+];
+
+// Elements that close an open p element, so the p end tag can be omitted in front of them
+const P_CLOSING_ELEMENTS: [&str; 29] = [
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "datagrid",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "fieldset",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "menu",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+    "main",
+];
+
+/// Sorts the attributes of all tags by name
+pub(crate) fn alphabetical_attributes(events: Vec<TreeEvent>) -> Vec<TreeEvent> {
+    events
+        .into_iter()
+        .map(|mut event| {
+            if let TreeEvent::StartTag { attributes, .. } | TreeEvent::EmptyTag { attributes, .. } =
+                &mut event
+            {
+                attributes.sort();
+            }
+            event
+        })
+        .collect()
+}
+
+/// Collapses runs of whitespace into a single space, except inside elements where whitespace
+/// is significant.
+pub(crate) fn strip_whitespace(events: Vec<TreeEvent>) -> Vec<TreeEvent> {
+    let mut preserve = 0;
+
+    events
+        .into_iter()
+        .map(|event| match event {
+            TreeEvent::StartTag { ref name, .. }
+                if preserve > 0 || SPACE_PRESERVE_ELEMENTS.contains(&name.as_str()) =>
+            {
+                preserve += 1;
+                event
+            }
+            TreeEvent::EndTag { .. } if preserve > 0 => {
+                preserve -= 1;
+                event
+            }
+            TreeEvent::SpaceCharacters(ref text) if preserve == 0 && !text.is_empty() => {
+                TreeEvent::SpaceCharacters(" ".to_string())
+            }
+            TreeEvent::Characters(text) if preserve == 0 => {
+                TreeEvent::Characters(collapse_spaces(&text))
+            }
+            _ => event,
+        })
+        .collect()
+}
+
+fn collapse_spaces(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if is_space_character(c) {
+            if !in_space {
+                result.push(' ');
+            }
+            in_space = true;
+        } else {
+            result.push(c);
+            in_space = false;
+        }
+    }
+    result
+}
+
+fn meta_charset(encoding: &str) -> TreeEvent {
+    TreeEvent::EmptyTag {
+        namespace: None,
+        name: "meta".to_string(),
+        attributes: vec![("charset".to_string(), encoding.to_string())],
+    }
+}
+
+/// Makes sure the head has a meta element with the given charset. Existing meta charset and
+/// content-type declarations are updated, otherwise a meta charset element is added as the
+/// first element in the head.
+pub(crate) fn inject_meta_charset(events: Vec<TreeEvent>, encoding: &str) -> Vec<TreeEvent> {
+    let mut result = Vec::with_capacity(events.len() + 1);
+    let mut in_head = false;
+    let mut meta_found = false;
+    let mut pending: Vec<TreeEvent> = Vec::new();
+
+    for mut event in events {
+        match &mut event {
+            TreeEvent::StartTag { name, .. } if name.eq_ignore_ascii_case("head") => {
+                in_head = true;
+            }
+            TreeEvent::EmptyTag {
+                name, attributes, ..
+            } if name.eq_ignore_ascii_case("meta") => {
+                if let Some(charset) = attributes
+                    .iter_mut()
+                    .find(|(name, _)| name.eq_ignore_ascii_case("charset"))
+                {
+                    charset.1 = encoding.to_string();
+                    meta_found = true;
+                } else if attributes.iter().any(|(name, value)| {
+                    name == "http-equiv" && value.eq_ignore_ascii_case("content-type")
+                }) {
+                    if let Some(content) = attributes.iter_mut().find(|(name, _)| name == "content")
+                    {
+                        content.1 = format!("text/html; charset={}", encoding);
+                        meta_found = true;
+                    }
+                }
+            }
+            TreeEvent::EmptyTag {
+                namespace,
+                name,
+                attributes,
+            } if name.eq_ignore_ascii_case("head") && !meta_found => {
+                // Insert the meta element into an empty head
+                result.push(TreeEvent::StartTag {
+                    namespace: namespace.clone(),
+                    name: name.clone(),
+                    attributes: attributes.clone(),
+                });
+                result.push(meta_charset(encoding));
+                result.push(TreeEvent::EndTag {
+                    namespace: namespace.clone(),
+                    name: name.clone(),
+                });
+                meta_found = true;
+                continue;
+            }
+            TreeEvent::EndTag { name, .. }
+                if name.eq_ignore_ascii_case("head") && !pending.is_empty() =>
+            {
+                // The head start tag comes first, followed by the meta element when needed
+                let mut pending_events = std::mem::take(&mut pending).into_iter();
+                result.extend(pending_events.next());
+                if !meta_found {
+                    result.push(meta_charset(encoding));
+                }
+                result.extend(pending_events);
+                meta_found = true;
+                in_head = false;
+            }
+            _ => {}
+        }
+
+        if in_head {
+            pending.push(event);
+        } else {
+            result.push(event);
+        }
+    }
+
+    result.extend(pending);
+    result
+}
+
+// Returns the kind and name of an event, used to look at the events around a tag
+fn event_kind(event: Option<&TreeEvent>) -> (Option<&'static str>, &str) {
+    match event {
+        Some(TreeEvent::StartTag { name, .. }) => (Some("StartTag"), name),
+        Some(TreeEvent::EmptyTag { name, .. }) => (Some("EmptyTag"), name),
+        Some(TreeEvent::EndTag { name, .. }) => (Some("EndTag"), name),
+        Some(TreeEvent::Comment(_)) => (Some("Comment"), ""),
+        Some(TreeEvent::SpaceCharacters(_)) => (Some("SpaceCharacters"), ""),
+        Some(TreeEvent::Characters(_)) => (Some("Characters"), ""),
+        Some(TreeEvent::Doctype { .. }) => (Some("Doctype"), ""),
+        None => (None, ""),
+    }
+}
+
+/// Removes start and end tags that can be omitted according to the optional tags section of the
+/// specification. Only the events directly around a tag are considered.
+pub(crate) fn omit_optional_tags(events: Vec<TreeEvent>) -> Vec<TreeEvent> {
+    let mut result = Vec::with_capacity(events.len());
+
+    for (idx, event) in events.iter().enumerate() {
+        let previous = if idx > 0 { events.get(idx - 1) } else { None };
+        let next = events.get(idx + 1);
+
+        let omit = match event {
+            TreeEvent::StartTag {
+                name, attributes, ..
+            } => attributes.is_empty() && is_optional_start(name, previous, next),
+            TreeEvent::EndTag { name, .. } => is_optional_end(name, next),
+            _ => false,
+        };
+        if !omit {
+            result.push(event.clone());
+        }
+    }
+
+    result
+}
+
+fn is_optional_start(name: &str, previous: Option<&TreeEvent>, next: Option<&TreeEvent>) -> bool {
+    let (kind, next_name) = event_kind(next);
+
+    match name {
+        // An html start tag may be omitted if the first thing inside is not a space character or
+        // a comment
+        "html" => !matches!(kind, Some("Comment") | Some("SpaceCharacters")),
+        // A head start tag may be omitted if the first thing inside is an element. We also omit
+        // it for an empty head.
+        "head" => match kind {
+            Some("StartTag") | Some("EmptyTag") => true,
+            Some("EndTag") => next_name == "head",
+            _ => false,
+        },
+        // A body start tag may be omitted if the first thing inside is not a space character or
+        // a comment. Since we don't look at the preceding head, it is never omitted in front of
+        // script or style.
+        "body" => match kind {
+            Some("Comment") | Some("SpaceCharacters") => false,
+            Some("StartTag") => !["script", "style"].contains(&next_name),
+            _ => true,
+        },
+        // A colgroup start tag may be omitted if the first thing inside is a col element. An
+        // immediately preceding colgroup keeps its end tag, see is_optional_end().
+        "colgroup" => matches!(kind, Some("StartTag") | Some("EmptyTag")) && next_name == "col",
+        // A tbody start tag may be omitted if the first thing inside is a tr element, and it is
+        // not preceded by a table section whose end tag has been omitted.
+        "tbody" => {
+            if kind != Some("StartTag") {
+                return false;
+            }
+            if let Some(TreeEvent::EndTag { name, .. }) = previous {
+                if ["tbody", "thead", "tfoot"].contains(&name.as_str()) {
+                    return false;
+                }
+            }
+            next_name == "tr"
+        }
+        _ => false,
+    }
+}
+
+fn is_optional_end(name: &str, next: Option<&TreeEvent>) -> bool {
+    let (kind, next_name) = event_kind(next);
+    let at_end = matches!(kind, Some("EndTag") | None);
+
+    match name {
+        // Omitted when not followed by a space character or a comment
+        "html" | "head" | "body" => !matches!(kind, Some("Comment") | Some("SpaceCharacters")),
+        // Omitted when followed by the same element, or at the end of the parent
+        "li" | "optgroup" | "tr" => match kind {
+            Some("StartTag") => next_name == name,
+            _ => at_end,
+        },
+        "dt" | "dd" => match kind {
+            Some("StartTag") => ["dt", "dd"].contains(&next_name),
+            _ => name == "dd" && at_end,
+        },
+        "p" => match kind {
+            Some("StartTag") | Some("EmptyTag") => P_CLOSING_ELEMENTS.contains(&next_name),
+            _ => at_end,
+        },
+        "option" => match kind {
+            Some("StartTag") => ["option", "optgroup"].contains(&next_name),
+            _ => at_end,
+        },
+        "rt" | "rp" => match kind {
+            Some("StartTag") => ["rt", "rp"].contains(&next_name),
+            _ => at_end,
+        },
+        // Omitted when not followed by a space character or a comment. We keep it in front of
+        // another colgroup, since the start tag of that one may be omitted.
+        "colgroup" => match kind {
+            Some("Comment") | Some("SpaceCharacters") => false,
+            Some("StartTag") => next_name != "colgroup",
+            _ => true,
+        },
+        "thead" | "tbody" => match kind {
+            Some("StartTag") => ["tbody", "tfoot"].contains(&next_name),
+            _ => name == "tbody" && at_end,
+        },
+        "tfoot" => match kind {
+            Some("StartTag") => next_name == "tbody",
+            _ => at_end,
+        },
+        "td" | "th" => match kind {
+            Some("StartTag") => ["td", "th"].contains(&next_name),
+            _ => at_end,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(name: &str) -> TreeEvent {
+        TreeEvent::StartTag {
+            namespace: None,
+            name: name.to_string(),
+            attributes: vec![],
+        }
+    }
+
+    fn end(name: &str) -> TreeEvent {
+        TreeEvent::EndTag {
+            namespace: None,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_omit_optional_tags() {
+        let events = vec![
+            start("html"),
+            start("body"),
+            start("p"),
+            TreeEvent::Characters("a".to_string()),
+            end("p"),
+            start("p"),
+            end("p"),
+            TreeEvent::Comment("x".to_string()),
+            end("body"),
+            end("html"),
+        ];
+        assert_eq!(
+            omit_optional_tags(events),
+            vec![
+                start("p"),
+                TreeEvent::Characters("a".to_string()),
+                start("p"),
+                end("p"),
+                TreeEvent::Comment("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_whitespace() {
+        let events = vec![
+            TreeEvent::SpaceCharacters("\n\n".to_string()),
+            TreeEvent::Characters("a  \t b".to_string()),
+            start("pre"),
+            TreeEvent::Characters("a  b".to_string()),
+            end("pre"),
+        ];
+        assert_eq!(
+            strip_whitespace(events),
+            vec![
+                TreeEvent::SpaceCharacters(" ".to_string()),
+                TreeEvent::Characters("a b".to_string()),
+                start("pre"),
+                TreeEvent::Characters("a  b".to_string()),
+                end("pre"),
+            ]
+        );
+    }
+}
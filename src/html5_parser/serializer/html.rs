@@ -0,0 +1,219 @@
+use crate::html5_parser::node::HTML_NAMESPACE;
+use crate::html5_parser::serializer::walker::{is_space_character, VOID_ELEMENTS};
+use crate::html5_parser::serializer::{QuoteAttrValues, SerializerOptions, TreeEvent};
+
+// Elements whose text is written as-is
+const RAW_TEXT_ELEMENTS: [&str; 8] = [
+    "style",
+    "script",
+    "xmp",
+    "iframe",
+    "noembed",
+    "noframes",
+    "noscript",
+    "plaintext",
+];
+
+// Attributes that can be minimized to just their name, per element. The attributes for the
+// empty element name apply to all elements.
+const BOOLEAN_ATTRIBUTES: [(&str, &[&str]); 20] = [
+    ("", &["irrelevant", "itemscope"]),
+    ("style", &["scoped"]),
+    ("img", &["ismap"]),
+    ("audio", &["autoplay", "controls"]),
+    ("video", &["autoplay", "controls"]),
+    ("script", &["defer", "async"]),
+    ("details", &["open"]),
+    ("datagrid", &["multiple", "disabled"]),
+    ("command", &["hidden", "disabled", "checked", "default"]),
+    ("hr", &["noshade"]),
+    ("menu", &["autosubmit"]),
+    ("fieldset", &["disabled", "readonly"]),
+    ("option", &["disabled", "readonly", "selected"]),
+    ("optgroup", &["disabled", "readonly"]),
+    ("button", &["disabled", "autofocus"]),
+    (
+        "input",
+        &[
+            "disabled",
+            "readonly",
+            "required",
+            "autofocus",
+            "checked",
+            "ismap",
+        ],
+    ),
+    ("select", &["disabled", "readonly", "autofocus", "multiple"]),
+    ("output", &["disabled", "readonly"]),
+    ("iframe", &["seamless"]),
+    (
+        "template",
+        &[
+            "shadowrootdelegatesfocus",
+            "shadowrootserializable",
+            "shadowrootclonable",
+        ],
+    ),
+];
+
+fn is_boolean_attribute(element: &str, attribute: &str) -> bool {
+    BOOLEAN_ATTRIBUTES
+        .iter()
+        .filter(|(name, _)| name.is_empty() || *name == element)
+        .any(|(_, attributes)| attributes.contains(&attribute))
+}
+
+// Returns true when the value cannot be written without quotes
+fn needs_quotes(value: &str, mode: QuoteAttrValues) -> bool {
+    let spec = |c: char| is_space_character(c) || matches!(c, '"' | '\'' | '=' | '<' | '>' | '`');
+
+    match mode {
+        QuoteAttrValues::Always => true,
+        QuoteAttrValues::Spec => value.is_empty() || value.chars().any(spec),
+        QuoteAttrValues::Legacy => value.is_empty() || value.chars().any(|c| c != '<' && spec(c)),
+    }
+}
+
+fn is_raw_text_element(namespace: &Option<String>, name: &str) -> bool {
+    matches!(namespace.as_deref(), None | Some(HTML_NAMESPACE)) && RAW_TEXT_ELEMENTS.contains(&name)
+}
+
+fn escape_text(text: &str, options: &SerializerOptions) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '\u{a0}' if options.escape_nbsp => result.push_str("&nbsp;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+fn write_attribute(
+    out: &mut String,
+    element: &str,
+    name: &str,
+    value: &str,
+    options: &SerializerOptions,
+) {
+    out.push(' ');
+    out.push_str(name);
+
+    if options.minimize_boolean_attributes && is_boolean_attribute(element, name) {
+        return;
+    }
+    out.push('=');
+
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' if options.escape_lt_in_attrs => escaped.push_str("&lt;"),
+            '\u{a0}' if options.escape_nbsp => escaped.push_str("&nbsp;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    if !needs_quotes(value, options.quote_attr_values) {
+        out.push_str(&escaped);
+        return;
+    }
+
+    let mut quote_char = options.quote_char;
+    if options.use_best_quote_char {
+        if value.contains('\'') && !value.contains('"') {
+            quote_char = '"';
+        } else if value.contains('"') && !value.contains('\'') {
+            quote_char = '\'';
+        }
+    }
+    let escaped = if quote_char == '\'' {
+        escaped.replace('\'', "&#39;")
+    } else {
+        escaped.replace('"', "&quot;")
+    };
+
+    out.push(quote_char);
+    out.push_str(&escaped);
+    out.push(quote_char);
+}
+
+// Writes the events as HTML
+pub(crate) fn write(events: &[TreeEvent], options: &SerializerOptions) -> String {
+    let mut out = String::new();
+    let mut in_raw_text = false;
+
+    for event in events {
+        match event {
+            TreeEvent::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => {
+                out.push_str("<!DOCTYPE ");
+                out.push_str(name);
+
+                let public_id = public_id.as_deref().filter(|id| !id.is_empty());
+                let system_id = system_id.as_deref().filter(|id| !id.is_empty());
+                if let Some(public_id) = public_id {
+                    out.push_str(&format!(" PUBLIC \"{}\"", public_id));
+                } else if system_id.is_some() {
+                    out.push_str(" SYSTEM");
+                }
+                if let Some(system_id) = system_id {
+                    let quote = if system_id.contains('"') { '\'' } else { '"' };
+                    out.push_str(&format!(" {}{}{}", quote, system_id, quote));
+                }
+                out.push('>');
+            }
+            TreeEvent::Characters(text) if !in_raw_text => {
+                out.push_str(&escape_text(text, options));
+            }
+            TreeEvent::Characters(text) | TreeEvent::SpaceCharacters(text) => {
+                out.push_str(text);
+            }
+            TreeEvent::StartTag {
+                namespace,
+                name,
+                attributes,
+            }
+            | TreeEvent::EmptyTag {
+                namespace,
+                name,
+                attributes,
+            } => {
+                if is_raw_text_element(namespace, name) && !options.escape_rcdata {
+                    in_raw_text = true;
+                }
+
+                out.push('<');
+                out.push_str(name);
+                for (attr_name, value) in attributes {
+                    write_attribute(&mut out, name, attr_name, value, options);
+                }
+                if options.use_trailing_solidus && VOID_ELEMENTS.contains(&name.as_str()) {
+                    if options.space_before_trailing_solidus {
+                        out.push_str(" /");
+                    } else {
+                        out.push('/');
+                    }
+                }
+                out.push('>');
+            }
+            TreeEvent::EndTag { namespace, name } => {
+                if is_raw_text_element(namespace, name) {
+                    in_raw_text = false;
+                }
+                out.push_str(&format!("</{}>", name));
+            }
+            TreeEvent::Comment(data) => {
+                out.push_str(&format!("<!--{}-->", data));
+            }
+        }
+    }
+
+    out
+}
@@ -0,0 +1,627 @@
+use crate::html5_parser::dom::shadow::ShadowRootInit;
+use crate::html5_parser::dom::{ShadowRootMode, SlotAssignmentMode};
+use crate::html5_parser::input_stream::{Position, Span};
+use crate::html5_parser::node::{Node, NodeData};
+use crate::html5_parser::parser::document::Document;
+use crate::html5_parser::parser::quirks::QuirksMode;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Version of the JSON schema. It changes when the schema changes in a way that older readers
+/// cannot handle.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Maximum nesting depth of an imported document. Deeper input is rejected instead of building a
+/// tree that the rest of the engine may not cope with.
+pub const MAX_IMPORT_DEPTH: usize = 65_536;
+
+/// A document as exported to JSON. The nodes are listed in tree order, each with the index of
+/// its parent in the list, so the JSON is not nested deeper for deeper documents. Node ids of the
+/// document are not part of the schema.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonDocument {
+    pub version: u32,
+    pub quirks_mode: JsonQuirksMode,
+    pub nodes: Vec<JsonNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JsonQuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
+/// A single node. Spans are only present when known.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonNode {
+    #[serde(flatten)]
+    pub data: JsonNodeData,
+    /// Index of the parent in the node list, which always comes before the node. Missing for
+    /// the children of the document.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    /// The tree of the parent the node is in
+    #[serde(default, skip_serializing_if = "JsonTree::is_children")]
+    pub tree: JsonTree,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub span: Option<JsonSpan>,
+}
+
+/// Data of a node. Attributes are sorted by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum JsonNodeData {
+    Doctype {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        public_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        system_id: Option<String>,
+    },
+    Element {
+        name: String,
+        namespace: String,
+        #[serde(default)]
+        attributes: BTreeMap<String, String>,
+        /// Whether the element is a template element with template contents
+        #[serde(default, skip_serializing_if = "is_false")]
+        content: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shadow_root: Option<JsonShadowRoot>,
+    },
+    Text {
+        value: String,
+    },
+    Comment {
+        value: String,
+    },
+}
+
+/// Where a node is below its parent element: one of its children, part of its template contents
+/// or part of its shadow tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JsonTree {
+    #[default]
+    Children,
+    Content,
+    ShadowRoot,
+}
+
+impl JsonTree {
+    fn is_children(&self) -> bool {
+        *self == JsonTree::Children
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JsonShadowRoot {
+    pub mode: JsonShadowRootMode,
+    #[serde(default)]
+    pub delegates_focus: bool,
+    #[serde(default)]
+    pub slot_assignment: JsonSlotAssignmentMode,
+    #[serde(default)]
+    pub clonable: bool,
+    #[serde(default)]
+    pub serializable: bool,
+    #[serde(default)]
+    pub declarative: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonShadowRootMode {
+    Open,
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonSlotAssignmentMode {
+    Manual,
+    #[default]
+    Named,
+}
+
+/// Part of the source, with zero-based offsets and one-based lines and columns
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct JsonSpan {
+    pub start: JsonPosition,
+    pub end: JsonPosition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct JsonPosition {
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+    /// Offset in bytes, the char offset when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byte_offset: Option<usize>,
+    /// Offset in UTF-16 code units, the char offset when missing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utf16_offset: Option<usize>,
+}
+
+impl From<Span> for JsonSpan {
+    fn from(span: Span) -> Self {
+        let position = |p: Position| JsonPosition {
+            offset: p.offset,
+            line: p.line,
+            col: p.col,
+            byte_offset: Some(p.byte_offset),
+            utf16_offset: Some(p.utf16_offset),
+        };
+        JsonSpan {
+            start: position(span.start),
+            end: position(span.end),
+        }
+    }
+}
+
+impl From<JsonSpan> for Span {
+    fn from(span: JsonSpan) -> Self {
+        let position = |p: JsonPosition| Position {
+            offset: p.offset,
+            line: p.line,
+            col: p.col,
+            byte_offset: p.byte_offset.unwrap_or(p.offset),
+            utf16_offset: p.utf16_offset.unwrap_or(p.offset),
+        };
+        Span {
+            start: position(span.start),
+            end: position(span.end),
+        }
+    }
+}
+
+// An imported node that later nodes in the list can have as their parent
+struct ImportedNode {
+    node_id: usize,
+    contents_id: Option<usize>,
+    shadow_root_id: Option<usize>,
+    depth: usize,
+}
+
+impl Document {
+    /// Exports the document in the JSON schema
+    pub fn to_json_document(&self) -> JsonDocument {
+        JsonDocument {
+            version: SCHEMA_VERSION,
+            quirks_mode: match self.quirks_mode {
+                QuirksMode::NoQuirks => JsonQuirksMode::NoQuirks,
+                QuirksMode::LimitedQuirks => JsonQuirksMode::LimitedQuirks,
+                QuirksMode::Quirks => JsonQuirksMode::Quirks,
+            },
+            nodes: self.json_nodes(),
+        }
+    }
+
+    /// Exports the document as a JSON string
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.to_json_document()).expect("document is valid json")
+    }
+
+    /// Imports a document from a JSON string as written by to_json()
+    pub fn from_json(json: &str) -> Result<Document, serde_json::Error> {
+        let document: JsonDocument = serde_json::from_str(json)?;
+        Document::from_json_document(&document).map_err(serde::de::Error::custom)
+    }
+
+    /// Imports a document from the JSON schema. Fails when the schema version is not supported,
+    /// a node does not have a valid parent or a shadow root cannot be attached to its host.
+    pub fn from_json_document(json: &JsonDocument) -> Result<Document, String> {
+        if json.version != SCHEMA_VERSION {
+            return Err(format!("unsupported schema version {}", json.version));
+        }
+
+        let mut document = Document::new();
+        document.quirks_mode = match json.quirks_mode {
+            JsonQuirksMode::NoQuirks => QuirksMode::NoQuirks,
+            JsonQuirksMode::LimitedQuirks => QuirksMode::LimitedQuirks,
+            JsonQuirksMode::Quirks => QuirksMode::Quirks,
+        };
+
+        let mut imported: Vec<Option<ImportedNode>> = Vec::with_capacity(json.nodes.len());
+        for (idx, node) in json.nodes.iter().enumerate() {
+            imported.push(document.import_json_node(idx, node, &imported)?);
+        }
+
+        Ok(document)
+    }
+
+    // Exports all nodes of the document in tree order. Template contents and the shadow tree of
+    // an element come after its children.
+    fn json_nodes(&self) -> Vec<JsonNode> {
+        let mut nodes = Vec::new();
+
+        // Node id, index of the parent in the list and the tree of the parent it is in, pushed in
+        // reverse order
+        let mut stack: Vec<(usize, Option<usize>, JsonTree)> = self
+            .get_root()
+            .children
+            .iter()
+            .rev()
+            .map(|&child_id| (child_id, None, JsonTree::Children))
+            .collect();
+
+        while let Some((node_id, parent, tree)) = stack.pop() {
+            let data = match self.json_node_data(node_id) {
+                Some(data) => data,
+                None => continue,
+            };
+            let idx = nodes.len();
+            nodes.push(JsonNode {
+                data,
+                parent,
+                tree,
+                span: self.node_span(node_id).map(JsonSpan::from),
+            });
+
+            let trees = [
+                (self.shadow_root(node_id), JsonTree::ShadowRoot),
+                (self.exported_template_contents(node_id), JsonTree::Content),
+                (Some(node_id), JsonTree::Children),
+            ];
+            for (root_id, tree) in trees {
+                let children = root_id
+                    .and_then(|root_id| self.get_node_by_id(root_id))
+                    .map(|root| root.children.as_slice())
+                    .unwrap_or_default();
+                stack.extend(
+                    children
+                        .iter()
+                        .rev()
+                        .map(|&child_id| (child_id, Some(idx), tree)),
+                );
+            }
+        }
+
+        nodes
+    }
+
+    // Returns the template contents of the element, unless it is a declarative shadow root. That
+    // is exported with its host, not with the template.
+    fn exported_template_contents(&self, node_id: usize) -> Option<usize> {
+        self.template_contents(node_id)
+            .filter(|contents_id| !self.shadow_roots.contains_key(contents_id))
+    }
+
+    // Exports the data of a single node, without its children
+    fn json_node_data(&self, node_id: usize) -> Option<JsonNodeData> {
+        let node = self.get_node_by_id(node_id)?;
+
+        let data = match &node.data {
+            NodeData::Document | NodeData::DocumentFragment => return None,
+            NodeData::DocType {
+                name,
+                public_id,
+                system_id,
+            } => JsonNodeData::Doctype {
+                name: name.clone(),
+                public_id: public_id.clone(),
+                system_id: system_id.clone(),
+            },
+            NodeData::Text { value } => JsonNodeData::Text {
+                value: value.clone(),
+            },
+            NodeData::Comment { value } => JsonNodeData::Comment {
+                value: value.clone(),
+            },
+            NodeData::Element { name, attributes } => {
+                let shadow_root = self.shadow_root(node_id).and_then(|root_id| {
+                    let shadow_root = self.shadow_root_info(root_id)?;
+                    Some(JsonShadowRoot {
+                        mode: match shadow_root.mode {
+                            ShadowRootMode::Open => JsonShadowRootMode::Open,
+                            ShadowRootMode::Closed => JsonShadowRootMode::Closed,
+                        },
+                        delegates_focus: shadow_root.delegates_focus,
+                        slot_assignment: match shadow_root.slot_assignment {
+                            SlotAssignmentMode::Manual => JsonSlotAssignmentMode::Manual,
+                            SlotAssignmentMode::Named => JsonSlotAssignmentMode::Named,
+                        },
+                        clonable: shadow_root.clonable,
+                        serializable: shadow_root.serializable,
+                        declarative: shadow_root.declarative,
+                    })
+                });
+
+                JsonNodeData::Element {
+                    name: name.clone(),
+                    namespace: node.namespace.clone().unwrap_or_default(),
+                    attributes: attributes
+                        .iter()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect(),
+                    content: self.exported_template_contents(node_id).is_some(),
+                    shadow_root,
+                }
+            }
+        };
+
+        Some(data)
+    }
+
+    // Imports the node at the given index of the node list. The nodes before it have already
+    // been imported, which includes its parent. Returns the imported node when it is an element,
+    // as only elements can be the parent of other nodes.
+    fn import_json_node(
+        &mut self,
+        idx: usize,
+        json: &JsonNode,
+        imported: &[Option<ImportedNode>],
+    ) -> Result<Option<ImportedNode>, String> {
+        let (parent_id, depth) = match json.parent {
+            None if json.tree == JsonTree::Children => (0, 1),
+            None => return Err(format!("node {} is not in a tree of an element", idx)),
+            Some(parent) => {
+                let parent_node = match imported.get(parent) {
+                    Some(Some(parent_node)) => parent_node,
+                    Some(None) => return Err(format!("parent of node {} is not an element", idx)),
+                    None => return Err(format!("parent of node {} does not precede it", idx)),
+                };
+                let parent_id = match json.tree {
+                    JsonTree::Children => Some(parent_node.node_id),
+                    JsonTree::Content => parent_node.contents_id,
+                    JsonTree::ShadowRoot => parent_node.shadow_root_id,
+                };
+                match parent_id {
+                    Some(parent_id) => (parent_id, parent_node.depth + 1),
+                    None => return Err(format!("parent of node {} does not have its tree", idx)),
+                }
+            }
+        };
+        if depth > MAX_IMPORT_DEPTH {
+            return Err(format!(
+                "document is nested deeper than {} levels",
+                MAX_IMPORT_DEPTH
+            ));
+        }
+
+        let node = match &json.data {
+            JsonNodeData::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => Node::new_doctype(name, public_id.as_deref(), system_id.as_deref()),
+            JsonNodeData::Text { value } => Node::new_text(value),
+            JsonNodeData::Comment { value } => Node::new_comment(value),
+            JsonNodeData::Element {
+                name,
+                namespace,
+                attributes,
+                ..
+            } => {
+                let attributes: HashMap<String, String> = attributes
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                Node::new_element(name, attributes, namespace)
+            }
+        };
+
+        let node_id = self.add_node(node, parent_id);
+        if let Some(span) = json.span {
+            self.set_node_span(node_id, span.into());
+        }
+
+        let (content, shadow_root) = match &json.data {
+            JsonNodeData::Element {
+                content,
+                shadow_root,
+                ..
+            } => (*content, shadow_root),
+            _ => return Ok(None),
+        };
+
+        let contents_id = content.then(|| self.create_template_contents(node_id));
+
+        let shadow_root_id = match shadow_root {
+            Some(shadow_root) => {
+                let init = ShadowRootInit {
+                    mode: match shadow_root.mode {
+                        JsonShadowRootMode::Open => ShadowRootMode::Open,
+                        JsonShadowRootMode::Closed => ShadowRootMode::Closed,
+                    },
+                    delegates_focus: shadow_root.delegates_focus,
+                    slot_assignment: match shadow_root.slot_assignment {
+                        JsonSlotAssignmentMode::Manual => SlotAssignmentMode::Manual,
+                        JsonSlotAssignmentMode::Named => SlotAssignmentMode::Named,
+                    },
+                    clonable: shadow_root.clonable,
+                    serializable: shadow_root.serializable,
+                };
+                let root_id = self
+                    .attach_shadow_root(node_id, init, shadow_root.declarative)
+                    .map_err(|err| err.to_string())?;
+                Some(root_id)
+            }
+            None => None,
+        };
+
+        Ok(Some(ImportedNode {
+            node_id,
+            contents_id,
+            shadow_root_id,
+            depth,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::input_stream::InputStream;
+    use crate::html5_parser::node::HTML_NAMESPACE;
+    use crate::html5_parser::parser::Html5Parser;
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut stream = InputStream::new();
+        stream.read_from_str(
+            "<!DOCTYPE html><!-- c --><div id=a class=x>text<template><p>in</p></template>\
+             <div><template shadowrootmode=open shadowrootclonable><slot></slot></template></div>",
+            None,
+        );
+        let mut parser = Html5Parser::new(&mut stream);
+        let (document, _) = parser.parse();
+
+        let json = document.to_json();
+        let imported = Document::from_json(&json).unwrap();
+        assert_eq!(imported.to_json(), json);
+        assert_eq!(imported.to_html5lib_tree(), document.to_html5lib_tree());
+
+        let host_id = imported
+            .get_elements_by_tag_name("div")
+            .item(&imported, 1)
+            .unwrap();
+        let root_id = imported.shadow_root(host_id).unwrap();
+        let shadow_root = imported.shadow_root_info(root_id).unwrap();
+        assert!(shadow_root.clonable && shadow_root.declarative);
+        assert_eq!(imported.inner_html(root_id), "<slot></slot>");
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let json = r#"{
+            "version": 2,
+            "quirks_mode": "limited-quirks",
+            "nodes": [
+                {"type": "doctype", "name": "html"},
+                {"type": "element", "name": "html", "namespace": "http://www.w3.org/1999/xhtml",
+                 "attributes": {"lang": "en"}},
+                {"type": "text", "value": "hi", "parent": 1, "span": {
+                     "start": {"offset": 0, "line": 1, "col": 1},
+                     "end": {"offset": 2, "line": 1, "col": 3}}},
+                {"type": "element", "name": "template",
+                 "namespace": "http://www.w3.org/1999/xhtml", "content": true, "parent": 1},
+                {"type": "comment", "value": "c", "parent": 3, "tree": "content"}
+            ]
+        }"#;
+        let document = Document::from_json(json).unwrap();
+        assert_eq!(document.quirks_mode, QuirksMode::LimitedQuirks);
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <!DOCTYPE html>\n| <html>\n|   lang=\"en\"\n|   \"hi\"\n|   <template>\n\
+             |     content\n|       <!-- c -->\n"
+        );
+
+        let html_id = document.get_root().children[1];
+        let text_id = document.get_node_by_id(html_id).unwrap().children[0];
+        assert_eq!(
+            document.node_span(text_id),
+            Some(Span {
+                start: Position::new(0, 1, 1),
+                end: Position::new(2, 1, 3)
+            })
+        );
+
+        // Byte and UTF-16 offsets are kept when they differ from the char offset
+        let mut document = document;
+        let span = Span {
+            start: Position::new(0, 1, 1),
+            end: Position {
+                byte_offset: 5,
+                utf16_offset: 3,
+                ..Position::new(2, 1, 3)
+            },
+        };
+        document.set_node_span(text_id, span);
+        let imported = Document::from_json(&document.to_json()).unwrap();
+        assert_eq!(imported.node_span(text_id), Some(span));
+
+        assert!(Document::from_json(&json.replace("\"version\": 2", "\"version\": 1")).is_err());
+        assert!(Document::from_json("{\"nodes\": []}").is_err());
+    }
+
+    #[test]
+    fn test_json_invalid_parents() {
+        let error = |nodes: &str| {
+            let json = format!(
+                "{{\"version\": 2, \"quirks_mode\": \"no-quirks\", \"nodes\": [{}]}}",
+                nodes
+            );
+            Document::from_json(&json).err().unwrap().to_string()
+        };
+        let div = r#"{"type": "element", "name": "div", "namespace": "", "parent": 0}"#;
+
+        assert_eq!(
+            error(&div.replace('0', "1")),
+            "parent of node 0 does not precede it"
+        );
+        assert_eq!(
+            error(&format!(r#"{{"type": "text", "value": "x"}}, {}"#, div)),
+            "parent of node 1 is not an element"
+        );
+        assert_eq!(
+            error(&format!(
+                r#"{}, {{"type": "text", "value": "x", "parent": 0, "tree": "content"}}"#,
+                div.replace(", \"parent\": 0", "")
+            )),
+            "parent of node 1 does not have its tree"
+        );
+        assert_eq!(
+            error(r#"{"type": "text", "value": "x", "tree": "shadow-root"}"#),
+            "node 0 is not in a tree of an element"
+        );
+    }
+
+    #[test]
+    fn test_json_deep_tree() {
+        let mut document = Document::new();
+        let mut parent_id = 0;
+        for _ in 0..20_000 {
+            parent_id = document.add_node(
+                Node::new_element("div", HashMap::new(), HTML_NAMESPACE),
+                parent_id,
+            );
+        }
+
+        // The JSON is not nested deeper for deeper documents, so serde_json reads it without
+        // reaching its recursion limit
+        let json = document.to_json();
+        let imported = Document::from_json(&json).unwrap();
+        assert_eq!(imported.to_html(), document.to_html());
+        assert_eq!(imported.to_json(), json);
+
+        // Too deep for the importer
+        let mut nodes = vec![JsonNode {
+            data: JsonNodeData::Element {
+                name: "div".to_string(),
+                namespace: HTML_NAMESPACE.to_string(),
+                attributes: BTreeMap::new(),
+                content: false,
+                shadow_root: None,
+            },
+            parent: None,
+            tree: JsonTree::Children,
+            span: None,
+        }];
+        for idx in 0..MAX_IMPORT_DEPTH {
+            nodes.push(JsonNode {
+                parent: Some(idx),
+                ..nodes[0].clone()
+            });
+        }
+        let json = JsonDocument {
+            version: SCHEMA_VERSION,
+            quirks_mode: JsonQuirksMode::NoQuirks,
+            nodes,
+        };
+        assert_eq!(
+            Document::from_json_document(&json).err(),
+            Some(format!(
+                "document is nested deeper than {} levels",
+                MAX_IMPORT_DEPTH
+            ))
+        );
+    }
+}
@@ -0,0 +1,284 @@
+mod filters;
+mod html;
+pub mod json;
+pub mod snapshot;
+pub(crate) mod walker;
+mod xml;
+
+use crate::html5_parser::parser::document::Document;
+
+pub use walker::{text_events, tree_events};
+pub use xml::{serialize_xml, XmlSerializeError, XmlSerializerOptions};
+
+/// When attribute values are quoted
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum QuoteAttrValues {
+    /// Always quote attribute values
+    Always,
+    /// Only quote values that cannot be written as unquoted attribute values according to the
+    /// specification (whitespace, quotes, `=`, `<`, `>` and backticks)
+    Spec,
+    /// Like Spec, but allows `<` in unquoted values as older versions of the specification did
+    Legacy,
+}
+
+/// Options for the HTML serializer. The default options follow the HTML fragment serialization
+/// algorithm (as used by innerHTML), the other options produce smaller or more readable output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SerializerOptions {
+    /// When attribute values are quoted
+    pub quote_attr_values: QuoteAttrValues,
+    /// Character used for quoting attribute values
+    pub quote_char: char,
+    /// Use the other quote character when it saves escaping the value
+    pub use_best_quote_char: bool,
+    /// Omit start and end tags that are optional according to the specification
+    pub omit_optional_tags: bool,
+    /// Write boolean attributes (ie: `disabled`) without a value
+    pub minimize_boolean_attributes: bool,
+    /// Write void elements as `<br />`
+    pub use_trailing_solidus: bool,
+    /// Add a space before the trailing solidus of void elements
+    pub space_before_trailing_solidus: bool,
+    /// Escape `<` in attribute values
+    pub escape_lt_in_attrs: bool,
+    /// Escape non-breaking spaces as `&nbsp;`
+    pub escape_nbsp: bool,
+    /// Escape the text of raw text elements like script and style (this changes their meaning)
+    pub escape_rcdata: bool,
+    /// Sort attributes by name. Attributes of document nodes are always sorted, since their
+    /// order is not kept by the document.
+    pub alphabetical_attributes: bool,
+    /// Collapse whitespace in text outside of pre, textarea and raw text elements
+    pub strip_whitespace: bool,
+    /// Add (or update) a meta charset element with the given encoding in the head
+    pub inject_meta_charset: Option<String>,
+    /// Write serializable shadow roots as declarative shadow roots (`<template shadowrootmode>`)
+    pub serializable_shadow_roots: bool,
+}
+
+impl Default for SerializerOptions {
+    fn default() -> Self {
+        Self {
+            quote_attr_values: QuoteAttrValues::Always,
+            quote_char: '"',
+            use_best_quote_char: false,
+            omit_optional_tags: false,
+            minimize_boolean_attributes: false,
+            use_trailing_solidus: false,
+            space_before_trailing_solidus: true,
+            escape_lt_in_attrs: false,
+            escape_nbsp: true,
+            escape_rcdata: false,
+            alphabetical_attributes: false,
+            strip_whitespace: false,
+            inject_meta_charset: None,
+            serializable_shadow_roots: false,
+        }
+    }
+}
+
+/// A single event in the stream that is written by the serializer. A document (or subtree) is
+/// turned into events by tree_events(), but events can also be generated by other means.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TreeEvent {
+    Doctype {
+        name: String,
+        public_id: Option<String>,
+        system_id: Option<String>,
+    },
+    StartTag {
+        namespace: Option<String>,
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    /// A void element, which has no children and no end tag
+    EmptyTag {
+        namespace: Option<String>,
+        name: String,
+        attributes: Vec<(String, String)>,
+    },
+    EndTag {
+        namespace: Option<String>,
+        name: String,
+    },
+    Characters(String),
+    /// Text that consists only of whitespace. This is never escaped.
+    SpaceCharacters(String),
+    Comment(String),
+}
+
+/// Serializes a stream of events to HTML
+pub fn serialize_events(events: Vec<TreeEvent>, options: &SerializerOptions) -> String {
+    let mut events = events;
+    if let Some(encoding) = &options.inject_meta_charset {
+        events = filters::inject_meta_charset(events, encoding);
+    }
+    if options.alphabetical_attributes {
+        events = filters::alphabetical_attributes(events);
+    }
+    if options.strip_whitespace {
+        events = filters::strip_whitespace(events);
+    }
+    if options.omit_optional_tags {
+        events = filters::omit_optional_tags(events);
+    }
+
+    html::write(&events, options)
+}
+
+/// Serializes the children of the given node. This is the HTML fragment serialization algorithm
+/// as used by innerHTML.
+pub fn serialize_children(
+    document: &Document,
+    node_id: usize,
+    options: &SerializerOptions,
+) -> String {
+    serialize_events(tree_events(document, node_id, false, options), options)
+}
+
+/// Serializes the given node including its children, as used by outerHTML
+pub fn serialize_node(document: &Document, node_id: usize, options: &SerializerOptions) -> String {
+    serialize_events(tree_events(document, node_id, true, options), options)
+}
+
+impl Document {
+    /// Serializes the whole document to HTML with the default serializer options
+    pub fn to_html(&self) -> String {
+        serialize_children(self, 0, &SerializerOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::dom::shadow::ShadowRootInit;
+    use crate::html5_parser::dom::ShadowRootMode;
+    use crate::html5_parser::node::{Node, HTML_NAMESPACE, SVG_NAMESPACE};
+    use std::collections::HashMap;
+
+    fn element(
+        document: &mut Document,
+        name: &str,
+        attrs: &[(&str, &str)],
+        parent: usize,
+    ) -> usize {
+        let attributes: HashMap<String, String> = attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        document.add_node(Node::new_element(name, attributes, HTML_NAMESPACE), parent)
+    }
+
+    #[test]
+    fn test_serialize_document() {
+        let mut document = Document::new();
+        document.add_node(Node::new_doctype("html", None, None), 0);
+        let html_id = element(&mut document, "html", &[], 0);
+        let body_id = element(&mut document, "body", &[], html_id);
+        let p_id = element(
+            &mut document,
+            "p",
+            &[("title", "a \"b\" &\u{a0}"), ("id", "x")],
+            body_id,
+        );
+        document.add_node(Node::new_text("1 < 2 & 3 > 2\u{a0}"), p_id);
+        element(&mut document, "br", &[], p_id);
+        document.add_node(Node::new_comment(" hi "), body_id);
+        let script_id = element(&mut document, "script", &[], body_id);
+        document.add_node(Node::new_text("if (a < b && c) {}"), script_id);
+        let svg_id = document.add_node(
+            Node::new_element("svg", HashMap::new(), SVG_NAMESPACE),
+            body_id,
+        );
+        let style_id = document.add_node(
+            Node::new_element("style", HashMap::new(), SVG_NAMESPACE),
+            svg_id,
+        );
+        document.add_node(Node::new_text("a > b"), style_id);
+
+        assert_eq!(
+            document.to_html(),
+            "<!DOCTYPE html><html><body>\
+             <p id=\"x\" title=\"a &quot;b&quot; &amp;&nbsp;\">1 &lt; 2 &amp; 3 &gt; 2&nbsp;<br></p>\
+             <!-- hi --><script>if (a < b && c) {}</script>\
+             <svg><style>a &gt; b</style></svg></body></html>"
+        );
+        assert_eq!(
+            serialize_children(&document, p_id, &SerializerOptions::default()),
+            "1 &lt; 2 &amp; 3 &gt; 2&nbsp;<br>"
+        );
+    }
+
+    #[test]
+    fn test_serialize_options() {
+        let mut document = Document::new();
+        let div_id = element(&mut document, "ul", &[("class", "a b")], 0);
+        let li_id = element(&mut document, "li", &[], div_id);
+        document.add_node(Node::new_text("one"), li_id);
+        let li_id = element(&mut document, "li", &[], div_id);
+        let input_id = element(
+            &mut document,
+            "input",
+            &[("disabled", ""), ("value", "x")],
+            li_id,
+        );
+
+        let options = SerializerOptions {
+            quote_attr_values: QuoteAttrValues::Spec,
+            omit_optional_tags: true,
+            minimize_boolean_attributes: true,
+            use_trailing_solidus: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serialize_node(&document, div_id, &options),
+            "<ul class=\"a b\"><li>one<li><input disabled value=x /></ul>"
+        );
+        assert_eq!(
+            serialize_node(&document, input_id, &SerializerOptions::default()),
+            "<input disabled=\"\" value=\"x\">"
+        );
+    }
+
+    #[test]
+    fn test_serialize_template_and_shadow_roots() {
+        let mut document = Document::new();
+        let template_id = element(&mut document, "template", &[], 0);
+        let contents_id = document.create_template_contents(template_id);
+        element(&mut document, "b", &[], contents_id);
+
+        let host_id = element(&mut document, "div", &[], 0);
+        let mut init = ShadowRootInit::new(ShadowRootMode::Open);
+        init.serializable = true;
+        let root_id = document.attach_shadow(host_id, init).unwrap();
+        element(&mut document, "slot", &[], root_id);
+        document.add_node(Node::new_text("light"), host_id);
+
+        assert_eq!(
+            document.to_html(),
+            "<template><b></b></template><div>light</div>"
+        );
+
+        let options = SerializerOptions {
+            serializable_shadow_roots: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serialize_node(&document, host_id, &options),
+            "<div><template shadowrootmode=\"open\" shadowrootserializable=\"\"><slot></slot></template>light</div>"
+        );
+    }
+
+    #[test]
+    fn test_serialize_deep_tree() {
+        let mut document = Document::new();
+        let mut parent_id = 0;
+        for _ in 0..20_000 {
+            parent_id = element(&mut document, "div", &[], parent_id);
+        }
+
+        let html = document.to_html();
+        assert_eq!(html, "<div>".repeat(20_000) + &"</div>".repeat(20_000));
+    }
+}
@@ -0,0 +1,810 @@
+use crate::html5_parser::dom::{ShadowRoot, ShadowRootMode, SlotAssignmentMode};
+use crate::html5_parser::input_stream::{Position, Span};
+use crate::html5_parser::node::{Node, NodeData};
+use crate::html5_parser::parser::document::{Document, DocumentType};
+use crate::html5_parser::parser::quirks::QuirksMode;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+// A snapshot is a binary encoding of the document arena. Node ids are kept, so ids handed out
+// before the snapshot was written are still valid in the restored document.
+//
+// Layout (all integers are unsigned LEB128 varints, strings are indexes in the string table):
+//
+//   magic "GSNP", version
+//   doctype, quirks mode, next node id
+//   string table: count, then (byte length, utf-8 bytes) per string
+//   nodes: count, then per node in ascending id order:
+//     id gap, parent, name, namespace + 1 (0 when none), prefix + 1 (0 when none), kind,
+//     kind data, child count, children
+//   template contents: count, then (template id, contents id)
+//   shadow roots: count, then (root id, host id, mode, slot assignment, flags)
+//   manual slot assignments: count, then (slot id, count, node ids)
+//
+// Ids within a node record are stored as zigzag encoded differences, as they are mostly close
+// to each other: the id gap is the id minus the previous id minus one, the parent is 0 for a
+// detached node and the difference with the node id plus one otherwise, and each child (or
+// assigned node of a slot) is the difference with the id before it.
+//   node spans: count, then (node id, start, end), with each position written as
+//     offset, line, col, byte offset, UTF-16 offset
+const MAGIC: &[u8; 4] = b"GSNP";
+
+/// Version of the snapshot format. Snapshots of other versions cannot be read.
+pub const SNAPSHOT_VERSION: u32 = 3;
+
+const KIND_DOCUMENT: u8 = 0;
+const KIND_DOCUMENT_FRAGMENT: u8 = 1;
+const KIND_DOCTYPE: u8 = 2;
+const KIND_TEXT: u8 = 3;
+const KIND_COMMENT: u8 = 4;
+const KIND_ELEMENT: u8 = 5;
+
+const FLAG_DELEGATES_FOCUS: u8 = 0b0001;
+const FLAG_CLONABLE: u8 = 0b0010;
+const FLAG_SERIALIZABLE: u8 = 0b0100;
+const FLAG_DECLARATIVE: u8 = 0b1000;
+
+/// Reasons why a snapshot could not be read
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// Reading the snapshot failed
+    Io(io::Error),
+    /// The data does not start with the snapshot magic
+    InvalidMagic,
+    /// The snapshot was written in a format version this version cannot read
+    UnsupportedVersion(u32),
+    /// The snapshot is truncated or contains invalid data
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "cannot read snapshot: {}", err),
+            SnapshotError::InvalidMagic => write!(f, "data is not a document snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Corrupt(reason) => write!(f, "corrupt snapshot: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl Document {
+    /// Writes a binary snapshot of the document, which can be restored with read_snapshot()
+    pub fn write_snapshot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut encoder = Encoder::default();
+        encoder.document(self);
+
+        let mut out = Vec::with_capacity(encoder.body.len() + encoder.strings_len + 16);
+        out.extend_from_slice(MAGIC);
+        write_varint(&mut out, SNAPSHOT_VERSION as u64);
+        out.push(match self.doctype {
+            DocumentType::HTML => 0,
+            DocumentType::IframeSrcDoc => 1,
+            DocumentType::XML => 2,
+        });
+        out.push(match self.quirks_mode {
+            QuirksMode::NoQuirks => 0,
+            QuirksMode::LimitedQuirks => 1,
+            QuirksMode::Quirks => 2,
+        });
+        write_varint(&mut out, self.next_node_id() as u64);
+
+        write_varint(&mut out, encoder.strings.len() as u64);
+        for s in &encoder.strings {
+            write_varint(&mut out, s.len() as u64);
+            out.extend_from_slice(s.as_bytes());
+        }
+        out.extend_from_slice(&encoder.body);
+
+        writer.write_all(&out)
+    }
+
+    /// Reads a document from a snapshot written by write_snapshot()
+    pub fn read_snapshot<R: Read>(reader: &mut R) -> Result<Document, SnapshotError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Decoder::new(&data).document()
+    }
+}
+
+#[derive(Default)]
+struct Encoder<'a> {
+    strings: Vec<&'a str>,
+    string_ids: HashMap<&'a str, u64>,
+    strings_len: usize,
+    body: Vec<u8>,
+}
+
+impl<'a> Encoder<'a> {
+    fn document(&mut self, document: &'a Document) {
+        let ids = document.node_ids();
+        self.varint(ids.len());
+        let mut next_id = 0;
+        for id in ids {
+            if let Some(node) = document.get_node_by_id(id) {
+                self.varint(id - next_id);
+                self.node(node);
+                next_id = id + 1;
+            }
+        }
+
+        let mut template_contents: Vec<_> = document.template_contents.iter().collect();
+        template_contents.sort_unstable();
+        self.varint(template_contents.len());
+        for (&template_id, &contents_id) in template_contents {
+            self.varint(template_id);
+            self.varint(contents_id);
+        }
+
+        let mut shadow_roots: Vec<_> = document.shadow_roots.iter().collect();
+        shadow_roots.sort_unstable_by_key(|(&id, _)| id);
+        self.varint(shadow_roots.len());
+        for (&root_id, shadow_root) in shadow_roots {
+            self.varint(root_id);
+            self.varint(shadow_root.host);
+            self.body.push(match shadow_root.mode {
+                ShadowRootMode::Open => 0,
+                ShadowRootMode::Closed => 1,
+            });
+            self.body.push(match shadow_root.slot_assignment {
+                SlotAssignmentMode::Named => 0,
+                SlotAssignmentMode::Manual => 1,
+            });
+            let mut flags = 0;
+            for (set, flag) in [
+                (shadow_root.delegates_focus, FLAG_DELEGATES_FOCUS),
+                (shadow_root.clonable, FLAG_CLONABLE),
+                (shadow_root.serializable, FLAG_SERIALIZABLE),
+                (shadow_root.declarative, FLAG_DECLARATIVE),
+            ] {
+                if set {
+                    flags |= flag;
+                }
+            }
+            self.body.push(flags);
+        }
+
+        let mut assignments: Vec<_> = document.manual_slot_assignments.iter().collect();
+        assignments.sort_unstable_by_key(|(&id, _)| id);
+        self.varint(assignments.len());
+        for (&slot_id, assigned) in assignments {
+            self.varint(slot_id);
+            self.ids(slot_id, assigned);
+        }
+
+        let mut spans: Vec<_> = document.node_spans.iter().collect();
+        spans.sort_unstable_by_key(|(&id, _)| id);
+        self.varint(spans.len());
+        for (&node_id, span) in spans {
+            self.varint(node_id);
+            for position in [span.start, span.end] {
+                self.varint(position.offset);
+                self.varint(position.line);
+                self.varint(position.col);
+                self.varint(position.byte_offset);
+                self.varint(position.utf16_offset);
+            }
+        }
+    }
+
+    fn node(&mut self, node: &'a Node) {
+        self.varint(node.parent.map_or(0, |id| zigzag(id, node.id) + 1));
+        self.string(&node.name);
+        self.optional_string(node.namespace.as_deref());
+        self.optional_string(node.prefix.as_deref());
+
+        match &node.data {
+            NodeData::Document => self.body.push(KIND_DOCUMENT),
+            NodeData::DocumentFragment => self.body.push(KIND_DOCUMENT_FRAGMENT),
+            NodeData::DocType {
+                name,
+                public_id,
+                system_id,
+            } => {
+                self.body.push(KIND_DOCTYPE);
+                self.string(name);
+                self.optional_string(public_id.as_deref());
+                self.optional_string(system_id.as_deref());
+            }
+            NodeData::Text { value } => {
+                self.body.push(KIND_TEXT);
+                self.string(value);
+            }
+            NodeData::Comment { value } => {
+                self.body.push(KIND_COMMENT);
+                self.string(value);
+            }
+            NodeData::Element { name, attributes } => {
+                self.body.push(KIND_ELEMENT);
+                self.string(name);
+                // Sorted, so the same document always gives the same snapshot
+                let mut attributes: Vec<_> = attributes.iter().collect();
+                attributes.sort_unstable();
+                self.varint(attributes.len());
+                for (name, value) in attributes {
+                    self.string(name);
+                    self.string(value);
+                }
+            }
+        }
+
+        self.ids(node.id, &node.children);
+    }
+
+    fn ids(&mut self, base: usize, ids: &[usize]) {
+        self.varint(ids.len());
+        let mut prev = base;
+        for &id in ids {
+            self.varint(zigzag(id, prev));
+            prev = id;
+        }
+    }
+
+    fn string(&mut self, s: &'a str) {
+        let id = self.intern(s);
+        write_varint(&mut self.body, id);
+    }
+
+    fn optional_string(&mut self, s: Option<&'a str>) {
+        let id = s.map_or(0, |s| self.intern(s) + 1);
+        write_varint(&mut self.body, id);
+    }
+
+    fn intern(&mut self, s: &'a str) -> u64 {
+        if let Some(&id) = self.string_ids.get(s) {
+            return id;
+        }
+        let id = self.strings.len() as u64;
+        self.strings.push(s);
+        self.string_ids.insert(s, id);
+        self.strings_len += s.len() + 1;
+        id
+    }
+
+    fn varint(&mut self, value: usize) {
+        write_varint(&mut self.body, value as u64);
+    }
+}
+
+// Encodes the difference between two ids as an unsigned number: 0, -1, 1, -2, 2, ...
+fn zigzag(id: usize, base: usize) -> usize {
+    if id >= base {
+        (id - base) * 2
+    } else {
+        (base - id) * 2 - 1
+    }
+}
+
+fn unzigzag(value: usize, base: usize) -> Option<usize> {
+    if value.is_multiple_of(2) {
+        base.checked_add(value / 2)
+    } else {
+        base.checked_sub(value / 2 + 1)
+    }
+}
+
+// Checks that the nodes, in ascending id order, form valid trees: the parent of every child is
+// the node that lists it, every node with a parent is listed exactly once, and following the
+// parents from any node ends at the root of its tree instead of going around in a cycle. Roots
+// are the document, the fragments of template contents and shadow trees, and detached nodes.
+fn validate_trees(nodes: &[Node]) -> Result<(), SnapshotError> {
+    match nodes.first() {
+        Some(node) if node.id == 0 && node.data == NodeData::Document && node.parent.is_none() => {}
+        _ => return Err(SnapshotError::Corrupt("missing document node")),
+    }
+
+    // Ids are usually dense, so the id is the index of the node. Otherwise, ids are looked up.
+    let dense = nodes.last().is_some_and(|node| node.id == nodes.len() - 1);
+    let index_of = |id: usize| match dense {
+        true => (id < nodes.len()).then_some(id),
+        false => nodes.binary_search_by_key(&id, |node| node.id).ok(),
+    };
+
+    let mut listed = vec![false; nodes.len()];
+    let mut listed_count = 0;
+    let mut roots = Vec::new();
+    for (idx, node) in nodes.iter().enumerate() {
+        match node.data {
+            NodeData::Document if idx != 0 => {
+                return Err(SnapshotError::Corrupt("more than one document node"));
+            }
+            NodeData::DocType { .. } | NodeData::Text { .. } | NodeData::Comment { .. }
+                if !node.children.is_empty() =>
+            {
+                return Err(SnapshotError::Corrupt("node cannot have children"));
+            }
+            _ => {}
+        }
+        if node.parent.is_none() {
+            roots.push(idx);
+        }
+
+        for &child_id in &node.children {
+            let child_idx = index_of(child_id)
+                .filter(|&child_idx| nodes[child_idx].parent == Some(node.id))
+                .ok_or(SnapshotError::Corrupt(
+                    "child does not belong to its parent",
+                ))?;
+            if listed[child_idx] {
+                return Err(SnapshotError::Corrupt("node has more than one parent"));
+            }
+            listed[child_idx] = true;
+            listed_count += 1;
+        }
+    }
+    // Every listed node has a parent, so all nodes with a parent are listed when the numbers match
+    if listed_count != nodes.len() - roots.len() {
+        return Err(SnapshotError::Corrupt("node is missing from its parent"));
+    }
+
+    // Every node has a single parent, so walking down from the roots reaches every node once.
+    // Nodes that are not reached have no root above them, they are in a cycle.
+    let mut reached = 0;
+    let mut stack = roots;
+    while let Some(idx) = stack.pop() {
+        reached += 1;
+        stack.extend(nodes[idx].children.iter().filter_map(|&id| index_of(id)));
+    }
+    if reached != nodes.len() {
+        return Err(SnapshotError::Corrupt("nodes form a cycle"));
+    }
+
+    Ok(())
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+    strings: Vec<&'a str>,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            strings: Vec::new(),
+        }
+    }
+
+    fn document(&mut self) -> Result<Document, SnapshotError> {
+        if self.bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(SnapshotError::InvalidMagic);
+        }
+        let version = self.varint()?;
+        if version != SNAPSHOT_VERSION as usize {
+            return Err(SnapshotError::UnsupportedVersion(
+                version.try_into().unwrap_or(u32::MAX),
+            ));
+        }
+
+        let mut document = Document::default();
+        document.doctype = match self.byte()? {
+            0 => DocumentType::HTML,
+            1 => DocumentType::IframeSrcDoc,
+            2 => DocumentType::XML,
+            _ => return Err(SnapshotError::Corrupt("invalid document type")),
+        };
+        document.quirks_mode = match self.byte()? {
+            0 => QuirksMode::NoQuirks,
+            1 => QuirksMode::LimitedQuirks,
+            2 => QuirksMode::Quirks,
+            _ => return Err(SnapshotError::Corrupt("invalid quirks mode")),
+        };
+        let next_id = self.varint()?;
+
+        let string_count = self.count()?;
+        self.strings.reserve(string_count);
+        for _ in 0..string_count {
+            let len = self.varint()?;
+            let bytes = self.bytes(len)?;
+            let s = std::str::from_utf8(bytes)
+                .map_err(|_| SnapshotError::Corrupt("string is not valid utf-8"))?;
+            self.strings.push(s);
+        }
+
+        let node_count = self.count()?;
+        let mut nodes = Vec::with_capacity(node_count);
+        let mut id: usize = 0;
+        for _ in 0..node_count {
+            id = id
+                .checked_add(self.varint()?)
+                .filter(|&id| id < next_id)
+                .ok_or(SnapshotError::Corrupt("invalid node id"))?;
+            nodes.push(self.node(id)?);
+            id += 1;
+        }
+        validate_trees(&nodes)?;
+        document.restore_nodes(nodes, next_id);
+
+        for _ in 0..self.count()? {
+            let template_id = self.node_id(&document)?;
+            let contents_id = self.node_id(&document)?;
+            document.template_contents.insert(template_id, contents_id);
+        }
+
+        for _ in 0..self.count()? {
+            let root_id = self.node_id(&document)?;
+            let host = self.node_id(&document)?;
+            let mode = match self.byte()? {
+                0 => ShadowRootMode::Open,
+                1 => ShadowRootMode::Closed,
+                _ => return Err(SnapshotError::Corrupt("invalid shadow root mode")),
+            };
+            let slot_assignment = match self.byte()? {
+                0 => SlotAssignmentMode::Named,
+                1 => SlotAssignmentMode::Manual,
+                _ => return Err(SnapshotError::Corrupt("invalid slot assignment mode")),
+            };
+            let flags = self.byte()?;
+            document.shadow_roots.insert(
+                root_id,
+                ShadowRoot {
+                    mode,
+                    delegates_focus: flags & FLAG_DELEGATES_FOCUS != 0,
+                    slot_assignment,
+                    clonable: flags & FLAG_CLONABLE != 0,
+                    serializable: flags & FLAG_SERIALIZABLE != 0,
+                    declarative: flags & FLAG_DECLARATIVE != 0,
+                    host,
+                },
+            );
+            document.shadow_hosts.insert(host, root_id);
+        }
+
+        for _ in 0..self.count()? {
+            let slot_id = self.node_id(&document)?;
+            let assigned = self.ids(slot_id)?;
+            document.manual_slot_assignments.insert(slot_id, assigned);
+        }
+
+        for _ in 0..self.count()? {
+            let node_id = self.node_id(&document)?;
+            let start = self.position()?;
+            let end = self.position()?;
+            document.set_node_span(node_id, Span { start, end });
+        }
+
+        if self.pos != self.data.len() {
+            return Err(SnapshotError::Corrupt("trailing data"));
+        }
+
+        Ok(document)
+    }
+
+    fn node(&mut self, id: usize) -> Result<Node, SnapshotError> {
+        let parent = match self.varint()? {
+            0 => None,
+            value => {
+                Some(unzigzag(value - 1, id).ok_or(SnapshotError::Corrupt("invalid parent id"))?)
+            }
+        };
+        let name = self.string()?.to_string();
+        let namespace = self.optional_string()?.map(String::from);
+        let prefix = self.optional_string()?.map(String::from);
+
+        let data = match self.byte()? {
+            KIND_DOCUMENT => NodeData::Document,
+            KIND_DOCUMENT_FRAGMENT => NodeData::DocumentFragment,
+            KIND_DOCTYPE => NodeData::DocType {
+                name: self.string()?.to_string(),
+                public_id: self.optional_string()?.map(String::from),
+                system_id: self.optional_string()?.map(String::from),
+            },
+            KIND_TEXT => NodeData::Text {
+                value: self.string()?.to_string(),
+            },
+            KIND_COMMENT => NodeData::Comment {
+                value: self.string()?.to_string(),
+            },
+            KIND_ELEMENT => {
+                let name = self.string()?.to_string();
+                let count = self.count()?;
+                let mut attributes = HashMap::with_capacity(count);
+                for _ in 0..count {
+                    let name = self.string()?.to_string();
+                    let value = self.string()?.to_string();
+                    attributes.insert(name, value);
+                }
+                NodeData::Element { name, attributes }
+            }
+            _ => return Err(SnapshotError::Corrupt("invalid node kind")),
+        };
+
+        let children = self.ids(id)?;
+
+        Ok(Node {
+            id,
+            parent,
+            children,
+            name,
+            namespace,
+            prefix,
+            data,
+        })
+    }
+
+    fn position(&mut self) -> Result<Position, SnapshotError> {
+        Ok(Position {
+            offset: self.varint()?,
+            line: self.varint()?,
+            col: self.varint()?,
+            byte_offset: self.varint()?,
+            utf16_offset: self.varint()?,
+        })
+    }
+
+    fn node_id(&mut self, document: &Document) -> Result<usize, SnapshotError> {
+        let id = self.varint()?;
+        match document.get_node_by_id(id) {
+            Some(_) => Ok(id),
+            None => Err(SnapshotError::Corrupt("unknown node id")),
+        }
+    }
+
+    fn ids(&mut self, base: usize) -> Result<Vec<usize>, SnapshotError> {
+        let count = self.count()?;
+        let mut ids = Vec::with_capacity(count);
+        let mut prev = base;
+        for _ in 0..count {
+            prev = unzigzag(self.varint()?, prev).ok_or(SnapshotError::Corrupt("invalid id"))?;
+            ids.push(prev);
+        }
+        Ok(ids)
+    }
+
+    fn string(&mut self) -> Result<&'a str, SnapshotError> {
+        let id = self.varint()?;
+        self.strings
+            .get(id)
+            .copied()
+            .ok_or(SnapshotError::Corrupt("unknown string"))
+    }
+
+    fn optional_string(&mut self) -> Result<Option<&'a str>, SnapshotError> {
+        match self.varint()? {
+            0 => Ok(None),
+            id => self
+                .strings
+                .get(id - 1)
+                .copied()
+                .map(Some)
+                .ok_or(SnapshotError::Corrupt("unknown string")),
+        }
+    }
+
+    // Reads a number of items. Every item takes at least one byte, so larger counts are invalid
+    // and would only make us allocate too much.
+    fn count(&mut self) -> Result<usize, SnapshotError> {
+        let count = self.varint()?;
+        if count > self.data.len() - self.pos {
+            return Err(SnapshotError::Corrupt("invalid count"));
+        }
+        Ok(count)
+    }
+
+    fn varint(&mut self) -> Result<usize, SnapshotError> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift > 63 {
+                return Err(SnapshotError::Corrupt("varint too long"));
+            }
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        usize::try_from(value).map_err(|_| SnapshotError::Corrupt("number too large"))
+    }
+
+    fn byte(&mut self) -> Result<u8, SnapshotError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or(SnapshotError::Corrupt("unexpected end of data"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::input_stream::InputStream;
+    use crate::html5_parser::node::HTML_NAMESPACE;
+    use crate::html5_parser::parser::Html5Parser;
+
+    fn round_trip(document: &Document) -> (Document, Vec<u8>) {
+        let mut snapshot = Vec::new();
+        document.write_snapshot(&mut snapshot).unwrap();
+        let restored = Document::read_snapshot(&mut snapshot.as_slice()).unwrap();
+        (restored, snapshot)
+    }
+
+    fn assert_identical(restored: &Document, document: &Document) {
+        assert_eq!(restored.to_html5lib_tree(), document.to_html5lib_tree());
+        assert_eq!(restored.to_json(), document.to_json());
+        assert_eq!(restored.quirks_mode, document.quirks_mode);
+        assert_eq!(restored.next_node_id(), document.next_node_id());
+        assert_eq!(restored.node_ids(), document.node_ids());
+        for id in document.node_ids() {
+            let node = document.get_node_by_id(id).unwrap();
+            let restored_node = restored.get_node_by_id(id).unwrap();
+            assert_eq!(restored_node.parent, node.parent);
+            assert_eq!(restored_node.children, node.children);
+            assert_eq!(restored_node.namespace, node.namespace);
+            assert_eq!(restored_node.prefix, node.prefix);
+            assert_eq!(restored_node.data, node.data);
+            assert_eq!(
+                restored.template_contents(id),
+                document.template_contents(id)
+            );
+            assert_eq!(restored.shadow_root(id), document.shadow_root(id));
+            assert_eq!(restored.shadow_root_info(id), document.shadow_root_info(id));
+            assert_eq!(restored.node_span(id), document.node_span(id));
+        }
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut stream = InputStream::new();
+        stream.read_from_str(
+            "<!DOCTYPE html><!-- c --><div id=a class='x y'>text &amp; more<template><p>in</p>\
+             </template><div><template shadowrootmode=closed shadowrootdelegatesfocus>\
+             <slot></slot></template><span>slotted</span></div><svg><path d=M0/></svg>",
+            None,
+        );
+        let mut parser = Html5Parser::new(&mut stream);
+        let (document, _) = parser.parse();
+
+        let (mut restored, snapshot) = round_trip(document);
+        assert_identical(&restored, document);
+
+        // Indexes are rebuilt
+        assert_eq!(
+            restored.get_element_by_id("a"),
+            document.get_element_by_id("a")
+        );
+        assert_eq!(
+            restored.get_elements_by_class_name("y").length(&restored),
+            1
+        );
+
+        // The same document gives the same snapshot
+        let mut again = Vec::new();
+        restored.write_snapshot(&mut again).unwrap();
+        assert_eq!(again, snapshot);
+
+        // Spans are kept, and new nodes do not reuse ids of removed nodes
+        restored.set_node_span(
+            2,
+            Span {
+                start: Position::new(1, 1, 2),
+                end: Position {
+                    byte_offset: 13,
+                    utf16_offset: 11,
+                    ..Position::new(10, 1, 11)
+                },
+            },
+        );
+        let div_id = restored.get_element_by_id("a").unwrap();
+        restored.remove_node(div_id);
+        let (restored_again, _) = round_trip(&restored);
+        assert_identical(&restored_again, &restored);
+    }
+
+    #[test]
+    fn test_snapshot_errors() {
+        let mut document = Document::new();
+        document.add_node(Node::new_text("hello"), 0);
+        let (_, snapshot) = round_trip(&document);
+
+        assert!(matches!(
+            Document::read_snapshot(&mut &b"<html>"[..]),
+            Err(SnapshotError::InvalidMagic)
+        ));
+
+        let mut other_version = snapshot.clone();
+        other_version[4] = 1;
+        assert!(matches!(
+            Document::read_snapshot(&mut other_version.as_slice()),
+            Err(SnapshotError::UnsupportedVersion(1))
+        ));
+
+        for len in 5..snapshot.len() {
+            assert!(matches!(
+                Document::read_snapshot(&mut &snapshot[..len]),
+                Err(SnapshotError::Corrupt(_))
+            ));
+        }
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_snapshot_invalid_trees() {
+        // Writes a snapshot of a document whose tree was broken by the given change
+        let corrupt = |change: &dyn Fn(&mut Document, usize, usize)| {
+            let mut document = Document::new();
+            let div_id =
+                document.add_node(Node::new_element("div", HashMap::new(), HTML_NAMESPACE), 0);
+            let text_id = document.add_node(Node::new_text("hello"), div_id);
+            change(&mut document, div_id, text_id);
+
+            let mut snapshot = Vec::new();
+            document.write_snapshot(&mut snapshot).unwrap();
+            match Document::read_snapshot(&mut snapshot.as_slice()) {
+                Err(SnapshotError::Corrupt(reason)) => reason,
+                Err(err) => panic!("unexpected error: {}", err),
+                Ok(_) => panic!("corrupt snapshot was read"),
+            }
+        };
+        fn node(document: &mut Document, id: usize) -> &mut Node {
+            document.get_mut_node_by_id(id).unwrap()
+        }
+
+        assert_eq!(
+            corrupt(&|document, _, text_id| node(document, text_id).parent = Some(0)),
+            "child does not belong to its parent"
+        );
+        assert_eq!(
+            corrupt(&|document, div_id, text_id| node(document, div_id).children.push(text_id)),
+            "node has more than one parent"
+        );
+        assert_eq!(
+            corrupt(&|document, div_id, _| node(document, div_id).children.clear()),
+            "node is missing from its parent"
+        );
+        assert_eq!(
+            corrupt(&|document, div_id, text_id| node(document, text_id).children.push(div_id)),
+            "node cannot have children"
+        );
+        assert_eq!(
+            corrupt(&|document, div_id, _| {
+                node(document, 0).children.clear();
+                node(document, div_id).parent = Some(div_id);
+                node(document, div_id).children.push(div_id);
+            }),
+            "nodes form a cycle"
+        );
+        assert_eq!(
+            corrupt(&|document, div_id, _| {
+                let span_id = document.add_node(
+                    Node::new_element("span", HashMap::new(), HTML_NAMESPACE),
+                    div_id,
+                );
+                node(document, 0).children.clear();
+                node(document, div_id).parent = Some(span_id);
+                node(document, span_id).children.push(div_id);
+            }),
+            "nodes form a cycle"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use crate::html5_parser::dom::ShadowRootMode;
+use crate::html5_parser::node::{Node, NodeData, HTML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+use crate::html5_parser::serializer::{SerializerOptions, TreeEvent};
+
+// Elements that have no end tag and no contents
+pub(crate) const VOID_ELEMENTS: [&str; 18] = [
+    "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input",
+    "keygen", "link", "meta", "param", "source", "track", "wbr",
+];
+
+// Characters that are considered whitespace when splitting text
+pub(crate) fn is_space_character(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\u{000C}' | '\r' | ' ')
+}
+
+/// Splits text into events, where leading and trailing whitespace become SpaceCharacters
+pub fn text_events(text: &str) -> Vec<TreeEvent> {
+    let mut events = Vec::new();
+
+    let middle = text.trim_start_matches(is_space_character);
+    let left = &text[..text.len() - middle.len()];
+    if !left.is_empty() {
+        events.push(TreeEvent::SpaceCharacters(left.to_string()));
+    }
+
+    let trimmed = middle.trim_end_matches(is_space_character);
+    let right = &middle[trimmed.len()..];
+    if !trimmed.is_empty() {
+        events.push(TreeEvent::Characters(trimmed.to_string()));
+    }
+    if !right.is_empty() {
+        events.push(TreeEvent::SpaceCharacters(right.to_string()));
+    }
+
+    events
+}
+
+// Work left to do while walking the tree. The walk keeps these on an explicit stack instead of
+// recursing, so deeply nested documents can't overflow the call stack.
+enum Walk {
+    Node(usize),
+    Children(usize),
+    ShadowRoot(usize),
+    Event(TreeEvent),
+}
+
+/// Walks the document from the given node and returns the events for the serializer. The node
+/// itself is only included when include_node is set. Template elements are walked through their
+/// template contents.
+pub fn tree_events(
+    document: &Document,
+    node_id: usize,
+    include_node: bool,
+    options: &SerializerOptions,
+) -> Vec<TreeEvent> {
+    let mut events = Vec::new();
+
+    let mut stack = vec![if include_node {
+        Walk::Node(node_id)
+    } else {
+        Walk::Children(node_id)
+    }];
+    while let Some(walk) = stack.pop() {
+        match walk {
+            Walk::Node(id) => walk_node(document, id, options, &mut events, &mut stack),
+            Walk::Children(id) => walk_children(document, id, &mut stack),
+            Walk::ShadowRoot(id) => walk_shadow_root(document, id, &mut events, &mut stack),
+            Walk::Event(event) => events.push(event),
+        }
+    }
+
+    events
+}
+
+fn walk_children(document: &Document, node_id: usize, stack: &mut Vec<Walk>) {
+    // The children of a template are found in its template contents
+    let parent_id = match document.get_node_by_id(node_id) {
+        Some(node) if is_html_element(node, "template") => {
+            document.template_contents(node_id).unwrap_or(node_id)
+        }
+        Some(_) => node_id,
+        None => return,
+    };
+
+    if let Some(parent) = document.get_node_by_id(parent_id) {
+        stack.extend(
+            parent
+                .children
+                .iter()
+                .rev()
+                .map(|&child_id| Walk::Node(child_id)),
+        );
+    }
+}
+
+fn walk_node(
+    document: &Document,
+    node_id: usize,
+    options: &SerializerOptions,
+    events: &mut Vec<TreeEvent>,
+    stack: &mut Vec<Walk>,
+) {
+    let node = match document.get_node_by_id(node_id) {
+        Some(node) => node,
+        None => return,
+    };
+
+    match &node.data {
+        NodeData::Document | NodeData::DocumentFragment => walk_children(document, node_id, stack),
+        NodeData::DocType {
+            name,
+            public_id,
+            system_id,
+        } => events.push(TreeEvent::Doctype {
+            name: name.clone(),
+            public_id: public_id.clone(),
+            system_id: system_id.clone(),
+        }),
+        NodeData::Text { value } => events.extend(text_events(value)),
+        NodeData::Comment { value } => events.push(TreeEvent::Comment(value.clone())),
+        NodeData::Element { name, attributes } => {
+            let mut attributes: Vec<(String, String)> = attributes
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect();
+            attributes.sort();
+
+            let is_html = node.namespace.as_deref() == Some(HTML_NAMESPACE);
+            if is_html && VOID_ELEMENTS.contains(&name.as_str()) {
+                events.push(TreeEvent::EmptyTag {
+                    namespace: node.namespace.clone(),
+                    name: name.clone(),
+                    attributes,
+                });
+                return;
+            }
+
+            events.push(TreeEvent::StartTag {
+                namespace: node.namespace.clone(),
+                name: name.clone(),
+                attributes,
+            });
+
+            // Pushed in reverse: the shadow root comes first, then the children, then the end tag
+            stack.push(Walk::Event(TreeEvent::EndTag {
+                namespace: node.namespace.clone(),
+                name: name.clone(),
+            }));
+            stack.push(Walk::Children(node_id));
+            if options.serializable_shadow_roots {
+                stack.push(Walk::ShadowRoot(node_id));
+            }
+        }
+    }
+}
+
+// Writes a serializable shadow root of the node as a declarative shadow root
+fn walk_shadow_root(
+    document: &Document,
+    host_id: usize,
+    events: &mut Vec<TreeEvent>,
+    stack: &mut Vec<Walk>,
+) {
+    let root_id = match document.shadow_root(host_id) {
+        Some(root_id) => root_id,
+        None => return,
+    };
+    let shadow_root = match document.shadow_root_info(root_id) {
+        Some(shadow_root) if shadow_root.serializable => shadow_root,
+        _ => return,
+    };
+
+    let mode = match shadow_root.mode {
+        ShadowRootMode::Open => "open",
+        ShadowRootMode::Closed => "closed",
+    };
+    let mut attributes = vec![("shadowrootmode".to_string(), mode.to_string())];
+    if shadow_root.delegates_focus {
+        attributes.push(("shadowrootdelegatesfocus".to_string(), String::new()));
+    }
+    attributes.push(("shadowrootserializable".to_string(), String::new()));
+    if shadow_root.clonable {
+        attributes.push(("shadowrootclonable".to_string(), String::new()));
+    }
+
+    events.push(TreeEvent::StartTag {
+        namespace: Some(HTML_NAMESPACE.to_string()),
+        name: "template".to_string(),
+        attributes,
+    });
+    stack.push(Walk::Event(TreeEvent::EndTag {
+        namespace: Some(HTML_NAMESPACE.to_string()),
+        name: "template".to_string(),
+    }));
+    stack.push(Walk::Children(root_id));
+}
+
+fn is_html_element(node: &Node, name: &str) -> bool {
+    node.is_element() && node.name == name && node.namespace.as_deref() == Some(HTML_NAMESPACE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_events() {
+        assert_eq!(
+            text_events(" \na b\t"),
+            vec![
+                TreeEvent::SpaceCharacters(" \n".to_string()),
+                TreeEvent::Characters("a b".to_string()),
+                TreeEvent::SpaceCharacters("\t".to_string()),
+            ]
+        );
+        assert_eq!(
+            text_events("  "),
+            vec![TreeEvent::SpaceCharacters("  ".to_string())]
+        );
+        assert!(text_events("").is_empty());
+    }
+}
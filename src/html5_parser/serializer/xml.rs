@@ -0,0 +1,860 @@
+use crate::html5_parser::node::{
+    Node, NodeData, HTML_NAMESPACE, XLINK_NAMESPACE, XMLNS_NAMESPACE, XML_NAMESPACE,
+};
+use crate::html5_parser::parser::document::Document;
+use crate::html5_parser::serializer::walker::VOID_ELEMENTS;
+use crate::html5_parser::xml_parser::{is_pubid_char, is_xml_char, is_xml_name};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+// Elements whose text is not escaped by the HTML parser
+const RAW_TEXT_ELEMENTS: [&str; 2] = ["script", "style"];
+
+/// Options for the XML serializer
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlSerializerOptions {
+    /// Fail on nodes that cannot be represented in well-formed XML, instead of writing them as
+    /// they are
+    pub require_well_formed: bool,
+    /// Only produce output that is read the same by an HTML and an XML parser. Text of script
+    /// and style elements is written unescaped, and fails when it contains `<` or `&`.
+    pub polyglot: bool,
+}
+
+impl Default for XmlSerializerOptions {
+    fn default() -> Self {
+        Self {
+            require_well_formed: true,
+            polyglot: false,
+        }
+    }
+}
+
+/// Reasons why a node could not be serialized to XML
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlSerializeError {
+    /// An element name that is not a valid XML name
+    InvalidElementName(String),
+    /// An attribute name that is not a valid XML name
+    InvalidAttributeName(String),
+    /// An attribute that occurs twice on an element after namespace resolution
+    DuplicateAttribute(String),
+    /// A namespace declaration that declares the xmlns namespace or undeclares a prefix
+    InvalidNamespaceDeclaration(String),
+    /// Text or an attribute value with characters that are not allowed in XML
+    InvalidCharacters(String),
+    /// A comment that contains `--` or ends with `-`
+    InvalidComment(String),
+    /// A doctype with an invalid public or system identifier
+    InvalidDoctype(String),
+    /// A document without a document element
+    NoDocumentElement,
+    /// Text of a script or style element that reads differently as HTML and as XML
+    NotPolyglot(String),
+}
+
+impl fmt::Display for XmlSerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlSerializeError::InvalidElementName(name) => {
+                write!(f, "invalid element name '{}'", name)
+            }
+            XmlSerializeError::InvalidAttributeName(name) => {
+                write!(f, "invalid attribute name '{}'", name)
+            }
+            XmlSerializeError::DuplicateAttribute(name) => {
+                write!(f, "duplicate attribute '{}'", name)
+            }
+            XmlSerializeError::InvalidNamespaceDeclaration(name) => {
+                write!(f, "invalid namespace declaration '{}'", name)
+            }
+            XmlSerializeError::InvalidCharacters(text) => {
+                write!(f, "invalid characters in '{}'", text)
+            }
+            XmlSerializeError::InvalidComment(text) => write!(f, "invalid comment '{}'", text),
+            XmlSerializeError::InvalidDoctype(name) => write!(f, "invalid doctype '{}'", name),
+            XmlSerializeError::NoDocumentElement => write!(f, "document has no document element"),
+            XmlSerializeError::NotPolyglot(name) => {
+                write!(
+                    f,
+                    "text of {} element cannot be written as polyglot markup",
+                    name
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for XmlSerializeError {}
+
+/// Serializes the given node including its children to XML, following the XML serialization
+/// algorithm of the DOM Parsing specification. Node 0 serializes the whole document.
+pub fn serialize_xml(
+    document: &Document,
+    node_id: usize,
+    options: &XmlSerializerOptions,
+) -> Result<String, XmlSerializeError> {
+    let mut serializer = XmlSerializer {
+        document,
+        options,
+        prefix_index: 1,
+        out: String::new(),
+    };
+
+    let mut map = NamespacePrefixMap::new();
+    map.add(Some(XML_NAMESPACE), "xml");
+
+    // Work is kept on an explicit stack instead of recursing, so deeply nested documents can't
+    // overflow the call stack
+    let mut stack = vec![XmlWork::Node(node_id, None, Rc::new(map))];
+    while let Some(work) = stack.pop() {
+        match work {
+            XmlWork::Node(id, namespace, map) => {
+                serializer.node(id, namespace.as_deref(), &map, &mut stack)?
+            }
+            XmlWork::EndTag(qualified_name) => {
+                serializer.out.push_str("</");
+                serializer.out.push_str(&qualified_name);
+                serializer.out.push('>');
+            }
+        }
+    }
+
+    Ok(serializer.out)
+}
+
+impl Document {
+    /// Serializes the whole document to well-formed XML
+    pub fn to_xml(&self) -> Result<String, XmlSerializeError> {
+        serialize_xml(self, 0, &XmlSerializerOptions::default())
+    }
+}
+
+// Work left to do while serializing
+enum XmlWork {
+    // A node with the inherited default namespace and the namespace prefix map of its parent.
+    // Siblings share the map, as only elements add to it and they do so on their own copy.
+    Node(usize, Option<String>, Rc<NamespacePrefixMap>),
+    // The end tag of an element with the given qualified name
+    EndTag(String),
+}
+
+// Namespace => prefixes declared for it, in declaration order
+#[derive(Clone)]
+struct NamespacePrefixMap(HashMap<Option<String>, Vec<String>>);
+
+impl NamespacePrefixMap {
+    fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    fn add(&mut self, namespace: Option<&str>, prefix: &str) {
+        self.0
+            .entry(namespace.map(String::from))
+            .or_default()
+            .push(prefix.to_string());
+    }
+
+    fn contains(&self, namespace: Option<&str>, prefix: &str) -> bool {
+        self.0
+            .get(&namespace.map(String::from))
+            .is_some_and(|prefixes| prefixes.iter().any(|p| p == prefix))
+    }
+
+    // Returns the preferred prefix when it is declared for the namespace, otherwise the prefix
+    // that was declared last
+    fn preferred_prefix(&self, namespace: &str, preferred: Option<&str>) -> Option<String> {
+        let candidates = self.0.get(&Some(namespace.to_string()))?;
+        candidates
+            .iter()
+            .find(|prefix| Some(prefix.as_str()) == preferred)
+            .or(candidates.last())
+            .cloned()
+    }
+}
+
+// An attribute with its namespace, prefix and local name
+struct Attribute<'a> {
+    namespace: Option<&'a str>,
+    prefix: Option<&'a str>,
+    local_name: &'a str,
+    value: &'a str,
+}
+
+// Returns the attributes of an element, sorted by name. The document stores namespaced
+// attributes by their qualified name, so the namespace follows from the prefix. Namespace
+// declarations (`xmlns` and `xmlns:*`) are read as such on all elements, as an html root element
+// with an xmlns attribute is common in XHTML documents. Other prefixes are resolved through the
+// declarations on the element and its ancestors, except on HTML elements where the HTML parser
+// keeps names like `xlink:href` as they are.
+fn attributes<'a>(
+    document: &'a Document,
+    node: &Node,
+    attributes: &'a HashMap<String, String>,
+) -> Vec<Attribute<'a>> {
+    let html = node.namespace.as_deref() == Some(HTML_NAMESPACE);
+    let mut result: Vec<Attribute> = attributes
+        .iter()
+        .map(|(name, value)| {
+            let (namespace, prefix, local_name) = match name.split_once(':') {
+                _ if name == "xmlns" => (Some(XMLNS_NAMESPACE), None, name.as_str()),
+                Some(("xmlns", local)) => (Some(XMLNS_NAMESPACE), Some("xmlns"), local),
+                Some(("xml", local)) if !html => (Some(XML_NAMESPACE), Some("xml"), local),
+                Some((prefix, local)) if !html => match lookup_namespace(document, node, prefix) {
+                    Some(namespace) => (Some(namespace), Some(prefix), local),
+                    None if prefix == "xlink" => (Some(XLINK_NAMESPACE), Some(prefix), local),
+                    None => (None, None, name.as_str()),
+                },
+                _ => (None, None, name.as_str()),
+            };
+            Attribute {
+                namespace,
+                prefix,
+                local_name,
+                value,
+            }
+        })
+        .collect();
+    result.sort_by(|a, b| (a.prefix, a.local_name).cmp(&(b.prefix, b.local_name)));
+    result
+}
+
+// Returns the namespace declared for the prefix on the element or its closest ancestor
+fn lookup_namespace<'a>(document: &'a Document, node: &Node, prefix: &str) -> Option<&'a str> {
+    let name = format!("xmlns:{}", prefix);
+    let mut current = document.get_node_by_id(node.id);
+    while let Some(node) = current {
+        if let NodeData::Element { attributes, .. } = &node.data {
+            if let Some(namespace) = attributes.get(&name) {
+                return Some(namespace.as_str()).filter(|namespace| !namespace.is_empty());
+            }
+        }
+        current = node
+            .parent
+            .and_then(|parent_id| document.get_node_by_id(parent_id));
+    }
+    None
+}
+
+// Pushes the children on the stack, so that the first child is serialized first
+fn push_children(
+    stack: &mut Vec<XmlWork>,
+    children: &[usize],
+    namespace: Option<&str>,
+    map: &Rc<NamespacePrefixMap>,
+) {
+    stack.extend(
+        children
+            .iter()
+            .rev()
+            .map(|&child_id| XmlWork::Node(child_id, namespace.map(String::from), Rc::clone(map))),
+    );
+}
+
+struct XmlSerializer<'a> {
+    document: &'a Document,
+    options: &'a XmlSerializerOptions,
+    prefix_index: usize,
+    out: String,
+}
+
+impl<'a> XmlSerializer<'a> {
+    // Writes a node, and pushes its children and end tag on the stack
+    fn node(
+        &mut self,
+        node_id: usize,
+        namespace: Option<&str>,
+        map: &Rc<NamespacePrefixMap>,
+        stack: &mut Vec<XmlWork>,
+    ) -> Result<(), XmlSerializeError> {
+        let node = match self.document.get_node_by_id(node_id) {
+            Some(node) => node,
+            None => return Ok(()),
+        };
+
+        match &node.data {
+            NodeData::Document => {
+                if self.options.require_well_formed
+                    && !node.children.iter().any(|&id| self.is_element(id))
+                {
+                    return Err(XmlSerializeError::NoDocumentElement);
+                }
+                push_children(stack, &node.children, namespace, map);
+                Ok(())
+            }
+            NodeData::DocumentFragment => {
+                push_children(stack, &node.children, namespace, map);
+                Ok(())
+            }
+            NodeData::DocType {
+                name,
+                public_id,
+                system_id,
+            } => self.doctype(name, public_id.as_deref(), system_id.as_deref()),
+            NodeData::Text { value } => self.text(node, value),
+            NodeData::Comment { value } => {
+                if self.options.require_well_formed
+                    && (!value.chars().all(is_xml_char)
+                        || value.contains("--")
+                        || value.ends_with('-'))
+                {
+                    return Err(XmlSerializeError::InvalidComment(value.clone()));
+                }
+                self.out.push_str("<!--");
+                self.out.push_str(value);
+                self.out.push_str("-->");
+                Ok(())
+            }
+            NodeData::Element { attributes, .. } => {
+                self.element(node, attributes, namespace, (**map).clone(), stack)
+            }
+        }
+    }
+
+    fn is_element(&self, node_id: usize) -> bool {
+        self.document
+            .get_node_by_id(node_id)
+            .is_some_and(|node| node.is_element())
+    }
+
+    fn element(
+        &mut self,
+        node: &Node,
+        attributes: &HashMap<String, String>,
+        inherited_namespace: Option<&str>,
+        mut map: NamespacePrefixMap,
+        stack: &mut Vec<XmlWork>,
+    ) -> Result<(), XmlSerializeError> {
+        let local_name = node.name.as_str();
+        if self.options.require_well_formed
+            && (local_name.contains(':') || !is_xml_name(local_name))
+        {
+            return Err(XmlSerializeError::InvalidElementName(
+                local_name.to_string(),
+            ));
+        }
+
+        let element_namespace = node.namespace.as_deref();
+        let attributes = self::attributes(self.document, node, attributes);
+        let mut local_prefixes = HashMap::new();
+        let local_default_namespace =
+            record_namespace_information(&attributes, &mut map, &mut local_prefixes);
+
+        let mut inherited_namespace = inherited_namespace.map(String::from);
+        let mut ignore_namespace_definition_attribute = false;
+        let qualified_name;
+
+        let preferred_prefix = element_namespace
+            .and_then(|namespace| map.preferred_prefix(namespace, node.prefix.as_deref()));
+
+        if inherited_namespace.as_deref() == element_namespace {
+            if local_default_namespace.is_some() {
+                ignore_namespace_definition_attribute = true;
+            }
+            qualified_name = match element_namespace {
+                Some(XML_NAMESPACE) => format!("xml:{}", local_name),
+                _ => local_name.to_string(),
+            };
+            self.out.push('<');
+            self.out.push_str(&qualified_name);
+        } else if let Some(prefix) = preferred_prefix {
+            qualified_name = format!("{}:{}", prefix, local_name);
+            if let Some(default_namespace) = local_default_namespace {
+                if default_namespace != XML_NAMESPACE {
+                    inherited_namespace = Some(default_namespace.to_string())
+                        .filter(|namespace| !namespace.is_empty());
+                }
+            }
+            self.out.push('<');
+            self.out.push_str(&qualified_name);
+        } else if element_namespace.is_none() || local_default_namespace != element_namespace {
+            // An element in no namespace undeclares the inherited default namespace
+            ignore_namespace_definition_attribute = true;
+            qualified_name = local_name.to_string();
+            inherited_namespace = element_namespace.map(String::from);
+            self.out.push('<');
+            self.out.push_str(&qualified_name);
+            self.out.push_str(" xmlns=\"");
+            self.out.push_str(&escape_attribute_value(
+                element_namespace.unwrap_or_default(),
+            ));
+            self.out.push('"');
+        } else {
+            qualified_name = local_name.to_string();
+            inherited_namespace = element_namespace.map(String::from);
+            self.out.push('<');
+            self.out.push_str(&qualified_name);
+        }
+
+        self.attributes(
+            &attributes,
+            &mut map,
+            &mut local_prefixes,
+            ignore_namespace_definition_attribute,
+        )?;
+
+        // Template contents are serialized as the children of the template
+        let children = match self.document.template_contents(node.id) {
+            Some(contents_id) if element_namespace == Some(HTML_NAMESPACE) => self
+                .document
+                .get_node_by_id(contents_id)
+                .map(|contents| contents.children.clone())
+                .unwrap_or_default(),
+            _ => node.children.clone(),
+        };
+
+        if children.is_empty() {
+            if element_namespace == Some(HTML_NAMESPACE) && VOID_ELEMENTS.contains(&local_name) {
+                self.out.push_str(" />");
+                return Ok(());
+            }
+            if element_namespace != Some(HTML_NAMESPACE) {
+                self.out.push_str("/>");
+                return Ok(());
+            }
+        }
+        self.out.push('>');
+
+        stack.push(XmlWork::EndTag(qualified_name));
+        push_children(
+            stack,
+            &children,
+            inherited_namespace.as_deref(),
+            &Rc::new(map),
+        );
+        Ok(())
+    }
+
+    fn attributes(
+        &mut self,
+        attributes: &[Attribute],
+        map: &mut NamespacePrefixMap,
+        local_prefixes: &mut HashMap<String, String>,
+        ignore_namespace_definition_attribute: bool,
+    ) -> Result<(), XmlSerializeError> {
+        let mut seen = HashSet::new();
+
+        for attribute in attributes {
+            if self.options.require_well_formed
+                && !seen.insert((attribute.namespace, attribute.local_name))
+            {
+                return Err(XmlSerializeError::DuplicateAttribute(
+                    attribute.local_name.to_string(),
+                ));
+            }
+
+            let mut candidate_prefix = None;
+            if let Some(namespace) = attribute.namespace {
+                candidate_prefix = map.preferred_prefix(namespace, attribute.prefix);
+
+                if namespace == XMLNS_NAMESPACE {
+                    let redundant = match attribute.prefix {
+                        None => ignore_namespace_definition_attribute,
+                        Some(_) => {
+                            local_prefixes.get(attribute.local_name).map(String::as_str)
+                                != Some(attribute.value)
+                        }
+                    };
+                    if attribute.value == XML_NAMESPACE || redundant {
+                        continue;
+                    }
+                    if self.options.require_well_formed
+                        && (attribute.value == XMLNS_NAMESPACE || attribute.value.is_empty())
+                    {
+                        return Err(XmlSerializeError::InvalidNamespaceDeclaration(
+                            attribute.local_name.to_string(),
+                        ));
+                    }
+                    if attribute.prefix == Some("xmlns") {
+                        candidate_prefix = Some("xmlns".to_string());
+                    }
+                } else if candidate_prefix.is_none() {
+                    let prefix = self.generate_prefix(map, local_prefixes, namespace);
+                    self.out.push_str(" xmlns:");
+                    self.out.push_str(&prefix);
+                    self.out.push_str("=\"");
+                    self.out.push_str(&escape_attribute_value(namespace));
+                    self.out.push('"');
+                    candidate_prefix = Some(prefix);
+                }
+            }
+
+            if self.options.require_well_formed
+                && (attribute.local_name.contains(':')
+                    || !is_xml_name(attribute.local_name)
+                    || (attribute.local_name == "xmlns" && attribute.namespace.is_none()))
+            {
+                return Err(XmlSerializeError::InvalidAttributeName(
+                    attribute.local_name.to_string(),
+                ));
+            }
+            if self.options.require_well_formed && !attribute.value.chars().all(is_xml_char) {
+                return Err(XmlSerializeError::InvalidCharacters(
+                    attribute.value.to_string(),
+                ));
+            }
+
+            self.out.push(' ');
+            if let Some(prefix) = candidate_prefix {
+                self.out.push_str(&prefix);
+                self.out.push(':');
+            }
+            self.out.push_str(attribute.local_name);
+            self.out.push_str("=\"");
+            self.out.push_str(&escape_attribute_value(attribute.value));
+            self.out.push('"');
+        }
+
+        Ok(())
+    }
+
+    fn generate_prefix(
+        &mut self,
+        map: &mut NamespacePrefixMap,
+        local_prefixes: &mut HashMap<String, String>,
+        namespace: &str,
+    ) -> String {
+        let prefix = format!("ns{}", self.prefix_index);
+        self.prefix_index += 1;
+        map.add(Some(namespace), &prefix);
+        local_prefixes.insert(prefix.clone(), namespace.to_string());
+        prefix
+    }
+
+    fn text(&mut self, node: &Node, value: &str) -> Result<(), XmlSerializeError> {
+        if self.options.require_well_formed && !value.chars().all(is_xml_char) {
+            return Err(XmlSerializeError::InvalidCharacters(value.to_string()));
+        }
+
+        if self.options.polyglot {
+            let parent = node.parent.and_then(|id| self.document.get_node_by_id(id));
+            if let Some(parent) = parent.filter(|parent| is_raw_text_element(parent)) {
+                if value.contains(['<', '&']) {
+                    return Err(XmlSerializeError::NotPolyglot(parent.name.clone()));
+                }
+                self.out.push_str(value);
+                return Ok(());
+            }
+        }
+
+        for c in value.chars() {
+            match c {
+                '&' => self.out.push_str("&amp;"),
+                '<' => self.out.push_str("&lt;"),
+                '>' => self.out.push_str("&gt;"),
+                _ => self.out.push(c),
+            }
+        }
+        Ok(())
+    }
+
+    fn doctype(
+        &mut self,
+        name: &str,
+        public_id: Option<&str>,
+        system_id: Option<&str>,
+    ) -> Result<(), XmlSerializeError> {
+        let public_id = public_id.unwrap_or_default();
+        let system_id = system_id.unwrap_or_default();
+
+        if self.options.require_well_formed
+            && (!public_id.chars().all(is_pubid_char)
+                || !system_id.chars().all(is_xml_char)
+                || (system_id.contains('"') && system_id.contains('\'')))
+        {
+            return Err(XmlSerializeError::InvalidDoctype(name.to_string()));
+        }
+
+        self.out.push_str("<!DOCTYPE ");
+        self.out.push_str(name);
+        if !public_id.is_empty() {
+            self.out.push_str(" PUBLIC \"");
+            self.out.push_str(public_id);
+            self.out.push('"');
+        } else if !system_id.is_empty() {
+            self.out.push_str(" SYSTEM");
+        }
+        if !system_id.is_empty() {
+            // A system id with a double quote is written between single quotes
+            let quote = if system_id.contains('"') { '\'' } else { '"' };
+            self.out.push(' ');
+            self.out.push(quote);
+            self.out.push_str(system_id);
+            self.out.push(quote);
+        }
+        self.out.push('>');
+        Ok(())
+    }
+}
+
+// Adds the namespace declarations of the element to the map, and returns the value of its
+// default namespace declaration (xmlns attribute), if any
+fn record_namespace_information<'a>(
+    attributes: &[Attribute<'a>],
+    map: &mut NamespacePrefixMap,
+    local_prefixes: &mut HashMap<String, String>,
+) -> Option<&'a str> {
+    let mut default_namespace = None;
+
+    for attribute in attributes {
+        if attribute.namespace != Some(XMLNS_NAMESPACE) {
+            continue;
+        }
+        if attribute.prefix.is_none() {
+            default_namespace = Some(attribute.value);
+            continue;
+        }
+
+        let prefix = attribute.local_name;
+        let namespace = match attribute.value {
+            XML_NAMESPACE => continue,
+            "" => None,
+            value => Some(value),
+        };
+        if map.contains(namespace, prefix) {
+            continue;
+        }
+        map.add(namespace, prefix);
+        local_prefixes.insert(prefix.to_string(), attribute.value.to_string());
+    }
+
+    default_namespace
+}
+
+fn is_raw_text_element(node: &Node) -> bool {
+    node.namespace.as_deref() == Some(HTML_NAMESPACE)
+        && RAW_TEXT_ELEMENTS.contains(&node.name.as_str())
+}
+
+fn escape_attribute_value(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '"' => result.push_str("&quot;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::node::{MATHML_NAMESPACE, SVG_NAMESPACE};
+
+    fn element(
+        document: &mut Document,
+        name: &str,
+        namespace: &str,
+        attrs: &[(&str, &str)],
+        parent: usize,
+    ) -> usize {
+        let attributes: HashMap<String, String> = attrs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        document.add_node(Node::new_element(name, attributes, namespace), parent)
+    }
+
+    // Returns a document with an html and a body element, and the id of the body
+    fn document_with_body(html_attrs: &[(&str, &str)]) -> (Document, usize) {
+        let mut document = Document::new();
+        let html_id = element(&mut document, "html", HTML_NAMESPACE, html_attrs, 0);
+        let body_id = element(&mut document, "body", HTML_NAMESPACE, &[], html_id);
+        (document, body_id)
+    }
+
+    #[test]
+    fn test_serialize_xml() {
+        let mut document = Document::new();
+        document.add_node(Node::new_doctype("html", None, None), 0);
+        let html_id = element(&mut document, "html", HTML_NAMESPACE, &[], 0);
+        let body_id = element(&mut document, "body", HTML_NAMESPACE, &[], html_id);
+        let p_id = element(
+            &mut document,
+            "p",
+            HTML_NAMESPACE,
+            &[("title", "1 < 2 & \"3\""), ("class", "a")],
+            body_id,
+        );
+        document.add_node(Node::new_text("a < b"), p_id);
+        element(&mut document, "br", HTML_NAMESPACE, &[], p_id);
+        element(&mut document, "div", HTML_NAMESPACE, &[], p_id);
+        document.add_node(Node::new_comment(" x "), body_id);
+
+        assert_eq!(
+            document.to_xml().unwrap(),
+            "<!DOCTYPE html><html xmlns=\"http://www.w3.org/1999/xhtml\"><body>\
+             <p class=\"a\" title=\"1 &lt; 2 &amp; &quot;3&quot;\">a &lt; b<br /><div></div></p>\
+             <!-- x --></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_serialize_xml_deep_tree() {
+        let mut document = Document::new();
+        let mut parent_id = element(&mut document, "html", HTML_NAMESPACE, &[], 0);
+        for _ in 0..20_000 {
+            parent_id = element(&mut document, "div", HTML_NAMESPACE, &[], parent_id);
+        }
+
+        let xml = document.to_xml().unwrap();
+        assert!(xml.starts_with("<html xmlns=\"http://www.w3.org/1999/xhtml\"><div><div>"));
+        assert!(xml.ends_with(&("<div></div>".to_string() + &"</div>".repeat(19_999) + "</html>")));
+    }
+
+    #[test]
+    fn test_serialize_xml_namespaces() {
+        let (mut document, body_id) = document_with_body(&[]);
+        let svg_id = element(
+            &mut document,
+            "svg",
+            SVG_NAMESPACE,
+            &[("viewBox", "0 0 1 1")],
+            body_id,
+        );
+        let a_id = element(
+            &mut document,
+            "a",
+            SVG_NAMESPACE,
+            &[("xlink:href", "#x"), ("xml:lang", "en")],
+            svg_id,
+        );
+        element(&mut document, "path", SVG_NAMESPACE, &[], a_id);
+        let math_id = element(&mut document, "math", MATHML_NAMESPACE, &[], body_id);
+        let mi_id = element(&mut document, "mi", MATHML_NAMESPACE, &[], math_id);
+        document.add_node(Node::new_text("x"), mi_id);
+        let template_id = element(&mut document, "template", HTML_NAMESPACE, &[], body_id);
+        let contents_id = document.create_template_contents(template_id);
+        element(&mut document, "td", HTML_NAMESPACE, &[], contents_id);
+
+        assert_eq!(
+            serialize_xml(&document, body_id, &XmlSerializerOptions::default()).unwrap(),
+            "<body xmlns=\"http://www.w3.org/1999/xhtml\">\
+             <svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 1 1\">\
+             <a xmlns:ns1=\"http://www.w3.org/1999/xlink\" ns1:href=\"#x\" xml:lang=\"en\">\
+             <path/></a></svg>\
+             <math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mi>x</mi></math>\
+             <template><td></td></template></body>"
+        );
+
+        // Declared namespaces and prefixes are kept
+        let (mut document, body_id) =
+            document_with_body(&[("xmlns", HTML_NAMESPACE), ("lang", "en")]);
+        let svg_id = element(
+            &mut document,
+            "svg",
+            SVG_NAMESPACE,
+            &[("xmlns", SVG_NAMESPACE), ("xmlns:xlink", XLINK_NAMESPACE)],
+            body_id,
+        );
+        element(
+            &mut document,
+            "use",
+            SVG_NAMESPACE,
+            &[("xlink:href", "#a")],
+            svg_id,
+        );
+        assert_eq!(
+            document.to_xml().unwrap(),
+            "<html lang=\"en\" xmlns=\"http://www.w3.org/1999/xhtml\"><body>\
+             <svg xmlns=\"http://www.w3.org/2000/svg\" \
+             xmlns:xlink=\"http://www.w3.org/1999/xlink\">\
+             <use xlink:href=\"#a\"/></svg></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_serialize_xml_errors() {
+        let options = XmlSerializerOptions::default();
+        let serialize = |name: &str, attrs: &[(&str, &str)], text: Option<&str>| {
+            let (mut document, body_id) = document_with_body(&[]);
+            let id = element(&mut document, name, HTML_NAMESPACE, attrs, body_id);
+            if let Some(text) = text {
+                document.add_node(Node::new_text(text), id);
+            }
+            serialize_xml(&document, 0, &options)
+        };
+
+        assert_eq!(
+            serialize("p", &[("a:b", "1")], None),
+            Err(XmlSerializeError::InvalidAttributeName("a:b".to_string()))
+        );
+        assert_eq!(
+            serialize("p", &[("1a", "1")], None),
+            Err(XmlSerializeError::InvalidAttributeName("1a".to_string()))
+        );
+        assert_eq!(
+            serialize("a:b", &[], None),
+            Err(XmlSerializeError::InvalidElementName("a:b".to_string()))
+        );
+        assert_eq!(
+            serialize("p", &[], Some("\u{b}")),
+            Err(XmlSerializeError::InvalidCharacters("\u{b}".to_string()))
+        );
+        assert_eq!(
+            serialize("p", &[("title", "\u{0}")], None),
+            Err(XmlSerializeError::InvalidCharacters("\u{0}".to_string()))
+        );
+
+        let (mut document, body_id) = document_with_body(&[]);
+        document.add_node(Node::new_comment(" a -- b "), body_id);
+        assert_eq!(
+            document.to_xml(),
+            Err(XmlSerializeError::InvalidComment(" a -- b ".to_string()))
+        );
+
+        let mut document = Document::new();
+        document.add_node(Node::new_doctype("html", Some("\"x\""), None), 0);
+        assert_eq!(
+            serialize_xml(&document, 1, &options),
+            Err(XmlSerializeError::InvalidDoctype("html".to_string()))
+        );
+        assert_eq!(document.to_xml(), Err(XmlSerializeError::NoDocumentElement));
+
+        let options = XmlSerializerOptions {
+            require_well_formed: false,
+            ..Default::default()
+        };
+        let (mut document, body_id) = document_with_body(&[]);
+        element(&mut document, "p", HTML_NAMESPACE, &[("a:b", "1")], body_id);
+        assert_eq!(
+            serialize_xml(&document, body_id, &options).unwrap(),
+            "<body xmlns=\"http://www.w3.org/1999/xhtml\"><p a:b=\"1\"></p></body>"
+        );
+    }
+
+    #[test]
+    fn test_serialize_polyglot() {
+        let (mut document, body_id) = document_with_body(&[]);
+        let script_id = element(&mut document, "script", HTML_NAMESPACE, &[], body_id);
+        let text_id = document.add_node(Node::new_text("if (a > b) {}"), script_id);
+        let p_id = element(&mut document, "p", HTML_NAMESPACE, &[], body_id);
+        document.add_node(Node::new_text("a > b"), p_id);
+
+        let options = XmlSerializerOptions {
+            polyglot: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            serialize_xml(&document, body_id, &options).unwrap(),
+            "<body xmlns=\"http://www.w3.org/1999/xhtml\">\
+             <script>if (a > b) {}</script><p>a &gt; b</p></body>"
+        );
+        assert_eq!(
+            serialize_xml(&document, body_id, &XmlSerializerOptions::default()).unwrap(),
+            "<body xmlns=\"http://www.w3.org/1999/xhtml\">\
+             <script>if (a &gt; b) {}</script><p>a &gt; b</p></body>"
+        );
+
+        *document.get_mut_text_by_id(text_id).unwrap() = "a && b".to_string();
+        assert_eq!(
+            serialize_xml(&document, body_id, &options),
+            Err(XmlSerializeError::NotPolyglot("script".to_string()))
+        );
+    }
+}
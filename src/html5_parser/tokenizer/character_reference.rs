@@ -2,11 +2,10 @@ use crate::html5_parser::error_logger::ParserError;
 use crate::html5_parser::input_stream::Element;
 use crate::read_char;
 
-extern crate lazy_static;
 use crate::html5_parser::input_stream::SeekMode::SeekCur;
-use crate::html5_parser::tokenizer::replacement_tables::{TOKEN_NAMED_CHARS, TOKEN_REPLACEMENTS};
+use crate::html5_parser::tokenizer::entity_trie::longest_match;
+use crate::html5_parser::tokenizer::replacement_tables::TOKEN_REPLACEMENTS;
 use crate::html5_parser::tokenizer::{Tokenizer, CHAR_REPLACEMENT};
-use lazy_static::lazy_static;
 
 // Different states for the character references
 pub enum CcrState {
@@ -35,15 +34,10 @@ macro_rules! consume_temp_buffer {
 }
 
 impl<'a> Tokenizer<'a> {
-    // Consumes a character reference and places this in the tokenizer consume buffer
-    // ref: 8.2.4.69 Tokenizing character references
-
-    // @TODO: fix additional allowed char
-    pub fn consume_character_reference(
-        &mut self,
-        _additional_allowed_char: Option<Element>,
-        as_attribute: bool,
-    ) {
+    // Consumes a character reference and places this in the tokenizer consume buffer, or in
+    // the current attribute value when as_attribute is set.
+    // ref: 13.2.5.72 Character reference state
+    pub fn consume_character_reference(&mut self, as_attribute: bool) {
         let mut ccr_state = CcrState::CharacterReference;
         let mut char_ref_code: Option<u32> = Some(0);
 
@@ -77,9 +71,13 @@ impl<'a> Tokenizer<'a> {
                     }
                 }
                 CcrState::NamedCharacterReference => {
-                    if let Some(entity) = self.find_entity() {
-                        self.stream.seek(SeekCur, entity.len() as isize);
+                    if let Some((len, entity_chars)) = longest_match(self.stream.remaining()) {
+                        let entity = self.stream.look_ahead_slice(len);
+                        self.stream.seek(SeekCur, len as isize);
                         let c = self.stream.look_ahead(0);
+
+                        // In attribute values, a legacy reference without semicolon followed by
+                        // '=' or an alphanumeric is not a reference, as in `?a=1&copy=2`
                         if as_attribute
                             && !entity.ends_with(';')
                             && c.is_utf8()
@@ -94,8 +92,6 @@ impl<'a> Tokenizer<'a> {
                             return;
                         }
 
-                        let entity_chars = *TOKEN_NAMED_CHARS.get(entity.as_str()).unwrap();
-
                         // Flush codepoints consumed as character reference
                         for c in entity_chars.chars() {
                             if as_attribute {
@@ -314,14 +310,8 @@ impl<'a> Tokenizer<'a> {
     }
 
     pub(crate) fn is_noncharacter(&self, num: u32) -> bool {
-        (0xFDD0..=0xFDEF).contains(&num)
-            || [
-                0xFFFE, 0xFFFF, 0x1FFFE, 0x1FFFF, 0x2FFFE, 0x2FFFF, 0x3FFFE, 0x3FFFF, 0x4FFFE,
-                0x4FFFF, 0x5FFFE, 0x5FFFF, 0x6FFFE, 0x6FFFF, 0x7FFFE, 0x7FFFF, 0x8FFFE, 0x8FFFF,
-                0x9FFFE, 0x9FFFF, 0xAFFFE, 0xAFFFF, 0xBFFFE, 0xBFFFF, 0xCFFFE, 0xCFFFF, 0xDFFFE,
-                0xDFFFF, 0xEFFFE, 0xEFFFF, 0xFFFFE, 0xFFFFF, 0x10FFFE, 0x10FFFF,
-            ]
-            .contains(&num)
+        // U+FDD0..U+FDEF, and the last two code points of every plane (U+xxFFFE and U+xxFFFF)
+        (0xFDD0..=0xFDEF).contains(&num) || (num <= 0x10FFFF && num & 0xFFFE == 0xFFFE)
     }
 
     pub(crate) fn is_control_char(&self, num: u32) -> bool {
@@ -332,27 +322,6 @@ impl<'a> Tokenizer<'a> {
 
         (0x0001..=0x001F).contains(&num) || (0x007F..=0x009F).contains(&num)
     }
-
-    // Finds the longest entity from the current position in the stream. Returns the entity
-    // replacement OR None when no entity has been found.
-    fn find_entity(&mut self) -> Option<String> {
-        let s = self.stream.look_ahead_slice(*LONGEST_ENTITY_LENGTH);
-        for i in (0..=s.len()).rev() {
-            if TOKEN_NAMED_CHARS.contains_key(&s[0..i]) {
-                // Move forward with the number of chars matching
-                // self.stream.skip(i);
-                return Some(String::from(&s[0..i]));
-            }
-        }
-        None
-    }
-}
-
-lazy_static! {
-    // Returns the longest entity in the TOKEN_NAMED_CHARS map (this could be a const actually)
-    static ref LONGEST_ENTITY_LENGTH: usize = {
-        TOKEN_NAMED_CHARS.keys().map(|key| key.len()).max().unwrap_or(0)
-    };
 }
 
 #[cfg(test)]
@@ -422,7 +391,7 @@ mod tests {
         entity_111: ("&copya", "©a")
         entity_112: ("&copya;", "©a;")
         entity_113: ("&#169;", "©")
-        // entity_114: ("&copy&", "©&")
+        entity_114: ("&copy&", "©&")
         entity_115: ("&copya ", "©a ")
         entity_116: ("&#169X ", "©X ")
 
@@ -490,5 +459,14 @@ mod tests {
         entity_259: ("&#11;", "\u{b}")
         entity_260: ("&#12;", "\u{c}")
         entity_261: ("&#13;", "\u{d}")
+
+        // Attribute values
+        entity_300: ("<a href='?a=1&copy=2'>", "<a href=\"?a=1&copy=2\">")
+        entity_301: ("<a href='?a=1&copy2'>", "<a href=\"?a=1&copy2\">")
+        entity_302: ("<a href='?a=1&copy;=2'>", "<a href=\"?a=1©=2\">")
+        entity_303: ("<a href='?a=1&copy-2'>", "<a href=\"?a=1©-2\">")
+        entity_304: ("<a title=&notin>", "<a title=\"&notin\">")
+        entity_305: ("<a title=\"&\">", "<a title=\"&\">")
+        entity_306: ("<a title=&amp>", "<a title=\"&\">")
     }
 }
@@ -0,0 +1,62 @@
+// A trie over the names of the named character references, generated at compile time from
+// TOKEN_NAMED_CHARS by build.rs.
+
+struct TrieNode {
+    first_edge: u16,             // Index of the first edge of this node in EDGES
+    edge_count: u8,              // Number of edges of this node
+    value: Option<&'static str>, // Replacement when a name ends at this node
+}
+
+include!(concat!(env!("OUT_DIR"), "/entity_trie.rs"));
+
+/// Finds the longest named character reference at the start of the input, in a single pass
+/// over it. Returns the length of the name in bytes (which is also its length in chars, as
+/// names are ascii) and its replacement.
+pub(crate) fn longest_match(input: &str) -> Option<(usize, &'static str)> {
+    let mut node = &NODES[0];
+    let mut longest = None;
+
+    for (idx, byte) in input.bytes().enumerate() {
+        let start = node.first_edge as usize;
+        let edges = &EDGES[start..start + node.edge_count as usize];
+        let child = match edges.binary_search_by_key(&byte, |&(b, _)| b) {
+            Ok(edge) => edges[edge].1,
+            Err(_) => break,
+        };
+
+        node = &NODES[child as usize];
+        if let Some(value) = node.value {
+            longest = Some((idx + 1, value));
+        }
+    }
+
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::tokenizer::replacement_tables::TOKEN_NAMED_CHARS;
+
+    #[test]
+    fn test_longest_match() {
+        assert_eq!(longest_match("amp;x"), Some((4, "&")));
+        assert_eq!(longest_match("ampx"), Some((3, "&")));
+        assert_eq!(longest_match("notin;"), Some((6, "\u{2209}")));
+        assert_eq!(longest_match("notit;"), Some((3, "\u{00AC}")));
+        assert_eq!(longest_match("noti"), Some((3, "\u{00AC}")));
+        assert_eq!(
+            longest_match("NotEqualTilde;"),
+            Some((14, "\u{2242}\u{0338}"))
+        );
+        assert_eq!(longest_match("xyz;"), None);
+        assert_eq!(longest_match(""), None);
+    }
+
+    #[test]
+    fn test_all_entities() {
+        for (name, value) in TOKEN_NAMED_CHARS.entries() {
+            assert_eq!(longest_match(name), Some((name.len(), *value)), "{}", name);
+        }
+    }
+}
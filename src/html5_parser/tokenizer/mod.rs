@@ -2,16 +2,20 @@ pub mod state;
 pub mod token;
 
 mod character_reference;
-mod replacement_tables;
+pub(crate) mod entity_trie;
+pub mod raw_token;
+pub(crate) mod replacement_tables;
 
 use crate::html5_parser::error_logger::{ErrorLogger, ParserError};
 use crate::html5_parser::input_stream::Element;
 use crate::html5_parser::input_stream::SeekMode::SeekCur;
 use crate::html5_parser::input_stream::{InputStream, Position};
+use crate::html5_parser::tokenizer::raw_token::RawToken;
 use crate::html5_parser::tokenizer::state::State;
 use crate::html5_parser::tokenizer::token::Token;
 use std::cell::{Ref, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
 use std::rc::Rc;
 
 // Constants that are not directly captured as visible chars
@@ -27,15 +31,20 @@ pub const CHAR_REPLACEMENT: char = '\u{FFFD}';
 pub struct Tokenizer<'a> {
     pub stream: &'a mut InputStream, // HTML character input stream
     pub state: State,                // Current state of the tokenizer
-    pub consumed: Vec<char>,         // Current consumed characters for current token
+    pub consumed: String,            // Current consumed characters for current token
     pub current_attr_name: String, // Current attribute name that we need to store temporary in case we are parsing attributes
     pub current_attr_value: String, // Current attribute value that we need to store temporary in case we are parsing attributes
     pub current_attrs: HashMap<String, String>, // Current attributes
     pub current_token: Option<Token>, // Token that is currently in the making (if any)
     pub temporary_buffer: Vec<char>, // Temporary buffer
-    pub token_queue: Vec<Token>, // Queue of emitted tokens. Needed because we can generate multiple tokens during iteration
-    pub last_start_token: String, // The last emitted start token (or empty if none)
+    pub token_queue: VecDeque<Token>, // Queue of emitted tokens. Needed because we can generate multiple tokens during iteration
+    pub last_start_token: String,     // The last emitted start token (or empty if none)
     pub error_logger: Rc<RefCell<ErrorLogger>>, // Parse errors
+    pub max_attributes: Option<usize>, // Attributes of a tag after this many are dropped
+    pub max_attribute_value_length: Option<usize>, // Attribute values are truncated to this many bytes
+    token_sources: VecDeque<Range<usize>>,         // Byte ranges of the source of the queued tokens
+    token_end: usize,                              // Byte offset where the last emitted token ended
+    markup_start: Option<usize>, // Byte offset of the "<" that started the current tag, comment or doctype
 }
 
 pub struct Options {
@@ -52,11 +61,12 @@ macro_rules! read_char {
                 $self.parse_error(ParserError::SurrogateInInputStream);
                 c = Element::Utf8(CHAR_REPLACEMENT);
             }
-            Element::Utf8(c) if $self.is_control_char(c as u32) => {
-                $self.parse_error(ParserError::ControlCharacterInInputStream);
-            }
-            Element::Utf8(c) if $self.is_noncharacter(c as u32) => {
-                $self.parse_error(ParserError::NoncharacterInInputStream);
+            Element::Utf8(c) if $self.is_invalid_input_char(c) => {
+                if $self.is_control_char(c as u32) {
+                    $self.parse_error(ParserError::ControlCharacterInInputStream);
+                } else {
+                    $self.parse_error(ParserError::NoncharacterInInputStream);
+                }
             }
             _ => {}
         }
@@ -160,37 +170,40 @@ macro_rules! to_lowercase {
 // Emits the current stored token
 macro_rules! emit_current_token {
     ($self:expr) => {
-        match $self.current_token {
-            None => {}
-            _ => {
-                emit_token!($self, $self.current_token.as_ref().unwrap());
-            }
-        };
-        $self.current_token = None;
+        if let Some(token) = $self.current_token.take() {
+            emit_token!($self, token);
+        }
     };
 }
 
 // Emits the given stored token. It does not have to be stored first.
 macro_rules! emit_token {
-    ($self:expr, $token:expr) => {
+    ($self:expr, $token:expr) => {{
+        let token: Token = $token;
+
         // Save the start token name if we are pushing it. This helps us in detecting matching tags.
-        match $token {
-            Token::StartTagToken { name, .. } => {
-                $self.last_start_token = String::from(name);
-            }
-            _ => {}
+        if let Token::StartTagToken { name, .. } = &token {
+            $self.last_start_token.clone_from(name);
         }
 
+        // The text before the token ends where the markup of the token starts. An EOF token has
+        // no markup, unless it follows a tag that was cut off by the end of the input.
+        let end = $self.stream.position.byte_offset;
+        let markup_start = $self.markup_start.take().unwrap_or(end);
+
         // If there is any consumed data, emit this first as a text token
         if $self.has_consumed_data() {
-            $self.token_queue.push(Token::TextToken {
-                value: $self.get_consumed_str(),
+            $self.token_queue.push_back(Token::TextToken {
+                value: std::mem::take(&mut $self.consumed),
             });
-            $self.clear_consume_buffer();
+            $self.token_sources.push_back($self.token_end..markup_start);
+            $self.token_end = markup_start;
         }
 
-        $self.token_queue.push($token.clone());
-    };
+        $self.token_queue.push_back(token);
+        $self.token_sources.push_back($self.token_end..end);
+        $self.token_end = end;
+    }};
 }
 
 impl<'a> Tokenizer<'a> {
@@ -200,21 +213,40 @@ impl<'a> Tokenizer<'a> {
         opts: Option<Options>,
         error_logger: Rc<RefCell<ErrorLogger>>,
     ) -> Self {
-        return Tokenizer {
+        let token_end = input.position.byte_offset;
+        let mut tokenizer = Tokenizer {
             stream: input,
             state: opts.as_ref().map_or(State::DataState, |o| o.initial_state),
             last_start_token: opts
                 .as_ref()
                 .map_or(String::new(), |o| o.last_start_tag.clone()),
-            consumed: vec![],
+            consumed: String::new(),
             current_token: None,
-            token_queue: vec![],
+            token_queue: VecDeque::new(),
             current_attr_name: String::new(),
             current_attr_value: String::new(),
             current_attrs: HashMap::new(),
             temporary_buffer: vec![],
             error_logger,
+            max_attributes: None,
+            max_attribute_value_length: None,
+            token_sources: VecDeque::new(),
+            token_end,
+            markup_start: None,
         };
+
+        // Starting in a comment continues a comment that started before the input
+        if matches!(
+            tokenizer.state,
+            State::CommentState | State::BogusCommentState
+        ) {
+            tokenizer.current_token = Some(Token::CommentToken {
+                value: String::new(),
+            });
+            tokenizer.markup_start = Some(token_end);
+        }
+
+        tokenizer
     }
 
     pub(crate) fn get_position(&self) -> Position {
@@ -229,10 +261,28 @@ impl<'a> Tokenizer<'a> {
             return Token::EofToken {};
         }
 
-        self.token_queue.remove(0)
+        self.token_sources.pop_front();
+        self.token_queue.pop_front().unwrap_or(Token::EofToken)
+    }
+
+    // Retrieves the next token together with its source. The raw text of all tokens together is
+    // the input, byte for byte, as long as the input is valid for its encoding.
+    pub fn next_raw_token(&mut self) -> RawToken {
+        self.consume_stream();
+
+        match (self.token_queue.pop_front(), self.token_sources.pop_front()) {
+            (Some(token), Some(source)) => {
+                let raw = self.stream.source(source.start, source.end).into_owned();
+                RawToken::new(token, raw, source.start)
+            }
+            _ => {
+                let end = self.stream.position.byte_offset;
+                RawToken::new(Token::EofToken, String::new(), end)
+            }
+        }
     }
 
-    pub fn get_error_logger(&self) -> Ref<ErrorLogger> {
+    pub fn get_error_logger(&self) -> Ref<'_, ErrorLogger> {
         self.error_logger.borrow()
     }
 
@@ -246,10 +296,17 @@ impl<'a> Tokenizer<'a> {
 
             match self.state {
                 State::DataState => {
+                    if self.consume_text_run(b"<&") {
+                        continue;
+                    }
+
                     let c = read_char!(self);
                     match c {
                         Element::Utf8('&') => self.state = State::CharacterReferenceInDataState,
-                        Element::Utf8('<') => self.state = State::TagOpenState,
+                        Element::Utf8('<') => {
+                            self.start_markup();
+                            self.state = State::TagOpenState;
+                        }
                         Element::Utf8(CHAR_NUL) => {
                             self.consume(c.utf8());
                             self.parse_error(ParserError::UnexpectedNullCharacter);
@@ -266,14 +323,21 @@ impl<'a> Tokenizer<'a> {
                     }
                 }
                 State::CharacterReferenceInDataState => {
-                    self.consume_character_reference(None, false);
+                    self.consume_character_reference(false);
                     self.state = State::DataState;
                 }
                 State::RcDataState => {
+                    if self.consume_text_run(b"<&") {
+                        continue;
+                    }
+
                     let c = read_char!(self);
                     match c {
                         Element::Utf8('&') => self.state = State::CharacterReferenceInRcDataState,
-                        Element::Utf8('<') => self.state = State::RcDataLessThanSignState,
+                        Element::Utf8('<') => {
+                            self.start_markup();
+                            self.state = State::RcDataLessThanSignState;
+                        }
                         Element::Eof => {
                             // if self.has_consumed_data() {
                             //     emit_token!(self, Token::TextToken { value: self.get_consumed_str().clone() });
@@ -290,13 +354,20 @@ impl<'a> Tokenizer<'a> {
                 }
                 State::CharacterReferenceInRcDataState => {
                     // consume character reference
-                    self.consume_character_reference(None, false);
+                    self.consume_character_reference(false);
                     self.state = State::RcDataState;
                 }
                 State::RawTextState => {
+                    if self.consume_text_run(b"<") {
+                        continue;
+                    }
+
                     let c = read_char!(self);
                     match c {
-                        Element::Utf8('<') => self.state = State::RawTextLessThanSignState,
+                        Element::Utf8('<') => {
+                            self.start_markup();
+                            self.state = State::RawTextLessThanSignState;
+                        }
                         Element::Utf8(CHAR_NUL) => {
                             self.consume(CHAR_REPLACEMENT);
                             self.parse_error(ParserError::UnexpectedNullCharacter);
@@ -313,9 +384,16 @@ impl<'a> Tokenizer<'a> {
                     }
                 }
                 State::ScriptDataState => {
+                    if self.consume_text_run(b"<") {
+                        continue;
+                    }
+
                     let c = read_char!(self);
                     match c {
-                        Element::Utf8('<') => self.state = State::ScriptDataLessThenSignState,
+                        Element::Utf8('<') => {
+                            self.start_markup();
+                            self.state = State::ScriptDataLessThenSignState;
+                        }
                         Element::Utf8(CHAR_NUL) => {
                             self.parse_error(ParserError::UnexpectedNullCharacter);
                             self.consume(CHAR_REPLACEMENT);
@@ -331,6 +409,10 @@ impl<'a> Tokenizer<'a> {
                     }
                 }
                 State::PlaintextState => {
+                    if self.consume_text_run(&[]) {
+                        continue;
+                    }
+
                     let c = read_char!(self);
                     match c {
                         Element::Utf8(CHAR_NUL) => {
@@ -416,6 +498,8 @@ impl<'a> Tokenizer<'a> {
                         }
                         Element::Utf8('>') => {
                             self.parse_error(ParserError::MissingEndTagName);
+                            // "</>" is dropped, its source goes with the text or token after it
+                            self.markup_start = None;
                             self.state = State::DataState;
                         }
                         Element::Eof => {
@@ -512,31 +596,25 @@ impl<'a> Tokenizer<'a> {
                         Element::Utf8(CHAR_TAB)
                         | Element::Utf8(CHAR_LF)
                         | Element::Utf8(CHAR_FF)
-                        | Element::Utf8(CHAR_SPACE) => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::BeforeAttributeNameState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        | Element::Utf8(CHAR_SPACE)
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::BeforeAttributeNameState;
                         }
-                        Element::Utf8('/') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::SelfClosingStartState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        Element::Utf8('/')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::SelfClosingStartState;
                         }
-                        Element::Utf8('>') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                let s: String = self.temporary_buffer.iter().collect::<String>();
-                                self.set_name_in_current_token(s);
+                        Element::Utf8('>')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            let s: String = self.temporary_buffer.iter().collect::<String>();
+                            self.set_name_in_current_token(s);
 
-                                self.last_start_token = String::new();
-                                emit_current_token!(self);
-                                self.state = State::DataState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                            self.last_start_token = String::new();
+                            emit_current_token!(self);
+                            self.state = State::DataState;
                         }
                         Element::Utf8(ch @ 'A'..='Z') => {
                             self.temporary_buffer.push(to_lowercase!(ch));
@@ -616,30 +694,24 @@ impl<'a> Tokenizer<'a> {
                         Element::Utf8(CHAR_TAB)
                         | Element::Utf8(CHAR_LF)
                         | Element::Utf8(CHAR_FF)
-                        | Element::Utf8(CHAR_SPACE) => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::BeforeAttributeNameState;
-                            } else {
-                                consume_anything_else = true;
-                            }
-                        }
-                        Element::Utf8('/') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::SelfClosingStartState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        | Element::Utf8(CHAR_SPACE)
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::BeforeAttributeNameState;
                         }
-                        Element::Utf8('>') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                let s: String = self.temporary_buffer.iter().collect::<String>();
-                                self.set_name_in_current_token(s);
-                                self.last_start_token = String::new();
-                                emit_current_token!(self);
-                                self.state = State::DataState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        Element::Utf8('/')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::SelfClosingStartState;
+                        }
+                        Element::Utf8('>')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            let s: String = self.temporary_buffer.iter().collect::<String>();
+                            self.set_name_in_current_token(s);
+                            self.last_start_token = String::new();
+                            emit_current_token!(self);
+                            self.state = State::DataState;
                         }
                         Element::Utf8(ch @ 'A'..='Z') => {
                             // add_to_token_name!(self, to_lowercase!(ch));
@@ -721,31 +793,25 @@ impl<'a> Tokenizer<'a> {
                         Element::Utf8(CHAR_TAB)
                         | Element::Utf8(CHAR_LF)
                         | Element::Utf8(CHAR_FF)
-                        | Element::Utf8(CHAR_SPACE) => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::BeforeAttributeNameState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        | Element::Utf8(CHAR_SPACE)
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::BeforeAttributeNameState;
                         }
-                        Element::Utf8('/') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::SelfClosingStartState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        Element::Utf8('/')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::SelfClosingStartState;
                         }
-                        Element::Utf8('>') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                let s: String = self.temporary_buffer.iter().collect::<String>();
-                                self.set_name_in_current_token(s);
+                        Element::Utf8('>')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            let s: String = self.temporary_buffer.iter().collect::<String>();
+                            self.set_name_in_current_token(s);
 
-                                self.last_start_token = String::new();
-                                emit_current_token!(self);
-                                self.state = State::DataState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                            self.last_start_token = String::new();
+                            emit_current_token!(self);
+                            self.state = State::DataState;
                         }
                         Element::Utf8(ch @ 'A'..='Z') => {
                             self.temporary_buffer.push(to_lowercase!(ch));
@@ -804,6 +870,7 @@ impl<'a> Tokenizer<'a> {
                             self.state = State::ScriptDataEscapedDashState;
                         }
                         Element::Utf8('<') => {
+                            self.start_markup();
                             self.state = State::ScriptDataEscapedLessThanSignState;
                         }
                         Element::Utf8(CHAR_NUL) => {
@@ -827,6 +894,7 @@ impl<'a> Tokenizer<'a> {
                             self.state = State::ScriptDataEscapedDashDashState;
                         }
                         Element::Utf8('<') => {
+                            self.start_markup();
                             self.state = State::ScriptDataEscapedLessThanSignState;
                         }
                         Element::Utf8(CHAR_NUL) => {
@@ -851,6 +919,7 @@ impl<'a> Tokenizer<'a> {
                             self.consume('-');
                         }
                         Element::Utf8('<') => {
+                            self.start_markup();
                             self.state = State::ScriptDataEscapedLessThanSignState;
                         }
                         Element::Utf8('>') => {
@@ -925,31 +994,25 @@ impl<'a> Tokenizer<'a> {
                         Element::Utf8(CHAR_TAB)
                         | Element::Utf8(CHAR_LF)
                         | Element::Utf8(CHAR_FF)
-                        | Element::Utf8(CHAR_SPACE) => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::BeforeAttributeNameState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        | Element::Utf8(CHAR_SPACE)
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::BeforeAttributeNameState;
                         }
-                        Element::Utf8('/') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                self.state = State::SelfClosingStartState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                        Element::Utf8('/')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            self.state = State::SelfClosingStartState;
                         }
-                        Element::Utf8('>') => {
-                            if self.is_appropriate_end_token(&self.temporary_buffer) {
-                                let s: String = self.temporary_buffer.iter().collect::<String>();
-                                self.set_name_in_current_token(s);
+                        Element::Utf8('>')
+                            if self.is_appropriate_end_token(&self.temporary_buffer) =>
+                        {
+                            let s: String = self.temporary_buffer.iter().collect::<String>();
+                            self.set_name_in_current_token(s);
 
-                                self.last_start_token = String::new();
-                                emit_current_token!(self);
-                                self.state = State::DataState;
-                            } else {
-                                consume_anything_else = true;
-                            }
+                            self.last_start_token = String::new();
+                            emit_current_token!(self);
+                            self.state = State::DataState;
                         }
                         Element::Utf8(ch @ 'A'..='Z') => {
                             self.temporary_buffer.push(to_lowercase!(ch));
@@ -1260,7 +1323,7 @@ impl<'a> Tokenizer<'a> {
                     match c {
                         Element::Utf8('"') => self.state = State::AfterAttributeValueQuotedState,
                         Element::Utf8('&') => {
-                            self.consume_character_reference(Some(Element::Utf8('"')), true);
+                            self.consume_character_reference(true);
                         }
                         Element::Utf8(CHAR_NUL) => {
                             self.parse_error(ParserError::UnexpectedNullCharacter);
@@ -1280,7 +1343,7 @@ impl<'a> Tokenizer<'a> {
                     match c {
                         Element::Utf8('\'') => self.state = State::AfterAttributeValueQuotedState,
                         Element::Utf8('&') => {
-                            self.consume_character_reference(Some(Element::Utf8('\'')), true);
+                            self.consume_character_reference(true);
                         }
                         Element::Utf8(CHAR_NUL) => {
                             self.parse_error(ParserError::UnexpectedNullCharacter);
@@ -1305,7 +1368,7 @@ impl<'a> Tokenizer<'a> {
                             self.state = State::BeforeAttributeNameState;
                         }
                         Element::Utf8('&') => {
-                            self.consume_character_reference(Some(Element::Utf8('>')), true);
+                            self.consume_character_reference(true);
                         }
                         Element::Utf8('>') => {
                             self.store_and_clear_current_attribute();
@@ -1435,7 +1498,8 @@ impl<'a> Tokenizer<'a> {
                         continue;
                     }
 
-                    self.stream.seek(SeekCur, 1);
+                    // The error is at the char after "<!", which can be EOF
+                    self.stream.read_char();
                     self.parse_error(ParserError::IncorrectlyOpenedComment);
                     self.stream.unread();
                     self.current_token = Some(Token::CommentToken { value: "".into() });
@@ -2237,27 +2301,70 @@ impl<'a> Tokenizer<'a> {
 
     // Consumes the given char
     pub(crate) fn consume(&mut self, c: char) {
+        // A "<" that turns out to be text does not start any markup
+        if c == '<' {
+            self.markup_start = None;
+        }
+
         // Add c to the current token data
         self.consumed.push(c)
     }
 
+    // Marks the "<" that was just read as the possible start of a tag, comment or doctype
+    fn start_markup(&mut self) {
+        self.markup_start = Some(self.stream.position.byte_offset - 1);
+    }
+
     // Consumes the given string
     pub(crate) fn consume_string(&mut self, s: &str) {
         // Add c to the current token data
-        for c in s.chars() {
-            self.consumed.push(c)
+        self.consumed.push_str(s)
+    }
+
+    // Consumes the run of text up to the next char that the current state has to handle itself:
+    // one of the delimiters, a NUL, or a char that is a parse error in the input stream. Returns
+    // false when there is no such run at the current position.
+    fn consume_text_run(&mut self, delimiters: &[u8]) -> bool {
+        let remaining = self.stream.remaining();
+        let bytes = remaining.as_bytes();
+        let end = match *delimiters {
+            [first, second] => memchr::memchr3(first, second, 0, bytes),
+            [first] => memchr::memchr2(first, 0, bytes),
+            _ => memchr::memchr(0, bytes),
+        }
+        .unwrap_or(bytes.len());
+
+        let len = remaining[..end]
+            .char_indices()
+            .find(|&(_, c)| self.is_invalid_input_char(c))
+            .map_or(end, |(idx, _)| idx);
+        if len == 0 {
+            return false;
         }
+
+        self.consumed.push_str(&remaining[..len]);
+        self.stream.skip_bytes(len);
+        true
+    }
+
+    // Returns true when reading the char from the input stream is a parse error
+    fn is_invalid_input_char(&self, c: char) -> bool {
+        if c.is_ascii() {
+            return c == '\u{007F}'
+                || (c < CHAR_SPACE
+                    && !matches!(c, CHAR_NUL | CHAR_TAB | CHAR_LF | CHAR_FF | CHAR_CR));
+        }
+        self.is_control_char(c as u32) || self.is_noncharacter(c as u32)
     }
 
     // Return true when the given end_token matches the stored start token (ie: 'table' matches when last_start_token = 'table')
     fn is_appropriate_end_token(&self, end_token: &[char]) -> bool {
-        let s: String = end_token.iter().collect();
-        self.last_start_token == s
+        self.last_start_token.chars().eq(end_token.iter().copied())
     }
 
     // Return the consumed string as a String
     pub fn get_consumed_str(&self) -> String {
-        return self.consumed.iter().collect();
+        self.consumed.clone()
     }
 
     // Returns true if there is anything in the consume buffer
@@ -2335,10 +2442,26 @@ impl<'a> Tokenizer<'a> {
         if !self.current_attr_name.is_empty()
             && !self.current_attrs.contains_key(&self.current_attr_name)
         {
-            self.current_attrs.insert(
-                self.current_attr_name.clone(),
-                self.current_attr_value.clone(),
-            );
+            if self
+                .max_attributes
+                .is_some_and(|max| self.current_attrs.len() >= max)
+            {
+                self.parse_error(ParserError::AttributeLimitExceeded);
+            } else {
+                let mut value = self.current_attr_value.clone();
+                if let Some(max) = self.max_attribute_value_length {
+                    if value.len() > max {
+                        let mut len = max;
+                        while !value.is_char_boundary(len) {
+                            len -= 1;
+                        }
+                        value.truncate(len);
+                        self.parse_error(ParserError::AttributeValueLengthLimitExceeded);
+                    }
+                }
+                self.current_attrs
+                    .insert(self.current_attr_name.clone(), value);
+            }
         }
 
         self.current_attr_name = String::new();
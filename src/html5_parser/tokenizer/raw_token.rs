@@ -0,0 +1,268 @@
+use crate::html5_parser::tokenizer::token::Token;
+use crate::html5_parser::tokenizer::{CHAR_NUL, CHAR_REPLACEMENT};
+
+/// A token together with the source it was read from, as returned by
+/// Tokenizer::next_raw_token(). Concatenating the raw text of all tokens up to and including the
+/// EOF token gives back the input.
+#[derive(Clone, PartialEq)]
+pub struct RawToken {
+    pub token: Token,
+    /// The source of the token, with character references, case and quotes as written
+    pub raw: String,
+    /// Byte offset of the source in the input stream (which has normalized newlines)
+    pub byte_offset: usize,
+    /// The attributes of a start or end tag as written, in source order. Unlike the attributes
+    /// of the token, this includes duplicate attributes.
+    pub attributes: Vec<RawAttribute>,
+}
+
+/// An attribute as written in the source of a tag
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawAttribute {
+    /// Name as the tokenizer reports it, so lowercased
+    pub name: String,
+    /// The whole attribute, from the start of the name to the end of the value
+    pub raw: String,
+    /// Byte offset of the attribute in the raw text of the tag
+    pub offset: usize,
+    /// The name as written
+    pub raw_name: String,
+    /// The value as written, without quotes. None when the attribute has no value
+    pub raw_value: Option<String>,
+    /// The quote around the value, if any
+    pub quote: Option<char>,
+}
+
+impl RawToken {
+    pub(crate) fn new(token: Token, raw: String, byte_offset: usize) -> Self {
+        let attributes = match token {
+            Token::StartTagToken { .. } | Token::EndTagToken { .. } => raw_attributes(&raw),
+            _ => Vec::new(),
+        };
+
+        RawToken {
+            token,
+            raw,
+            byte_offset,
+            attributes,
+        }
+    }
+}
+
+// Whitespace between attributes. The raw source can still contain a CR, which the tokenizer saw
+// as a LF.
+fn is_space(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+// Splits the source of a complete tag into its attributes. This follows the attribute states of
+// the tokenizer, but only looks at the chars that end names and values.
+fn raw_attributes(tag: &str) -> Vec<RawAttribute> {
+    let name_start = if tag.starts_with("</") { 2 } else { 1 };
+    let mut pos = tag[name_start..]
+        .find(|c| is_space(c) || c == '/' || c == '>')
+        .map_or(tag.len(), |idx| name_start + idx);
+
+    let mut attributes = Vec::new();
+    loop {
+        // Before attribute name: a solidus that is not followed by ">" is skipped as well
+        pos += tag[pos..]
+            .find(|c| !is_space(c) && c != '/')
+            .unwrap_or(tag.len() - pos);
+        if pos == tag.len() || tag[pos..].starts_with('>') {
+            return attributes;
+        }
+
+        // Attribute name. A "=" as the first char is part of the name.
+        let start = pos;
+        let first_len = tag[pos..].chars().next().map_or(0, char::len_utf8);
+        let name_end = tag[pos + first_len..]
+            .find(|c| is_space(c) || c == '/' || c == '>' || c == '=')
+            .map_or(tag.len(), |idx| pos + first_len + idx);
+        let raw_name = &tag[start..name_end];
+
+        // After attribute name: only a "=" starts a value
+        let after_name = name_end
+            + tag[name_end..]
+                .find(|c| !is_space(c))
+                .unwrap_or(tag.len() - name_end);
+        let mut end = name_end;
+        let mut raw_value = None;
+        let mut quote = None;
+        if tag[after_name..].starts_with('=') {
+            let value_start = after_name
+                + 1
+                + tag[after_name + 1..]
+                    .find(|c| !is_space(c))
+                    .unwrap_or(tag.len() - after_name - 1);
+            let rest = &tag[value_start..];
+            match rest.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let len = rest[1..].find(q).unwrap_or(rest.len() - 1);
+                    raw_value = Some(&rest[1..1 + len]);
+                    quote = Some(q);
+                    end = (value_start + len + 2).min(tag.len());
+                }
+                Some('>') | None => {
+                    // Missing value, the attribute ends at the "="
+                    raw_value = Some("");
+                    end = after_name + 1;
+                }
+                Some(_) => {
+                    let len = rest.find(|c| is_space(c) || c == '>').unwrap_or(rest.len());
+                    raw_value = Some(&rest[..len]);
+                    end = value_start + len;
+                }
+            }
+        }
+
+        attributes.push(RawAttribute {
+            name: raw_name
+                .chars()
+                .map(|c| match c {
+                    'A'..='Z' => c.to_ascii_lowercase(),
+                    CHAR_NUL => CHAR_REPLACEMENT,
+                    _ => c,
+                })
+                .collect(),
+            raw: tag[start..end].to_string(),
+            offset: start,
+            raw_name: raw_name.to_string(),
+            raw_value: raw_value.map(String::from),
+            quote,
+        });
+        pos = end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::error_logger::ErrorLogger;
+    use crate::html5_parser::input_stream::InputStream;
+    use crate::html5_parser::tokenizer::state::State;
+    use crate::html5_parser::tokenizer::{Options, Tokenizer};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    fn raw_tokens(html: &str, initial_state: State) -> Vec<RawToken> {
+        let mut stream = InputStream::new();
+        stream.read_from_str(html, None);
+        let options = Options {
+            initial_state,
+            last_start_tag: "script".into(),
+        };
+        let error_logger = Rc::new(RefCell::new(ErrorLogger::new()));
+        let mut tokenizer = Tokenizer::new(&mut stream, Some(options), error_logger);
+
+        let mut tokens = Vec::new();
+        loop {
+            let token = tokenizer.next_raw_token();
+            let is_eof = token.token.is_eof();
+            tokens.push(token);
+            if is_eof {
+                return tokens;
+            }
+        }
+    }
+
+    fn raw_texts(html: &str, initial_state: State) -> Vec<String> {
+        raw_tokens(html, initial_state)
+            .into_iter()
+            .map(|token| token.raw)
+            .collect()
+    }
+
+    #[test]
+    fn test_raw_tokens() {
+        let html = "<!DOCTYPE html>\r\n<p CLASS='a'>x &amp; y</P><!-- c -->";
+        let tokens = raw_tokens(html, State::DataState);
+        let raw: Vec<&str> = tokens.iter().map(|token| token.raw.as_str()).collect();
+        assert_eq!(
+            raw,
+            [
+                "<!DOCTYPE html>",
+                "\r\n",
+                "<p CLASS='a'>",
+                "x &amp; y",
+                "</P>",
+                "<!-- c -->",
+                ""
+            ]
+        );
+        let offsets: Vec<usize> = tokens.iter().map(|token| token.byte_offset).collect();
+        assert_eq!(offsets, [0, 15, 16, 29, 38, 42, 52]);
+        assert!(
+            tokens[3].token
+                == Token::TextToken {
+                    value: "x & y".into()
+                }
+        );
+        assert_eq!(tokens[2].attributes[0].raw, "CLASS='a'");
+
+        assert_eq!(raw_texts("a < b", State::DataState), ["a < b", ""]);
+        assert_eq!(raw_texts("a<div", State::DataState), ["a", "<div"]);
+        assert_eq!(
+            raw_texts("<p>a</>b", State::DataState),
+            ["<p>", "a</>b", ""]
+        );
+        assert_eq!(raw_texts("a<", State::DataState), ["a<", ""]);
+        assert_eq!(
+            raw_texts("a<b</p><!-- x --></script>x", State::ScriptDataState),
+            ["a<b</p><!-- x -->", "</script>", "x", ""]
+        );
+    }
+
+    fn attribute(
+        offset: usize,
+        raw: &str,
+        raw_name: &str,
+        raw_value: Option<&str>,
+    ) -> RawAttribute {
+        RawAttribute {
+            name: raw_name.to_ascii_lowercase(),
+            raw: raw.to_string(),
+            offset,
+            raw_name: raw_name.to_string(),
+            raw_value: raw_value.map(String::from),
+            quote: match raw.chars().last() {
+                Some(q @ ('"' | '\'')) if raw_value.is_some() => Some(q),
+                _ => None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_raw_attributes() {
+        assert_eq!(raw_attributes("<br>"), vec![]);
+        assert_eq!(raw_attributes("<br/>"), vec![]);
+        assert_eq!(
+            raw_attributes("<a HREF = 'x&amp;y' checked data-x=1/>"),
+            vec![
+                attribute(3, "HREF = 'x&amp;y'", "HREF", Some("x&amp;y")),
+                attribute(20, "checked", "checked", None),
+                attribute(28, "data-x=1/", "data-x", Some("1/")),
+            ]
+        );
+        assert_eq!(
+            raw_attributes("<p\r\na=\"1\"b='2'/ c =>"),
+            vec![
+                attribute(4, "a=\"1\"", "a", Some("1")),
+                attribute(9, "b='2'", "b", Some("2")),
+                attribute(16, "c =", "c", Some("")),
+            ]
+        );
+        assert_eq!(
+            raw_attributes("</p =x a a>"),
+            vec![
+                attribute(4, "=x", "=x", None),
+                attribute(7, "a", "a", None),
+                attribute(9, "a", "a", None),
+            ]
+        );
+        assert_eq!(
+            raw_attributes("<x a\"b=\"c\">"),
+            vec![attribute(3, "a\"b=\"c\"", "a\"b", Some("c"))]
+        );
+    }
+}
@@ -76,3 +76,18 @@ pub enum State {
     CDataSectionBracketState,
     CDataSectionEndState,
 }
+
+impl State {
+    // Returns the state in which the content of the html element with the given name is
+    // tokenized, as the tree builder switches to it after the start tag. Noscript is parsed as
+    // raw text, as if scripting is enabled.
+    pub fn for_content_of(name: &str) -> State {
+        match name {
+            "title" | "textarea" => State::RcDataState,
+            "style" | "xmp" | "iframe" | "noembed" | "noframes" | "noscript" => State::RawTextState,
+            "script" => State::ScriptDataState,
+            "plaintext" => State::PlaintextState,
+            _ => State::DataState,
+        }
+    }
+}
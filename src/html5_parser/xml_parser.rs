@@ -0,0 +1,1302 @@
+use crate::html5_parser::error_logger::{ErrorLogger, ParseError};
+use crate::html5_parser::input_stream::{InputStream, Position};
+use crate::html5_parser::node::{Node, NodeData, XMLNS_NAMESPACE, XML_NAMESPACE};
+use crate::html5_parser::parser::document::{Document, DocumentType};
+use crate::html5_parser::tokenizer::replacement_tables::TOKEN_NAMED_CHARS;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Public identifiers of doctypes whose DTD declares the HTML named character references. Like
+// browsers, we do not load these DTDs but know the entities they declare.
+const HTML_ENTITY_PUBLIC_IDS: [&str; 3] = [
+    "-//W3C//DTD XHTML ",
+    "-//W3C//DTD MathML ",
+    "-//WAPFORUM//DTD XHTML Mobile ",
+];
+
+// Limits on the expansion of internal entities, against entities that reference other entities
+// many times over ("billion laughs"). The depth is the number of entity references that can be
+// expanded inside each other, the size is the total length of all expanded replacement text.
+const MAX_ENTITY_DEPTH: usize = 16;
+const MAX_ENTITY_EXPANSION: usize = 8 * 1024 * 1024;
+
+// Well-formedness errors of the XML parser. All of them are fatal: parsing stops at the first
+// error, and the document contains the nodes that were parsed up to that point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XmlParserError {
+    CdataEndInContent,
+    DoubleHyphenInComment,
+    DuplicateAttribute,
+    EntityExpansionLimitExceeded,
+    InvalidCharacter,
+    InvalidCharacterReference,
+    InvalidName,
+    InvalidNamespaceDeclaration,
+    InvalidQualifiedName,
+    LtInAttributeValue,
+    MalformedMarkup,
+    MalformedXmlDeclaration,
+    MismatchedEndTag,
+    MisplacedDoctype,
+    MisplacedXmlDeclaration,
+    MissingRootElement,
+    MissingSemicolonAfterReference,
+    MissingWhitespace,
+    MultipleRootElements,
+    RecursiveEntity,
+    TextOutsideRootElement,
+    UnbalancedEntity,
+    UnboundNamespacePrefix,
+    UnclosedElement,
+    UndefinedEntity,
+    UnexpectedEndTag,
+    UnexpectedEof,
+    UnquotedAttributeValue,
+}
+
+// Parser errors as string representation
+impl XmlParserError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            XmlParserError::CdataEndInContent => "cdata-end-in-content",
+            XmlParserError::DoubleHyphenInComment => "double-hyphen-in-comment",
+            XmlParserError::DuplicateAttribute => "duplicate-attribute",
+            XmlParserError::EntityExpansionLimitExceeded => "entity-expansion-limit-exceeded",
+            XmlParserError::InvalidCharacter => "invalid-character",
+            XmlParserError::InvalidCharacterReference => "invalid-character-reference",
+            XmlParserError::InvalidName => "invalid-name",
+            XmlParserError::InvalidNamespaceDeclaration => "invalid-namespace-declaration",
+            XmlParserError::InvalidQualifiedName => "invalid-qualified-name",
+            XmlParserError::LtInAttributeValue => "lt-in-attribute-value",
+            XmlParserError::MalformedMarkup => "malformed-markup",
+            XmlParserError::MalformedXmlDeclaration => "malformed-xml-declaration",
+            XmlParserError::MismatchedEndTag => "mismatched-end-tag",
+            XmlParserError::MisplacedDoctype => "misplaced-doctype",
+            XmlParserError::MisplacedXmlDeclaration => "misplaced-xml-declaration",
+            XmlParserError::MissingRootElement => "missing-root-element",
+            XmlParserError::MissingSemicolonAfterReference => "missing-semicolon-after-reference",
+            XmlParserError::MissingWhitespace => "missing-whitespace",
+            XmlParserError::MultipleRootElements => "multiple-root-elements",
+            XmlParserError::RecursiveEntity => "recursive-entity",
+            XmlParserError::TextOutsideRootElement => "text-outside-root-element",
+            XmlParserError::UnbalancedEntity => "unbalanced-entity",
+            XmlParserError::UnboundNamespacePrefix => "unbound-namespace-prefix",
+            XmlParserError::UnclosedElement => "unclosed-element",
+            XmlParserError::UndefinedEntity => "undefined-entity",
+            XmlParserError::UnexpectedEndTag => "unexpected-end-tag",
+            XmlParserError::UnexpectedEof => "unexpected-eof",
+            XmlParserError::UnquotedAttributeValue => "unquoted-attribute-value",
+        }
+    }
+}
+
+type XmlResult<T> = Result<T, (XmlParserError, Position)>;
+
+// An element on the stack of open elements
+struct OpenElement {
+    node_id: usize,
+    qualified_name: String,
+    namespaces: HashMap<String, Option<String>>, // Prefix ("" for the default) => declared namespace
+}
+
+// The replacement text of an internal entity that is being parsed in place of its reference
+struct EntityFrame {
+    name: String,
+    text: Rc<str>,
+    pos: usize,           // Byte offset in the replacement text
+    reference: Position,  // Position of the reference in the document
+    open_elements: usize, // Number of open elements when the reference was read
+}
+
+// A character or entity reference
+enum Reference {
+    // Text that is used as is: a character reference or a predefined or HTML named entity
+    Text(String),
+    // An internal entity, whose replacement text is parsed in place of the reference
+    Entity(String, Rc<str>, Position),
+}
+
+/// Parser for XML 1.0 documents with namespaces, as used for documents served as
+/// application/xhtml+xml or other XML MIME types. It builds the same document structure as
+/// Html5Parser. Processing instructions are not part of the document structure and are skipped.
+pub struct XmlParser<'a> {
+    input: &'a str,
+    pos: usize,    // Byte offset in the input
+    offset: usize, // Char offset in the input
+    utf16_offset: usize,
+    line: usize,
+    col: usize,
+    entity_frames: Vec<EntityFrame>,
+    expanded: usize, // Total length of the expanded replacement text of internal entities
+    document: Document,
+    error_logger: ErrorLogger,
+    open_elements: Vec<OpenElement>,
+    entities: HashMap<String, Rc<str>>, // General entities declared in the internal subset
+    html_entities: bool,                // The doctype declares the HTML named entities
+    seen_doctype: bool,
+    seen_root: bool,
+}
+
+impl<'a> XmlParser<'a> {
+    // Creates a new parser for the contents of the given input stream
+    pub fn new(stream: &'a mut InputStream) -> Self {
+        let stream: &'a InputStream = stream;
+
+        let mut document = Document::new();
+        document.doctype = DocumentType::XML;
+
+        XmlParser {
+            input: stream.remaining(),
+            pos: 0,
+            offset: 0,
+            utf16_offset: 0,
+            line: 1,
+            col: 1,
+            entity_frames: Vec::new(),
+            expanded: 0,
+            document,
+            error_logger: ErrorLogger::new(),
+            open_elements: Vec::new(),
+            entities: HashMap::new(),
+            html_entities: false,
+            seen_doctype: false,
+            seen_root: false,
+        }
+    }
+
+    // Parses the input stream into a Node tree. Parsing stops at the first well-formedness
+    // error, which is returned as parse error.
+    pub fn parse(&mut self) -> (&Document, Vec<ParseError>) {
+        if let Err((error, position)) = self.parse_document() {
+            self.error_logger.add_error(position, error.as_str());
+        }
+
+        (&self.document, self.error_logger.get_errors())
+    }
+
+    // Returns the parsed document
+    pub fn into_document(self) -> Document {
+        self.document
+    }
+
+    fn parse_document(&mut self) -> XmlResult<()> {
+        if self.peek() == Some('\u{FEFF}') {
+            self.advance();
+        }
+        if self.starts_with("<?xml") && self.peek_at(5).is_some_and(is_xml_whitespace) {
+            self.xml_declaration()?;
+        }
+
+        loop {
+            match self.peek() {
+                Some('<') => self.markup()?,
+                Some(_) => self.text()?,
+                None if !self.entity_frames.is_empty() => self.end_entity()?,
+                None => break,
+            }
+        }
+
+        if !self.open_elements.is_empty() {
+            return Err((XmlParserError::UnclosedElement, self.position()));
+        }
+        if !self.seen_root {
+            return Err((XmlParserError::MissingRootElement, self.position()));
+        }
+        Ok(())
+    }
+
+    fn xml_declaration(&mut self) -> XmlResult<()> {
+        let start = self.position();
+        self.skip(5);
+
+        let mut names = Vec::new();
+        loop {
+            let had_whitespace = self.skip_whitespace();
+            if self.starts_with("?>") {
+                self.skip(2);
+                break;
+            }
+            if !had_whitespace {
+                return Err((XmlParserError::MalformedXmlDeclaration, self.position()));
+            }
+            let name = self.name()?;
+            self.skip_whitespace();
+            self.expect('=', XmlParserError::MalformedXmlDeclaration)?;
+            self.skip_whitespace();
+            let value = self.literal()?;
+
+            let valid = match name.as_str() {
+                "version" => names.is_empty() && value.starts_with("1."),
+                "encoding" => names == ["version"] && !value.is_empty(),
+                "standalone" => {
+                    names.contains(&"version") && matches!(value.as_str(), "yes" | "no")
+                }
+                _ => false,
+            };
+            if !valid || names.contains(&name.as_str()) {
+                return Err((XmlParserError::MalformedXmlDeclaration, start));
+            }
+            names.push(match name.as_str() {
+                "version" => "version",
+                "encoding" => "encoding",
+                _ => "standalone",
+            });
+        }
+
+        if names.is_empty() {
+            return Err((XmlParserError::MalformedXmlDeclaration, start));
+        }
+        Ok(())
+    }
+
+    fn markup(&mut self) -> XmlResult<()> {
+        if self.starts_with("</") {
+            self.end_tag()
+        } else if self.starts_with("<!--") {
+            let comment = self.comment()?;
+            let parent_id = self.current_node_id();
+            self.document
+                .add_node(Node::new_comment(&comment), parent_id);
+            Ok(())
+        } else if self.starts_with("<![CDATA[") {
+            self.cdata()
+        } else if self.starts_with("<!DOCTYPE") {
+            self.doctype()
+        } else if self.starts_with("<?") {
+            self.processing_instruction()
+        } else if self.starts_with("<!") {
+            Err((XmlParserError::MalformedMarkup, self.position()))
+        } else {
+            self.start_tag()
+        }
+    }
+
+    fn start_tag(&mut self) -> XmlResult<()> {
+        let start = self.position();
+        self.advance();
+        let qualified_name = self.name()?;
+
+        let mut attributes: Vec<(String, String, Position)> = Vec::new();
+        let self_closing = loop {
+            let had_whitespace = self.skip_whitespace();
+            match self.peek() {
+                None => return Err((XmlParserError::UnexpectedEof, self.position())),
+                Some('>') => {
+                    self.advance();
+                    break false;
+                }
+                Some('/') => {
+                    self.advance();
+                    self.expect('>', XmlParserError::MalformedMarkup)?;
+                    break true;
+                }
+                Some(_) if !had_whitespace => {
+                    return Err((XmlParserError::MissingWhitespace, self.position()))
+                }
+                Some(_) => {
+                    let position = self.position();
+                    let name = self.name()?;
+                    self.skip_whitespace();
+                    self.expect('=', XmlParserError::MalformedMarkup)?;
+                    self.skip_whitespace();
+                    let value = self.attribute_value()?;
+                    if attributes.iter().any(|(n, _, _)| *n == name) {
+                        return Err((XmlParserError::DuplicateAttribute, position));
+                    }
+                    attributes.push((name, value, position));
+                }
+            }
+        };
+
+        if self.open_elements.is_empty() && self.seen_root {
+            return Err((XmlParserError::MultipleRootElements, start));
+        }
+        self.seen_root = true;
+
+        // Namespace declarations of this element
+        let mut namespaces = HashMap::new();
+        for (name, value, position) in &attributes {
+            let prefix = match name.as_str() {
+                "xmlns" => "",
+                _ => match name.strip_prefix("xmlns:") {
+                    Some(prefix) => prefix,
+                    None => continue,
+                },
+            };
+            let valid = match (prefix, value.as_str()) {
+                ("xmlns", _) | (_, XMLNS_NAMESPACE) => false,
+                ("xml", value) => value == XML_NAMESPACE,
+                (_, XML_NAMESPACE) => false,
+                ("", _) => true,
+                (_, value) => !value.is_empty(),
+            };
+            if !valid {
+                return Err((XmlParserError::InvalidNamespaceDeclaration, *position));
+            }
+            let namespace = Some(value.clone()).filter(|value| !value.is_empty());
+            namespaces.insert(prefix.to_string(), namespace);
+        }
+
+        let (prefix, local_name) = split_qualified_name(&qualified_name)
+            .ok_or((XmlParserError::InvalidQualifiedName, start))?;
+        let namespace = match prefix {
+            Some("xmlns") => return Err((XmlParserError::InvalidQualifiedName, start)),
+            Some(prefix) => Some(
+                self.lookup_namespace(&namespaces, prefix)
+                    .ok_or((XmlParserError::UnboundNamespacePrefix, start))?,
+            ),
+            None => self.lookup_namespace(&namespaces, ""),
+        };
+
+        // Attributes are unique by namespace and local name. Unprefixed attributes are in no
+        // namespace.
+        let mut expanded_names = Vec::new();
+        for (name, _, position) in &attributes {
+            let (prefix, local_name) = split_qualified_name(name)
+                .ok_or((XmlParserError::InvalidQualifiedName, *position))?;
+            let namespace = match prefix {
+                None if name == "xmlns" => Some(XMLNS_NAMESPACE.to_string()),
+                None => None,
+                Some("xmlns") => Some(XMLNS_NAMESPACE.to_string()),
+                Some(prefix) => Some(
+                    self.lookup_namespace(&namespaces, prefix)
+                        .ok_or((XmlParserError::UnboundNamespacePrefix, *position))?,
+                ),
+            };
+            if expanded_names.contains(&(namespace.clone(), local_name)) {
+                return Err((XmlParserError::DuplicateAttribute, *position));
+            }
+            expanded_names.push((namespace, local_name));
+        }
+
+        // Attributes are stored by their qualified name, like the HTML parser does for the
+        // namespaced attributes of foreign elements
+        let attributes = attributes
+            .into_iter()
+            .map(|(name, value, _)| (name, value))
+            .collect();
+        let mut node = Node::new_element(local_name, attributes, "");
+        node.namespace = namespace;
+        node.prefix = prefix.map(String::from);
+
+        let parent_id = self.current_node_id();
+        let node_id = self.document.add_node(node, parent_id);
+
+        if !self_closing {
+            self.open_elements.push(OpenElement {
+                node_id,
+                qualified_name,
+                namespaces,
+            });
+        }
+        Ok(())
+    }
+
+    fn end_tag(&mut self) -> XmlResult<()> {
+        let start = self.position();
+        self.skip(2);
+        let name = self.name()?;
+        self.skip_whitespace();
+        self.expect('>', XmlParserError::MalformedMarkup)?;
+
+        // Elements that start in the replacement text of an entity also end in it
+        if let Some(frame) = self.entity_frames.last() {
+            if self.open_elements.len() <= frame.open_elements {
+                return Err((XmlParserError::UnbalancedEntity, start));
+            }
+        }
+
+        match self.open_elements.last() {
+            None => Err((XmlParserError::UnexpectedEndTag, start)),
+            Some(element) if element.qualified_name != name => {
+                Err((XmlParserError::MismatchedEndTag, start))
+            }
+            Some(_) => {
+                self.open_elements.pop();
+                Ok(())
+            }
+        }
+    }
+
+    fn attribute_value(&mut self) -> XmlResult<String> {
+        let quote = match self.peek() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Err((XmlParserError::UnquotedAttributeValue, self.position())),
+        };
+        self.advance();
+
+        // Quotes in the replacement text of an entity do not end the value
+        let depth = self.entity_frames.len();
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None if self.entity_frames.len() > depth => self.end_entity()?,
+                None => return Err((XmlParserError::UnexpectedEof, self.position())),
+                Some(c) if c == quote && self.entity_frames.len() == depth => {
+                    self.advance();
+                    return Ok(value);
+                }
+                Some('<') => return Err((XmlParserError::LtInAttributeValue, self.position())),
+                Some('&') => match self.reference()? {
+                    Reference::Text(text) => value.push_str(&text),
+                    Reference::Entity(name, text, start) => self.start_entity(name, text, start)?,
+                },
+                // Attribute value normalization
+                Some(c) if is_xml_whitespace(c) => {
+                    self.advance();
+                    value.push(' ');
+                }
+                Some(_) => value.push(self.next_char()?),
+            }
+        }
+    }
+
+    fn text(&mut self) -> XmlResult<()> {
+        let start = self.position();
+        let outside_root = self.open_elements.is_empty();
+
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '<' => break,
+                '&' if outside_root => {
+                    return Err((XmlParserError::TextOutsideRootElement, start));
+                }
+                '&' => match self.reference()? {
+                    Reference::Text(value) => text.push_str(&value),
+                    Reference::Entity(name, value, start) => {
+                        self.append_text(&text);
+                        return self.start_entity(name, value, start);
+                    }
+                },
+                ']' if self.starts_with("]]>") => {
+                    return Err((XmlParserError::CdataEndInContent, self.position()));
+                }
+                _ => text.push(self.next_char()?),
+            }
+        }
+
+        // Only whitespace is allowed outside the root element, and it is not part of the tree
+        if outside_root {
+            if !text.chars().all(is_xml_whitespace) {
+                return Err((XmlParserError::TextOutsideRootElement, start));
+            }
+            return Ok(());
+        }
+
+        self.append_text(&text);
+        Ok(())
+    }
+
+    // Reads a character or entity reference
+    fn reference(&mut self) -> XmlResult<Reference> {
+        let start = self.position();
+        self.advance();
+
+        if self.peek() == Some('#') {
+            self.advance();
+            let hex = self.peek() == Some('x');
+            if hex {
+                self.advance();
+            }
+            let mut digits = String::new();
+            while let Some(c) = self.peek().filter(|c| {
+                if hex {
+                    c.is_ascii_hexdigit()
+                } else {
+                    c.is_ascii_digit()
+                }
+            }) {
+                digits.push(c);
+                self.advance();
+            }
+            if self.peek() != Some(';') {
+                return Err((XmlParserError::InvalidCharacterReference, start));
+            }
+            self.advance();
+
+            return u32::from_str_radix(&digits, if hex { 16 } else { 10 })
+                .ok()
+                .and_then(char::from_u32)
+                .filter(|&c| is_xml_char(c))
+                .map(|c| Reference::Text(c.to_string()))
+                .ok_or((XmlParserError::InvalidCharacterReference, start));
+        }
+
+        let name = self.name()?;
+        if self.peek() != Some(';') {
+            return Err((
+                XmlParserError::MissingSemicolonAfterReference,
+                self.position(),
+            ));
+        }
+        self.advance();
+
+        let value = match name.as_str() {
+            "amp" => Some("&"),
+            "lt" => Some("<"),
+            "gt" => Some(">"),
+            "quot" => Some("\""),
+            "apos" => Some("'"),
+            _ => {
+                if let Some(text) = self.entities.get(&name) {
+                    return Ok(Reference::Entity(name, text.clone(), start));
+                }
+                match self.html_entities {
+                    true => TOKEN_NAMED_CHARS
+                        .get(format!("{};", name).as_str())
+                        .copied(),
+                    false => None,
+                }
+            }
+        };
+        value
+            .map(|value| Reference::Text(value.to_string()))
+            .ok_or((XmlParserError::UndefinedEntity, start))
+    }
+
+    // Continues parsing in the replacement text of an internal entity, which is parsed as if it
+    // was in place of the reference (XML 1.0 section 4.4.5)
+    fn start_entity(&mut self, name: String, text: Rc<str>, start: Position) -> XmlResult<()> {
+        if self.entity_frames.iter().any(|frame| frame.name == name) {
+            return Err((XmlParserError::RecursiveEntity, start));
+        }
+        self.expanded += text.len();
+        if self.entity_frames.len() >= MAX_ENTITY_DEPTH || self.expanded > MAX_ENTITY_EXPANSION {
+            return Err((XmlParserError::EntityExpansionLimitExceeded, start));
+        }
+
+        self.entity_frames.push(EntityFrame {
+            name,
+            text,
+            pos: 0,
+            reference: start,
+            open_elements: self.open_elements.len(),
+        });
+        Ok(())
+    }
+
+    // Continues parsing after the reference of the entity whose replacement text was parsed
+    fn end_entity(&mut self) -> XmlResult<()> {
+        let position = self.position();
+        if let Some(frame) = self.entity_frames.pop() {
+            if frame.open_elements != self.open_elements.len() {
+                return Err((XmlParserError::UnbalancedEntity, position));
+            }
+        }
+        Ok(())
+    }
+
+    fn comment(&mut self) -> XmlResult<String> {
+        self.skip(4);
+
+        let mut comment = String::new();
+        loop {
+            if self.peek().is_none() {
+                return Err((XmlParserError::UnexpectedEof, self.position()));
+            }
+            if self.starts_with("--") {
+                if !self.starts_with("-->") {
+                    return Err((XmlParserError::DoubleHyphenInComment, self.position()));
+                }
+                self.skip(3);
+                return Ok(comment);
+            }
+            comment.push(self.next_char()?);
+        }
+    }
+
+    fn cdata(&mut self) -> XmlResult<()> {
+        if self.open_elements.is_empty() {
+            return Err((XmlParserError::TextOutsideRootElement, self.position()));
+        }
+        self.skip(9);
+
+        let mut text = String::new();
+        loop {
+            if self.peek().is_none() {
+                return Err((XmlParserError::UnexpectedEof, self.position()));
+            }
+            if self.starts_with("]]>") {
+                self.skip(3);
+                break;
+            }
+            text.push(self.next_char()?);
+        }
+
+        self.append_text(&text);
+        Ok(())
+    }
+
+    fn processing_instruction(&mut self) -> XmlResult<()> {
+        let start = self.position();
+        self.skip(2);
+        let target = self.name()?;
+        if target.eq_ignore_ascii_case("xml") {
+            return Err((XmlParserError::MisplacedXmlDeclaration, start));
+        }
+
+        if !self.starts_with("?>") && !self.skip_whitespace() {
+            return Err((XmlParserError::MissingWhitespace, self.position()));
+        }
+        loop {
+            if self.peek().is_none() {
+                return Err((XmlParserError::UnexpectedEof, self.position()));
+            }
+            if self.starts_with("?>") {
+                self.skip(2);
+                return Ok(());
+            }
+            self.next_char()?;
+        }
+    }
+
+    fn doctype(&mut self) -> XmlResult<()> {
+        let start = self.position();
+        if self.seen_root || self.seen_doctype {
+            return Err((XmlParserError::MisplacedDoctype, start));
+        }
+        self.seen_doctype = true;
+        self.skip(9);
+
+        self.expect_whitespace()?;
+        let name = self.name()?;
+
+        let mut public_id = None;
+        let mut system_id = None;
+        let had_whitespace = self.skip_whitespace();
+        if had_whitespace && self.starts_with("PUBLIC") {
+            self.skip(6);
+            self.expect_whitespace()?;
+            let position = self.position();
+            let id = self.literal()?;
+            if !id.chars().all(is_pubid_char) {
+                return Err((XmlParserError::InvalidCharacter, position));
+            }
+            public_id = Some(id);
+            self.expect_whitespace()?;
+            system_id = Some(self.literal()?);
+            self.skip_whitespace();
+        } else if had_whitespace && self.starts_with("SYSTEM") {
+            self.skip(6);
+            self.expect_whitespace()?;
+            system_id = Some(self.literal()?);
+            self.skip_whitespace();
+        }
+
+        if self.peek() == Some('[') {
+            self.internal_subset()?;
+            self.skip_whitespace();
+        }
+        self.expect('>', XmlParserError::MalformedMarkup)?;
+
+        self.html_entities = public_id
+            .as_deref()
+            .is_some_and(|id| HTML_ENTITY_PUBLIC_IDS.iter().any(|p| id.starts_with(p)));
+
+        self.document.add_node(
+            Node::new_doctype(&name, public_id.as_deref(), system_id.as_deref()),
+            0,
+        );
+        Ok(())
+    }
+
+    // Reads the internal subset of the doctype. Only general entity declarations with a
+    // literal value are used, other declarations are skipped.
+    fn internal_subset(&mut self) -> XmlResult<()> {
+        self.advance();
+
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Err((XmlParserError::UnexpectedEof, self.position())),
+                Some(']') => {
+                    self.advance();
+                    return Ok(());
+                }
+                Some('%') => {
+                    // Parameter entity reference
+                    self.advance();
+                    self.name()?;
+                    self.expect(';', XmlParserError::MissingSemicolonAfterReference)?;
+                }
+                Some('<') if self.starts_with("<!--") => {
+                    self.comment()?;
+                }
+                Some('<') if self.starts_with("<?") => self.processing_instruction()?,
+                Some('<') if self.starts_with("<!ENTITY") => self.entity_declaration()?,
+                Some('<') if self.starts_with("<!") => self.skip_declaration()?,
+                Some(_) => return Err((XmlParserError::MalformedMarkup, self.position())),
+            }
+        }
+    }
+
+    fn entity_declaration(&mut self) -> XmlResult<()> {
+        self.skip(8);
+        self.expect_whitespace()?;
+        if self.peek() == Some('%') {
+            // Parameter entities are only used in declarations, which we do not process
+            return self.skip_declaration();
+        }
+
+        let name = self.name()?;
+        self.expect_whitespace()?;
+        if !matches!(self.peek(), Some('"' | '\'')) {
+            // External entity, which is not loaded
+            return self.skip_declaration();
+        }
+
+        let position = self.position();
+        let literal = self.literal()?;
+        let value = expand_character_references(&literal)
+            .ok_or((XmlParserError::InvalidCharacterReference, position))?;
+        self.skip_whitespace();
+        self.expect('>', XmlParserError::MalformedMarkup)?;
+
+        // The first declaration of an entity is binding
+        self.entities.entry(name).or_insert_with(|| value.into());
+        Ok(())
+    }
+
+    // Skips a markup declaration up to and including its closing '>'
+    fn skip_declaration(&mut self) -> XmlResult<()> {
+        let mut quote = None;
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err((XmlParserError::UnexpectedEof, self.position())),
+            };
+            self.next_char()?;
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (None, '>') => return Ok(()),
+                (Some(q), c) if q == c => quote = None,
+                _ => {}
+            }
+        }
+    }
+
+    // Reads a quoted literal
+    fn literal(&mut self) -> XmlResult<String> {
+        let quote = match self.peek() {
+            Some(c @ ('"' | '\'')) => c,
+            _ => return Err((XmlParserError::MalformedMarkup, self.position())),
+        };
+        self.advance();
+
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                None => return Err((XmlParserError::UnexpectedEof, self.position())),
+                Some(c) if c == quote => {
+                    self.advance();
+                    return Ok(value);
+                }
+                Some(_) => value.push(self.next_char()?),
+            }
+        }
+    }
+
+    fn name(&mut self) -> XmlResult<String> {
+        let start = self.position();
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            let valid = match name.is_empty() {
+                true => is_name_start_char(c),
+                false => is_name_char(c),
+            };
+            if !valid {
+                break;
+            }
+            name.push(c);
+            self.advance();
+        }
+
+        match self.peek() {
+            _ if !name.is_empty() => Ok(name),
+            None => Err((XmlParserError::UnexpectedEof, start)),
+            Some(_) => Err((XmlParserError::InvalidName, start)),
+        }
+    }
+
+    fn lookup_namespace(
+        &self,
+        namespaces: &HashMap<String, Option<String>>,
+        prefix: &str,
+    ) -> Option<String> {
+        if prefix == "xml" {
+            return Some(XML_NAMESPACE.to_string());
+        }
+        std::iter::once(namespaces)
+            .chain(self.open_elements.iter().rev().map(|e| &e.namespaces))
+            .find_map(|namespaces| namespaces.get(prefix))
+            .cloned()
+            .flatten()
+    }
+
+    fn current_node_id(&self) -> usize {
+        self.open_elements.last().map_or(0, |e| e.node_id)
+    }
+
+    // Appends text to the current node, merging it with a preceding text node
+    fn append_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        let parent_id = self.current_node_id();
+        let last_child_id = self
+            .document
+            .get_node_by_id(parent_id)
+            .and_then(|parent| parent.children.last().copied());
+        if let Some(last_child_id) = last_child_id {
+            let is_text = self
+                .document
+                .get_node_by_id(last_child_id)
+                .is_some_and(|node| matches!(node.data, NodeData::Text { .. }));
+            if is_text {
+                if let Some(value) = self.document.get_mut_text_by_id(last_child_id) {
+                    value.push_str(text);
+                    return;
+                }
+            }
+        }
+
+        self.document.add_node(Node::new_text(text), parent_id);
+    }
+
+    // Returns the position in the document. Inside the replacement text of an entity, this is
+    // the position of the outermost reference.
+    fn position(&self) -> Position {
+        match self.entity_frames.first() {
+            Some(frame) => frame.reference,
+            None => Position {
+                offset: self.offset,
+                line: self.line,
+                col: self.col,
+                byte_offset: self.pos,
+                utf16_offset: self.utf16_offset,
+            },
+        }
+    }
+
+    // Returns the input that is still to be read, which is the rest of the replacement text when
+    // parsing an entity
+    fn rest(&self) -> &str {
+        match self.entity_frames.last() {
+            Some(frame) => &frame.text[frame.pos..],
+            None => &self.input[self.pos..],
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.rest().chars().nth(offset)
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        self.rest().starts_with(s)
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        if let Some(frame) = self.entity_frames.last_mut() {
+            frame.pos += c.len_utf8();
+            return Some(c);
+        }
+
+        self.pos += c.len_utf8();
+        self.offset += 1;
+        self.utf16_offset += c.len_utf16();
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
+        }
+        Some(c)
+    }
+
+    fn skip(&mut self, count: usize) {
+        for _ in 0..count {
+            self.advance();
+        }
+    }
+
+    // Consumes the next character, which must be allowed in XML
+    fn next_char(&mut self) -> XmlResult<char> {
+        let position = self.position();
+        match self.advance() {
+            Some(c) if is_xml_char(c) => Ok(c),
+            Some(_) => Err((XmlParserError::InvalidCharacter, position)),
+            None => Err((XmlParserError::UnexpectedEof, position)),
+        }
+    }
+
+    fn expect(&mut self, expected: char, error: XmlParserError) -> XmlResult<()> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.advance();
+                Ok(())
+            }
+            None => Err((XmlParserError::UnexpectedEof, self.position())),
+            Some(_) => Err((error, self.position())),
+        }
+    }
+
+    fn expect_whitespace(&mut self) -> XmlResult<()> {
+        match self.skip_whitespace() {
+            true => Ok(()),
+            false if self.peek().is_none() => Err((XmlParserError::UnexpectedEof, self.position())),
+            false => Err((XmlParserError::MissingWhitespace, self.position())),
+        }
+    }
+
+    // Skips whitespace and returns true when there was any
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while self.peek().is_some_and(is_xml_whitespace) {
+            self.advance();
+            skipped = true;
+        }
+        skipped
+    }
+}
+
+// Splits a qualified name into its prefix and local name. Returns None when the name is not a
+// valid qualified name.
+fn split_qualified_name(name: &str) -> Option<(Option<&str>, &str)> {
+    match name.split_once(':') {
+        None => Some((None, name)),
+        Some((prefix, local_name))
+            if !prefix.is_empty() && is_xml_name(local_name) && !local_name.contains(':') =>
+        {
+            Some((Some(prefix), local_name))
+        }
+        Some(_) => None,
+    }
+}
+
+// Expands the character references in an entity value
+fn expand_character_references(value: &str) -> Option<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find("&#") {
+        result.push_str(&rest[..idx]);
+        let end = rest[idx..].find(';')? + idx;
+        let reference = &rest[idx + 2..end];
+        let c = match reference.strip_prefix('x') {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => reference.parse(),
+        }
+        .ok()
+        .and_then(char::from_u32)
+        .filter(|&c| is_xml_char(c))?;
+        result.push(c);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+fn is_xml_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\n' | '\r')
+}
+
+// Char production of XML 1.0
+pub(crate) fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | ' '..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+// PubidChar production of XML 1.0
+pub(crate) fn is_pubid_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || " \r\n-'()+,./:=?;!*#@$_%".contains(c)
+}
+
+fn is_name_start_char(c: char) -> bool {
+    matches!(c,
+        ':' | 'A'..='Z' | '_' | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}')
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c)
+        || matches!(c,
+            '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+}
+
+// Name production of XML 1.0
+pub(crate) fn is_xml_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_name_start_char) && chars.all(is_name_char)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::node::{HTML_NAMESPACE, SVG_NAMESPACE};
+
+    fn parse(xml: &str) -> (Document, Vec<ParseError>) {
+        let mut stream = InputStream::new();
+        stream.read_from_str(xml, None);
+        let mut parser = XmlParser::new(&mut stream);
+        let (_, errors) = parser.parse();
+        (parser.into_document(), errors)
+    }
+
+    fn errors_of(xml: &str) -> Vec<String> {
+        let (_, errors) = parse(xml);
+        errors.into_iter().map(|error| error.message).collect()
+    }
+
+    fn error(xml: &str) -> (String, usize, usize) {
+        let (_, errors) = parse(xml);
+        assert_eq!(errors.len(), 1, "expected one error for {}", xml);
+        (errors[0].message.clone(), errors[0].line, errors[0].col)
+    }
+
+    #[test]
+    fn test_parse_xhtml() {
+        let (document, errors) = parse(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \
+             \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">\n\
+             <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:svg=\"http://www.w3.org/2000/svg\"\n\
+             xmlns:xlink='http://www.w3.org/1999/xlink'>\
+             <body><p class='a\tb'>x &amp; y&nbsp;&#x41;<![CDATA[<b>]]><br/></p>\
+             <!-- c --><?php echo 1 ?>\
+             <svg:svg><svg:use xlink:href=\"#a\"/></svg:svg></body></html>\n",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(document.doctype, DocumentType::XML);
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <!DOCTYPE html \"-//W3C//DTD XHTML 1.0 Strict//EN\" \
+             \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">\n\
+             | <html>\n\
+             |   xmlns=\"http://www.w3.org/1999/xhtml\"\n\
+             |   xmlns:svg=\"http://www.w3.org/2000/svg\"\n\
+             |   xmlns:xlink=\"http://www.w3.org/1999/xlink\"\n\
+             |   <body>\n\
+             |     <p>\n\
+             |       class=\"a b\"\n\
+             |       \"x & y\u{a0}A<b>\"\n\
+             |       <br>\n\
+             |     <!--  c  -->\n\
+             |     <svg svg>\n\
+             |       <svg use>\n\
+             |         xlink href=\"#a\"\n"
+        );
+
+        let svg_id = document
+            .get_elements_by_tag_name("use")
+            .item(&document, 0)
+            .unwrap();
+        let node = document.get_node_by_id(svg_id).unwrap();
+        assert_eq!(node.namespace.as_deref(), Some(SVG_NAMESPACE));
+        assert_eq!(
+            node.get_attribute("xlink:href").map(String::as_str),
+            Some("#a")
+        );
+    }
+
+    #[test]
+    fn test_parse_namespaces() {
+        let (document, errors) = parse(
+            "<root xmlns:a='urn:a'><a:x a:attr='1' attr='2'><y xmlns='urn:b'><z xmlns=''/></y>\
+             </a:x></root>",
+        );
+        assert!(errors.is_empty());
+
+        let namespace = |name: &str| {
+            let id = document
+                .get_elements_by_tag_name(name)
+                .item(&document, 0)
+                .unwrap();
+            document.get_node_by_id(id).unwrap().namespace.clone()
+        };
+        assert_eq!(namespace("root"), None);
+        assert_eq!(namespace("x"), Some("urn:a".to_string()));
+        assert_eq!(namespace("y"), Some("urn:b".to_string()));
+        assert_eq!(namespace("z"), None);
+
+        // The document serializes back to the same XML
+        assert_eq!(
+            document.to_xml().unwrap(),
+            "<root xmlns:a=\"urn:a\"><a:x attr=\"2\" a:attr=\"1\"><y xmlns=\"urn:b\">\
+             <z xmlns=\"\"/></y></a:x></root>"
+        );
+
+        let (document, _) = parse(
+            "<html xmlns='http://www.w3.org/1999/xhtml'><head/><body><p>a<br/>b</p></body></html>",
+        );
+        assert_eq!(
+            document.to_xml().unwrap(),
+            "<html xmlns=\"http://www.w3.org/1999/xhtml\"><head></head><body><p>a<br />b</p>\
+             </body></html>"
+        );
+        let html_id = document.get_root().children[0];
+        assert_eq!(
+            document
+                .get_node_by_id(html_id)
+                .unwrap()
+                .namespace
+                .as_deref(),
+            Some(HTML_NAMESPACE)
+        );
+    }
+
+    #[test]
+    fn test_parse_entities() {
+        let (document, errors) = parse(
+            "<!DOCTYPE r [<!ENTITY e 'a&#x42;c'><!ENTITY % p 'x'><!-- ] --><!ELEMENT r ANY>]>\
+             <r t='&e;'>&e;&lt;&#65;</r>",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <!DOCTYPE r>\n| <r>\n|   t=\"aBc\"\n|   \"aBc<A\"\n"
+        );
+
+        // Replacement text is parsed as content, so references and markup in it are recognized
+        let (document, errors) = parse(
+            "<!DOCTYPE r [<!ENTITY a 'a &amp; b'><!ENTITY m '<b t=\"&a;\">x</b>&#38;#60;'>\
+             <!ENTITY q '\"'>]><r t='&a;' u=\"&q;\">&a;|&m;</r>",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <!DOCTYPE r>\n| <r>\n|   t=\"a & b\"\n|   u=\"\"\"\n|   \"a & b|\"\n|   <b>\n\
+             |     t=\"a & b\"\n|     \"x\"\n|   \"<\"\n"
+        );
+
+        assert_eq!(error("<r>&nbsp;</r>"), ("undefined-entity".into(), 1, 4));
+        assert_eq!(
+            error("<r>&#0;</r>"),
+            ("invalid-character-reference".into(), 1, 4)
+        );
+    }
+
+    #[test]
+    fn test_parse_entity_errors() {
+        assert_eq!(
+            error("<!DOCTYPE r [<!ENTITY e '<b>'>]><r>&e;</b></r>"),
+            ("unbalanced-entity".into(), 1, 36)
+        );
+        assert_eq!(
+            error("<!DOCTYPE r [<!ENTITY e '</r>'>]><r>&e;"),
+            ("unbalanced-entity".into(), 1, 37)
+        );
+        assert_eq!(
+            error("<!DOCTYPE r [<!ENTITY e '&f;'><!ENTITY f '&e;'>]><r>&e;</r>"),
+            ("recursive-entity".into(), 1, 53)
+        );
+        assert_eq!(
+            error("<!DOCTYPE r [<!ENTITY e '<'>]><r t='&e;'/>"),
+            ("lt-in-attribute-value".into(), 1, 37)
+        );
+
+        // Billion laughs: every entity references the previous one ten times
+        let mut xml = "<!DOCTYPE r [<!ENTITY l0 'lol'>".to_string();
+        for level in 1..10 {
+            xml.push_str(&format!(
+                "<!ENTITY l{} '{}'>",
+                level,
+                format!("&l{};", level - 1).repeat(10)
+            ));
+        }
+        xml.push_str("]><r>&l9;</r>");
+        let (document, errors) = parse(&xml);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "entity-expansion-limit-exceeded");
+        let r_id = document.get_root().children[1];
+        let text_id = document.get_node_by_id(r_id).unwrap().children[0];
+        let text_len = match &document.get_node_by_id(text_id).unwrap().data {
+            NodeData::Text { value } => value.len(),
+            _ => 0,
+        };
+        assert!(text_len <= MAX_ENTITY_EXPANSION);
+
+        // Nesting is limited too, even when the text stays small
+        let mut xml = "<!DOCTYPE r [<!ENTITY e0 'x'>".to_string();
+        for level in 1..=MAX_ENTITY_DEPTH {
+            xml.push_str(&format!("<!ENTITY e{} '&e{};'>", level, level - 1));
+        }
+        xml.push_str(&format!("]><r>&e{};</r>", MAX_ENTITY_DEPTH));
+        assert_eq!(errors_of(&xml), ["entity-expansion-limit-exceeded"]);
+        xml = xml.replace(
+            &format!("&e{};</r>", MAX_ENTITY_DEPTH),
+            &format!("&e{};</r>", MAX_ENTITY_DEPTH - 1),
+        );
+        assert!(errors_of(&xml).is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(error("<a><b></a>"), ("mismatched-end-tag".into(), 1, 7));
+        assert_eq!(error("<a>\n<b>"), ("unclosed-element".into(), 2, 4));
+        assert_eq!(error("<a/><b/>"), ("multiple-root-elements".into(), 1, 5));
+        assert_eq!(error("<!-- x -->"), ("missing-root-element".into(), 1, 11));
+        assert_eq!(error("x<a/>"), ("text-outside-root-element".into(), 1, 1));
+        assert_eq!(error("<a x=1/>"), ("unquoted-attribute-value".into(), 1, 6));
+        assert_eq!(
+            error("<a x='1' x='2'/>"),
+            ("duplicate-attribute".into(), 1, 10)
+        );
+        assert_eq!(
+            error("<a xmlns:p='u' xmlns:q='u' p:x='' q:x=''/>"),
+            ("duplicate-attribute".into(), 1, 35)
+        );
+        assert_eq!(error("<a x='<'/>"), ("lt-in-attribute-value".into(), 1, 7));
+        assert_eq!(
+            error("<a x='1'y='2'/>"),
+            ("missing-whitespace".into(), 1, 9)
+        );
+        assert_eq!(error("<p:a/>"), ("unbound-namespace-prefix".into(), 1, 1));
+        assert_eq!(
+            error("<a xmlns:p=''/>"),
+            ("invalid-namespace-declaration".into(), 1, 4)
+        );
+        assert_eq!(
+            error("<a><!-- a -- b --></a>"),
+            ("double-hyphen-in-comment".into(), 1, 11)
+        );
+        assert_eq!(error("<a>]]></a>"), ("cdata-end-in-content".into(), 1, 4));
+        assert_eq!(error("<a>\u{1}</a>"), ("invalid-character".into(), 1, 4));
+        assert_eq!(error("<1a/>"), ("invalid-name".into(), 1, 2));
+        assert_eq!(
+            error("<a/><!DOCTYPE a>"),
+            ("misplaced-doctype".into(), 1, 5)
+        );
+        assert_eq!(
+            error(" <?xml version='1.0'?><a/>"),
+            ("misplaced-xml-declaration".into(), 1, 2)
+        );
+        assert_eq!(
+            error("<?xml encoding='utf-8'?><a/>"),
+            ("malformed-xml-declaration".into(), 1, 1)
+        );
+        assert_eq!(error("<a>"), ("unclosed-element".into(), 1, 4));
+
+        // The nodes before the error are kept
+        let (document, _) = parse("<a><b>text</b><c></a>");
+        assert_eq!(
+            document.to_html5lib_tree(),
+            "| <a>\n|   <b>\n|     \"text\"\n|   <c>\n"
+        );
+    }
+}
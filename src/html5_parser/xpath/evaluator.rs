@@ -0,0 +1,960 @@
+use crate::html5_parser::node::{Node, NodeData, HTML_NAMESPACE};
+use crate::html5_parser::node::{XLINK_NAMESPACE, XMLNS_NAMESPACE, XML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+use crate::html5_parser::xpath::functions;
+use crate::html5_parser::xpath::{
+    Axis, BinaryOperator, Expr, NodeTest, Step, Value, XPathContext, XPathError, XPathNode,
+};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+// The context node together with its position in the current node list
+pub(crate) struct Focus {
+    pub node: XPathNode,
+    pub position: usize, // 1-based position of the node
+    pub size: usize,     // Number of nodes in the list
+}
+
+// Sort key for document order: the position of the node, the kind of node and the attribute name
+// or namespace prefix
+type OrderKey = ((usize, usize), u8, String);
+
+pub(crate) struct Evaluator<'a> {
+    context: &'a XPathContext<'a>,
+    // Position of every node that has been sorted so far: the id of the root of its tree and its
+    // index in that tree in tree order. Filled one tree at a time.
+    order: RefCell<HashMap<usize, (usize, usize)>>,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(context: &'a XPathContext<'a>) -> Self {
+        Evaluator {
+            context,
+            order: RefCell::new(HashMap::new()),
+        }
+    }
+
+    pub fn document(&self) -> &'a Document {
+        self.context.document
+    }
+
+    pub fn evaluate(&self, expr: &Expr, node: XPathNode) -> Result<Value, XPathError> {
+        // Unknown prefixes are an error even when there are no nodes to test against
+        self.check_prefixes(expr)?;
+
+        let focus = Focus {
+            node,
+            position: 1,
+            size: 1,
+        };
+        self.eval(expr, &focus)
+    }
+
+    pub fn eval(&self, expr: &Expr, focus: &Focus) -> Result<Value, XPathError> {
+        match expr {
+            Expr::Literal(value) => Ok(Value::String(value.clone())),
+            Expr::Number(value) => Ok(Value::Number(*value)),
+            Expr::Variable(name) => match self.context.variables.get(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(evaluation_error(&format!("unknown variable '${}'", name))),
+            },
+            Expr::Negate(expr) => Ok(Value::Number(
+                -self.eval(expr, focus)?.to_number(self.document()),
+            )),
+            Expr::Binary(operator, left, right) => self.eval_binary(*operator, left, right, focus),
+            Expr::FunctionCall(name, arguments) => functions::call(self, name, arguments, focus),
+            Expr::Filter(expr, predicates) => {
+                let nodes = match self.eval(expr, focus)? {
+                    Value::NodeSet(nodes) => nodes,
+                    _ => {
+                        return Err(evaluation_error(
+                            "predicates can only be applied to node-sets",
+                        ))
+                    }
+                };
+                Ok(Value::NodeSet(self.filter(nodes, predicates)?))
+            }
+            Expr::Path(expr, steps) => {
+                let nodes = match self.eval(expr, focus)? {
+                    Value::NodeSet(nodes) => nodes,
+                    _ => {
+                        return Err(evaluation_error(
+                            "location steps can only be applied to node-sets",
+                        ))
+                    }
+                };
+                Ok(Value::NodeSet(self.eval_steps(nodes, steps)?))
+            }
+            Expr::LocationPath(path) => {
+                let start = if path.absolute {
+                    XPathNode::Node(self.root(focus.node.node_id()))
+                } else {
+                    focus.node.clone()
+                };
+                Ok(Value::NodeSet(self.eval_steps(vec![start], &path.steps)?))
+            }
+        }
+    }
+
+    fn check_prefixes(&self, expr: &Expr) -> Result<(), XPathError> {
+        let check_steps = |steps: &[Step]| -> Result<(), XPathError> {
+            for step in steps {
+                match &step.node_test {
+                    NodeTest::NamespaceAny(prefix) | NodeTest::Name(Some(prefix), _) => {
+                        self.resolve_prefix(prefix)?;
+                    }
+                    _ => {}
+                }
+                for predicate in &step.predicates {
+                    self.check_prefixes(predicate)?;
+                }
+            }
+            Ok(())
+        };
+
+        match expr {
+            Expr::Binary(_, left, right) => {
+                self.check_prefixes(left)?;
+                self.check_prefixes(right)
+            }
+            Expr::Negate(expr) => self.check_prefixes(expr),
+            Expr::FunctionCall(_, arguments) => arguments
+                .iter()
+                .try_for_each(|arg| self.check_prefixes(arg)),
+            Expr::Filter(expr, predicates) => {
+                self.check_prefixes(expr)?;
+                predicates
+                    .iter()
+                    .try_for_each(|pred| self.check_prefixes(pred))
+            }
+            Expr::Path(expr, steps) => {
+                self.check_prefixes(expr)?;
+                check_steps(steps)
+            }
+            Expr::LocationPath(path) => check_steps(&path.steps),
+            Expr::Literal(_) | Expr::Number(_) | Expr::Variable(_) => Ok(()),
+        }
+    }
+
+    fn eval_binary(
+        &self,
+        operator: BinaryOperator,
+        left: &Expr,
+        right: &Expr,
+        focus: &Focus,
+    ) -> Result<Value, XPathError> {
+        // The logical operators don't evaluate the right operand when not needed
+        match operator {
+            BinaryOperator::Or => {
+                return Ok(Value::Boolean(
+                    self.eval(left, focus)?.to_boolean() || self.eval(right, focus)?.to_boolean(),
+                ))
+            }
+            BinaryOperator::And => {
+                return Ok(Value::Boolean(
+                    self.eval(left, focus)?.to_boolean() && self.eval(right, focus)?.to_boolean(),
+                ))
+            }
+            _ => {}
+        }
+
+        let left = self.eval(left, focus)?;
+        let right = self.eval(right, focus)?;
+        let document = self.document();
+
+        let value = match operator {
+            BinaryOperator::Union => match (left, right) {
+                (Value::NodeSet(mut left), Value::NodeSet(right)) => {
+                    left.extend(right);
+                    Value::NodeSet(self.sort_nodes(left))
+                }
+                _ => return Err(evaluation_error("operands of '|' must be node-sets")),
+            },
+            BinaryOperator::Add => {
+                Value::Number(left.to_number(document) + right.to_number(document))
+            }
+            BinaryOperator::Subtract => {
+                Value::Number(left.to_number(document) - right.to_number(document))
+            }
+            BinaryOperator::Multiply => {
+                Value::Number(left.to_number(document) * right.to_number(document))
+            }
+            BinaryOperator::Divide => {
+                Value::Number(left.to_number(document) / right.to_number(document))
+            }
+            BinaryOperator::Modulo => {
+                Value::Number(left.to_number(document) % right.to_number(document))
+            }
+            _ => Value::Boolean(self.compare(operator, &left, &right)),
+        };
+
+        Ok(value)
+    }
+
+    // Compares two values following the rules for node-sets in section 3.4 of the spec: a
+    // comparison involving a node-set is true when it is true for any of its nodes.
+    fn compare(&self, operator: BinaryOperator, left: &Value, right: &Value) -> bool {
+        let document = self.document();
+        let string_of = |node: &XPathNode| Value::String(string_value(document, node));
+
+        match (left, right) {
+            (Value::NodeSet(left), Value::NodeSet(right)) => {
+                let right: Vec<Value> = right.iter().map(string_of).collect();
+                left.iter().any(|l| {
+                    let l = string_of(l);
+                    right.iter().any(|r| self.compare_values(operator, &l, r))
+                })
+            }
+            (Value::NodeSet(_), Value::Boolean(_)) => {
+                self.compare_values(operator, &Value::Boolean(left.to_boolean()), right)
+            }
+            (Value::Boolean(_), Value::NodeSet(_)) => {
+                self.compare_values(operator, left, &Value::Boolean(right.to_boolean()))
+            }
+            (Value::NodeSet(left), right) => left
+                .iter()
+                .any(|l| self.compare_values(operator, &string_of(l), right)),
+            (left, Value::NodeSet(right)) => right
+                .iter()
+                .any(|r| self.compare_values(operator, left, &string_of(r))),
+            _ => self.compare_values(operator, left, right),
+        }
+    }
+
+    // Compares two values that are not node-sets
+    fn compare_values(&self, operator: BinaryOperator, left: &Value, right: &Value) -> bool {
+        let document = self.document();
+
+        match operator {
+            BinaryOperator::Equal | BinaryOperator::NotEqual => {
+                let equal = match (left, right) {
+                    (Value::Boolean(_), _) | (_, Value::Boolean(_)) => {
+                        left.to_boolean() == right.to_boolean()
+                    }
+                    (Value::Number(_), _) | (_, Value::Number(_)) => {
+                        left.to_number(document) == right.to_number(document)
+                    }
+                    _ => left.to_string(document) == right.to_string(document),
+                };
+                if operator == BinaryOperator::Equal {
+                    equal
+                } else {
+                    !equal
+                }
+            }
+            _ => {
+                let left = left.to_number(document);
+                let right = right.to_number(document);
+                match operator {
+                    BinaryOperator::Less => left < right,
+                    BinaryOperator::LessOrEqual => left <= right,
+                    BinaryOperator::Greater => left > right,
+                    _ => left >= right,
+                }
+            }
+        }
+    }
+
+    // Applies the location steps to the given nodes and returns the result in document order
+    fn eval_steps(
+        &self,
+        mut nodes: Vec<XPathNode>,
+        steps: &[Step],
+    ) -> Result<Vec<XPathNode>, XPathError> {
+        for step in steps {
+            let mut result = Vec::new();
+            for node in &nodes {
+                let mut candidates = Vec::new();
+                for candidate in self.axis_nodes(step.axis, node) {
+                    if self.matches_node_test(&step.node_test, step.axis, &candidate)? {
+                        candidates.push(candidate);
+                    }
+                }
+                // Candidates are in axis order, so positions in predicates count from the
+                // context node outwards on reverse axes
+                result.extend(self.filter(candidates, &step.predicates)?);
+            }
+            nodes = self.sort_nodes(result);
+        }
+
+        Ok(nodes)
+    }
+
+    // Filters the nodes with each predicate in turn. Positions are based on the order of the
+    // given nodes.
+    fn filter(
+        &self,
+        mut nodes: Vec<XPathNode>,
+        predicates: &[Expr],
+    ) -> Result<Vec<XPathNode>, XPathError> {
+        for predicate in predicates {
+            let size = nodes.len();
+            let mut result = Vec::new();
+            for (idx, node) in nodes.into_iter().enumerate() {
+                let focus = Focus {
+                    node,
+                    position: idx + 1,
+                    size,
+                };
+                let keep = match self.eval(predicate, &focus)? {
+                    Value::Number(n) => n == focus.position as f64,
+                    value => value.to_boolean(),
+                };
+                if keep {
+                    result.push(focus.node);
+                }
+            }
+            nodes = result;
+        }
+
+        Ok(nodes)
+    }
+
+    // Returns the nodes on the axis of the given node, in axis order
+    fn axis_nodes(&self, axis: Axis, node: &XPathNode) -> Vec<XPathNode> {
+        let document = self.document();
+
+        // Attribute and namespace nodes have no children or siblings
+        let node_id = match node {
+            XPathNode::Node(id) => Some(*id),
+            _ => None,
+        };
+
+        match axis {
+            Axis::SelfAxis => vec![node.clone()],
+            Axis::Child => node_id
+                .and_then(|id| document.get_node_by_id(id))
+                .map(|n| n.children.iter().map(|&id| XPathNode::Node(id)).collect())
+                .unwrap_or_default(),
+            Axis::Descendant => node_id.map(|id| self.descendants(id)).unwrap_or_default(),
+            Axis::DescendantOrSelf => {
+                let mut nodes = vec![node.clone()];
+                if let Some(id) = node_id {
+                    nodes.extend(self.descendants(id));
+                }
+                nodes
+            }
+            Axis::Parent => self.parent(node).into_iter().collect(),
+            Axis::Ancestor | Axis::AncestorOrSelf => {
+                let mut nodes = Vec::new();
+                if axis == Axis::AncestorOrSelf {
+                    nodes.push(node.clone());
+                }
+                let mut current = self.parent(node);
+                while let Some(parent) = current {
+                    current = self.parent(&parent);
+                    nodes.push(parent);
+                }
+                nodes
+            }
+            Axis::FollowingSibling => node_id.map(|id| self.siblings(id).1).unwrap_or_default(),
+            Axis::PrecedingSibling => node_id
+                .map(|id| {
+                    let mut nodes = self.siblings(id).0;
+                    nodes.reverse();
+                    nodes
+                })
+                .unwrap_or_default(),
+            Axis::Following => {
+                // The descendants of the element that owns an attribute follow that attribute
+                let mut nodes = match node {
+                    XPathNode::Node(_) => Vec::new(),
+                    _ => self.descendants(node.node_id()),
+                };
+                let mut current = Some(node.node_id());
+                while let Some(id) = current {
+                    for sibling in self.siblings(id).1 {
+                        let sibling_id = sibling.node_id();
+                        nodes.push(sibling);
+                        nodes.extend(self.descendants(sibling_id));
+                    }
+                    current = document.get_node_by_id(id).and_then(|n| n.parent);
+                }
+                nodes
+            }
+            Axis::Preceding => {
+                // Walk from the root down to the node, collecting all preceding siblings of
+                // every ancestor (and the node itself) in document order
+                let mut chain = vec![node.node_id()];
+                while let Some(parent) = document
+                    .get_node_by_id(*chain.last().expect("chain is never empty"))
+                    .and_then(|n| n.parent)
+                {
+                    chain.push(parent);
+                }
+
+                let mut nodes = Vec::new();
+                for &id in chain.iter().rev() {
+                    for sibling in self.siblings(id).0 {
+                        let sibling_id = sibling.node_id();
+                        nodes.push(sibling);
+                        nodes.extend(self.descendants(sibling_id));
+                    }
+                }
+                nodes.reverse();
+                nodes
+            }
+            Axis::Attribute => match node_id.and_then(|id| document.get_node_by_id(id)) {
+                Some(Node {
+                    data: NodeData::Element { attributes, .. },
+                    id,
+                    ..
+                }) => {
+                    let mut names: Vec<&String> = attributes
+                        .keys()
+                        .filter(|name| *name != "xmlns" && !name.starts_with("xmlns:"))
+                        .collect();
+                    names.sort();
+                    names
+                        .into_iter()
+                        .map(|name| XPathNode::Attribute(*id, name.clone()))
+                        .collect()
+                }
+                _ => Vec::new(),
+            },
+            Axis::Namespace => match node_id.and_then(|id| document.get_node_by_id(id)) {
+                Some(element) if element.is_element() => in_scope_namespaces(document, element.id)
+                    .into_keys()
+                    .map(|prefix| XPathNode::Namespace(element.id, prefix))
+                    .collect(),
+                _ => Vec::new(),
+            },
+        }
+    }
+
+    fn descendants(&self, node_id: usize) -> Vec<XPathNode> {
+        self.document()
+            .descendants(node_id)
+            .into_iter()
+            .map(XPathNode::Node)
+            .collect()
+    }
+
+    fn parent(&self, node: &XPathNode) -> Option<XPathNode> {
+        match node {
+            XPathNode::Node(id) => self
+                .document()
+                .get_node_by_id(*id)
+                .and_then(|n| n.parent)
+                .map(XPathNode::Node),
+            XPathNode::Attribute(id, _) | XPathNode::Namespace(id, _) => Some(XPathNode::Node(*id)),
+        }
+    }
+
+    // Returns the siblings before and after the given node, both in document order
+    fn siblings(&self, node_id: usize) -> (Vec<XPathNode>, Vec<XPathNode>) {
+        let document = self.document();
+        let parent = document
+            .get_node_by_id(node_id)
+            .and_then(|n| n.parent)
+            .and_then(|id| document.get_node_by_id(id));
+
+        match parent {
+            Some(parent) => {
+                let idx = parent
+                    .children
+                    .iter()
+                    .position(|&id| id == node_id)
+                    .unwrap_or(0);
+                (
+                    parent.children[..idx]
+                        .iter()
+                        .map(|&id| XPathNode::Node(id))
+                        .collect(),
+                    parent.children[idx + 1..]
+                        .iter()
+                        .map(|&id| XPathNode::Node(id))
+                        .collect(),
+                )
+            }
+            None => (Vec::new(), Vec::new()),
+        }
+    }
+
+    // Returns the top-most ancestor of the node, which is the document for connected nodes
+    pub fn root(&self, node_id: usize) -> usize {
+        let mut current = node_id;
+        while let Some(parent) = self
+            .document()
+            .get_node_by_id(current)
+            .and_then(|n| n.parent)
+        {
+            current = parent;
+        }
+        current
+    }
+
+    fn matches_node_test(
+        &self,
+        test: &NodeTest,
+        axis: Axis,
+        node: &XPathNode,
+    ) -> Result<bool, XPathError> {
+        let document = self.document();
+
+        match test {
+            // Doctypes are not part of the XPath data model
+            NodeTest::Node => {
+                return Ok(!matches!(
+                    node,
+                    XPathNode::Node(id) if matches!(
+                        document.get_node_by_id(*id).map(|n| &n.data),
+                        Some(NodeData::DocType { .. })
+                    )
+                ))
+            }
+            NodeTest::ProcessingInstruction(_) => return Ok(false),
+            NodeTest::Text | NodeTest::Comment => {
+                let data = match node {
+                    XPathNode::Node(id) => document.get_node_by_id(*id).map(|n| &n.data),
+                    _ => None,
+                };
+                return Ok(match data {
+                    Some(NodeData::Text { .. }) => *test == NodeTest::Text,
+                    Some(NodeData::Comment { .. }) => *test == NodeTest::Comment,
+                    _ => false,
+                });
+            }
+            _ => {}
+        }
+
+        // Name tests only match nodes of the principal node type of the axis
+        match (axis, node) {
+            (Axis::Attribute, XPathNode::Attribute(owner_id, name)) => {
+                self.matches_attribute_name(test, *owner_id, name)
+            }
+            (Axis::Namespace, XPathNode::Namespace(_, prefix)) => Ok(match test {
+                NodeTest::Any => true,
+                NodeTest::Name(None, local) => prefix == local,
+                _ => false,
+            }),
+            (Axis::Attribute, _) | (Axis::Namespace, _) => Ok(false),
+            (_, XPathNode::Node(id)) => match document.get_node_by_id(*id) {
+                Some(element) if element.is_element() => self.matches_element_name(test, element),
+                _ => Ok(false),
+            },
+            _ => Ok(false),
+        }
+    }
+
+    // Unprefixed names match elements in the HTML namespace (ASCII case-insensitive) and
+    // elements without a namespace. Other elements need a prefixed name test.
+    fn matches_element_name(&self, test: &NodeTest, element: &Node) -> Result<bool, XPathError> {
+        let namespace = element.namespace.as_deref();
+        let local_name = local_part(&element.name);
+
+        Ok(match test {
+            NodeTest::Any => true,
+            NodeTest::NamespaceAny(prefix) => namespace == Some(self.resolve_prefix(prefix)?),
+            NodeTest::Name(None, name) => match namespace {
+                Some(HTML_NAMESPACE) => local_name == name.to_ascii_lowercase(),
+                None => local_name == name,
+                _ => false,
+            },
+            NodeTest::Name(Some(prefix), name) => {
+                namespace == Some(self.resolve_prefix(prefix)?) && local_name == name
+            }
+            _ => false,
+        })
+    }
+
+    fn matches_attribute_name(
+        &self,
+        test: &NodeTest,
+        owner_id: usize,
+        name: &str,
+    ) -> Result<bool, XPathError> {
+        let (prefix, local_name) = match name.split_once(':') {
+            Some((prefix, local)) => (Some(prefix), local),
+            None => (None, name),
+        };
+
+        Ok(match test {
+            NodeTest::Any => true,
+            NodeTest::Name(None, test_name) => {
+                let html = self
+                    .document()
+                    .get_node_by_id(owner_id)
+                    .is_some_and(|n| n.namespace.as_deref() == Some(HTML_NAMESPACE));
+                prefix.is_none()
+                    && (local_name == test_name
+                        || (html && local_name == test_name.to_ascii_lowercase()))
+            }
+            NodeTest::Name(Some(test_prefix), test_name) => {
+                let uri = self.resolve_prefix(test_prefix)?;
+                local_name == test_name
+                    && attribute_namespace(self.document(), owner_id, name).as_deref() == Some(uri)
+            }
+            NodeTest::NamespaceAny(test_prefix) => {
+                let uri = self.resolve_prefix(test_prefix)?;
+                attribute_namespace(self.document(), owner_id, name).as_deref() == Some(uri)
+            }
+            _ => false,
+        })
+    }
+
+    fn resolve_prefix(&self, prefix: &str) -> Result<&str, XPathError> {
+        match self.context.namespaces.get(prefix) {
+            Some(uri) => Ok(uri.as_str()),
+            None => Err(evaluation_error(&format!(
+                "unknown namespace prefix '{}'",
+                prefix
+            ))),
+        }
+    }
+
+    // Sorts the nodes in document order and removes duplicates. Attribute and namespace nodes
+    // come right after the element they belong to, before its children.
+    pub fn sort_nodes(&self, nodes: Vec<XPathNode>) -> Vec<XPathNode> {
+        let mut keyed: Vec<(OrderKey, XPathNode)> = nodes
+            .into_iter()
+            .map(|node| (self.order_key(&node), node))
+            .collect();
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        keyed.dedup_by(|a, b| a.0 == b.0);
+        keyed.into_iter().map(|(_, node)| node).collect()
+    }
+
+    fn order_key(&self, node: &XPathNode) -> OrderKey {
+        let position = self.tree_position(node.node_id());
+
+        match node {
+            XPathNode::Node(_) => (position, 0, String::new()),
+            XPathNode::Namespace(_, prefix) => (position, 1, prefix.clone()),
+            XPathNode::Attribute(_, name) => (position, 2, name.clone()),
+        }
+    }
+
+    // Returns the root of the tree the node is in and the index of the node in that tree. The
+    // root is included first so nodes from different trees don't get mixed up. The first time a
+    // node of a tree is seen, the whole tree is numbered with a single walk.
+    fn tree_position(&self, node_id: usize) -> (usize, usize) {
+        if let Some(&position) = self.order.borrow().get(&node_id) {
+            return position;
+        }
+
+        let document = self.document();
+        let root_id = document.root_node(node_id);
+
+        let mut order = self.order.borrow_mut();
+        order.insert(root_id, (root_id, 0));
+        for (index, id) in document.descendants(root_id).into_iter().enumerate() {
+            order.insert(id, (root_id, index + 1));
+        }
+
+        // A node that is not in the arena has no tree of its own
+        *order.entry(node_id).or_insert((root_id, 0))
+    }
+}
+
+pub(crate) fn evaluation_error(message: &str) -> XPathError {
+    XPathError::Evaluation {
+        message: message.to_string(),
+    }
+}
+
+// Returns the part of a qualified name after the prefix
+pub(crate) fn local_part(name: &str) -> &str {
+    match name.split_once(':') {
+        Some((_, local)) => local,
+        None => name,
+    }
+}
+
+// Returns the namespace prefixes that are in scope for the element, mapped to their namespace
+// URI. These come from xmlns attributes on the element and its ancestors. An element without a
+// default namespace declaration gets its own namespace as default namespace.
+pub(crate) fn in_scope_namespaces(
+    document: &Document,
+    element_id: usize,
+) -> BTreeMap<String, String> {
+    let mut namespaces = BTreeMap::new();
+
+    let mut current = document.get_node_by_id(element_id);
+    while let Some(node) = current {
+        if let NodeData::Element { attributes, .. } = &node.data {
+            for (name, value) in attributes {
+                let prefix = match name.as_str() {
+                    "xmlns" => "",
+                    _ => match name.strip_prefix("xmlns:") {
+                        Some(prefix) => prefix,
+                        None => continue,
+                    },
+                };
+                // Declarations closer to the element win
+                namespaces
+                    .entry(prefix.to_string())
+                    .or_insert_with(|| value.clone());
+            }
+        }
+        current = node.parent.and_then(|id| document.get_node_by_id(id));
+    }
+
+    if let Some(namespace) = document
+        .get_node_by_id(element_id)
+        .and_then(|n| n.namespace.clone())
+    {
+        namespaces.entry(String::new()).or_insert(namespace);
+    }
+    namespaces.insert("xml".to_string(), XML_NAMESPACE.to_string());
+
+    // An empty URI undeclares the prefix
+    namespaces.retain(|_, uri| !uri.is_empty());
+    namespaces
+}
+
+// Returns the namespace of the attribute, based on its prefix. The HTML parser uses the xlink,
+// xml and xmlns prefixes without declaring them, so these are always known.
+pub(crate) fn attribute_namespace(
+    document: &Document,
+    owner_id: usize,
+    name: &str,
+) -> Option<String> {
+    let (prefix, _) = name.split_once(':')?;
+
+    match prefix {
+        "xlink" => Some(XLINK_NAMESPACE.to_string()),
+        "xmlns" => Some(XMLNS_NAMESPACE.to_string()),
+        _ => in_scope_namespaces(document, owner_id).remove(prefix),
+    }
+}
+
+// Returns the string-value of the node as defined by the XPath data model
+pub(crate) fn string_value(document: &Document, node: &XPathNode) -> String {
+    match node {
+        XPathNode::Node(id) => match document.get_node_by_id(*id).map(|n| &n.data) {
+            Some(NodeData::Text { value }) | Some(NodeData::Comment { value }) => value.clone(),
+            Some(_) => document
+                .descendants(*id)
+                .into_iter()
+                .filter_map(|id| match document.get_node_by_id(id).map(|n| &n.data) {
+                    Some(NodeData::Text { value }) => Some(value.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            None => String::new(),
+        },
+        XPathNode::Attribute(id, name) => document
+            .get_node_by_id(*id)
+            .and_then(|n| n.get_attribute(name))
+            .cloned()
+            .unwrap_or_default(),
+        XPathNode::Namespace(id, prefix) => in_scope_namespaces(document, *id)
+            .remove(prefix)
+            .unwrap_or_default(),
+    }
+}
+
+// Converts a string to a number. Only an optional minus sign followed by digits with an optional
+// decimal point is accepted (surrounded by whitespace), anything else is NaN.
+pub(crate) fn string_to_number(s: &str) -> f64 {
+    let s = s.trim_matches(|c| matches!(c, ' ' | '\t' | '\r' | '\n'));
+    let digits = s.strip_prefix('-').unwrap_or(s);
+
+    let valid = !digits.is_empty()
+        && digits != "."
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && digits.chars().filter(|&c| c == '.').count() <= 1;
+
+    if valid {
+        s.parse().unwrap_or(f64::NAN)
+    } else {
+        f64::NAN
+    }
+}
+
+// Converts a number to a string. Integers are written without a decimal point and no
+// exponential notation is used.
+pub(crate) fn number_to_string(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string()
+    } else if n == 0.0 {
+        // Also covers negative zero
+        "0".to_string()
+    } else {
+        format!("{}", n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::node::SVG_NAMESPACE;
+    use crate::html5_parser::xpath::XPathExpression;
+    use std::collections::HashMap;
+
+    fn element(name: &str, attrs: &[(&str, &str)]) -> Node {
+        let mut attributes = HashMap::new();
+        for (key, value) in attrs {
+            attributes.insert(key.to_string(), value.to_string());
+        }
+        Node::new_element(name, attributes, HTML_NAMESPACE)
+    }
+
+    // <html><body><ul><li id="1">a</li><li id="2">b<!--c--></li><li id="3" class="x">d</li></ul>
+    // <p title="t">e</p></body></html>
+    fn build_document() -> Document {
+        let mut document = Document::new();
+        let html_id = document.add_node(element("html", &[]), 0);
+        let body_id = document.add_node(element("body", &[]), html_id);
+        let ul_id = document.add_node(element("ul", &[]), body_id);
+        let li_id = document.add_node(element("li", &[("id", "1")]), ul_id);
+        document.add_node(Node::new_text("a"), li_id);
+        let li_id = document.add_node(element("li", &[("id", "2")]), ul_id);
+        document.add_node(Node::new_text("b"), li_id);
+        document.add_node(Node::new_comment("c"), li_id);
+        let li_id = document.add_node(element("li", &[("id", "3"), ("class", "x")]), ul_id);
+        document.add_node(Node::new_text("d"), li_id);
+        let p_id = document.add_node(element("p", &[("title", "t")]), body_id);
+        document.add_node(Node::new_text("e"), p_id);
+        document
+    }
+
+    fn eval(document: &Document, expression: &str) -> Value {
+        let context = XPathContext::new(document);
+        XPathExpression::parse(expression)
+            .unwrap()
+            .evaluate(&context, 0)
+            .unwrap()
+    }
+
+    fn eval_strings(document: &Document, expression: &str) -> Vec<String> {
+        match eval(document, expression) {
+            Value::NodeSet(nodes) => nodes
+                .iter()
+                .map(|node| string_value(document, node))
+                .collect(),
+            other => panic!("expected a node set, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_axes() {
+        let document = build_document();
+
+        assert_eq!(
+            eval_strings(&document, "//li[2]/child::node()"),
+            vec!["b", "c"]
+        );
+        assert_eq!(eval_strings(&document, "//li[2]/comment()"), vec!["c"]);
+        assert_eq!(
+            eval_strings(&document, "//li[2]/parent::ul/li[last()]"),
+            vec!["d"]
+        );
+        assert_eq!(
+            eval_strings(&document, "//li[3]/ancestor::*[1]"),
+            vec!["abd"]
+        );
+        assert_eq!(
+            eval_strings(&document, "//li[3]/ancestor-or-self::*[2]/li[1]"),
+            vec!["a"]
+        );
+        assert_eq!(
+            eval_strings(&document, "//li[1]/following-sibling::li"),
+            vec!["b", "d"]
+        );
+        assert_eq!(
+            eval_strings(&document, "//li[3]/preceding-sibling::li[1]"),
+            vec!["b"]
+        );
+        assert_eq!(
+            eval_strings(&document, "//li[2]/following::text()"),
+            vec!["d", "e"]
+        );
+        assert_eq!(eval_strings(&document, "//p/preceding::li[1]"), vec!["d"]);
+        assert_eq!(
+            eval_strings(&document, "//li[3]/preceding::node()[self::text()]"),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            eval_strings(&document, "//li[3]/attribute::*"),
+            vec!["x", "3"]
+        );
+        assert_eq!(eval_strings(&document, "//li[3]/@class/.."), vec!["d"]);
+        assert_eq!(
+            eval_strings(&document, "//li[1]/@id/following::text()"),
+            vec!["a", "b", "d", "e"]
+        );
+        assert_eq!(
+            eval_strings(&document, "//p/self::p/descendant-or-self::node()"),
+            vec!["e", "e"]
+        );
+        assert_eq!(
+            eval_strings(&document, "/html/namespace::*"),
+            vec![HTML_NAMESPACE, XML_NAMESPACE]
+        );
+    }
+
+    #[test]
+    fn test_document_order_of_unions() {
+        let document = build_document();
+
+        assert_eq!(
+            eval_strings(&document, "//p | //li[3] | //li[1] | //li[1]/@id"),
+            vec!["a", "1", "d", "e"]
+        );
+        assert_eq!(eval_strings(&document, "(//li | //p)[last()]"), vec!["e"]);
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let document = build_document();
+
+        assert_eq!(eval(&document, "//li = 'b'"), Value::Boolean(true));
+        assert_eq!(eval(&document, "//li != 'b'"), Value::Boolean(true));
+        assert_eq!(eval(&document, "//li/@id > 2"), Value::Boolean(true));
+        assert_eq!(eval(&document, "//li/@id > 3"), Value::Boolean(false));
+        assert_eq!(eval(&document, "//li = //p"), Value::Boolean(false));
+        assert_eq!(eval(&document, "//nothing = false()"), Value::Boolean(true));
+        assert_eq!(eval(&document, "'1' = 1.0"), Value::Boolean(true));
+        assert_eq!(eval(&document, "true() = 'false'"), Value::Boolean(true));
+        assert_eq!(eval(&document, "'abc' < 'abd'"), Value::Boolean(false));
+        assert_eq!(eval(&document, "0 div 0 != 0 div 0"), Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let document = build_document();
+
+        assert_eq!(eval(&document, "5 mod 2"), Value::Number(1.0));
+        assert_eq!(eval(&document, "-5 mod 2"), Value::Number(-1.0));
+        assert_eq!(eval(&document, "1 div 0"), Value::Number(f64::INFINITY));
+        assert_eq!(eval(&document, "--2 - -1"), Value::Number(3.0));
+        assert_eq!(eval(&document, "sum(//li/@id) * 2"), Value::Number(12.0));
+    }
+
+    #[test]
+    fn test_name_tests() {
+        let mut document = Document::new();
+        let svg_id = document.add_node(Node::new_element("svg", HashMap::new(), SVG_NAMESPACE), 0);
+        let mut attributes = HashMap::new();
+        attributes.insert("xlink:href".to_string(), "#a".to_string());
+        attributes.insert("href".to_string(), "#b".to_string());
+        document.add_node(Node::new_element("use", attributes, SVG_NAMESPACE), svg_id);
+
+        assert_eq!(eval(&document, "count(//use)"), Value::Number(0.0));
+        assert_eq!(eval(&document, "count(//svg:*)"), Value::Number(2.0));
+        assert_eq!(eval_strings(&document, "//svg:use/@href"), vec!["#b"]);
+        assert_eq!(eval_strings(&document, "//svg:use/@xlink:href"), vec!["#a"]);
+        assert_eq!(eval_strings(&document, "//svg:use/@xlink:*"), vec!["#a"]);
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(string_to_number(" 12.5 "), 12.5);
+        assert_eq!(string_to_number("-.5"), -0.5);
+        assert!(string_to_number("+1").is_nan());
+        assert!(string_to_number("1e3").is_nan());
+        assert!(string_to_number("").is_nan());
+        assert!(string_to_number(".").is_nan());
+
+        assert_eq!(number_to_string(1.0), "1");
+        assert_eq!(number_to_string(-0.0), "0");
+        assert_eq!(number_to_string(0.5), "0.5");
+        assert_eq!(number_to_string(1e21), "1000000000000000000000");
+        assert_eq!(number_to_string(f64::NAN), "NaN");
+        assert_eq!(number_to_string(f64::NEG_INFINITY), "-Infinity");
+    }
+}
@@ -0,0 +1,328 @@
+use crate::html5_parser::node::NodeData;
+use crate::html5_parser::xpath::evaluator::{
+    attribute_namespace, evaluation_error, local_part, string_to_number, string_value, Evaluator,
+    Focus,
+};
+use crate::html5_parser::xpath::{Expr, Value, XPathError, XPathNode};
+
+// Returns the minimum and maximum (None when unbounded) number of arguments of the function, or
+// None when it is not part of the core function library
+pub(crate) fn arity(name: &str) -> Option<(usize, Option<usize>)> {
+    let arity = match name {
+        "last" | "position" | "true" | "false" => (0, Some(0)),
+        "count" | "id" | "sum" | "not" | "boolean" | "floor" | "ceiling" | "round" | "lang" => {
+            (1, Some(1))
+        }
+        "local-name" | "namespace-uri" | "name" | "string" | "string-length"
+        | "normalize-space" | "number" => (0, Some(1)),
+        "starts-with" | "contains" | "substring-before" | "substring-after" => (2, Some(2)),
+        "substring" => (2, Some(3)),
+        "translate" => (3, Some(3)),
+        "concat" => (2, None),
+        _ => return None,
+    };
+    Some(arity)
+}
+
+// Calls a function from the core function library. The number of arguments has already been
+// checked by the parser.
+pub(crate) fn call(
+    evaluator: &Evaluator,
+    name: &str,
+    arguments: &[Expr],
+    focus: &Focus,
+) -> Result<Value, XPathError> {
+    let document = evaluator.document();
+
+    let mut values = Vec::with_capacity(arguments.len());
+    for argument in arguments {
+        values.push(evaluator.eval(argument, focus)?);
+    }
+
+    // Functions with an optional argument default to a node-set with only the context node
+    let context_node_set = || Value::NodeSet(vec![focus.node.clone()]);
+    let string_arg = |idx: usize| -> String {
+        match values.get(idx) {
+            Some(value) => value.to_string(document),
+            None => context_node_set().to_string(document),
+        }
+    };
+    let number_arg = |idx: usize| values[idx].to_number(document);
+    let node_set_arg = |idx: usize| -> Result<Vec<XPathNode>, XPathError> {
+        match values.get(idx) {
+            Some(Value::NodeSet(nodes)) => Ok(nodes.clone()),
+            Some(_) => Err(evaluation_error(&format!(
+                "argument {} of {}() must be a node-set",
+                idx + 1,
+                name
+            ))),
+            None => Ok(vec![focus.node.clone()]),
+        }
+    };
+
+    let value = match name {
+        // Node set functions
+        "last" => Value::Number(focus.size as f64),
+        "position" => Value::Number(focus.position as f64),
+        "count" => Value::Number(node_set_arg(0)?.len() as f64),
+        "id" => {
+            let ids = match &values[0] {
+                Value::NodeSet(nodes) => nodes
+                    .iter()
+                    .map(|node| string_value(document, node))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                value => value.to_string(document),
+            };
+            let nodes = ids
+                .split_ascii_whitespace()
+                .filter_map(|id| document.get_element_by_id(id))
+                .map(XPathNode::Node)
+                .collect();
+            Value::NodeSet(evaluator.sort_nodes(nodes))
+        }
+        "local-name" | "namespace-uri" | "name" => {
+            let names = match node_set_arg(0)?.first() {
+                Some(node) => node_names(evaluator, node),
+                None => Default::default(),
+            };
+            Value::String(match name {
+                "local-name" => names.local_name,
+                "namespace-uri" => names.namespace_uri,
+                _ => names.name,
+            })
+        }
+
+        // String functions
+        "string" => Value::String(string_arg(0)),
+        "concat" => Value::String((0..values.len()).map(string_arg).collect()),
+        "starts-with" => Value::Boolean(string_arg(0).starts_with(&string_arg(1))),
+        "contains" => Value::Boolean(string_arg(0).contains(&string_arg(1))),
+        "substring-before" => {
+            let s = string_arg(0);
+            Value::String(match s.find(&string_arg(1)) {
+                Some(idx) => s[..idx].to_string(),
+                None => String::new(),
+            })
+        }
+        "substring-after" => {
+            let s = string_arg(0);
+            let needle = string_arg(1);
+            Value::String(match s.find(&needle) {
+                Some(idx) => s[idx + needle.len()..].to_string(),
+                None => String::new(),
+            })
+        }
+        "substring" => {
+            // Characters are included when round(start) <= position < round(start) + round(len).
+            // NaN makes all comparisons false, so the result is empty in that case.
+            let start = round(number_arg(1));
+            let end = match values.get(2) {
+                Some(len) => start + round(len.to_number(document)),
+                None => f64::INFINITY,
+            };
+            Value::String(
+                string_arg(0)
+                    .chars()
+                    .enumerate()
+                    .filter(|(idx, _)| {
+                        let position = (*idx + 1) as f64;
+                        position >= start && position < end
+                    })
+                    .map(|(_, c)| c)
+                    .collect(),
+            )
+        }
+        "string-length" => Value::Number(string_arg(0).chars().count() as f64),
+        "normalize-space" => Value::String(
+            string_arg(0)
+                .split([' ', '\t', '\r', '\n'])
+                .filter(|part| !part.is_empty())
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        "translate" => {
+            let from: Vec<char> = string_arg(1).chars().collect();
+            let to: Vec<char> = string_arg(2).chars().collect();
+            Value::String(
+                string_arg(0)
+                    .chars()
+                    .filter_map(|c| match from.iter().position(|&f| f == c) {
+                        Some(idx) => to.get(idx).copied(),
+                        None => Some(c),
+                    })
+                    .collect(),
+            )
+        }
+
+        // Boolean functions
+        "boolean" => Value::Boolean(values[0].to_boolean()),
+        "not" => Value::Boolean(!values[0].to_boolean()),
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        "lang" => Value::Boolean(lang_matches(evaluator, &focus.node, &string_arg(0))),
+
+        // Number functions
+        "number" => Value::Number(match values.first() {
+            Some(value) => value.to_number(document),
+            None => context_node_set().to_number(document),
+        }),
+        "sum" => Value::Number(
+            node_set_arg(0)?
+                .iter()
+                .map(|node| string_to_number(&string_value(document, node)))
+                // Iterator::sum() starts at -0.0, which would make the sum of no nodes negative
+                .fold(0.0, |sum, n| sum + n),
+        ),
+        "floor" => Value::Number(number_arg(0).floor()),
+        "ceiling" => Value::Number(number_arg(0).ceil()),
+        "round" => Value::Number(round(number_arg(0))),
+
+        _ => return Err(evaluation_error(&format!("unknown function '{}'", name))),
+    };
+
+    Ok(value)
+}
+
+// Rounds to the closest integer, with halves rounded towards positive infinity
+fn round(n: f64) -> f64 {
+    if n.is_nan() || n.is_infinite() {
+        return n;
+    }
+
+    let rounded = (n + 0.5).floor();
+    if rounded == 0.0 && n < 0.0 {
+        -0.0
+    } else {
+        rounded
+    }
+}
+
+#[derive(Default)]
+struct NodeNames {
+    name: String,
+    local_name: String,
+    namespace_uri: String,
+}
+
+fn node_names(evaluator: &Evaluator, node: &XPathNode) -> NodeNames {
+    let document = evaluator.document();
+
+    match node {
+        XPathNode::Node(id) => match document.get_node_by_id(*id) {
+            Some(element) if matches!(element.data, NodeData::Element { .. }) => NodeNames {
+                name: element.name.clone(),
+                local_name: local_part(&element.name).to_string(),
+                namespace_uri: element.namespace.clone().unwrap_or_default(),
+            },
+            _ => NodeNames::default(),
+        },
+        XPathNode::Attribute(id, name) => NodeNames {
+            name: name.clone(),
+            local_name: local_part(name).to_string(),
+            namespace_uri: attribute_namespace(document, *id, name).unwrap_or_default(),
+        },
+        XPathNode::Namespace(_, prefix) => NodeNames {
+            name: prefix.clone(),
+            local_name: prefix.clone(),
+            namespace_uri: String::new(),
+        },
+    }
+}
+
+// Checks the language of the context node against the given language. The language comes from
+// the closest xml:lang attribute, or the lang attribute as used by HTML.
+fn lang_matches(evaluator: &Evaluator, node: &XPathNode, lang: &str) -> bool {
+    let document = evaluator.document();
+
+    let mut current = document.get_node_by_id(node.node_id());
+    while let Some(element) = current {
+        if let Some(value) = element
+            .get_attribute("xml:lang")
+            .or_else(|| element.get_attribute("lang"))
+        {
+            let value = value.to_ascii_lowercase();
+            let lang = lang.to_ascii_lowercase();
+            return value == lang || value.starts_with(&format!("{}-", lang));
+        }
+        current = element.parent.and_then(|id| document.get_node_by_id(id));
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html5_parser::node::{Node, HTML_NAMESPACE};
+    use crate::html5_parser::parser::document::Document;
+    use crate::html5_parser::xpath::Value;
+    use std::collections::HashMap;
+
+    fn eval(expression: &str) -> Value {
+        let mut document = Document::new();
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "a b".to_string());
+        attributes.insert("lang".to_string(), "EN-gb".to_string());
+        let div_id = document.add_node(Node::new_element("div", attributes, HTML_NAMESPACE), 0);
+        document.add_node(Node::new_text(" x  y "), div_id);
+        let mut attributes = HashMap::new();
+        attributes.insert("id".to_string(), "c".to_string());
+        document.add_node(Node::new_element("p", attributes, HTML_NAMESPACE), div_id);
+
+        document.evaluate_xpath(expression, 0).unwrap()
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_string())
+    }
+
+    #[test]
+    fn test_node_set_functions() {
+        assert_eq!(eval("count(//node())"), Value::Number(3.0));
+        assert_eq!(eval("//node()[last()]/@id = 'c'"), Value::Boolean(true));
+        assert_eq!(eval("count(//*[position() < 2])"), Value::Number(2.0));
+        assert_eq!(eval("count(id('c a b'))"), Value::Number(1.0));
+        assert_eq!(eval("name(id(//p/@id))"), string("p"));
+        assert_eq!(eval("local-name(//div/@lang)"), string("lang"));
+        assert_eq!(eval("namespace-uri(//div)"), string(HTML_NAMESPACE));
+        assert_eq!(eval("name(//nothing)"), string(""));
+    }
+
+    #[test]
+    fn test_string_functions() {
+        assert_eq!(eval("string(1 div 0)"), string("Infinity"));
+        assert_eq!(eval("concat('a', 1, true())"), string("a1true"));
+        assert_eq!(eval("starts-with('abc', 'ab')"), Value::Boolean(true));
+        assert_eq!(eval("contains('abc', 'd')"), Value::Boolean(false));
+        assert_eq!(eval("substring-before('1999/04/01', '/')"), string("1999"));
+        assert_eq!(eval("substring-after('1999/04/01', '/')"), string("04/01"));
+        assert_eq!(eval("substring('12345', 2, 3)"), string("234"));
+        assert_eq!(eval("substring('12345', 1.5, 2.6)"), string("234"));
+        assert_eq!(eval("substring('12345', 0, 3)"), string("12"));
+        assert_eq!(eval("substring('12345', 0 div 0, 3)"), string(""));
+        assert_eq!(eval("substring('12345', -42, 1 div 0)"), string("12345"));
+        assert_eq!(eval("string-length('héllo')"), Value::Number(5.0));
+        assert_eq!(eval("normalize-space(//div)"), string("x y"));
+        assert_eq!(eval("translate('--aaa--', 'abc-', 'ABC')"), string("AAA"));
+    }
+
+    #[test]
+    fn test_boolean_and_number_functions() {
+        assert_eq!(eval("not(boolean(''))"), Value::Boolean(true));
+        assert_eq!(
+            eval("//p[lang('en')] and not(//p[lang('gb')])"),
+            Value::Boolean(true)
+        );
+        assert_eq!(eval("number(' 12 ')"), Value::Number(12.0));
+        assert_eq!(eval("string(sum(//@id))"), string("NaN"));
+        assert_eq!(
+            eval("1 div sum(//p/@missing)"),
+            Value::Number(f64::INFINITY)
+        );
+        assert_eq!(eval("floor(-1.5)"), Value::Number(-2.0));
+        assert_eq!(eval("ceiling(1.2)"), Value::Number(2.0));
+        assert_eq!(eval("round(2.5)"), Value::Number(3.0));
+        assert_eq!(eval("round(-2.5)"), Value::Number(-2.0));
+        assert_eq!(eval("string(round(-0.2))"), string("0"));
+    }
+}
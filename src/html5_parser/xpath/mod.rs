@@ -0,0 +1,397 @@
+mod evaluator;
+mod functions;
+mod parser;
+
+use crate::html5_parser::node::{HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE};
+use crate::html5_parser::node::{XLINK_NAMESPACE, XMLNS_NAMESPACE, XML_NAMESPACE};
+use crate::html5_parser::parser::document::Document;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A parsed XPath 1.0 expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Binary(BinaryOperator, Box<Expr>, Box<Expr>),
+    Negate(Box<Expr>),
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    FunctionCall(String, Vec<Expr>),
+    /// Primary expression followed by one or more predicates (ie: `$nodes[1]`)
+    Filter(Box<Expr>, Vec<Expr>),
+    /// Filter expression followed by location steps (ie: `id('foo')/p`)
+    Path(Box<Expr>, Vec<Step>),
+    LocationPath(LocationPath),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Union,
+}
+
+/// A relative or absolute location path. Abbreviations like `//`, `.`, `..` and `@` are
+/// expanded into their full steps by the parser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocationPath {
+    pub absolute: bool,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub axis: Axis,
+    pub node_test: NodeTest,
+    pub predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    Ancestor,
+    AncestorOrSelf,
+    Attribute,
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    Following,
+    FollowingSibling,
+    Namespace,
+    Parent,
+    Preceding,
+    PrecedingSibling,
+    SelfAxis,
+}
+
+impl Axis {
+    /// Reverse axes number their nodes in reverse document order inside predicates
+    pub fn is_reverse(&self) -> bool {
+        matches!(
+            self,
+            Axis::Ancestor | Axis::AncestorOrSelf | Axis::Preceding | Axis::PrecedingSibling
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeTest {
+    /// `*`: any node of the principal node type of the axis
+    Any,
+    /// `prefix:*`
+    NamespaceAny(String),
+    /// Optional prefix and local name
+    Name(Option<String>, String),
+    Node,
+    Text,
+    Comment,
+    /// Optional literal with the target. We don't have processing instructions in our tree,
+    /// so this never matches.
+    ProcessingInstruction(Option<String>),
+}
+
+/// A node in the XPath data model. Attributes and namespaces are not nodes in our tree, so they
+/// are referenced through the element that owns them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum XPathNode {
+    /// Document, element, text or comment node
+    Node(usize),
+    /// Element id and attribute name
+    Attribute(usize, String),
+    /// Element id and namespace prefix (empty for the default namespace)
+    Namespace(usize, String),
+}
+
+impl XPathNode {
+    /// Returns the id of the node in the document. For attribute and namespace nodes this is the
+    /// id of the element they belong to.
+    pub fn node_id(&self) -> usize {
+        match self {
+            XPathNode::Node(id) | XPathNode::Attribute(id, _) | XPathNode::Namespace(id, _) => *id,
+        }
+    }
+}
+
+/// Result of an XPath expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// Nodes in document order without duplicates
+    NodeSet(Vec<XPathNode>),
+    String(String),
+    Number(f64),
+    Boolean(bool),
+}
+
+impl Value {
+    /// Converts the value to a boolean as done by the boolean() function
+    pub fn to_boolean(&self) -> bool {
+        match self {
+            Value::NodeSet(nodes) => !nodes.is_empty(),
+            Value::String(s) => !s.is_empty(),
+            Value::Number(n) => *n != 0.0 && !n.is_nan(),
+            Value::Boolean(b) => *b,
+        }
+    }
+
+    /// Converts the value to a number as done by the number() function
+    pub fn to_number(&self, document: &Document) -> f64 {
+        match self {
+            Value::Number(n) => *n,
+            Value::Boolean(b) => {
+                if *b {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            _ => evaluator::string_to_number(&self.to_string(document)),
+        }
+    }
+
+    /// Converts the value to a string as done by the string() function. A node set converts to
+    /// the string value of its first node.
+    pub fn to_string(&self, document: &Document) -> String {
+        match self {
+            Value::NodeSet(nodes) => nodes
+                .first()
+                .map(|node| evaluator::string_value(document, node))
+                .unwrap_or_default(),
+            Value::String(s) => s.clone(),
+            Value::Number(n) => evaluator::number_to_string(*n),
+            Value::Boolean(b) => b.to_string(),
+        }
+    }
+}
+
+/// Error returned when an expression cannot be parsed or evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub enum XPathError {
+    /// The expression is not valid XPath. The position is the character offset in the
+    /// expression where the error was found.
+    Syntax { message: String, position: usize },
+    /// The expression is valid but could not be evaluated (ie: wrong argument types)
+    Evaluation { message: String },
+}
+
+impl fmt::Display for XPathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XPathError::Syntax { message, position } => {
+                write!(f, "{} at position {}", message, position)
+            }
+            XPathError::Evaluation { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for XPathError {}
+
+/// Variable bindings and namespace prefixes that are available to an expression
+pub struct XPathContext<'a> {
+    document: &'a Document,
+    variables: HashMap<String, Value>,
+    namespaces: HashMap<String, String>,
+}
+
+impl<'a> XPathContext<'a> {
+    /// Creates a new context. The prefixes html, svg, math, xlink, xml and xmlns are bound to
+    /// their namespaces by default.
+    pub fn new(document: &'a Document) -> Self {
+        let mut namespaces = HashMap::new();
+        for (prefix, uri) in [
+            ("html", HTML_NAMESPACE),
+            ("svg", SVG_NAMESPACE),
+            ("math", MATHML_NAMESPACE),
+            ("xlink", XLINK_NAMESPACE),
+            ("xml", XML_NAMESPACE),
+            ("xmlns", XMLNS_NAMESPACE),
+        ] {
+            namespaces.insert(prefix.to_string(), uri.to_string());
+        }
+
+        Self {
+            document,
+            variables: HashMap::new(),
+            namespaces,
+        }
+    }
+
+    /// Binds a value to the variable $name
+    pub fn set_variable(&mut self, name: &str, value: Value) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    /// Binds a prefix that can be used in name tests (ie: `foo:bar`) to a namespace URI
+    pub fn add_namespace(&mut self, prefix: &str, uri: &str) {
+        self.namespaces.insert(prefix.to_string(), uri.to_string());
+    }
+}
+
+/// A compiled XPath expression that can be evaluated multiple times
+#[derive(Debug, Clone, PartialEq)]
+pub struct XPathExpression {
+    pub expr: Expr,
+}
+
+impl XPathExpression {
+    /// Parses the given XPath 1.0 expression
+    pub fn parse(expression: &str) -> Result<XPathExpression, XPathError> {
+        Ok(XPathExpression {
+            expr: parser::XPathParser::new(expression)?.parse()?,
+        })
+    }
+
+    /// Evaluates the expression with the given node as context node
+    pub fn evaluate(&self, context: &XPathContext, node_id: usize) -> Result<Value, XPathError> {
+        evaluator::Evaluator::new(context).evaluate(&self.expr, XPathNode::Node(node_id))
+    }
+}
+
+impl Document {
+    /// Parses and evaluates the XPath expression with the given node as context node
+    pub fn evaluate_xpath(&self, expression: &str, node_id: usize) -> Result<Value, XPathError> {
+        XPathExpression::parse(expression)?.evaluate(&XPathContext::new(self), node_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html5_parser::node::Node;
+
+    fn element(name: &str, attrs: &[(&str, &str)], namespace: &str) -> Node {
+        let mut attributes = HashMap::new();
+        for (key, value) in attrs {
+            attributes.insert(key.to_string(), value.to_string());
+        }
+        Node::new_element(name, attributes, namespace)
+    }
+
+    // <html><body><div id="main"><p class="a">one</p><p>two</p><span lang="en-US">three</span>
+    // <svg><rect width="10"/></svg></div></body></html>
+    fn build_document() -> Document {
+        let mut document = Document::new();
+        let html_id = document.add_node(element("html", &[], HTML_NAMESPACE), 0);
+        let body_id = document.add_node(element("body", &[], HTML_NAMESPACE), html_id);
+        let div_id = document.add_node(element("div", &[("id", "main")], HTML_NAMESPACE), body_id);
+        let p_id = document.add_node(element("p", &[("class", "a")], HTML_NAMESPACE), div_id);
+        document.add_node(Node::new_text("one"), p_id);
+        let p_id = document.add_node(element("p", &[], HTML_NAMESPACE), div_id);
+        document.add_node(Node::new_text("two"), p_id);
+        let span_id = document.add_node(
+            element("span", &[("lang", "en-US")], HTML_NAMESPACE),
+            div_id,
+        );
+        document.add_node(Node::new_text("three"), span_id);
+        let svg_id = document.add_node(element("svg", &[], SVG_NAMESPACE), div_id);
+        document.add_node(element("rect", &[("width", "10")], SVG_NAMESPACE), svg_id);
+        document
+    }
+
+    fn strings(document: &Document, value: &Value) -> Vec<String> {
+        match value {
+            Value::NodeSet(nodes) => nodes
+                .iter()
+                .map(|node| evaluator::string_value(document, node))
+                .collect(),
+            _ => panic!("expected a node set, got {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_evaluate_xpath() {
+        let document = build_document();
+
+        let result = document.evaluate_xpath("//p", 0).unwrap();
+        assert_eq!(strings(&document, &result), vec!["one", "two"]);
+
+        let result = document.evaluate_xpath("//p[2]", 0).unwrap();
+        assert_eq!(strings(&document, &result), vec!["two"]);
+
+        let result = document.evaluate_xpath("//p[@class]/text()", 0).unwrap();
+        assert_eq!(strings(&document, &result), vec!["one"]);
+
+        let result = document.evaluate_xpath("count(//div/*)", 0).unwrap();
+        assert_eq!(result, Value::Number(4.0));
+
+        let result = document
+            .evaluate_xpath("string(id('main')/@id)", 0)
+            .unwrap();
+        assert_eq!(result, Value::String("main".into()));
+
+        let result = document.evaluate_xpath("//*[lang('en')]", 0).unwrap();
+        assert_eq!(strings(&document, &result), vec!["three"]);
+
+        let result = document.evaluate_xpath("//P", 0).unwrap();
+        assert_eq!(strings(&document, &result).len(), 2);
+    }
+
+    #[test]
+    fn test_context_variables_and_namespaces() {
+        let document = build_document();
+        let mut context = XPathContext::new(&document);
+        context.set_variable("min", Value::Number(5.0));
+        context.add_namespace("s", SVG_NAMESPACE);
+
+        let expr = XPathExpression::parse("//s:rect[@width > $min]").unwrap();
+        match expr.evaluate(&context, 0).unwrap() {
+            Value::NodeSet(nodes) => assert_eq!(nodes.len(), 1),
+            other => panic!("expected a node set, got {:?}", other),
+        }
+
+        // Unprefixed names only match HTML elements and elements without a namespace
+        let expr = XPathExpression::parse("count(//rect) + count(//svg:rect)").unwrap();
+        assert_eq!(expr.evaluate(&context, 0).unwrap(), Value::Number(1.0));
+
+        let expr = XPathExpression::parse("$unknown").unwrap();
+        assert!(matches!(
+            expr.evaluate(&context, 0),
+            Err(XPathError::Evaluation { .. })
+        ));
+    }
+
+    #[test]
+    fn test_deep_document() {
+        let mut document = Document::new();
+        let mut parent_id = 0;
+        for _ in 0..20_000 {
+            parent_id = document.add_node(element("div", &[], HTML_NAMESPACE), parent_id);
+        }
+
+        // Sorting the node set must not build a path from the root for every node
+        assert_eq!(
+            document.evaluate_xpath("count(//div)", 0).unwrap(),
+            Value::Number(20_000.0)
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let document = Document::new();
+        assert_eq!(
+            document.evaluate_xpath("//p[", 0).unwrap_err().to_string(),
+            "expected expression but found end of input at position 4"
+        );
+        assert_eq!(
+            document
+                .evaluate_xpath("count(1)", 0)
+                .unwrap_err()
+                .to_string(),
+            "argument 1 of count() must be a node-set"
+        );
+        assert_eq!(
+            document.evaluate_xpath("//x:p", 0).unwrap_err().to_string(),
+            "unknown namespace prefix 'x'"
+        );
+    }
+}
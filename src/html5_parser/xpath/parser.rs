@@ -0,0 +1,853 @@
+use crate::html5_parser::xpath::functions;
+use crate::html5_parser::xpath::{
+    Axis, BinaryOperator, Expr, LocationPath, NodeTest, Step, XPathError,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    Dot,
+    DotDot,
+    At,
+    Comma,
+    ColonColon,
+    Slash,
+    DoubleSlash,
+    Pipe,
+    Plus,
+    Minus,
+    Equals,
+    NotEquals,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Star,
+    Literal(String),
+    Number(f64),
+    Variable(String),
+    Name(String),          // NCName or QName
+    NamespaceStar(String), // prefix:*
+}
+
+// Splits the expression into tokens. Whether a `*` or a name like `and` is an operator or a name
+// test depends on the preceding token, so that is left to the parser.
+fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, XPathError> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        let start = pos;
+
+        if matches!(c, ' ' | '\t' | '\r' | '\n') {
+            pos += 1;
+            continue;
+        }
+
+        let token = match c {
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '[' => Token::LeftBracket,
+            ']' => Token::RightBracket,
+            '@' => Token::At,
+            ',' => Token::Comma,
+            '|' => Token::Pipe,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '=' => Token::Equals,
+            '*' => Token::Star,
+            '!' if chars.get(pos + 1) == Some(&'=') => {
+                pos += 1;
+                Token::NotEquals
+            }
+            '<' | '>' => {
+                let or_equal = chars.get(pos + 1) == Some(&'=');
+                if or_equal {
+                    pos += 1;
+                }
+                match (c, or_equal) {
+                    ('<', false) => Token::Less,
+                    ('<', true) => Token::LessOrEqual,
+                    (_, false) => Token::Greater,
+                    (_, true) => Token::GreaterOrEqual,
+                }
+            }
+            '/' => {
+                if chars.get(pos + 1) == Some(&'/') {
+                    pos += 1;
+                    Token::DoubleSlash
+                } else {
+                    Token::Slash
+                }
+            }
+            ':' if chars.get(pos + 1) == Some(&':') => {
+                pos += 1;
+                Token::ColonColon
+            }
+            '"' | '\'' => {
+                let end = chars[pos + 1..]
+                    .iter()
+                    .position(|&q| q == c)
+                    .ok_or_else(|| syntax_error("unterminated string literal", start))?;
+                let value = chars[pos + 1..pos + 1 + end].iter().collect();
+                pos += end + 1;
+                Token::Literal(value)
+            }
+            '.' if chars.get(pos + 1) == Some(&'.') => {
+                pos += 1;
+                Token::DotDot
+            }
+            '.' | '0'..='9'
+                if c != '.' || chars.get(pos + 1).is_some_and(|c| c.is_ascii_digit()) =>
+            {
+                let mut end = pos;
+                while end < chars.len() && chars[end].is_ascii_digit() {
+                    end += 1;
+                }
+                if end < chars.len() && chars[end] == '.' {
+                    end += 1;
+                    while end < chars.len() && chars[end].is_ascii_digit() {
+                        end += 1;
+                    }
+                }
+                let number: String = chars[pos..end].iter().collect();
+                pos = end - 1;
+                Token::Number(number.parse().unwrap_or(f64::NAN))
+            }
+            '.' => Token::Dot,
+            '$' => {
+                let (name, end) = read_qname(&chars, pos + 1)
+                    .ok_or_else(|| syntax_error("expected variable name after '$'", start))?;
+                pos = end - 1;
+                Token::Variable(name)
+            }
+            c if is_name_start_char(c) => {
+                let (name, end) = read_qname(&chars, pos).expect("name start char");
+                pos = end - 1;
+                if chars.get(end) == Some(&':') && chars.get(end + 1) == Some(&'*') {
+                    if name.contains(':') {
+                        return Err(syntax_error("invalid name test", start));
+                    }
+                    pos = end + 1;
+                    Token::NamespaceStar(name)
+                } else {
+                    Token::Name(name)
+                }
+            }
+            c => {
+                return Err(syntax_error(
+                    &format!("unexpected character '{}'", c),
+                    start,
+                ))
+            }
+        };
+
+        tokens.push((token, start));
+        pos += 1;
+    }
+
+    Ok(tokens)
+}
+
+fn is_name_start_char(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii()
+}
+
+fn is_name_char(c: char) -> bool {
+    is_name_start_char(c) || c.is_ascii_digit() || c == '-' || c == '.'
+}
+
+// Reads a NCName or a QName (prefix:local) starting at pos and returns it together with the
+// position just after it
+fn read_qname(chars: &[char], pos: usize) -> Option<(String, usize)> {
+    let read_ncname = |start: usize| -> Option<usize> {
+        if !chars.get(start).copied().is_some_and(is_name_start_char) {
+            return None;
+        }
+        let mut end = start + 1;
+        while end < chars.len() && is_name_char(chars[end]) {
+            end += 1;
+        }
+        Some(end)
+    };
+
+    let mut end = read_ncname(pos)?;
+    if chars.get(end) == Some(&':') {
+        if let Some(local_end) = read_ncname(end + 1) {
+            end = local_end;
+        }
+    }
+
+    Some((chars[pos..end].iter().collect(), end))
+}
+
+fn syntax_error(message: &str, position: usize) -> XPathError {
+    XPathError::Syntax {
+        message: message.to_string(),
+        position,
+    }
+}
+
+// Recursive descent parser for the XPath 1.0 expression grammar
+pub(crate) struct XPathParser {
+    tokens: Vec<(Token, usize)>, // Tokens and their character offset in the expression
+    pos: usize,                  // Current position in tokens
+    end: usize,                  // Character length of the expression
+}
+
+impl XPathParser {
+    pub fn new(expression: &str) -> Result<Self, XPathError> {
+        Ok(XPathParser {
+            tokens: tokenize(expression)?,
+            pos: 0,
+            end: expression.chars().count(),
+        })
+    }
+
+    // Parses all tokens as a single expression
+    pub fn parse(&mut self) -> Result<Expr, XPathError> {
+        let expr = self.parse_or()?;
+
+        if let Some(token) = self.peek() {
+            let message = format!("unexpected {}", describe(token));
+            return Err(self.error(&message));
+        }
+
+        Ok(expr)
+    }
+
+    fn error(&self, message: &str) -> XPathError {
+        syntax_error(message, self.position())
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, position)| *position)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    // Consumes the next token when it equals the given token
+    fn accept(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    // Consumes the next token when it is the given operator name (and, or, div, mod)
+    fn accept_operator_name(&mut self, name: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Name(n)) if n == name) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), XPathError> {
+        if self.accept(&token) {
+            return Ok(());
+        }
+
+        let message = match self.peek() {
+            Some(found) => format!(
+                "expected {} but found {}",
+                describe(&token),
+                describe(found)
+            ),
+            None => format!("expected {} but found end of input", describe(&token)),
+        };
+        Err(self.error(&message))
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.parse_and()?;
+        while self.accept_operator_name("or") {
+            expr = binary(BinaryOperator::Or, expr, self.parse_and()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.parse_equality()?;
+        while self.accept_operator_name("and") {
+            expr = binary(BinaryOperator::And, expr, self.parse_equality()?);
+        }
+        Ok(expr)
+    }
+
+    fn parse_equality(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.parse_relational()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Equals) => BinaryOperator::Equal,
+                Some(Token::NotEquals) => BinaryOperator::NotEqual,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = binary(operator, expr, self.parse_relational()?);
+        }
+    }
+
+    fn parse_relational(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.parse_additive()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Less) => BinaryOperator::Less,
+                Some(Token::LessOrEqual) => BinaryOperator::LessOrEqual,
+                Some(Token::Greater) => BinaryOperator::Greater,
+                Some(Token::GreaterOrEqual) => BinaryOperator::GreaterOrEqual,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = binary(operator, expr, self.parse_additive()?);
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.parse_multiplicative()?;
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => BinaryOperator::Add,
+                Some(Token::Minus) => BinaryOperator::Subtract,
+                _ => return Ok(expr),
+            };
+            self.pos += 1;
+            expr = binary(operator, expr, self.parse_multiplicative()?);
+        }
+    }
+
+    // After an operand, `*` is always the multiply operator and div/mod are operator names
+    fn parse_multiplicative(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let operator = if self.accept(&Token::Star) {
+                BinaryOperator::Multiply
+            } else if self.accept_operator_name("div") {
+                BinaryOperator::Divide
+            } else if self.accept_operator_name("mod") {
+                BinaryOperator::Modulo
+            } else {
+                return Ok(expr);
+            };
+            expr = binary(operator, expr, self.parse_unary()?);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, XPathError> {
+        if self.accept(&Token::Minus) {
+            return Ok(Expr::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_union()
+    }
+
+    fn parse_union(&mut self) -> Result<Expr, XPathError> {
+        let mut expr = self.parse_path()?;
+        while self.accept(&Token::Pipe) {
+            expr = binary(BinaryOperator::Union, expr, self.parse_path()?);
+        }
+        Ok(expr)
+    }
+
+    // Parses either a location path or a filter expression optionally followed by a relative
+    // location path
+    fn parse_path(&mut self) -> Result<Expr, XPathError> {
+        let starts_filter = match self.peek() {
+            Some(Token::Variable(_))
+            | Some(Token::LeftParen)
+            | Some(Token::Literal(_))
+            | Some(Token::Number(_)) => true,
+            Some(Token::Name(name)) => {
+                self.peek_at(1) == Some(&Token::LeftParen) && !is_node_type(name)
+            }
+            _ => false,
+        };
+
+        if !starts_filter {
+            if !self.at_step_start()
+                && !matches!(self.peek(), Some(Token::Slash) | Some(Token::DoubleSlash))
+            {
+                let message = match self.peek() {
+                    Some(token) => format!("expected expression but found {}", describe(token)),
+                    None => "expected expression but found end of input".to_string(),
+                };
+                return Err(self.error(&message));
+            }
+            return Ok(Expr::LocationPath(self.parse_location_path()?));
+        }
+
+        let mut expr = self.parse_primary()?;
+
+        let mut predicates = Vec::new();
+        while self.peek() == Some(&Token::LeftBracket) {
+            predicates.push(self.parse_predicate()?);
+        }
+        if !predicates.is_empty() {
+            expr = Expr::Filter(Box::new(expr), predicates);
+        }
+
+        let mut steps = Vec::new();
+        loop {
+            if self.accept(&Token::Slash) {
+                steps.push(self.parse_step()?);
+            } else if self.accept(&Token::DoubleSlash) {
+                steps.push(descendant_or_self_step());
+                steps.push(self.parse_step()?);
+            } else {
+                break;
+            }
+        }
+        if !steps.is_empty() {
+            expr = Expr::Path(Box::new(expr), steps);
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, XPathError> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Variable(name)) => Ok(Expr::Variable(name)),
+            Some(Token::Literal(value)) => Ok(Expr::Literal(value)),
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::LeftParen) => {
+                let expr = self.parse_or()?;
+                self.expect(Token::RightParen)?;
+                Ok(expr)
+            }
+            Some(Token::Name(name)) => {
+                self.expect(Token::LeftParen)?;
+                let mut arguments = Vec::new();
+                if !self.accept(&Token::RightParen) {
+                    loop {
+                        arguments.push(self.parse_or()?);
+                        if self.accept(&Token::RightParen) {
+                            break;
+                        }
+                        self.expect(Token::Comma)?;
+                    }
+                }
+
+                match functions::arity(&name) {
+                    Some((min, max)) => {
+                        if arguments.len() < min || max.is_some_and(|max| arguments.len() > max) {
+                            let message =
+                                format!("wrong number of arguments for function '{}'", name);
+                            return Err(syntax_error(&message, position));
+                        }
+                    }
+                    None => {
+                        let message = format!("unknown function '{}'", name);
+                        return Err(syntax_error(&message, position));
+                    }
+                }
+
+                Ok(Expr::FunctionCall(name, arguments))
+            }
+            _ => unreachable!("parse_primary called on a token that does not start one"),
+        }
+    }
+
+    fn parse_location_path(&mut self) -> Result<LocationPath, XPathError> {
+        let mut steps = Vec::new();
+
+        let absolute = match self.peek() {
+            Some(Token::Slash) => {
+                self.pos += 1;
+                // A lone '/' selects the root node
+                if !self.at_step_start() {
+                    return Ok(LocationPath {
+                        absolute: true,
+                        steps,
+                    });
+                }
+                true
+            }
+            Some(Token::DoubleSlash) => {
+                self.pos += 1;
+                steps.push(descendant_or_self_step());
+                true
+            }
+            _ => false,
+        };
+
+        steps.push(self.parse_step()?);
+        loop {
+            if self.accept(&Token::Slash) {
+                steps.push(self.parse_step()?);
+            } else if self.accept(&Token::DoubleSlash) {
+                steps.push(descendant_or_self_step());
+                steps.push(self.parse_step()?);
+            } else {
+                break;
+            }
+        }
+
+        Ok(LocationPath { absolute, steps })
+    }
+
+    fn at_step_start(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::Dot)
+                | Some(Token::DotDot)
+                | Some(Token::At)
+                | Some(Token::Star)
+                | Some(Token::Name(_))
+                | Some(Token::NamespaceStar(_))
+        )
+    }
+
+    fn parse_step(&mut self) -> Result<Step, XPathError> {
+        if self.accept(&Token::Dot) {
+            return Ok(step(Axis::SelfAxis, NodeTest::Node));
+        }
+        if self.accept(&Token::DotDot) {
+            return Ok(step(Axis::Parent, NodeTest::Node));
+        }
+
+        let axis = if self.accept(&Token::At) {
+            Axis::Attribute
+        } else if self.peek_at(1) == Some(&Token::ColonColon) {
+            let axis = match self.peek() {
+                Some(Token::Name(name)) => axis_from_name(name),
+                _ => None,
+            };
+            match axis {
+                Some(axis) => {
+                    self.pos += 2;
+                    axis
+                }
+                None => return Err(self.error("unknown axis")),
+            }
+        } else {
+            Axis::Child
+        };
+
+        let node_test = self.parse_node_test()?;
+
+        let mut predicates = Vec::new();
+        while self.peek() == Some(&Token::LeftBracket) {
+            predicates.push(self.parse_predicate()?);
+        }
+
+        Ok(Step {
+            axis,
+            node_test,
+            predicates,
+        })
+    }
+
+    fn parse_node_test(&mut self) -> Result<NodeTest, XPathError> {
+        match self.peek().cloned() {
+            Some(Token::Star) => {
+                self.pos += 1;
+                Ok(NodeTest::Any)
+            }
+            Some(Token::NamespaceStar(prefix)) => {
+                self.pos += 1;
+                Ok(NodeTest::NamespaceAny(prefix))
+            }
+            Some(Token::Name(name))
+                if is_node_type(&name) && self.peek_at(1) == Some(&Token::LeftParen) =>
+            {
+                self.pos += 2;
+                let test = match name.as_str() {
+                    "node" => NodeTest::Node,
+                    "text" => NodeTest::Text,
+                    "comment" => NodeTest::Comment,
+                    _ => match self.peek().cloned() {
+                        Some(Token::Literal(target)) => {
+                            self.pos += 1;
+                            NodeTest::ProcessingInstruction(Some(target))
+                        }
+                        _ => NodeTest::ProcessingInstruction(None),
+                    },
+                };
+                self.expect(Token::RightParen)?;
+                Ok(test)
+            }
+            Some(Token::Name(name)) => {
+                self.pos += 1;
+                match name.split_once(':') {
+                    Some((prefix, local)) => {
+                        Ok(NodeTest::Name(Some(prefix.to_string()), local.to_string()))
+                    }
+                    None => Ok(NodeTest::Name(None, name)),
+                }
+            }
+            Some(token) => {
+                let message = format!("expected node test but found {}", describe(&token));
+                Err(self.error(&message))
+            }
+            None => Err(self.error("expected node test but found end of input")),
+        }
+    }
+
+    fn parse_predicate(&mut self) -> Result<Expr, XPathError> {
+        self.expect(Token::LeftBracket)?;
+        let expr = self.parse_or()?;
+        self.expect(Token::RightBracket)?;
+        Ok(expr)
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Expr, right: Expr) -> Expr {
+    Expr::Binary(operator, Box::new(left), Box::new(right))
+}
+
+fn step(axis: Axis, node_test: NodeTest) -> Step {
+    Step {
+        axis,
+        node_test,
+        predicates: Vec::new(),
+    }
+}
+
+// The step that '//' expands to
+fn descendant_or_self_step() -> Step {
+    step(Axis::DescendantOrSelf, NodeTest::Node)
+}
+
+fn is_node_type(name: &str) -> bool {
+    matches!(name, "node" | "text" | "comment" | "processing-instruction")
+}
+
+fn axis_from_name(name: &str) -> Option<Axis> {
+    match name {
+        "ancestor" => Some(Axis::Ancestor),
+        "ancestor-or-self" => Some(Axis::AncestorOrSelf),
+        "attribute" => Some(Axis::Attribute),
+        "child" => Some(Axis::Child),
+        "descendant" => Some(Axis::Descendant),
+        "descendant-or-self" => Some(Axis::DescendantOrSelf),
+        "following" => Some(Axis::Following),
+        "following-sibling" => Some(Axis::FollowingSibling),
+        "namespace" => Some(Axis::Namespace),
+        "parent" => Some(Axis::Parent),
+        "preceding" => Some(Axis::Preceding),
+        "preceding-sibling" => Some(Axis::PrecedingSibling),
+        "self" => Some(Axis::SelfAxis),
+        _ => None,
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::LeftParen => "'('".into(),
+        Token::RightParen => "')'".into(),
+        Token::LeftBracket => "'['".into(),
+        Token::RightBracket => "']'".into(),
+        Token::Dot => "'.'".into(),
+        Token::DotDot => "'..'".into(),
+        Token::At => "'@'".into(),
+        Token::Comma => "','".into(),
+        Token::ColonColon => "'::'".into(),
+        Token::Slash => "'/'".into(),
+        Token::DoubleSlash => "'//'".into(),
+        Token::Pipe => "'|'".into(),
+        Token::Plus => "'+'".into(),
+        Token::Minus => "'-'".into(),
+        Token::Equals => "'='".into(),
+        Token::NotEquals => "'!='".into(),
+        Token::Less => "'<'".into(),
+        Token::LessOrEqual => "'<='".into(),
+        Token::Greater => "'>'".into(),
+        Token::GreaterOrEqual => "'>='".into(),
+        Token::Star => "'*'".into(),
+        Token::Literal(value) => format!("string '{}'", value),
+        Token::Number(value) => format!("number {}", value),
+        Token::Variable(name) => format!("variable ${}", name),
+        Token::Name(name) => format!("name '{}'", name),
+        Token::NamespaceStar(prefix) => format!("'{}:*'", prefix),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(expression: &str) -> Result<Expr, XPathError> {
+        XPathParser::new(expression)?.parse()
+    }
+
+    fn name_step(axis: Axis, name: &str) -> Step {
+        step(axis, NodeTest::Name(None, name.to_string()))
+    }
+
+    #[test]
+    fn test_parse_abbreviations() {
+        assert_eq!(
+            parse("//p/@class").unwrap(),
+            Expr::LocationPath(LocationPath {
+                absolute: true,
+                steps: vec![
+                    descendant_or_self_step(),
+                    name_step(Axis::Child, "p"),
+                    name_step(Axis::Attribute, "class"),
+                ]
+            })
+        );
+        assert_eq!(
+            parse("../.").unwrap(),
+            Expr::LocationPath(LocationPath {
+                absolute: false,
+                steps: vec![
+                    step(Axis::Parent, NodeTest::Node),
+                    step(Axis::SelfAxis, NodeTest::Node),
+                ]
+            })
+        );
+        assert_eq!(
+            parse("/").unwrap(),
+            Expr::LocationPath(LocationPath {
+                absolute: true,
+                steps: vec![]
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_axes_and_node_tests() {
+        let expr = parse("ancestor-or-self::svg:*/following-sibling::text()").unwrap();
+        assert_eq!(
+            expr,
+            Expr::LocationPath(LocationPath {
+                absolute: false,
+                steps: vec![
+                    step(Axis::AncestorOrSelf, NodeTest::NamespaceAny("svg".into())),
+                    step(Axis::FollowingSibling, NodeTest::Text),
+                ]
+            })
+        );
+
+        let expr = parse("processing-instruction('foo')").unwrap();
+        assert_eq!(
+            expr,
+            Expr::LocationPath(LocationPath {
+                absolute: false,
+                steps: vec![step(
+                    Axis::Child,
+                    NodeTest::ProcessingInstruction(Some("foo".into()))
+                )]
+            })
+        );
+    }
+
+    #[test]
+    fn test_operator_disambiguation() {
+        // The first '*' is a name test, the second the multiply operator. 'div' and 'mod' are
+        // element names when they appear where an operand is expected.
+        let expr = parse("* * div div mod").unwrap();
+        let child = |name: &str| {
+            Expr::LocationPath(LocationPath {
+                absolute: false,
+                steps: vec![match name {
+                    "*" => step(Axis::Child, NodeTest::Any),
+                    _ => name_step(Axis::Child, name),
+                }],
+            })
+        };
+        assert_eq!(
+            expr,
+            binary(
+                BinaryOperator::Divide,
+                binary(BinaryOperator::Multiply, child("*"), child("div")),
+                child("mod")
+            )
+        );
+
+        let expr = parse("1 + 2 * 3 = 7 or false()").unwrap();
+        assert_eq!(
+            expr,
+            binary(
+                BinaryOperator::Or,
+                binary(
+                    BinaryOperator::Equal,
+                    binary(
+                        BinaryOperator::Add,
+                        Expr::Number(1.0),
+                        binary(
+                            BinaryOperator::Multiply,
+                            Expr::Number(2.0),
+                            Expr::Number(3.0)
+                        )
+                    ),
+                    Expr::Number(7.0)
+                ),
+                Expr::FunctionCall("false".into(), vec![])
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_filter_expressions() {
+        let expr = parse("$nodes[1]//a | id('x')").unwrap();
+        assert_eq!(
+            expr,
+            binary(
+                BinaryOperator::Union,
+                Expr::Path(
+                    Box::new(Expr::Filter(
+                        Box::new(Expr::Variable("nodes".into())),
+                        vec![Expr::Number(1.0)]
+                    )),
+                    vec![descendant_or_self_step(), name_step(Axis::Child, "a")]
+                ),
+                Expr::FunctionCall("id".into(), vec![Expr::Literal("x".into())])
+            )
+        );
+        assert_eq!(
+            parse("-.5").unwrap(),
+            Expr::Negate(Box::new(Expr::Number(0.5)))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |expression: &str| parse(expression).unwrap_err().to_string();
+
+        assert_eq!(error("'foo"), "unterminated string literal at position 0");
+        assert_eq!(error("a ! b"), "unexpected character '!' at position 2");
+        assert_eq!(error("foo::bar"), "unknown axis at position 0");
+        assert_eq!(error("foo(1)"), "unknown function 'foo' at position 0");
+        assert_eq!(
+            error("count()"),
+            "wrong number of arguments for function 'count' at position 0"
+        );
+        assert_eq!(
+            error("p[1"),
+            "expected ']' but found end of input at position 3"
+        );
+        assert_eq!(error("p]"), "unexpected ']' at position 1");
+        assert_eq!(
+            error("1 + )"),
+            "expected expression but found ')' at position 4"
+        );
+        assert_eq!(
+            error("a/"),
+            "expected node test but found end of input at position 2"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use test_case::test_case;
+
+use gosub_engine::html5_parser::node::HTML_NAMESPACE;
+use gosub_engine::html5_parser::serializer::{
+    serialize_events, text_events, QuoteAttrValues, SerializerOptions, TreeEvent,
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Root {
+    pub tests: Vec<Test>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Test {
+    pub description: String,
+    #[serde(default)]
+    pub options: serde_json::Map<String, Value>,
+    pub input: Vec<Vec<Value>>,
+    pub expected: Vec<String>,
+}
+
+// The html5lib serializer defaults, which differ from the defaults of our serializer
+fn serializer_options(test: &Test) -> SerializerOptions {
+    let mut options = SerializerOptions {
+        quote_attr_values: QuoteAttrValues::Legacy,
+        use_best_quote_char: true,
+        omit_optional_tags: true,
+        minimize_boolean_attributes: true,
+        escape_nbsp: false,
+        alphabetical_attributes: true,
+        ..Default::default()
+    };
+
+    let encoding = test.options.get("encoding").and_then(|v| v.as_str());
+    for (key, value) in test.options.iter() {
+        match key.as_str() {
+            "encoding" => {}
+            "inject_meta_charset" => {
+                options.inject_meta_charset = match value.as_bool() {
+                    Some(true) => encoding.map(String::from),
+                    _ => None,
+                }
+            }
+            "quote_char" => {
+                options.quote_char = value.as_str().unwrap().chars().next().unwrap();
+                options.use_best_quote_char = false;
+            }
+            "quote_attr_values" => {
+                options.quote_attr_values = match value {
+                    Value::Bool(true) => QuoteAttrValues::Always,
+                    Value::String(s) if s == "spec" => QuoteAttrValues::Spec,
+                    _ => QuoteAttrValues::Legacy,
+                }
+            }
+            "minimize_boolean_attributes" => {
+                options.minimize_boolean_attributes = value.as_bool().unwrap()
+            }
+            "use_trailing_solidus" => options.use_trailing_solidus = value.as_bool().unwrap(),
+            "escape_lt_in_attrs" => options.escape_lt_in_attrs = value.as_bool().unwrap(),
+            "escape_rcdata" => options.escape_rcdata = value.as_bool().unwrap(),
+            "strip_whitespace" => options.strip_whitespace = value.as_bool().unwrap(),
+            _ => panic!("unknown serializer option {}", key),
+        }
+    }
+
+    // html5lib injects the meta charset by default when an encoding is given
+    if !test.options.contains_key("inject_meta_charset") {
+        options.inject_meta_charset = encoding.map(String::from);
+    }
+
+    options
+}
+
+fn attributes(value: Option<&Value>) -> Vec<(String, String)> {
+    match value {
+        Some(Value::Array(attributes)) => attributes
+            .iter()
+            .map(|attribute| {
+                (
+                    attribute["name"].as_str().unwrap().to_string(),
+                    attribute["value"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn string(value: Option<&Value>) -> Option<String> {
+    value.and_then(|v| v.as_str()).map(String::from)
+}
+
+// Converts the input tokens of a test into serializer events, the same way the html5lib test
+// harness does.
+fn tree_events(input: &[Vec<Value>]) -> Vec<TreeEvent> {
+    let mut events = Vec::new();
+
+    for token in input {
+        let html_namespace = Some(HTML_NAMESPACE.to_string());
+        match token[0].as_str().unwrap() {
+            "StartTag" => events.push(TreeEvent::StartTag {
+                namespace: string(token.get(1)),
+                name: string(token.get(2)).unwrap(),
+                attributes: attributes(token.get(3)),
+            }),
+            "EmptyTag" if token.len() == 4 => events.push(TreeEvent::EmptyTag {
+                namespace: string(token.get(1)),
+                name: string(token.get(2)).unwrap(),
+                attributes: attributes(token.get(3)),
+            }),
+            "EmptyTag" => events.push(TreeEvent::EmptyTag {
+                namespace: html_namespace,
+                name: string(token.get(1)).unwrap(),
+                attributes: attributes(token.get(2)),
+            }),
+            "EndTag" if token.len() == 3 => events.push(TreeEvent::EndTag {
+                namespace: string(token.get(1)),
+                name: string(token.get(2)).unwrap(),
+            }),
+            "EndTag" => events.push(TreeEvent::EndTag {
+                namespace: html_namespace,
+                name: string(token.get(1)).unwrap(),
+            }),
+            "Comment" => events.push(TreeEvent::Comment(string(token.get(1)).unwrap())),
+            "Characters" | "SpaceCharacters" => {
+                events.extend(text_events(&string(token.get(1)).unwrap()))
+            }
+            "Doctype" => events.push(TreeEvent::Doctype {
+                name: string(token.get(1)).unwrap(),
+                public_id: string(token.get(2)),
+                system_id: string(token.get(3)),
+            }),
+            kind => panic!("unknown token type {}", kind),
+        }
+    }
+
+    events
+}
+
+#[test_case("core.test")]
+#[test_case("injectmeta.test")]
+#[test_case("optionaltags.test")]
+#[test_case("options.test")]
+#[test_case("whitespace.test")]
+fn serializer(filename: &str) {
+    const ROOT: &str = "./tests/data/html5lib-tests/serializer";
+    let path = PathBuf::from(ROOT).join(filename);
+    let contents = fs::read_to_string(&path).unwrap();
+    let container: Root = serde_json::from_str(&contents).unwrap();
+
+    for test in container.tests {
+        let options = serializer_options(&test);
+        let result = serialize_events(tree_events(&test.input), &options);
+
+        assert!(
+            test.expected.contains(&result),
+            "{}: expected {:?}, got {:?}",
+            test.description,
+            test.expected,
+            result
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::panic;
+use std::path::PathBuf;
+use std::sync::{mpsc, Once};
+use std::thread;
+use std::time::Duration;
+use test_case::test_case;
+
+use gosub_engine::html5_parser::input_stream::InputStream;
+use gosub_engine::html5_parser::node::{Node, HTML_NAMESPACE, MATHML_NAMESPACE, SVG_NAMESPACE};
+use gosub_engine::html5_parser::parser::{Html5Parser, ParserLimits, ParserOptions};
+
+const ROOT: &str = "./tests/data/html5lib-tests/tree-construction";
+
+// Cases that are known to fail, one "<file>:<line> script-<on|off>" per line. Lines starting
+// with # are comments.
+const KNOWN_FAILURES: &str = "./tests/data/tree_construction_known_failures.txt";
+
+// Parsing runs on its own thread, so a parser that does not terminate fails the case instead of
+// hanging the test run
+const PARSE_TIMEOUT: Duration = Duration::from_secs(10);
+const PARSE_THREAD_NAME: &str = "tree-construction-parser";
+
+#[derive(Debug, Default, Clone)]
+struct Test {
+    line: usize,                      // Line of the #data section in the file
+    data: String,                     // Input to parse
+    fragment_context: Option<String>, // Context element when parsing a fragment
+    scripting: Option<bool>,          // Scripting mode, or None to run in both modes
+    document: String,                 // Expected tree in the html5lib format
+}
+
+// Reads all tests from a .dat file, see the README.md in the tree-construction directory. Only
+// the trees are compared, the expected errors are checked by the parser_test binary.
+fn read_tests(path: &PathBuf) -> Vec<Test> {
+    let contents = fs::read_to_string(path).unwrap();
+    let lines: Vec<&str> = contents.split('\n').collect();
+
+    let mut tests = Vec::new();
+    let mut test: Option<Test> = None;
+    let mut section = "";
+
+    for (idx, &line) in lines.iter().enumerate() {
+        // A new test starts with #data, at the start of the file or after an empty line
+        if line == "#data" && (idx == 0 || lines[idx - 1].is_empty()) {
+            tests.extend(test.take());
+            test = Some(Test {
+                line: idx + 1,
+                ..Default::default()
+            });
+            section = "#data";
+            continue;
+        }
+
+        let test = match test.as_mut() {
+            Some(test) => test,
+            None => continue,
+        };
+
+        match line {
+            "#errors" | "#new-errors" | "#document-fragment" | "#document" => {
+                section = line;
+                continue;
+            }
+            "#script-on" => {
+                test.scripting = Some(true);
+                continue;
+            }
+            "#script-off" => {
+                test.scripting = Some(false);
+                continue;
+            }
+            _ => {}
+        }
+
+        match section {
+            "#data" => {
+                test.data.push_str(line);
+                test.data.push('\n');
+            }
+            "#document-fragment" if !line.is_empty() => {
+                test.fragment_context = Some(line.to_string())
+            }
+            "#document" => {
+                test.document.push_str(line);
+                test.document.push('\n');
+            }
+            _ => {}
+        }
+    }
+    tests.extend(test);
+
+    for test in tests.iter_mut() {
+        // The final newline of the data is not part of the input
+        test.data.pop();
+        // Tests are separated by an empty line, which is not part of the tree
+        while test.document.ends_with("\n\n") {
+            test.document.pop();
+        }
+    }
+
+    tests
+}
+
+// Returns the context element for the fragment context of a test, ie: "td" or "svg path"
+fn context_node(context: &str) -> Node {
+    let (namespace, name) = match context.split_once(' ') {
+        Some(("svg", name)) => (SVG_NAMESPACE, name),
+        Some(("math", name)) => (MATHML_NAMESPACE, name),
+        _ => (HTML_NAMESPACE, context),
+    };
+    Node::new_element(name, HashMap::new(), namespace)
+}
+
+// Parses the test data and returns the tree in the html5lib format
+fn parse(test: &Test, options: ParserOptions) -> String {
+    let mut stream = InputStream::new();
+    stream.read_from_str(&test.data, None);
+
+    match &test.fragment_context {
+        Some(context) => {
+            let context = context_node(context);
+            let mut parser = Html5Parser::new_fragment(&mut stream, &context, options);
+            let (document, _, _) = parser.parse_fragment();
+            // The parsed nodes are the children of the html root element
+            let root_id = document.get_root().children.first().copied().unwrap_or(0);
+            document.to_html5lib_tree_from(root_id)
+        }
+        None => {
+            let mut parser = Html5Parser::new_with_options(&mut stream, options);
+            let (document, _) = parser.parse();
+            document.to_html5lib_tree()
+        }
+    }
+}
+
+// Parses on a separate thread, turning panics and timeouts into an error
+fn run_parser(test: &Test, options: ParserOptions) -> Result<String, String> {
+    // Panics of the parser are reported as failed cases, so keep them out of the test output
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if thread::current().name() != Some(PARSE_THREAD_NAME) {
+                default_hook(info);
+            }
+        }));
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let thread_test = test.clone();
+    thread::Builder::new()
+        .name(PARSE_THREAD_NAME.to_string())
+        .spawn(move || {
+            let result = panic::catch_unwind(|| parse(&thread_test, options));
+            let _ = sender.send(result);
+        })
+        .unwrap();
+
+    match receiver.recv_timeout(PARSE_TIMEOUT) {
+        Ok(Ok(tree)) => Ok(tree),
+        Ok(Err(payload)) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(format!("parser panicked: {}", message))
+        }
+        Err(_) => Err("parser did not finish".to_string()),
+    }
+}
+
+fn known_failures(filename: &str) -> BTreeSet<String> {
+    fs::read_to_string(KNOWN_FAILURES)
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter(|line| line.split(':').next() == Some(filename))
+        .map(String::from)
+        .collect()
+}
+
+#[test_case("adoption01.dat")]
+#[test_case("adoption02.dat")]
+#[test_case("blocks.dat")]
+#[test_case("comments01.dat")]
+#[test_case("doctype01.dat")]
+#[test_case("domjs-unsafe.dat")]
+#[test_case("entities01.dat")]
+#[test_case("entities02.dat")]
+#[test_case("foreign-fragment.dat")]
+#[test_case("html5test-com.dat")]
+#[test_case("inbody01.dat")]
+#[test_case("isindex.dat")]
+#[test_case("main-element.dat")]
+#[test_case("math.dat")]
+#[test_case("menuitem-element.dat")]
+#[test_case("namespace-sensitivity.dat")]
+#[test_case("noscript01.dat")]
+#[test_case("pending-spec-changes-plain-text-unsafe.dat")]
+#[test_case("pending-spec-changes.dat")]
+#[test_case("plain-text-unsafe.dat")]
+#[test_case("quirks01.dat")]
+#[test_case("ruby.dat")]
+#[test_case("scriptdata01.dat")]
+#[test_case("search-element.dat")]
+#[test_case("svg.dat")]
+#[test_case("tables01.dat")]
+#[test_case("template.dat")]
+#[test_case("tests1.dat")]
+#[test_case("tests2.dat")]
+#[test_case("tests3.dat")]
+#[test_case("tests4.dat")]
+#[test_case("tests5.dat")]
+#[test_case("tests6.dat")]
+#[test_case("tests7.dat")]
+#[test_case("tests8.dat")]
+#[test_case("tests9.dat")]
+#[test_case("tests10.dat")]
+#[test_case("tests11.dat")]
+#[test_case("tests12.dat")]
+#[test_case("tests14.dat")]
+#[test_case("tests15.dat")]
+#[test_case("tests16.dat")]
+#[test_case("tests17.dat")]
+#[test_case("tests18.dat")]
+#[test_case("tests19.dat")]
+#[test_case("tests20.dat")]
+#[test_case("tests21.dat")]
+#[test_case("tests22.dat")]
+#[test_case("tests23.dat")]
+#[test_case("tests24.dat")]
+#[test_case("tests25.dat")]
+#[test_case("tests26.dat")]
+#[test_case("tests_innerHTML_1.dat")]
+#[test_case("tricky01.dat")]
+#[test_case("webkit01.dat")]
+#[test_case("webkit02.dat")]
+fn tree_construction(filename: &str) {
+    let path = PathBuf::from(ROOT).join(filename);
+    let known_failures = known_failures(filename);
+
+    let mut regressions = Vec::new();
+    let mut newly_passing = Vec::new();
+
+    for test in read_tests(&path) {
+        let modes = match test.scripting {
+            Some(scripting) => vec![scripting],
+            None => vec![true, false],
+        };
+
+        for scripting in modes {
+            let id = format!(
+                "{}:{} script-{}",
+                filename,
+                test.line,
+                if scripting { "on" } else { "off" }
+            );
+
+            let options = ParserOptions {
+                scripting_enabled: scripting,
+                ..Default::default()
+            };
+            let result = run_parser(&test, options);
+            let passed = result.as_deref() == Ok(test.document.as_str());
+
+            match (passed, known_failures.contains(&id)) {
+                (false, false) => regressions.push(format!(
+                    "{}\n#data\n{}\n#expected\n{}#actual\n{}",
+                    id,
+                    test.data,
+                    test.document,
+                    result.unwrap_or_else(|err| err + "\n")
+                )),
+                (true, true) => newly_passing.push(id),
+                _ => {}
+            }
+        }
+    }
+
+    assert!(
+        regressions.is_empty() && newly_passing.is_empty(),
+        "{} unexpected failures, {} cases now pass that are listed in {} (remove them from \
+         the list):\n{}\n\n{}",
+        regressions.len(),
+        newly_passing.len(),
+        KNOWN_FAILURES,
+        newly_passing.join("\n"),
+        regressions.join("\n")
+    );
+}
+
+// The parser limits change the tree of hostile input, but must never make the parser panic or
+// hang. Run all inputs that parse without limits with small tree depth limits, as those hit every
+// insertion mode.
+#[test]
+fn tree_depth_limits() {
+    let mut paths: Vec<PathBuf> = fs::read_dir(ROOT)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "dat"))
+        .collect();
+    paths.sort();
+
+    let mut failures = Vec::new();
+    for path in paths {
+        for test in read_tests(&path) {
+            let options = ParserOptions {
+                scripting_enabled: test.scripting.unwrap_or(true),
+                ..Default::default()
+            };
+            if run_parser(&test, options.clone()).is_err() {
+                continue;
+            }
+
+            for depth in 1..=6 {
+                let options = ParserOptions {
+                    limits: ParserLimits {
+                        max_tree_depth: Some(depth),
+                        ..Default::default()
+                    },
+                    ..options.clone()
+                };
+                if let Err(err) = run_parser(&test, options) {
+                    failures.push(format!(
+                        "{}:{} max_tree_depth {}: {}",
+                        path.file_name().unwrap().to_string_lossy(),
+                        test.line,
+                        depth,
+                        err
+                    ));
+                }
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} failures:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}